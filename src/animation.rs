@@ -0,0 +1,468 @@
+//! A builder for animations, so that avatars generated from Rust code can move. Figura splits an
+//! animation in two: its [metadata][moon::Animation] goes in [Moon::animations], and the keyframes
+//! for each part go in that part's [anim][ModelPart::anim] tag, keyed by the animation's index.
+//! [AnimationBuilder] produces both halves and [BuiltAnimation::insert_into] puts them in place.
+//!
+//! ```no_run
+//! # use fia::animation::{AnimationBuilder, Channel};
+//! # let mut moon = fia::Moon::default();
+//! AnimationBuilder::new("wave")
+//!     .loops()
+//!     .channel("model/body/right_arm", Channel::Rotation)
+//!     .key(0.0, [0.0, 0.0, 0.0])
+//!     .key(0.5, [0.0, 0.0, 120.0])
+//!     .key(1.0, [0.0, 0.0, 0.0])
+//!     .build()
+//!     .insert_into(&mut moon)
+//!     .expect("part exists");
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+use thiserror::Error;
+use crate::moon::{self, CodeKeyframe, KeyframeValue, Loop, ModelPart, PartChannels, PartKeyframe};
+use crate::Moon;
+pub use crate::moon::Interpolation;
+
+/// A property of a part that an animation can change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Channel {
+    /// Rotation in degrees around each axis.
+    Rotation,
+    /// Offset from the part's resting position.
+    Position,
+    /// Scale along each axis, where 1 is unchanged.
+    Scale,
+}
+
+impl Channel {
+    /// The key Figura stores this channel's keyframes under.
+    pub fn key(self) -> &'static str {
+        match self {
+            Channel::Rotation => "rot",
+            Channel::Position => "pos",
+            Channel::Scale => "scl",
+        }
+    }
+}
+
+impl Display for Channel {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        fmt.write_str(match self {
+            Channel::Rotation => "rotation",
+            Channel::Position => "position",
+            Channel::Scale => "scale",
+        })
+    }
+}
+
+impl PartChannels {
+    /// The keyframes for one channel.
+    pub fn channel(&self, channel: Channel) -> &Vec<PartKeyframe> {
+        match channel {
+            Channel::Rotation => &self.rot,
+            Channel::Position => &self.pos,
+            Channel::Scale => &self.scl,
+        }
+    }
+
+    /// Mutable access to the keyframes for one channel.
+    pub fn channel_mut(&mut self, channel: Channel) -> &mut Vec<PartKeyframe> {
+        match channel {
+            Channel::Rotation => &mut self.rot,
+            Channel::Position => &mut self.pos,
+            Channel::Scale => &mut self.scl,
+        }
+    }
+}
+
+impl PartChannels {
+    /// The channels that have at least one keyframe.
+    pub fn channels(&self) -> impl Iterator<Item = Channel> + '_ {
+        [Channel::Rotation, Channel::Position, Channel::Scale].into_iter().filter(|&c| !self.channel(c).is_empty())
+    }
+}
+
+impl ModelPart {
+    /// Lists the descendants of this part (including itself) that an animation drives, by path
+    /// relative to this part, along with their keyframes.
+    pub fn animated_parts(&self, index: usize) -> Vec<(String, &PartChannels)> {
+        fn visit<'a>(part: &'a ModelPart, path: String, index: usize, out: &mut Vec<(String, &'a PartChannels)>) {
+            if let Some(channels) = part.anim.get(&index) {
+                out.push((path.clone(), channels));
+            }
+            for child in part.chld.iter() {
+                let path = if path.is_empty() { child.name.clone() } else { format!("{path}/{}", child.name) };
+                visit(child, path, index, out);
+            }
+        }
+        let mut out = vec![];
+        visit(self, String::new(), index, &mut out);
+        out
+    }
+
+    /// Adds `offset` to the animation index of every keyframe on this part and its descendants,
+    /// such as when adding a compiled model's animations after a moon's existing ones.
+    pub fn offset_animations(&mut self, offset: usize) {
+        self.anim = std::mem::take(&mut self.anim).into_iter().map(|(i, channels)| (i + offset, channels)).collect();
+        for child in self.chld.iter_mut() {
+            child.offset_animations(offset);
+        }
+    }
+}
+
+impl Moon {
+    /// Finds an animation's index in [Moon::animations] by the name of its model and its own name.
+    pub fn find_animation(&self, model: &str, name: &str) -> Option<usize> {
+        self.animations.iter().position(|a| a.mdl == model && a.name == name)
+    }
+
+    /// Lists the parts an animation drives, by path as in [AnimationBuilder::channel], along with
+    /// their keyframes.
+    pub fn animated_parts(&self, index: usize) -> Vec<(String, &PartChannels)> {
+        self.models.as_ref().map_or(vec![], |models| models.animated_parts(index))
+    }
+}
+
+/// Builds an animation one channel at a time. Keyframes added with [key][Self::key] go to the
+/// channel most recently selected with [channel][Self::channel].
+#[derive(Clone, Debug)]
+pub struct AnimationBuilder {
+    animation: moon::Animation,
+    length: Option<f64>,
+    channels: BTreeMap<String, PartChannels>,
+    current: Option<(String, Channel)>,
+}
+
+impl AnimationBuilder {
+    /// Starts an animation with the given name. It plays once, and its length is the time of its
+    /// last keyframe unless [length][Self::length] is called.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            animation: moon::Animation { name: name.into(), ..Default::default() },
+            length: None,
+            channels: BTreeMap::new(),
+            current: None,
+        }
+    }
+
+    /// Makes the animation start over when it ends.
+    pub fn loops(mut self) -> Self {
+        self.animation.r#loop = Some(Loop::Loop);
+        self
+    }
+
+    /// Makes the animation keep its last keyframe's values when it ends.
+    pub fn holds(mut self) -> Self {
+        self.animation.r#loop = Some(Loop::Hold);
+        self
+    }
+
+    /// Sets the animation's length in seconds.
+    pub fn length(mut self, length: f64) -> Self {
+        self.length = Some(length);
+        self
+    }
+
+    /// Sets the model the animation belongs to. By default, this is the first component of the
+    /// first channel's part path.
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.animation.mdl = model.into();
+        self
+    }
+
+    /// Makes the animation override vanilla animations on parent-typed parts.
+    pub fn overrides(mut self) -> Self {
+        self.animation.ovr = 1;
+        self
+    }
+
+    /// Sets the animation's blend weight.
+    pub fn blend(mut self, weight: f64) -> Self {
+        self.animation.bld = Some(weight);
+        self
+    }
+
+    /// Sets how long to wait before starting the animation, in seconds.
+    pub fn start_delay(mut self, delay: f64) -> Self {
+        self.animation.sdel = delay;
+        self
+    }
+
+    /// Sets how long to wait before looping the animation, in seconds.
+    pub fn loop_delay(mut self, delay: f64) -> Self {
+        self.animation.ldel = delay;
+        self
+    }
+
+    /// Runs a snippet of Lua when the animation reaches the given time.
+    pub fn code(mut self, time: f64, src: impl Into<String>) -> Self {
+        self.animation.code.push(CodeKeyframe { time, src: src.into() });
+        self
+    }
+
+    /// Selects the channel that following keyframes are added to. The part is given as a path of
+    /// part names separated by `/`, starting with the model's name (the child of [Moon::models]).
+    pub fn channel(mut self, part: impl Into<String>, channel: Channel) -> Self {
+        let part = part.into();
+        self.channels.entry(part.clone()).or_default();
+        self.current = Some((part, channel));
+        self
+    }
+
+    /// Adds a linearly-interpolated keyframe to the current channel.
+    ///
+    /// # Panics
+    /// Panics if [channel][Self::channel] hasn't been called yet.
+    pub fn key(self, time: f64, value: [f64; 3]) -> Self {
+        self.key_with(time, value, Interpolation::Linear)
+    }
+
+    /// Adds a keyframe with the given interpolation to the current channel.
+    ///
+    /// # Panics
+    /// Panics if [channel][Self::channel] hasn't been called yet.
+    pub fn key_with(mut self, time: f64, value: [f64; 3], interpolation: Interpolation) -> Self {
+        let (part, channel) = self.current.as_ref().expect("AnimationBuilder::key called before selecting a channel");
+        self.channels.get_mut(part).unwrap().channel_mut(*channel).push(PartKeyframe {
+            time: time as f32,
+            int: interpolation,
+            pre: KeyframeValue::Numbers(value.map(|v| v as f32)),
+            end: None,
+            bl: None,
+            br: None,
+            blt: None,
+            brt: None,
+        });
+        self
+    }
+
+    /// Finishes the animation.
+    pub fn build(self) -> BuiltAnimation {
+        let mut animation = self.animation;
+        let mut channels = self.channels;
+        let mut last = animation.code.iter().map(|c| c.time).fold(0.0, f64::max);
+        for part in channels.values_mut() {
+            for channel in [Channel::Rotation, Channel::Position, Channel::Scale] {
+                let keys = part.channel_mut(channel);
+                keys.sort_by(|a, b| a.time.total_cmp(&b.time));
+                last = keys.iter().map(|k| k.time as f64).fold(last, f64::max);
+            }
+        }
+        animation.len = self.length.unwrap_or(last);
+        if animation.mdl.is_empty() {
+            if let Some(path) = channels.keys().next() {
+                animation.mdl = path.split('/').next().unwrap_or_default().into();
+            }
+        }
+        BuiltAnimation { animation, parts: channels }
+    }
+}
+
+/// The output of an [AnimationBuilder].
+#[derive(Debug)]
+pub struct BuiltAnimation {
+    /// The animation's metadata, for [Moon::animations].
+    pub animation: moon::Animation,
+    /// The keyframes for each animated part, keyed by part path.
+    pub parts: BTreeMap<String, PartChannels>,
+}
+
+/// An error encountered while adding an animation to a moon.
+#[derive(Debug, Error)]
+pub enum AnimationError {
+    /// A channel refers to a part that doesn't exist.
+    #[error("no part at {0:?}")]
+    MissingPart(String),
+}
+
+impl BuiltAnimation {
+    /// Adds this animation to a moon, returning its index in [Moon::animations]. Nothing is
+    /// changed if any part can't be found.
+    pub fn insert_into(self, moon: &mut Moon) -> Result<usize, AnimationError> {
+        let index = moon.animations.len();
+        let root = moon.models.get_or_insert_with(Default::default);
+        for path in self.parts.keys() {
+            root.find_mut(path).ok_or_else(|| AnimationError::MissingPart(path.clone()))?;
+        }
+        for (path, channels) in self.parts {
+            root.find_mut(&path).unwrap().anim.insert(index, channels);
+        }
+        moon.animations.push(self.animation);
+        Ok(index)
+    }
+}
+
+/// The first Figura version that reads bézier handles. Older versions play bézier keyframes as
+/// linear ones, which [Moon::adapt_beziers] works around.
+pub const BEZIER_SINCE: (u32, u32, u32) = (0, 1, 1);
+
+/// How many keyframes [Moon::adapt_beziers] puts on each bézier curve it samples.
+pub const DEFAULT_BEZIER_SAMPLES: u32 = 8;
+
+/// Reads the `major.minor.patch` part of a Figura version, ignoring suffixes like `-rc.1`.
+fn parse_version(version: &str) -> Option<(u32, u32, u32)> {
+    let mut parts = version.split(['.', '-', '+']).map(str::parse::<u32>);
+    Some((parts.next()?.ok()?, parts.next()?.ok()?, parts.next().and_then(Result::ok).unwrap_or(0)))
+}
+
+/// Whether a Figura version can play bézier keyframes. Unknown or empty versions are assumed to
+/// be recent.
+pub fn supports_bezier(version: &str) -> bool {
+    parse_version(version).map_or(true, |v| v >= BEZIER_SINCE)
+}
+
+/// The first Figura version that evaluates expressions in keyframes. Older versions can only
+/// play constant keyframes, which [Moon::adapt_expressions] works around.
+pub const EXPRESSIONS_SINCE: (u32, u32, u32) = (0, 1, 1);
+
+/// Whether a Figura version can play keyframes whose values are expressions. Unknown or empty
+/// versions are assumed to be recent.
+pub fn supports_expressions(version: &str) -> bool {
+    parse_version(version).map_or(true, |v| v >= EXPRESSIONS_SINCE)
+}
+
+/// Finds the value of one axis of a bézier segment at `time`. Handles are offsets from their
+/// keyframe, as Blockbench stores them, and default to Blockbench's own defaults.
+fn bezier_at(from: &PartKeyframe, to: &PartKeyframe, axis: usize, time: f32) -> Option<f32> {
+    let value = |v: &KeyframeValue| match v {
+        KeyframeValue::Numbers(n) => Some(n[axis]),
+        KeyframeValue::Expressions(_) => None,
+    };
+    let (t0, t3) = (from.time, to.time);
+    let v0 = value(from.end.as_ref().unwrap_or(&from.pre))?;
+    let v3 = value(&to.pre)?;
+    // keeping the handles inside the segment keeps time increasing along the curve
+    let t1 = (t0 + from.brt.map_or(0.1, |h| h[axis])).clamp(t0, t3);
+    let t2 = (t3 + to.blt.map_or(-0.1, |h| h[axis])).clamp(t0, t3);
+    let v1 = v0 + from.br.map_or(0.0, |h| h[axis]);
+    let v2 = v3 + to.bl.map_or(0.0, |h| h[axis]);
+    let cubic = |s: f32, a: f32, b: f32, c: f32, d: f32| {
+        let r = 1.0 - s;
+        r * r * r * a + 3.0 * r * r * s * b + 3.0 * r * s * s * c + s * s * s * d
+    };
+    let (mut lo, mut hi) = (0.0, 1.0);
+    for _ in 0..32 {
+        let mid = (lo + hi) / 2.0;
+        if cubic(mid, t0, t1, t2, t3) < time { lo = mid } else { hi = mid }
+    }
+    Some(cubic((lo + hi) / 2.0, v0, v1, v2, v3))
+}
+
+/// Replaces bézier keyframes in one channel with Catmull-Rom keyframes sampled from each curve.
+/// Keyframes with expressions can't be sampled, so they become linear.
+fn sample_beziers(keys: &mut Vec<PartKeyframe>, samples: u32) {
+    let samples = samples.max(1);
+    let mut out = Vec::with_capacity(keys.len());
+    for (i, key) in keys.iter().enumerate() {
+        let mut key = key.clone();
+        if key.int != Interpolation::Bezier {
+            out.push(key);
+            continue;
+        }
+        key.int = Interpolation::CatmullRom;
+        let next = keys.get(i + 1);
+        let points: Option<Vec<_>> = next.map(|next| (1..samples).map(|step| {
+            let time = key.time + (next.time - key.time) * step as f32 / samples as f32;
+            let axes = [0, 1, 2].map(|axis| bezier_at(&keys[i], next, axis, time));
+            Some((time, [axes[0]?, axes[1]?, axes[2]?]))
+        }).collect()).flatten();
+        if points.is_none() {
+            key.int = Interpolation::Linear;
+        }
+        (key.bl, key.br, key.blt, key.brt) = (None, None, None, None);
+        out.push(key.clone());
+        for (time, value) in points.into_iter().flatten() {
+            out.push(PartKeyframe { time, pre: KeyframeValue::Numbers(value), end: None, ..key.clone() });
+        }
+    }
+    *keys = out;
+}
+
+impl PartChannels {
+    /// Replaces bézier keyframes with `samples` Catmull-Rom keyframes along each curve, for Figura
+    /// versions that don't support béziers. A single sample keeps just the original keyframes, and
+    /// so does 0.
+    pub fn sample_beziers(&mut self, samples: u32) {
+        for channel in [Channel::Rotation, Channel::Position, Channel::Scale] {
+            sample_beziers(self.channel_mut(channel), samples);
+        }
+    }
+
+    /// Replaces keyframe values that are expressions with constants, for Figura versions that
+    /// don't support them. Each axis that is just a number keeps it; others get the channel's
+    /// resting value (0, or 1 for scale). Returns whether there were any expressions.
+    pub fn freeze_expressions(&mut self) -> bool {
+        let mut found = false;
+        for channel in [Channel::Rotation, Channel::Position, Channel::Scale] {
+            let rest = if channel == Channel::Scale { 1.0 } else { 0.0 };
+            let freeze = |value: &mut KeyframeValue| if let KeyframeValue::Expressions(axes) = value {
+                *value = KeyframeValue::Numbers(axes.each_ref().map(|axis| axis.trim().parse().unwrap_or(rest)));
+                true
+            } else {
+                false
+            };
+            for key in self.channel_mut(channel) {
+                found |= freeze(&mut key.pre);
+                found |= key.end.as_mut().is_some_and(freeze);
+            }
+        }
+        found
+    }
+}
+
+impl Moon {
+    /// Makes bézier keyframes playable by the `target` Figura version: they're left alone if it
+    /// [supports them][supports_bezier], and [sampled][PartChannels::sample_beziers] with
+    /// [DEFAULT_BEZIER_SAMPLES] otherwise. Returns whether anything was sampled.
+    ///
+    /// The avatar's own [ver][moon::Metadata::ver] is the version that exported it, not the one
+    /// that will play it, so it isn't consulted.
+    pub fn adapt_beziers(&mut self, target: &str) -> bool {
+        if supports_bezier(target) {
+            return false;
+        }
+        self.sample_beziers(DEFAULT_BEZIER_SAMPLES)
+    }
+
+    /// Makes expression keyframes playable by the `target` Figura version: they're left alone if
+    /// it [supports them][supports_expressions], and [frozen][PartChannels::freeze_expressions]
+    /// into constants otherwise. Returns whether anything was frozen. Like
+    /// [adapt_beziers][Moon::adapt_beziers], this ignores the avatar's own version.
+    pub fn adapt_expressions(&mut self, target: &str) -> bool {
+        fn visit(part: &mut ModelPart) -> bool {
+            let mut found = false;
+            for channels in part.anim.values_mut() {
+                found |= channels.freeze_expressions();
+            }
+            for child in part.chld.iter_mut() {
+                found |= visit(child);
+            }
+            found
+        }
+        if supports_expressions(target) {
+            return false;
+        }
+        self.models.as_mut().is_some_and(visit)
+    }
+
+    /// Samples every bézier keyframe in the avatar; see [PartChannels::sample_beziers]. Returns
+    /// whether there were any.
+    pub fn sample_beziers(&mut self, samples: u32) -> bool {
+        fn visit(part: &mut ModelPart, samples: u32) -> bool {
+            let mut found = false;
+            for channels in part.anim.values_mut() {
+                let has_bezier = channels.channels().any(|c| channels.channel(c).iter().any(|k| k.int == Interpolation::Bezier));
+                if has_bezier {
+                    channels.sample_beziers(samples);
+                    found = true;
+                }
+            }
+            for child in part.chld.iter_mut() {
+                found |= visit(child, samples);
+            }
+            found
+        }
+        self.models.as_mut().is_some_and(|models| visit(models, samples))
+    }
+}
@@ -0,0 +1,238 @@
+//! Avatar files that haven't necessarily been loaded yet. An [Asset] records where its data lives
+//! and only reads it when it's needed: [Moon::pack_dir_lazy] leaves an avatar's scripts, sounds,
+//! and resources on disk until the avatar is written out, and the avatar files fia's commands
+//! take can be [URLs][Asset::locate], which are only fetched once the command gets to them.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use quartz_nbt::io::{Flavor, NbtIoError};
+use quartz_nbt::serde::Array;
+use serde::{Serialize, Serializer};
+use thiserror::Error;
+use url::Url;
+use crate::http;
+use crate::moon::{Animation, Metadata, ModelPart, TextureData};
+use crate::report::CliError;
+use crate::Moon;
+
+/// The contents of one file in an avatar.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Asset {
+    /// Data that has already been loaded.
+    InMemory(Vec<u8>),
+    /// A file that is read when needed.
+    OnDisk(PathBuf),
+    /// A `file:`, `http:`, or `https:` URL that is fetched when needed.
+    Remote(Url),
+}
+
+/// An error encountered while loading an [Asset].
+#[derive(Debug, Error)]
+pub enum AssetError {
+    /// The file couldn't be read.
+    #[error("couldn't read {0}: {1}")]
+    Io(String, #[source] io::Error),
+    /// The URL's scheme isn't one fia knows how to fetch.
+    #[error("can't fetch {0}: unsupported scheme")]
+    UnsupportedScheme(Url),
+    /// The server responded with something other than a successful response.
+    #[error("couldn't fetch {0}: {1}")]
+    Http(Url, String),
+}
+
+impl From<AssetError> for CliError {
+    fn from(error: AssetError) -> Self {
+        match error {
+            AssetError::Io(path, e) => CliError::io("read", path, e),
+            AssetError::UnsupportedScheme(_) => CliError::usage(error),
+            AssetError::Http(..) => CliError::backend(error),
+        }
+    }
+}
+
+impl Asset {
+    /// Where a file named on the command line is: at a URL if the name starts with a scheme fia
+    /// can fetch (`file:`, `http:`, or `https:`) and parses as one, and on disk otherwise.
+    pub fn locate(name: &Path) -> Asset {
+        name.to_str()
+            .filter(|name| ["file:", "http:", "https:"].iter().any(|scheme| name.starts_with(scheme)))
+            .and_then(|name| Url::parse(name).ok())
+            .map_or_else(|| Asset::OnDisk(name.into()), Asset::Remote)
+    }
+
+    /// Returns this asset's data, reading or fetching it if necessary. The result is not cached;
+    /// use [Asset::make_loaded] to keep it.
+    pub fn load(&self) -> Result<Cow<[u8]>, AssetError> {
+        match self {
+            Asset::InMemory(data) => Ok(Cow::Borrowed(data)),
+            Asset::OnDisk(path) => fs::read(path).map(Cow::Owned).map_err(|e| AssetError::Io(path.display().to_string(), e)),
+            Asset::Remote(url) => fetch(url).map(Cow::Owned),
+        }
+    }
+
+    /// Loads this asset and replaces it with the loaded data.
+    pub fn make_loaded(&mut self) -> Result<&mut Vec<u8>, AssetError> {
+        if !self.is_loaded() {
+            *self = Asset::InMemory(self.load()?.into_owned());
+        }
+        let Asset::InMemory(data) = self else { unreachable!() };
+        Ok(data)
+    }
+
+    /// Converts this asset into its data, reading or fetching it if necessary.
+    pub fn into_bytes(self) -> Result<Vec<u8>, AssetError> {
+        match self {
+            Asset::InMemory(data) => Ok(data),
+            other => other.load().map(Cow::into_owned),
+        }
+    }
+
+    /// Whether this asset's data is already in memory.
+    pub fn is_loaded(&self) -> bool {
+        matches!(self, Asset::InMemory(_))
+    }
+
+    /// The size of this asset's data in bytes, if it can be known without loading it.
+    pub fn size_hint(&self) -> Option<u64> {
+        match self {
+            Asset::InMemory(data) => Some(data.len() as u64),
+            Asset::OnDisk(path) => fs::metadata(path).ok().map(|m| m.len()),
+            Asset::Remote(_) => None,
+        }
+    }
+}
+
+impl From<Vec<u8>> for Asset {
+    fn from(data: Vec<u8>) -> Self {
+        Asset::InMemory(data)
+    }
+}
+
+impl From<Array<Vec<u8>>> for Asset {
+    fn from(data: Array<Vec<u8>>) -> Self {
+        Asset::InMemory(Array::into_inner(data))
+    }
+}
+
+impl From<PathBuf> for Asset {
+    fn from(path: PathBuf) -> Self {
+        Asset::OnDisk(path)
+    }
+}
+
+impl From<Url> for Asset {
+    fn from(url: Url) -> Self {
+        Asset::Remote(url)
+    }
+}
+
+/// Serializes as an NBT byte array, loading the asset first. Loading errors become serialization
+/// errors.
+impl Serialize for Asset {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let data = self.load().map_err(serde::ser::Error::custom)?;
+        Array::from(data.into_owned()).serialize(serializer)
+    }
+}
+
+/// Fetches a `file:`, `http:`, or `https:` URL.
+fn fetch(url: &Url) -> Result<Vec<u8>, AssetError> {
+    match url.scheme() {
+        "file" => {
+            let path = url.to_file_path().map_err(|_| AssetError::UnsupportedScheme(url.clone()))?;
+            fs::read(&path).map_err(|e| AssetError::Io(path.display().to_string(), e))
+        }
+        "http" | "https" => {
+            let response = http::get(url, &[]).map_err(|e| AssetError::Http(url.clone(), e.to_string()))?;
+            if response.is_success() {
+                Ok(response.body)
+            } else {
+                Err(AssetError::Http(url.clone(), format!("status {}", response.status)))
+            }
+        }
+        _ => Err(AssetError::UnsupportedScheme(url.clone())),
+    }
+}
+
+/// A [Moon] whose blobs are [Asset]s, so that they are only loaded while it is being written. The
+/// blob maps in [moon][Self::moon] are ignored when serializing; everything else comes from it.
+#[derive(Debug, Default)]
+pub struct LazyMoon {
+    /// The avatar's models, animations, metadata, and texture data.
+    pub moon: Moon,
+    /// Texture images, keyed like [Textures::src][crate::moon::Textures::src].
+    pub textures: HashMap<String, Asset>,
+    /// Scripts, keyed like [Moon::scripts].
+    pub scripts: HashMap<String, Asset>,
+    /// Sounds, keyed like [Moon::sounds].
+    pub sounds: HashMap<String, Asset>,
+    /// Resources, keyed like [Moon::resources].
+    pub resources: HashMap<String, Asset>,
+}
+
+#[derive(Serialize)]
+struct LazyTextures<'a> {
+    src: &'a HashMap<String, Asset>,
+    data: &'a [TextureData],
+}
+
+#[derive(Serialize)]
+struct LazyRepr<'a> {
+    textures: LazyTextures<'a>,
+    scripts: &'a HashMap<String, Asset>,
+    animations: &'a Vec<Animation>,
+    models: &'a Option<ModelPart>,
+    resources: &'a HashMap<String, Asset>,
+    sounds: &'a HashMap<String, Asset>,
+    metadata: &'a Metadata,
+}
+
+impl LazyMoon {
+    /// Writes the avatar, loading each asset as it is reached.
+    pub fn write_to(&self, out: &mut impl Write, name: &str, flavor: Flavor) -> Result<(), NbtIoError> {
+        quartz_nbt::serde::serialize_into(out, &self.repr(), Some(name), flavor)
+    }
+
+    /// Loads every asset, producing an ordinary [Moon].
+    pub fn load(self) -> Result<Moon, AssetError> {
+        let mut moon = self.moon;
+        let load = |map: HashMap<String, Asset>| -> Result<HashMap<String, Array<Vec<u8>>>, AssetError> {
+            map.into_iter().map(|(k, v)| Ok((k, v.into_bytes()?.into()))).collect()
+        };
+        moon.textures.src = load(self.textures)?;
+        moon.scripts = load(self.scripts)?;
+        moon.sounds = load(self.sounds)?;
+        moon.resources = load(self.resources)?;
+        Ok(moon)
+    }
+
+    fn repr(&self) -> LazyRepr {
+        LazyRepr {
+            textures: LazyTextures { src: &self.textures, data: &self.moon.textures.data },
+            scripts: &self.scripts,
+            animations: &self.moon.animations,
+            models: &self.moon.models,
+            resources: &self.resources,
+            sounds: &self.sounds,
+            metadata: &self.moon.metadata,
+        }
+    }
+}
+
+impl From<Moon> for LazyMoon {
+    fn from(mut moon: Moon) -> Self {
+        let take = |map: &mut HashMap<String, Array<Vec<u8>>>| -> HashMap<String, Asset> {
+            map.drain().map(|(k, v)| (k, v.into())).collect()
+        };
+        Self {
+            textures: take(&mut moon.textures.src),
+            scripts: take(&mut moon.scripts),
+            sounds: take(&mut moon.sounds),
+            resources: take(&mut moon.resources),
+            moon,
+        }
+    }
+}
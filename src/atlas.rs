@@ -0,0 +1,163 @@
+//! Packing several textures into one atlas. Every texture costs Figura a separate upload and
+//! render layer, so an avatar with many small textures can be made cheaper by drawing them all
+//! onto one image and moving each face's UVs to where its texture ended up.
+//!
+//! Scripts that look textures up by name won't find the textures that were merged, and faces
+//! whose UVs reach outside their texture would show their neighbors in the atlas, so textures
+//! with such faces are left alone.
+
+use std::collections::{HashMap, HashSet};
+use crate::image::{self, ImageError};
+use crate::moon::{ModelData, ModelPart, TextureData};
+use crate::Moon;
+
+/// Several images drawn onto one, from [pack].
+#[derive(Debug, Clone)]
+pub struct Atlas {
+    /// The atlas itself, as a PNG.
+    pub png: Vec<u8>,
+    /// The size of the atlas.
+    pub size: (u32, u32),
+    /// Where each image's top left corner is on the atlas, in the order they were given.
+    pub offsets: Vec<(u32, u32)>,
+}
+
+/// Decides where each image of the given sizes goes, placing them in rows from tallest to
+/// shortest. Returns the size of the atlas and each image's offset.
+fn arrange(sizes: &[(u32, u32)]) -> ((u32, u32), Vec<(u32, u32)>) {
+    let area: u64 = sizes.iter().map(|&(w, h)| w as u64 * h as u64).sum();
+    let widest = sizes.iter().map(|&(w, _)| w).max().unwrap_or(0);
+    // aim for a square, but never narrower than the widest image
+    let width = widest.max((area as f64).sqrt().ceil() as u32).next_power_of_two();
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by_key(|&i| (std::cmp::Reverse(sizes[i].1), std::cmp::Reverse(sizes[i].0)));
+    let mut offsets = vec![(0, 0); sizes.len()];
+    let (mut x, mut y, mut row) = (0, 0, 0);
+    for i in order {
+        let (w, h) = sizes[i];
+        if x + w > width {
+            (x, y, row) = (0, y + row, 0);
+        }
+        offsets[i] = (x, y);
+        x += w;
+        row = row.max(h);
+    }
+    ((width, y + row), offsets)
+}
+
+/// Draws PNG images onto one atlas.
+pub fn pack(pngs: &[&[u8]]) -> Result<Atlas, ImageError> {
+    let images = pngs.iter().map(|png| image::decode_rgba(png)).collect::<Result<Vec<_>, _>>()?;
+    let sizes: Vec<_> = images.iter().map(|&(w, h, _)| (w, h)).collect();
+    let (size, offsets) = arrange(&sizes);
+    let mut pixels = vec![0; size.0 as usize * size.1 as usize * 4];
+    for ((w, _, image), &(x, y)) in images.iter().zip(&offsets) {
+        let row = *w as usize * 4;
+        for (dy, line) in image.chunks_exact(row).enumerate() {
+            let start = ((y as usize + dy) * size.0 as usize + x as usize) * 4;
+            pixels[start..start + row].copy_from_slice(line);
+        }
+    }
+    Ok(Atlas { png: image::encode_rgba(size.0, size.1, &pixels)?, size, offsets })
+}
+
+/// Whether a UV coordinate is inside an image of the given size.
+pub(crate) fn within([u, v]: [f64; 2], (width, height): (u32, u32)) -> bool {
+    (0.0..=width as f64).contains(&u) && (0.0..=height as f64).contains(&v)
+}
+
+impl Moon {
+    /// Packs the avatar's textures into one atlas stored as `name`, and moves every face's UVs to
+    /// match; see [the module documentation][self]. The atlas takes the place of the first
+    /// texture merged into it, and the others are removed. Returns how many textures were merged,
+    /// which is 0 if there weren't at least two that could be.
+    pub fn atlas_textures(&mut self, name: &str) -> Result<usize, ImageError> {
+        let mut sizes = HashMap::new();
+        for (i, data) in self.textures.data.iter().enumerate() {
+            if let Some(png) = self.textures.src.get(&data.d) {
+                sizes.insert(i, image::dimensions(png.as_ref())?);
+            }
+        }
+        fn exclude_wrapping(part: &ModelPart, sizes: &HashMap<usize, (u32, u32)>, out: &mut HashSet<usize>) {
+            match &part.data {
+                ModelData::Group {} => {}
+                ModelData::Cube { cube_data, .. } => {
+                    for face in [&cube_data.n, &cube_data.s, &cube_data.u, &cube_data.d, &cube_data.w, &cube_data.e].into_iter().flatten() {
+                        let inside = |size| within([face.uv.x0, face.uv.y0], size) && within([face.uv.x1, face.uv.y1], size);
+                        if !sizes.get(&face.tex).is_some_and(|&size| inside(size)) {
+                            out.insert(face.tex);
+                        }
+                    }
+                }
+                ModelData::Mesh { mesh_data } => {
+                    for face in mesh_data.faces() {
+                        if !sizes.get(&face.tex).is_some_and(|&size| face.vertices.iter().all(|v| within(v.uv, size))) {
+                            out.insert(face.tex);
+                        }
+                    }
+                }
+            }
+            part.chld.iter().for_each(|child| exclude_wrapping(child, sizes, out));
+        }
+        let mut excluded = HashSet::new();
+        if let Some(models) = &self.models {
+            exclude_wrapping(models, &sizes, &mut excluded);
+        }
+        let mut merged: Vec<usize> = sizes.keys().copied().filter(|i| !excluded.contains(i)).collect();
+        merged.sort();
+        if merged.len() < 2 {
+            return Ok(0);
+        }
+        let pngs: Vec<&[u8]> = merged.iter().map(|&i| self.textures.src[&self.textures.data[i].d].as_ref().as_slice()).collect();
+        let atlas = pack(&pngs)?;
+        let offsets: HashMap<usize, (f64, f64)> = merged.iter().zip(&atlas.offsets).map(|(&i, &(x, y))| (i, (x as f64, y as f64))).collect();
+
+        fn move_uvs(part: &mut ModelPart, offsets: &HashMap<usize, (f64, f64)>) {
+            match &mut part.data {
+                ModelData::Group {} => {}
+                ModelData::Cube { cube_data, .. } => {
+                    for face in [&mut cube_data.n, &mut cube_data.s, &mut cube_data.u, &mut cube_data.d, &mut cube_data.w, &mut cube_data.e].into_iter().flatten() {
+                        if let Some(&(x, y)) = offsets.get(&face.tex) {
+                            face.uv = [face.uv.x0 + x, face.uv.y0 + y, face.uv.x1 + x, face.uv.y1 + y].into();
+                        }
+                    }
+                }
+                ModelData::Mesh { mesh_data } => mesh_data.map_uvs(|tex, [u, v]| match offsets.get(&tex) {
+                    Some(&(x, y)) => [u + x, v + y],
+                    None => [u, v],
+                }),
+            }
+            part.chld.iter_mut().for_each(|child| move_uvs(child, offsets));
+        }
+        if let Some(models) = &mut self.models {
+            move_uvs(models, &offsets);
+        }
+
+        let names: Vec<String> = merged.iter().map(|&i| self.textures.data[i].d.clone()).collect();
+        let old = std::mem::take(&mut self.textures.data).into_vec();
+        let mut data = vec![];
+        let mut map = HashMap::new();
+        let mut atlas_index = None;
+        for (i, texture) in old.into_iter().enumerate() {
+            if offsets.contains_key(&i) {
+                let index = *atlas_index.get_or_insert_with(|| {
+                    data.push(TextureData { d: name.into() });
+                    data.len() - 1
+                });
+                map.insert(i, index);
+            } else {
+                map.insert(i, data.len());
+                data.push(texture);
+            }
+        }
+        self.textures.data = data.into();
+        // images that only merged textures used are now in the atlas
+        let used: HashSet<&String> = self.textures.data.iter().map(|d| &d.d).collect();
+        for key in names.iter().filter(|key| !used.contains(key)) {
+            self.textures.src.remove(key);
+        }
+        self.textures.src.insert(name.into(), atlas.png.into());
+        self.remap_textures(&map);
+        Ok(merged.len())
+    }
+}
@@ -0,0 +1,640 @@
+//! A Figura-compatible backend, for running alongside a Minecraft server so that its players can
+//! share avatars without the official backend. It speaks the same HTTP API that
+//! [cloud][crate::cloud] uses as a client, plus the WebSocket protocol Figura uses to subscribe to
+//! other players' avatars and send pings between them.
+//!
+//! Figura only connects over HTTPS, so the backend is meant to sit behind a reverse proxy that
+//! takes care of TLS. Avatars are stored under the [data directory][Backend::data_dir] as
+//! `<owner>/<id>.moon`, with each player's equipped avatars in `<owner>/equipped.json`, so they
+//! survive restarts; logins don't, and players simply log in again. Logins also expire after
+//! [a day][TOKEN_LIFETIME], and unfinished ones after [a minute][PENDING_LIFETIME].
+//!
+//! The WebSocket protocol is binary, with big-endian numbers and UUIDs as 16 bytes. Messages from
+//! the client start with a type byte:
+//!
+//! * `0`, followed by the token from logging in, authenticates the connection. The backend
+//!   answers with a lone `0`.
+//! * `1`, followed by a 32-bit ping ID, a byte that is 1 if the sender should get the ping back,
+//!   and the ping's data, sends a ping to everyone subscribed to the sender.
+//! * `2` or `3`, followed by a UUID, subscribes to or unsubscribes from a player.
+//!
+//! Pings reach subscribers as `1`, the sender's UUID, and the rest of the ping as it was sent.
+//! When a player equips an avatar, their subscribers get `2` followed by their UUID, and should
+//! download it again.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+use url::Url;
+use uuid::Uuid;
+use websocket::OwnedMessage;
+use websocket::sync::Writer;
+use websocket::sync::server::IntoWs;
+use crate::http;
+use crate::limits::Tier;
+use crate::moon::offline_uuid;
+use crate::pipeline::Pipeline;
+
+/// The largest ping the backend passes on, in bytes, as on the official backend.
+const MAX_PING_SIZE: usize = 1024;
+
+/// How long a server ID from `/api/auth/id` can be verified for.
+pub const PENDING_LIFETIME: Duration = Duration::from_secs(60);
+
+/// How long a login lasts before the player has to log in again.
+pub const TOKEN_LIFETIME: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The most unfinished logins, and the most logins, remembered at once. Past this, the oldest are
+/// forgotten.
+const MAX_LOGINS: usize = 4096;
+
+/// The longest request line or header the backend reads, in bytes.
+const MAX_LINE: usize = 8192;
+
+/// The most headers the backend reads in one request.
+const MAX_HEADERS: usize = 64;
+
+/// How long the backend waits for a client to send anything, and how long it may take to send
+/// the whole of a request's line and headers, before giving up on it.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Settings for a backend; see [the module documentation][self].
+#[derive(Clone, Debug)]
+pub struct Backend {
+    /// Where avatars are stored.
+    pub data_dir: PathBuf,
+    /// Transforms applied to uploaded avatars before they are stored.
+    pub pipeline: Pipeline,
+    /// The size limit for uploads.
+    pub tier: Tier,
+    /// Accept any username without checking with Mojang, giving players the UUIDs an
+    /// offline-mode server would. Anyone can then upload as anyone.
+    pub offline: bool,
+    /// The message of the day, shown in Figura's wardrobe.
+    pub motd: String,
+}
+
+/// An avatar a player has equipped, as stored in `equipped.json` and listed by `GET /api/<uuid>`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Equipped {
+    id: String,
+    owner: Uuid,
+    hash: String,
+}
+
+/// The body of `POST /api/equip`.
+#[derive(Deserialize)]
+struct EquipRequest {
+    id: String,
+    owner: Uuid,
+}
+
+/// A player who has logged in.
+#[derive(Clone, Debug)]
+struct User {
+    uuid: Uuid,
+}
+
+/// A WebSocket connection: who it's subscribed to, and how to reach it.
+struct Connection {
+    subscriptions: HashSet<Uuid>,
+    writer: Writer<TcpStream>,
+}
+
+/// A map that forgets each entry once it's been there for a while, and its oldest entries once
+/// it's full.
+struct ExpiringMap<V> {
+    lifetime: Duration,
+    entries: HashMap<String, (V, Instant)>,
+}
+
+impl<V: Clone> ExpiringMap<V> {
+    fn new(lifetime: Duration) -> Self {
+        ExpiringMap { lifetime, entries: HashMap::new() }
+    }
+
+    /// Adds an entry, first forgetting expired ones and, if that doesn't make room, the oldest.
+    fn insert(&mut self, key: String, value: V) {
+        let lifetime = self.lifetime;
+        self.entries.retain(|_, (_, added)| added.elapsed() < lifetime);
+        if self.entries.len() >= MAX_LOGINS {
+            let oldest = self.entries.iter().min_by_key(|(_, (_, added))| *added).map(|(key, _)| key.clone());
+            self.entries.remove(&oldest.expect("map is full"));
+        }
+        self.entries.insert(key, (value, Instant::now()));
+    }
+
+    fn get(&self, key: &str) -> Option<V> {
+        self.entries.get(key).filter(|(_, added)| added.elapsed() < self.lifetime).map(|(value, _)| value.clone())
+    }
+
+    fn remove(&mut self, key: &str) -> Option<V> {
+        self.entries.remove(key).filter(|(_, added)| added.elapsed() < self.lifetime).map(|(value, _)| value)
+    }
+}
+
+/// Everything shared between connections.
+struct State {
+    backend: Backend,
+    /// Usernames that asked for a server ID, by server ID.
+    pending: Mutex<ExpiringMap<String>>,
+    /// Logged-in players, by token.
+    tokens: Mutex<ExpiringMap<User>>,
+    connections: Mutex<HashMap<u64, Connection>>,
+    next_connection: AtomicU64,
+}
+
+/// A hex string of 20 bytes from the OS's secure random number generator, for server IDs and
+/// tokens.
+fn random_hex() -> io::Result<String> {
+    let mut bytes = [0; 20];
+    getrandom::getrandom(&mut bytes)?;
+    Ok(bytes.iter().map(|b| format!("{b:02x}")).collect())
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data).iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// A request read from a connection.
+struct Request {
+    method: String,
+    path: String,
+    query: HashMap<String, String>,
+    /// Header values by lowercase name.
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    /// Reads a request, or returns the response to refuse it with if its body would be longer
+    /// than `max_body` or its head is too long. The body is never read past `max_body`, whatever
+    /// its length is said to be, and lines are never read past [MAX_LINE]. Taking longer than
+    /// [READ_TIMEOUT] to send the request line and headers is an error.
+    fn read(reader: &mut impl BufRead, max_body: usize) -> io::Result<Result<Request, Response>> {
+        let start = Instant::now();
+        let mut line = String::new();
+        let mut read_line = |line: &mut String| -> io::Result<bool> {
+            line.clear();
+            if start.elapsed() > READ_TIMEOUT {
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+            reader.take(MAX_LINE as u64 + 1).read_line(line)?;
+            Ok(line.len() <= MAX_LINE)
+        };
+        if !read_line(&mut line)? {
+            return Ok(Err(Response::text(414, format!("request lines may be at most {MAX_LINE}B"))));
+        }
+        let mut parts = line.split_whitespace();
+        let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "malformed request line"));
+        };
+        // only the path and query matter, but Url wants a whole URL
+        let url = Url::parse("http://backend").unwrap().join(target).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let method = method.to_string();
+        let mut headers = HashMap::new();
+        for count in 0.. {
+            if !read_line(&mut line)? || count > MAX_HEADERS {
+                return Ok(Err(Response::text(431, format!("requests may have at most {MAX_HEADERS} headers of {MAX_LINE}B"))));
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+            }
+        }
+        let length = match headers.get("content-length").map(|l| l.parse::<u64>()) {
+            None => 0,
+            Some(Ok(length)) if length <= max_body as u64 => length,
+            Some(Ok(_)) => return Ok(Err(Response::text(413, format!("request bodies may be at most {max_body}B")))),
+            Some(Err(_)) => return Ok(Err(Response::text(400, "malformed content length"))),
+        };
+        let mut body = vec![];
+        reader.take(length).read_to_end(&mut body)?;
+        if body.len() as u64 != length {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(Ok(Request {
+            method,
+            path: url.path().to_string(),
+            query: url.query_pairs().into_owned().collect(),
+            headers,
+            body,
+        }))
+    }
+}
+
+/// A response to send back.
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn text(status: u16, body: impl Into<String>) -> Self {
+        Response { status, content_type: "text/plain; charset=utf-8", body: body.into().into_bytes() }
+    }
+
+    fn json(value: &impl Serialize) -> Self {
+        Response { status: 200, content_type: "application/json", body: serde_json::to_vec(value).unwrap() }
+    }
+
+    fn write(&self, stream: &mut impl Write) -> io::Result<()> {
+        let reason = match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            404 => "Not Found",
+            413 => "Payload Too Large",
+            414 => "URI Too Long",
+            431 => "Request Header Fields Too Large",
+            _ => "Error",
+        };
+        write!(stream, "HTTP/1.1 {} {reason}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", self.status, self.content_type, self.body.len())?;
+        stream.write_all(&self.body)?;
+        stream.flush()
+    }
+}
+
+impl State {
+    fn new(backend: Backend) -> Self {
+        State {
+            backend,
+            pending: Mutex::new(ExpiringMap::new(PENDING_LIFETIME)),
+            tokens: Mutex::new(ExpiringMap::new(TOKEN_LIFETIME)),
+            connections: Mutex::default(),
+            next_connection: AtomicU64::new(0),
+        }
+    }
+
+    fn user_dir(&self, uuid: Uuid) -> PathBuf {
+        self.backend.data_dir.join(uuid.hyphenated().to_string())
+    }
+
+    /// The file an avatar is stored in, if its ID is safe to use as a file name.
+    fn avatar_path(&self, owner: Uuid, id: &str) -> Option<PathBuf> {
+        let safe = !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'));
+        safe.then(|| self.user_dir(owner).join(format!("{id}.moon")))
+    }
+
+    fn equipped(&self, uuid: Uuid) -> Vec<Equipped> {
+        fs::read(self.user_dir(uuid).join("equipped.json")).ok()
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .unwrap_or_default()
+    }
+
+    fn set_equipped(&self, uuid: Uuid, equipped: &[Equipped]) -> io::Result<()> {
+        fs::create_dir_all(self.user_dir(uuid))?;
+        fs::write(self.user_dir(uuid).join("equipped.json"), serde_json::to_vec(equipped)?)?;
+        self.broadcast(uuid, [&[2][..], uuid.as_bytes()].concat(), None);
+        Ok(())
+    }
+
+    fn user(&self, request: &Request) -> Option<User> {
+        self.tokens.lock().unwrap().get(request.headers.get("token")?)
+    }
+
+    /// Sends a message to every connection subscribed to `uuid`, except `except`.
+    fn broadcast(&self, uuid: Uuid, message: Vec<u8>, except: Option<u64>) {
+        let message = OwnedMessage::Binary(message);
+        let mut connections = self.connections.lock().unwrap();
+        for (id, connection) in connections.iter_mut() {
+            if Some(*id) != except && connection.subscriptions.contains(&uuid) {
+                // a connection that has gone away is removed by its own thread
+                let _ = connection.writer.send_message(&message);
+            }
+        }
+    }
+
+    fn verify(&self, username: &str, server_id: &str) -> Option<Uuid> {
+        if self.backend.offline {
+            return Some(offline_uuid(username));
+        }
+        let mut url = Url::parse("https://sessionserver.mojang.com/session/minecraft/hasJoined").unwrap();
+        url.query_pairs_mut().append_pair("username", username).append_pair("serverId", server_id);
+        let response = http::get(&url, &[]).ok().filter(|r| r.status == 200)?;
+        let profile: serde_json::Value = serde_json::from_slice(&response.body).ok()?;
+        Uuid::try_parse(profile["id"].as_str()?).ok()
+    }
+
+    fn handle(&self, request: Request) -> Response {
+        let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+        let Some((&"api", segments)) = segments.split_first() else { return Response::text(404, "not found") };
+        match (request.method.as_str(), segments) {
+            ("GET", [] | [""]) => Response::text(200, "ok"),
+            ("GET", ["motd"]) => Response::text(200, self.backend.motd.clone()),
+            ("GET", ["limits"]) => Response::json(&serde_json::json!({
+                "rate": { "pingSize": MAX_PING_SIZE, "pingRate": 32, "equip": 1, "download": 50, "upload": 1 },
+                "limits": { "maxAvatarSize": self.backend.tier.limit(), "maxAvatars": 1 },
+            })),
+            ("GET", ["auth", "id"]) => {
+                let Some(username) = request.query.get("username") else { return Response::text(400, "no username") };
+                let server_id = match random_hex() {
+                    Ok(server_id) => server_id,
+                    Err(e) => return Response::text(500, e.to_string()),
+                };
+                self.pending.lock().unwrap().insert(server_id.clone(), username.clone());
+                Response::text(200, server_id)
+            }
+            ("GET", ["auth", "verify"]) => {
+                let Some(server_id) = request.query.get("id") else { return Response::text(400, "no id") };
+                let Some(username) = self.pending.lock().unwrap().remove(server_id) else { return Response::text(401, "unknown id") };
+                let Some(uuid) = self.verify(&username, server_id) else { return Response::text(401, "not verified") };
+                let token = match random_hex() {
+                    Ok(token) => token,
+                    Err(e) => return Response::text(500, e.to_string()),
+                };
+                self.tokens.lock().unwrap().insert(token.clone(), User { uuid });
+                Response::text(200, token)
+            }
+            ("PUT", ["avatar", id]) => {
+                let Some(user) = self.user(&request) else { return Response::text(401, "not logged in") };
+                let Some(path) = self.avatar_path(user.uuid, id) else { return Response::text(400, "bad avatar id") };
+                if request.body.len() > self.backend.tier.limit() {
+                    return Response::text(413, format!("avatars may be at most {}B", self.backend.tier.limit()));
+                }
+                let processed = match self.backend.pipeline.process(request.body) {
+                    Ok(processed) => processed,
+                    Err(e) => return Response::text(400, format!("not an avatar: {e}")),
+                };
+                let stored = fs::create_dir_all(self.user_dir(user.uuid)).and_then(|()| {
+                    if let Some(original) = &processed.original {
+                        fs::write(path.with_extension("original.moon"), original)?;
+                    }
+                    fs::write(&path, &processed.data)
+                });
+                match stored {
+                    Ok(()) => Response::text(200, "uploaded"),
+                    Err(e) => Response::text(500, e.to_string()),
+                }
+            }
+            ("DELETE", ["avatar", id]) => {
+                let Some(user) = self.user(&request) else { return Response::text(401, "not logged in") };
+                let Some(path) = self.avatar_path(user.uuid, id) else { return Response::text(400, "bad avatar id") };
+                let _ = fs::remove_file(path.with_extension("original.moon"));
+                if fs::remove_file(&path).is_err() {
+                    return Response::text(404, "no such avatar");
+                }
+                let mut equipped = self.equipped(user.uuid);
+                let before = equipped.len();
+                equipped.retain(|e| !(e.owner == user.uuid && e.id == *id));
+                if equipped.len() != before {
+                    if let Err(e) = self.set_equipped(user.uuid, &equipped) {
+                        return Response::text(500, e.to_string());
+                    }
+                }
+                Response::text(200, "deleted")
+            }
+            ("POST", ["equip"]) => {
+                let Some(user) = self.user(&request) else { return Response::text(401, "not logged in") };
+                let Ok(requests) = serde_json::from_slice::<Vec<EquipRequest>>(&request.body) else { return Response::text(400, "malformed body") };
+                let mut equipped = vec![];
+                for EquipRequest { id, owner } in requests {
+                    let Some(data) = self.avatar_path(owner, &id).and_then(|path| fs::read(path).ok()) else {
+                        return Response::text(404, format!("{owner} has no avatar {id:?}"));
+                    };
+                    equipped.push(Equipped { id, owner, hash: sha256_hex(&data) });
+                }
+                match self.set_equipped(user.uuid, &equipped) {
+                    Ok(()) => Response::text(200, "equipped"),
+                    Err(e) => Response::text(500, e.to_string()),
+                }
+            }
+            ("GET", [uuid]) => {
+                let Ok(uuid) = Uuid::try_parse(uuid) else { return Response::text(404, "not found") };
+                Response::json(&serde_json::json!({
+                    "uuid": uuid.hyphenated().to_string(),
+                    "rank": self.backend.tier.to_string(),
+                    "equipped": self.equipped(uuid),
+                    "equippedBadges": { "special": vec![0; 6], "pride": vec![0; 25] },
+                    "banned": false,
+                }))
+            }
+            ("GET", [owner, id]) => {
+                let Ok(owner) = Uuid::try_parse(owner) else { return Response::text(404, "not found") };
+                match self.avatar_path(owner, id).and_then(|path| fs::read(path).ok()) {
+                    Some(data) => Response { status: 200, content_type: "application/octet-stream", body: data },
+                    None => Response::text(404, "no such avatar"),
+                }
+            }
+            _ => Response::text(404, "not found"),
+        }
+    }
+
+    /// Completes a WebSocket handshake and serves the connection until it closes.
+    fn serve_websocket(&self, stream: TcpStream) -> io::Result<()> {
+        let socket = stream.try_clone()?;
+        let upgrade = stream.into_ws().map_err(|(_, _, _, e)| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        let client = upgrade.accept().map_err(|(_, e)| e)?;
+        let (mut receiver, mut writer) = client.split()?;
+        // the first message has to be a token
+        let user = match receiver.recv_message() {
+            Ok(OwnedMessage::Binary(message)) if message.first() == Some(&0) => {
+                let token = String::from_utf8_lossy(&message[1..]);
+                self.tokens.lock().unwrap().get(token.as_ref())
+            }
+            _ => None,
+        };
+        let Some(user) = user else {
+            let _ = writer.send_message(&OwnedMessage::Close(None));
+            return Ok(());
+        };
+        if writer.send_message(&OwnedMessage::Binary(vec![0])).is_err() {
+            return Ok(());
+        }
+        // subscribers can go quiet for as long as they like once they've logged in
+        socket.set_read_timeout(None)?;
+        let id = self.next_connection.fetch_add(1, Ordering::Relaxed);
+        self.connections.lock().unwrap().insert(id, Connection { subscriptions: HashSet::new(), writer });
+        for message in receiver.incoming_messages() {
+            let Ok(message) = message else { break };
+            let message = match message {
+                OwnedMessage::Binary(message) => message,
+                OwnedMessage::Close(_) => break,
+                _ => continue,
+            };
+            match message.split_first() {
+                // ping: ID, sync flag, then data
+                Some((1, ping)) if ping.len() >= 5 && ping.len() - 5 <= MAX_PING_SIZE => {
+                    let sync = ping[4] != 0;
+                    let out = [&[1][..], user.uuid.as_bytes(), ping].concat();
+                    self.broadcast(user.uuid, out.clone(), Some(id));
+                    if sync {
+                        if let Some(connection) = self.connections.lock().unwrap().get_mut(&id) {
+                            let _ = connection.writer.send_message(&OwnedMessage::Binary(out));
+                        }
+                    }
+                }
+                Some((&kind @ (2 | 3), target)) => {
+                    let Ok(target) = Uuid::from_slice(target) else { continue };
+                    if let Some(connection) = self.connections.lock().unwrap().get_mut(&id) {
+                        if kind == 2 {
+                            connection.subscriptions.insert(target);
+                        } else {
+                            connection.subscriptions.remove(&target);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.connections.lock().unwrap().remove(&id);
+        Ok(())
+    }
+
+    /// Whether a connection is asking for `/ws`, found without consuming any of its data, since
+    /// the WebSocket handshake has to read the request itself.
+    fn is_websocket(stream: &TcpStream) -> io::Result<bool> {
+        let start = Instant::now();
+        let mut buf = [0; 64];
+        loop {
+            if start.elapsed() > READ_TIMEOUT {
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+            let n = stream.peek(&mut buf)?;
+            let head = &buf[..n];
+            if n == 0 || n == buf.len() || head.contains(&b'\n') {
+                return Ok(head.starts_with(b"GET /ws ") || head.starts_with(b"GET /ws?"));
+            }
+            // the request line hasn't all arrived yet
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+    }
+
+    fn serve(&self, stream: TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        stream.set_write_timeout(Some(READ_TIMEOUT))?;
+        if Self::is_websocket(&stream)? {
+            return self.serve_websocket(stream);
+        }
+        let mut reader = BufReader::new(stream);
+        let response = match Request::read(&mut reader, self.backend.tier.limit())? {
+            Ok(request) => self.handle(request),
+            Err(refusal) => refusal,
+        };
+        response.write(reader.get_mut())
+    }
+}
+
+impl Backend {
+    /// Listens for connections on the given address, serving each on its own thread, until
+    /// listening fails.
+    pub fn run(self, addr: impl ToSocketAddrs) -> io::Result<!> {
+        fs::create_dir_all(&self.data_dir)?;
+        let listener = TcpListener::bind(addr)?;
+        let state = Arc::new(State::new(self));
+        loop {
+            let (stream, _) = listener.accept()?;
+            let state = state.clone();
+            std::thread::spawn(move || state.serve(stream));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(request: &str) -> Result<Request, Response> {
+        Request::read(&mut request.as_bytes(), 16).unwrap()
+    }
+
+    fn get(path: &str, headers: &[(&str, &str)]) -> Request {
+        Request {
+            method: "GET".into(),
+            path: path.split('?').next().unwrap().into(),
+            query: Url::parse("http://backend").unwrap().join(path).unwrap().query_pairs().into_owned().collect(),
+            headers: headers.iter().map(|&(k, v)| (k.into(), v.into())).collect(),
+            body: vec![],
+        }
+    }
+
+    fn offline_state() -> State {
+        State::new(Backend {
+            data_dir: PathBuf::new(),
+            pipeline: Pipeline::default(),
+            tier: Tier::Default,
+            offline: true,
+            motd: String::new(),
+        })
+    }
+
+    #[test]
+    fn reads_requests() {
+        let Ok(request) = read("PUT /api/avatar/x?a=b%20c HTTP/1.1\r\nToken: abc\r\nContent-Length: 5\r\n\r\nhello") else { panic!() };
+        assert_eq!(request.method, "PUT");
+        assert_eq!(request.path, "/api/avatar/x");
+        assert_eq!(request.query["a"], "b c");
+        assert_eq!(request.headers["token"], "abc");
+        assert_eq!(request.body, b"hello");
+    }
+
+    #[test]
+    fn refuses_oversized_bodies() {
+        let Err(response) = read("PUT / HTTP/1.1\r\nContent-Length: 17\r\n\r\n") else { panic!() };
+        assert_eq!(response.status, 413);
+        let Err(response) = read("PUT / HTTP/1.1\r\nContent-Length: lots\r\n\r\n") else { panic!() };
+        assert_eq!(response.status, 400);
+    }
+
+    #[test]
+    fn refuses_long_heads() {
+        let long = "a".repeat(MAX_LINE);
+        let Err(response) = read(&format!("GET /{long} HTTP/1.1\r\n\r\n")) else { panic!() };
+        assert_eq!(response.status, 414);
+        let Err(response) = read(&format!("GET / HTTP/1.1\r\nX: {long}\r\n\r\n")) else { panic!() };
+        assert_eq!(response.status, 431);
+        let Err(response) = read(&format!("GET / HTTP/1.1\r\n{}\r\n", "X: y\r\n".repeat(MAX_HEADERS + 1))) else { panic!() };
+        assert_eq!(response.status, 431);
+    }
+
+    #[test]
+    fn refuses_truncated_bodies() {
+        assert!(Request::read(&mut "PUT / HTTP/1.1\r\nContent-Length: 5\r\n\r\nhi".as_bytes(), 16).is_err());
+    }
+
+    #[test]
+    fn logs_in() {
+        let state = offline_state();
+        let server_id = state.handle(get("/api/auth/id?username=Steve", &[]));
+        assert_eq!(server_id.status, 200);
+        let server_id = String::from_utf8(server_id.body).unwrap();
+        let token = state.handle(get(&format!("/api/auth/verify?id={server_id}"), &[]));
+        assert_eq!(token.status, 200);
+        let token = String::from_utf8(token.body).unwrap();
+        assert_eq!(state.user(&get("/api", &[("token", &token)])).map(|user| user.uuid), Some(offline_uuid("Steve")));
+        // server IDs only verify once
+        assert_eq!(state.handle(get(&format!("/api/auth/verify?id={server_id}"), &[])).status, 401);
+    }
+
+    #[test]
+    fn refuses_unknown_logins() {
+        let state = offline_state();
+        assert_eq!(state.handle(get("/api/auth/verify?id=nope", &[])).status, 401);
+        assert!(state.user(&get("/api", &[("token", "nope")])).is_none());
+    }
+
+    #[test]
+    fn forgets_expired_and_oldest_logins() {
+        let mut map = ExpiringMap::new(Duration::ZERO);
+        map.insert("a".into(), 1);
+        assert_eq!(map.get("a"), None);
+        let mut map = ExpiringMap::new(Duration::from_secs(60));
+        for i in 0..=MAX_LOGINS {
+            map.insert(i.to_string(), i);
+        }
+        assert_eq!(map.entries.len(), MAX_LOGINS);
+        assert_eq!(map.get("0"), None);
+        assert_eq!(map.get(&MAX_LOGINS.to_string()), Some(MAX_LOGINS));
+    }
+}
@@ -1,10 +1,34 @@
 #![warn(missing_docs)]
 
-use std::collections::HashMap;
+pub mod compat;
+pub mod builder;
+pub mod outliner;
+pub mod merge;
+pub mod obj;
+pub mod lenient;
+pub mod stats;
+pub mod skin;
+pub mod canonical;
+pub mod stream;
+pub mod layers;
+pub mod walk;
+pub mod atlas;
+#[cfg(feature = "gltf")]
+pub mod gltf;
+
+use std::collections::{HashMap, HashSet};
 use std::ffi::OsStr;
 use serde::{Serialize, Deserialize};
 use serde_repr::{Serialize_repr, Deserialize_repr};
 use serde_json::{Value, Number, Map};
+use base64::{Engine as _, prelude::BASE64_STANDARD};
+use thiserror::Error;
+use crate::check::{Diagnostic, Severity};
+use crate::molang::{self, FiguraValue};
+use crate::image::{self, ImageError};
+use crate::moon::{self, Interpolation, MeshBuilder, ModelPart, ModelData, ParentType, TextureData, Textures};
+use crate::moon::space::{blockbench_from_moon, moon_from_blockbench};
+pub use obj::{export_obj, ObjExport};
 type Any = Option<Value>;
 type Object = Map<Value, Value>;
 
@@ -23,16 +47,149 @@ pub struct BBModel {
     pub meta: Meta,
     pub model_identifier: Option<String>,
     pub name: Option<String>,
-    pub outliner: Any,
-    pub reference_images: Any,
+    /// The tree of groups and elements shown in Blockbench's outliner.
+    #[serde(default)]
+    pub outliner: Vec<OutlinerItem>,
+    /// Images shown in the editor to model against, added in Blockbench 4.9.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reference_images: Vec<ReferenceImage>,
     pub resolution: Resolution,
+    #[serde(default)]
     pub textures: Vec<Texture>,
+    #[serde(default)]
     pub timeline_setups: Vec<Value>,
     pub unhandled_root_fields: Any,
+    #[serde(default)]
     pub variable_placeholder_buttons: Vec<Value>,
+    #[serde(default)]
     pub variable_placeholders: String,
     pub visible_box: Option<[Number; 3]>,
-    pub texture_groups: Any,
+    /// Folders in Blockbench's texture panel, added in Blockbench 4.10. Textures refer to these by
+    /// UUID.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub texture_groups: Vec<TextureGroup>,
+    /// Named sets of outliner nodes, added in Blockbench 4.10.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub collections: Vec<Collection>,
+}
+
+impl BBModel {
+    /// Moves every face's UVs from one grid to another, given each texture's current and new UV
+    /// size. Faces using textures that `sizes` returns [None] for, or that don't exist, are left
+    /// alone.
+    fn rescale_uvs(&mut self, mut sizes: impl FnMut(&Texture) -> Option<((f64, f64), (f64, f64))>) {
+        let sizes: Vec<_> = self.textures.iter().map(&mut sizes).collect();
+        let size = |tex: Option<usize>| tex.and_then(|tex| *sizes.get(tex)?);
+        let textures = &self.textures;
+        for element in &mut self.elements {
+            match &mut element.extra {
+                ElementType::Cube { faces, .. } => {
+                    let Faces { north, east, south, west, up, down } = faces;
+                    for face in [north, east, south, west, up, down].into_iter().flatten() {
+                        if let Some((from, to)) = size(face.texture.resolve(textures)) {
+                            face.uv = face.uv.rescale(from, to);
+                        }
+                    }
+                }
+                ElementType::Mesh { faces, .. } => for face in faces.values_mut() {
+                    if let Some((from, to)) = size(face.texture) {
+                        for uv in face.uv.values_mut() {
+                            *uv = rescale_uv(*uv, from, to);
+                        }
+                    }
+                },
+                ElementType::Locator { .. } | ElementType::NullObject { .. } | ElementType::TextureMesh { .. } => {}
+            }
+        }
+    }
+
+    /// Converts every face's UVs into the pixels of its texture's image, which is how moons store
+    /// them, and sets each texture's UV size to match. Textures whose image size isn't known are
+    /// left as they are.
+    pub fn normalize_uvs(&mut self) {
+        let resolution = Resolution { width: self.resolution.width, height: self.resolution.height };
+        self.rescale_uvs(|texture| Some((texture.uv_size(&resolution), texture.image_size()?)));
+        for texture in &mut self.textures {
+            if texture.image_size().is_some() {
+                texture.uv_width = texture.width;
+                texture.uv_height = texture.height;
+            }
+        }
+    }
+
+    /// Converts every face's UVs into the project's resolution and sets every texture's UV size to
+    /// match, for Blockbench versions that ignore per-texture UV sizes. This is the reverse of
+    /// [normalize_uvs][Self::normalize_uvs].
+    pub fn uvs_to_project_resolution(&mut self) {
+        let resolution = Resolution { width: self.resolution.width, height: self.resolution.height };
+        let project = (resolution.width as f64, resolution.height as f64);
+        self.rescale_uvs(|texture| Some((texture.uv_size(&resolution), project)));
+        for texture in &mut self.textures {
+            texture.uv_width = resolution.width;
+            texture.uv_height = resolution.height;
+        }
+    }
+
+    /// Switches every Box UV cube to per-face UV; see [Element::unwrap_box_uv]. Returns how many
+    /// cubes were switched.
+    pub fn unwrap_box_uvs(&mut self) -> usize {
+        self.elements.iter_mut().map(Element::unwrap_box_uv).filter(|&unwrapped| unwrapped).count()
+    }
+
+    /// Removes a texture. Faces that used it are left without a texture, and faces using later
+    /// textures are pointed at their new indices.
+    pub fn remove_texture(&mut self, index: usize) -> Option<Texture> {
+        if index >= self.textures.len() {
+            return None;
+        }
+        let texture = self.textures.remove(index);
+        for (i, texture) in self.textures.iter_mut().enumerate().skip(index) {
+            texture.id = i.to_string();
+        }
+        let remap = |tex: &mut Option<usize>| match *tex {
+            Some(t) if t == index => *tex = None,
+            Some(t) if t > index => *tex = Some(t - 1),
+            _ => {}
+        };
+        for element in &mut self.elements {
+            match &mut element.extra {
+                ElementType::Cube { faces, .. } => {
+                    let Faces { north, east, south, west, up, down } = faces;
+                    for face in [north, east, south, west, up, down].into_iter().flatten() {
+                        match &mut face.texture {
+                            FaceTexture::Index(t) if *t == index => face.texture = FaceTexture::None,
+                            FaceTexture::Index(t) if *t > index => *t -= 1,
+                            FaceTexture::Uuid(uuid) if *uuid == texture.uuid => face.texture = FaceTexture::None,
+                            _ => {}
+                        }
+                    }
+                }
+                ElementType::Mesh { faces, .. } => for face in faces.values_mut() {
+                    remap(&mut face.texture);
+                },
+                ElementType::Locator { .. } | ElementType::NullObject { .. } | ElementType::TextureMesh { .. } => {}
+            }
+        }
+        Some(texture)
+    }
+
+    /// Removes a texture group, moving its textures out of it.
+    pub fn remove_texture_group(&mut self, uuid: &str) -> Option<TextureGroup> {
+        let index = self.texture_groups.iter().position(|g| g.uuid == uuid)?;
+        for texture in &mut self.textures {
+            if texture.group.as_deref() == Some(uuid) {
+                texture.group = None;
+            }
+        }
+        Some(self.texture_groups.remove(index))
+    }
+
+    /// Deserializes a model, first [upgrading][compat::upgrade] layouts from older Blockbench
+    /// versions.
+    pub fn from_json(mut json: Value) -> serde_json::Result<Self> {
+        compat::upgrade(&mut json);
+        serde_json::from_value(json)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Default)]
@@ -42,9 +199,11 @@ pub struct Resolution {
     width: usize,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(deny_unknown_fields)]
-struct Texture {
+/// A texture in the model. Figura only looks at the name and the embedded image. Most fields
+/// didn't exist before Blockbench 4.0, so they all default.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Texture {
     folder: String,
     frame_interpolate: Option<bool>,
     layers: Any,
@@ -76,6 +235,146 @@ struct Texture {
     width: usize,
 }
 
+impl Texture {
+    /// Makes an empty texture with the settings Blockbench gives new ones.
+    fn new(name: String, index: usize, uuid: String) -> Self {
+        Texture {
+            name,
+            id: index.to_string(),
+            uuid,
+            internal: true,
+            visible: true,
+            render_mode: "default".into(),
+            render_sides: "auto".into(),
+            frame_order_type: "loop".into(),
+            ..Default::default()
+        }
+    }
+
+    /// [Sets the source][Self::set_source] and makes the UV size match the image, since moons
+    /// store UVs in the image's pixels.
+    fn embed(&mut self, png: &[u8]) {
+        self.set_source(png);
+        self.uv_width = self.width;
+        self.uv_height = self.height;
+    }
+
+    /// The size of the grid this texture's face UVs are given in: the texture's own UV size, or
+    /// the project's resolution for textures without one (including all textures saved by
+    /// Blockbench versions before per-texture UV sizes).
+    pub fn uv_size(&self, project: &Resolution) -> (f64, f64) {
+        match (self.uv_width, self.uv_height) {
+            (0, _) | (_, 0) => (project.width as f64, project.height as f64),
+            (w, h) => (w as f64, h as f64),
+        }
+    }
+
+    /// The size of the texture's image, if known.
+    pub fn image_size(&self) -> Option<(f64, f64)> {
+        (self.width != 0 && self.height != 0).then_some((self.width as f64, self.height as f64))
+    }
+
+    /// Decodes the PNG embedded in the texture's `data:` URL.
+    pub fn decode_source(&self) -> Result<Vec<u8>, CompileError> {
+        if self.source.is_empty() {
+            return Err(CompileError::ExternalTexture(self.name.clone()));
+        }
+        decode_data_url(&self.source).ok_or_else(|| CompileError::MalformedTexture(self.name.clone()))
+    }
+
+    /// Where the image this texture links to is, relative to the model, if it was saved to a file.
+    pub fn relative_path(&self) -> Option<&str> {
+        self.relative_path.as_deref().filter(|path| !path.is_empty())
+    }
+
+    /// The UUID of the [texture group][TextureGroup] this texture is in, if any.
+    pub fn group(&self) -> Option<&str> {
+        self.group.as_deref()
+    }
+
+    /// Moves this texture into a [texture group][TextureGroup], or out of its group for [None].
+    pub fn set_group(&mut self, group: Option<String>) {
+        self.group = group;
+    }
+
+    /// Embeds a PNG in the texture as a `data:` URL, the way Blockbench saves textures that aren't
+    /// linked to a file. The texture's size is updated to match if the PNG's header is readable.
+    pub fn set_source(&mut self, png: &[u8]) {
+        self.source = png_data_url(png);
+        if let Ok((width, height)) = image::dimensions(png) {
+            self.width = width as usize;
+            self.height = height as usize;
+        }
+    }
+}
+
+/// Decodes the data in a base64 `data:` URL, whatever its type.
+fn decode_data_url(url: &str) -> Option<Vec<u8>> {
+    let (_, data) = url.strip_prefix("data:")?.split_once(";base64,")?;
+    BASE64_STANDARD.decode(data).ok()
+}
+
+fn png_data_url(png: &[u8]) -> String {
+    format!("data:image/png;base64,{}", BASE64_STANDARD.encode(png))
+}
+
+/// A folder in Blockbench's texture panel. Figura ignores these.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TextureGroup {
+    /// The group's name.
+    pub name: String,
+    pub uuid: String,
+    /// Whether the group is collapsed in the texture panel.
+    pub folded: bool,
+    /// Whether the group's textures make up a PBR material, rather than being separate textures.
+    pub is_material: bool,
+    /// Settings that only matter to the editor, such as a material's configuration.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// An image shown in the editor to model against, such as a concept sketch. Figura ignores these,
+/// but they're kept so that projects can be edited and saved without losing them.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReferenceImage {
+    pub uuid: String,
+    /// The name shown in the editor, usually the image's file name.
+    pub name: String,
+    /// Where the image is drawn: `"background"` and `"viewport"` put it behind or in front of the
+    /// model, `"float"` in a movable window, and `"blueprint"` in the scene.
+    pub layer: String,
+    /// The position of the image's center, in screen pixels for screen layers or model units for
+    /// blueprints.
+    pub position: [f64; 2],
+    /// The width and height of the image, in the same units as its position.
+    pub size: [f64; 2],
+    /// The image's rotation in degrees.
+    pub rotation: f64,
+    /// How opaque the image is, from 0 to 1.
+    pub opacity: f64,
+    pub visibility: bool,
+    /// The image as a `data:` URL, or empty if it's linked to a file.
+    pub source: String,
+    /// Display settings that only matter to the editor, such as which views show the image.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl ReferenceImage {
+    /// Decodes the embedded image, in whatever format it was added in. Returns [None] if the
+    /// image isn't embedded.
+    pub fn decode_source(&self) -> Option<Vec<u8>> {
+        decode_data_url(&self.source)
+    }
+
+    /// Embeds a PNG as the image.
+    pub fn set_source(&mut self, png: &[u8]) {
+        self.source = png_data_url(png);
+    }
+}
+
 /// Contains metadata about this model important for making sense of the contents.
 #[derive(Debug, Serialize, Deserialize, Default)]
 #[serde(deny_unknown_fields)]
@@ -89,11 +388,21 @@ pub struct Meta {
     /// use Box UV.
     #[serde(default)]
     box_uv: bool,
+    /// When the project was created, as a Unix timestamp. Only saved by Blockbench 3.x.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    creation_time: Any,
 }
 
-/// One animation in the model.
+impl Meta {
+    /// The model's format version.
+    pub fn format_version(&self) -> &FormatVersion {
+        &self.format_version
+    }
+}
+
+/// One animation in the model. Fields that Blockbench 3.x didn't save default to empty.
 #[derive(Debug, Serialize, Deserialize, Default)]
-#[serde(deny_unknown_fields)]
+#[serde(default, deny_unknown_fields)]
 pub struct Animation {
     /// A Molang expression that evaluates to the animation's time. This is only useful for
     /// Bedrock; it is completely ignored by Figura.
@@ -149,9 +458,10 @@ pub struct Keyframe {
     pub uniform: Any,
     /// The keyframe's color, or -1 if no color is specified. Did you know keyframes could be
     /// colored?
+    #[serde(default = "no_color")]
     pub color: i8,
     /// Why the fuck are there multiple?
-    pub data_points: Vec<XYZ<SoN>>,
+    pub data_points: Vec<DataPoint>,
     /// The interpolation style of this keyframe.
     pub interpolation: String,
     /// When this keyframe is.
@@ -166,6 +476,26 @@ pub struct Keyframe {
     pub bezier_right_value: Option<[f64; 3]>,
 }
 
+/// One of a [Keyframe]'s values, which depends on its channel.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum DataPoint {
+    /// A rotation, position or scale.
+    Value(XYZ<SoN>),
+    /// Lua code, on an instruction (`timeline`) keyframe.
+    Script(Script),
+    /// A sound or particle effect, which Figura ignores.
+    Effect(Map<String, Value>),
+}
+
+/// The code an instruction keyframe runs.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Script {
+    /// The code.
+    pub script: String,
+}
+
 /// A value in three axes.
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct XYZ<T> {
@@ -184,29 +514,10 @@ pub enum SoN {
     Number(f64),
 }
 
-fn coerce_keyframes<'de, D: serde::Deserializer<'de>>(de: D) -> Result<f64, D::Error> {
-    use serde::de::{Visitor, Error};
-    use std::fmt::{self, Formatter};
-    struct ConvertToFloatVisitor;
-    impl<'de> Visitor<'de> for ConvertToFloatVisitor {
-        type Value = f64;
-        fn expecting(&self, fmt: &mut Formatter) -> fmt::Result {
-            write!(fmt, "anything that smells like a string")
-        }
-        fn visit_i32<E>(self, v: i32) -> Result<f64, E> { Ok(v.into()) }
-        fn visit_u32<E>(self, v: u32) -> Result<f64, E> { Ok(v.into()) }
-        fn visit_f64<E>(self, v: f64) -> Result<f64, E> { Ok(v) }
-        fn visit_str<E: Error>(self, v: &str) -> Result<f64, E> {
-            match v.parse() {
-                Ok(f) => Ok(f),
-                Err(e) => todo!(),
-            }
-        }
-    }
-    de.deserialize_any(ConvertToFloatVisitor)
-}
+fn no_color() -> i8 { -1 }
 
-/// One of the 4.x Blockbench format versions.
+/// One of the 4.x Blockbench format versions, or [Other][Self::Other] for anything else (such as
+/// 3.x projects, which [compat] upgrades when loading with [BBModel::from_json]).
 #[derive(Debug, Serialize, Deserialize, Default)]
 #[allow(missing_docs)]
 pub enum FormatVersion {
@@ -233,6 +544,16 @@ pub enum FormatVersion {
     V4_1,
     #[serde(rename = "4.0")]
     V4_0,
+    /// A version not listed above, kept as written.
+    #[serde(untagged)]
+    Other(String),
+}
+
+impl FormatVersion {
+    /// Whether this version predates Blockbench 4.0.
+    pub fn is_legacy(&self) -> bool {
+        matches!(self, FormatVersion::Other(version) if compat::is_legacy(version))
+    }
 }
 
 fn return_true() -> bool { true }
@@ -256,6 +577,7 @@ pub struct Element {
     /// Whether the cube should be exported. If this is disabled, Figura completely ignores the
     /// cube (not even adding it to the modelpart hiearchy).
     pub export: Option<bool>,
+    #[serde(default)]
     pub color: u8,
     #[serde(default)]
     pub rotation: [f64; 3],
@@ -264,7 +586,7 @@ pub struct Element {
     pub extra: ElementType,
 }
 
-/// A type of element with a model, excluding groups.
+/// A type of element, excluding groups.
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 #[serde(tag = "type")]
@@ -281,7 +603,9 @@ pub enum ElementType {
         /// The faces on this cube.
         faces: Faces,
         box_uv: Any,
+        #[serde(default)]
         rescale: bool,
+        #[serde(default)]
         autouv: u8,
         light_emission: Option<u8>,
         mirror_uv: Option<bool>,
@@ -293,7 +617,48 @@ pub enum ElementType {
     Mesh {
         vertices: HashMap<String, [f64; 3]>,
         faces: HashMap<String, MeshFace>,
-    }
+    },
+    /// A point with no geometry, used to mark where things attach. Figura has no equivalent, so
+    /// locators become empty groups.
+    #[serde(rename = "locator")]
+    Locator {
+        /// Where the locator is, in [Blockbench space][crate::moon::space]. Locators have no
+        /// separate pivot; this is what they rotate around.
+        #[serde(default)]
+        position: [f64; 3],
+        /// Whether the locator ignores its parents' scale in Bedrock. Figura ignores this.
+        #[serde(default)]
+        ignore_inherited_scale: bool,
+    },
+    /// A point that inverse kinematics can aim a chain of groups at. Figura doesn't support these,
+    /// so they're left out of moons.
+    #[serde(rename = "null_object")]
+    NullObject {
+        /// Where the null object is, in [Blockbench space][crate::moon::space].
+        #[serde(default)]
+        position: [f64; 3],
+        /// The UUID of the group whose chain is aimed at this null object, if any.
+        #[serde(default)]
+        ik_target: String,
+        #[serde(default)]
+        lock_ik_target_rotation: bool,
+        /// Whether the chain's bend direction is worked out automatically.
+        ik_auto_angle: Any,
+    },
+    /// A flat mesh generated from a texture's opaque pixels, for Java item models. Figura doesn't
+    /// support these, so they're left out of moons.
+    #[serde(rename = "texture_mesh")]
+    TextureMesh {
+        /// The name of the texture the mesh is made from.
+        #[serde(default)]
+        texture_name: String,
+        /// The mesh's pivot, relative to its origin.
+        #[serde(default)]
+        local_pivot: [f64; 3],
+        /// The mesh's scale on each axis.
+        scale: Option<[f64; 3]>,
+        render_mode: Any,
+    },
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -320,8 +685,1379 @@ pub struct Faces {
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct Face {
-    uv: [f64; 4],
-    texture: Option<usize>,
+    /// The area of the texture shown on the face, in the project's UV resolution.
+    uv: moon::UvRect,
+    #[serde(default)]
+    texture: FaceTexture,
     #[serde(default)]
     rotation: u16,
 }
+
+/// The texture a [Face] uses. Blockbench usually saves the texture's index, but newer versions
+/// can save its UUID instead.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum FaceTexture {
+    /// The texture's index in [BBModel::textures].
+    Index(usize),
+    /// The texture's UUID.
+    Uuid(String),
+    /// The face has no texture, so it isn't drawn.
+    #[default]
+    None,
+}
+
+impl FaceTexture {
+    /// Finds the index of the texture in a model's textures. Indices are returned as they are,
+    /// even if they're out of range; UUIDs that don't match any texture give [None].
+    pub fn resolve(&self, textures: &[Texture]) -> Option<usize> {
+        match self {
+            FaceTexture::Index(index) => Some(*index),
+            FaceTexture::Uuid(uuid) => textures.iter().position(|t| &t.uuid == uuid),
+            FaceTexture::None => None,
+        }
+    }
+
+    /// The texture's index, if it's given as one.
+    pub fn index(&self) -> Option<usize> {
+        match self {
+            FaceTexture::Index(index) => Some(*index),
+            _ => None,
+        }
+    }
+}
+
+impl From<Option<usize>> for FaceTexture {
+    fn from(index: Option<usize>) -> Self {
+        index.map_or(FaceTexture::None, FaceTexture::Index)
+    }
+}
+
+/// An entry in the outliner.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum OutlinerItem {
+    /// A reference to an element, by UUID.
+    Element(String),
+    /// A group, which can contain more items.
+    Group(Group),
+}
+
+/// A group in the outliner. Figura turns each group into a [ModelPart] with no model data.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Group {
+    /// The group's name.
+    pub name: String,
+    /// The group's pivot point.
+    #[serde(default)]
+    pub origin: [f64; 3],
+    /// The group's rotation around its pivot point.
+    #[serde(default)]
+    pub rotation: [f64; 3],
+    /// The group's marker color.
+    #[serde(default)]
+    pub color: u8,
+    pub uuid: String,
+    /// Whether the group should be exported. Figura ignores non-exported groups entirely.
+    #[serde(default = "return_true")]
+    pub export: bool,
+    #[serde(default = "return_true")]
+    pub visibility: bool,
+    /// The items inside this group.
+    #[serde(default)]
+    pub children: Vec<OutlinerItem>,
+    /// Editor state that Figura doesn't care about, such as whether the group is expanded.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl Group {
+    /// Converts this group into a part without its children. The part gets the parent type its
+    /// name gives it, so keyword groups like `Head`, `RightArm`, and `World` follow the player or
+    /// the world. As in Figura, prefixed names such as `MOVE_Head` get none (see
+    /// [ParentType::infer]).
+    pub fn to_model_part(&self) -> ModelPart {
+        ModelPart {
+            name: self.name.clone(),
+            piv: moon_from_blockbench(self.origin),
+            rot: moon_from_blockbench(self.rotation),
+            vsb: self.visibility,
+            pt: ParentType::for_part(&self.name),
+            ..Default::default()
+        }
+    }
+}
+
+/// A named set of groups and elements, which Blockbench can export on their own. Figura stores
+/// the names in the model's [cn][ModelPart::cn] and each member's indices in its
+/// [pr][ModelPart::pr].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Collection {
+    /// The collection's name.
+    pub name: String,
+    pub uuid: String,
+    /// The UUIDs of the groups and elements in the collection.
+    #[serde(default)]
+    pub children: Vec<String>,
+    /// Export settings and editor state that Figura doesn't care about.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// Works out the [pr][ModelPart::pr] indices for each outliner node from a model's collections,
+/// keyed by the node's UUID. The collections' names, in the same order, become the model's
+/// [cn][ModelPart::cn].
+pub fn collection_indices(collections: &[Collection]) -> HashMap<String, Vec<i32>> {
+    let mut indices = HashMap::<String, Vec<i32>>::new();
+    for (i, collection) in collections.iter().enumerate() {
+        for child in &collection.children {
+            indices.entry(child.clone()).or_default().push(i as i32);
+        }
+    }
+    indices
+}
+
+/// The geometry of a model: its elements, and the outliner tree arranging them into groups.
+#[derive(Debug, Default)]
+pub struct Hierarchy {
+    /// The name of the model, which is also the name of its part in the moon.
+    pub name: String,
+    /// Every element in the model, in no particular order.
+    pub elements: Vec<Element>,
+    /// The roots of the outliner tree.
+    pub outliner: Vec<OutlinerItem>,
+    /// The model's collections.
+    pub collections: Vec<Collection>,
+}
+
+impl From<Hierarchy> for BBModel {
+    fn from(hierarchy: Hierarchy) -> Self {
+        hierarchy.into_bbmodel(&Textures::default())
+    }
+}
+
+impl Hierarchy {
+    /// Makes a Blockbench model out of this hierarchy, embedding a moon's textures so that the
+    /// model opens with them in place.
+    ///
+    /// Only the textures this model's faces use are included, in the order they have in the moon,
+    /// and faces are pointed at their new indices; otherwise every model unpacked from a moon
+    /// would carry every other model's textures, and packing them again would duplicate them.
+    /// Faces using a texture the moon doesn't have are left without one. Each texture's name loses
+    /// the model's name as a prefix (the reverse of [compile]), and its UV size is set to the
+    /// image's size, since moons store UVs in pixels. Textures whose image is missing are left
+    /// empty.
+    pub fn into_bbmodel(self, textures: &Textures) -> BBModel {
+        let Hierarchy { name, mut elements, outliner, collections } = self;
+        let mut used = vec![];
+        for element in &mut elements {
+            map_texture_indices(element, |tex| {
+                used.push(tex);
+                Some(tex)
+            });
+        }
+        used.sort();
+        used.dedup();
+        used.retain(|&tex| tex < textures.data.len());
+        for element in &mut elements {
+            map_texture_indices(element, |tex| used.binary_search(&tex).ok());
+        }
+        let prefix = format!("{name}.");
+        let textures: Vec<_> = used.iter().enumerate().map(|(i, &tex)| {
+            let data = &textures.data[tex];
+            let tex_name = format!("{}.png", data.d.strip_prefix(&prefix).unwrap_or(&data.d));
+            let mut texture = Texture::new(tex_name, i, derived_uuid(&format!("{name}/texture{tex}")));
+            if let Some(png) = textures.src.get(&data.d) {
+                texture.embed(png.as_ref());
+            }
+            texture
+        }).collect();
+        // the project resolution only matters for textures without their own UV size
+        let resolution = textures.iter()
+            .find(|t| t.width != 0 && t.height != 0)
+            .map_or(Resolution { width: 64, height: 64 }, |t| Resolution { width: t.width, height: t.height });
+        BBModel {
+            meta: Meta {
+                format_version: FormatVersion::default(),
+                model_format: "free".into(),
+                box_uv: false,
+                creation_time: None,
+            },
+            name: (!name.is_empty()).then_some(name),
+            resolution,
+            textures,
+            elements,
+            outliner,
+            collections,
+            ..Default::default()
+        }
+    }
+}
+
+/// Replaces the texture index of each of an element's faces with what `map` returns for it, leaving
+/// the face without a texture for [None]. Faces that refer to their texture by UUID are skipped.
+fn map_texture_indices(element: &mut Element, mut map: impl FnMut(usize) -> Option<usize>) {
+    match &mut element.extra {
+        ElementType::Cube { faces, .. } => {
+            let Faces { north, east, south, west, up, down } = faces;
+            for face in [north, east, south, west, up, down].into_iter().flatten() {
+                if let Some(tex) = face.texture.index() {
+                    face.texture = map(tex).into();
+                }
+            }
+        }
+        ElementType::Mesh { faces, .. } => for face in faces.values_mut() {
+            face.texture = face.texture.and_then(&mut map);
+        },
+        ElementType::Locator { .. } | ElementType::NullObject { .. } | ElementType::TextureMesh { .. } => {}
+    }
+}
+
+/// Generates a UUID for something derived from a moon, so that unpacking the same moon twice
+/// produces identical files.
+fn derived_uuid(seed: &str) -> String {
+    use sha2::{Digest, Sha256};
+    let hash = Sha256::digest(seed.as_bytes());
+    uuid::Builder::from_random_bytes(hash[..16].try_into().unwrap()).into_uuid().hyphenated().to_string()
+}
+
+/// How many marker colors Blockbench has.
+const MARKER_COLORS: u8 = 8;
+
+/// The marker color to give a part's group or element: the one it was compiled with, the default
+/// if the model was compiled with colors but this part had the default, or one picked from the
+/// part's name if the model has no colors.
+fn marker_color(part: &ModelPart, colored: bool) -> u8 {
+    use sha2::{Digest, Sha256};
+    match part.color {
+        Some(color) => color,
+        None if colored => 0,
+        None => Sha256::digest(part.name.as_bytes())[0] % MARKER_COLORS,
+    }
+}
+
+/// Converts a face for Figura, drawing it with `single` if given rather than its own texture.
+fn convert_face_back(face: &Option<Face>, textures: &[Texture], single: Option<usize>) -> Option<moon::Face> {
+    let face = face.as_ref()?;
+    let (uv, rot) = face.uv.normalize_rotation(face.rotation.into());
+    let tex = match single {
+        Some(tex) => tex,
+        None => face.texture.resolve(textures)?,
+    };
+    Some(moon::Face { tex, uv, rot: rot.into() })
+}
+
+impl Element {
+    /// Converts a cube element into the [ModelPart] Figura would make from it, or returns [None]
+    /// for other kinds of element. `from`, `to`, and `inflate` are copied as they are, even for
+    /// inverted cubes and negative inflates; see [ModelData::cube_corners] for why. Faces that
+    /// refer to their texture by UUID are looked up in `textures`, and left out if it isn't there.
+    ///
+    /// Cubes using Box UV get the UVs of their [Box UV layout][box_uv_layout], as Blockbench would
+    /// give them on saving.
+    pub fn to_cube_part(&self, textures: &[Texture]) -> Option<ModelPart> {
+        self.cube_part(textures, None)
+    }
+
+    /// Like [to_cube_part][Self::to_cube_part], but with every face drawn with the texture
+    /// `single` if given, as in [skin projects][skin].
+    fn cube_part(&self, textures: &[Texture], single: Option<usize>) -> Option<ModelPart> {
+        let ElementType::Cube { from, to, faces, inflate, .. } = &self.extra else { return None };
+        let rects = self.box_uv_rects();
+        let face = |face: &Option<Face>, side: usize| {
+            let mut face = convert_face_back(face, textures, single)?;
+            if let Some(rects) = rects {
+                face.uv = rects[side];
+                face.rot = 0.0;
+            }
+            Some(face)
+        };
+        Some(ModelPart {
+            name: self.name.clone(),
+            rot: moon_from_blockbench(self.rotation),
+            piv: moon_from_blockbench(self.origin),
+            vsb: self.visibility.unwrap_or(true),
+            data: ModelData::Cube {
+                cube_data: moon::Sided {
+                    n: face(&faces.north, 0),
+                    e: face(&faces.east, 1),
+                    s: face(&faces.south, 2),
+                    w: face(&faces.west, 3),
+                    u: face(&faces.up, 4),
+                    d: face(&faces.down, 5),
+                },
+                f: moon_from_blockbench(*from),
+                t: moon_from_blockbench(*to),
+                inf: inflate.unwrap_or(0.0),
+            },
+            ..Default::default()
+        })
+    }
+
+    /// Converts a mesh element into the [ModelPart] Figura would make from it, or returns [None]
+    /// for other kinds of element. Blockbench gives vertices relative to the element's origin, but
+    /// Figura stores them in absolute positions like everything else. Faces without a texture
+    /// aren't rendered, so they're left out, and each quad's corners are put in order around its
+    /// edge. UVs are copied as they are, in the project's UV resolution.
+    pub fn to_mesh_part(&self) -> Option<ModelPart> {
+        let ElementType::Mesh { vertices, faces } = &self.extra else { return None };
+        let mut builder = MeshBuilder::new();
+        let mut keys: Vec<_> = vertices.keys().collect();
+        keys.sort();
+        let mut positions = vec![];
+        let indices: HashMap<&str, usize> = keys.into_iter().map(|key| {
+            let [x, y, z] = vertices[key];
+            let pos = moon_from_blockbench([x + self.origin[0], y + self.origin[1], z + self.origin[2]]);
+            positions.push(pos);
+            (key.as_str(), builder.vertex(pos))
+        }).collect();
+        let mut keys: Vec<_> = faces.keys().collect();
+        keys.sort();
+        for face in keys.into_iter().map(|key| &faces[key]) {
+            let Some(tex) = face.texture else { continue };
+            let mut corners: Vec<_> = face.vertices.iter()
+                .filter_map(|v| Some((*indices.get(v.as_str())?, face.uv.get(v).copied().unwrap_or_default())))
+                .collect();
+            match corners.len() {
+                3 => {}
+                4 => order_quad(&mut corners, &positions),
+                _ => continue,
+            }
+            builder.face(tex, &corners);
+        }
+        Some(ModelPart {
+            name: self.name.clone(),
+            rot: moon_from_blockbench(self.rotation),
+            piv: moon_from_blockbench(self.origin),
+            vsb: self.visibility.unwrap_or(true),
+            data: ModelData::Mesh { mesh_data: builder.build() },
+            ..Default::default()
+        })
+    }
+
+    /// Converts a locator into the part Figura would make from an empty group in its place, or
+    /// returns [None] for other kinds of element. The part pivots at the locator's position, and
+    /// gets the parent type its name implies, so that scripts can use it as an attachment point.
+    pub fn to_locator_part(&self) -> Option<ModelPart> {
+        let ElementType::Locator { position, .. } = &self.extra else { return None };
+        Some(ModelPart {
+            name: self.name.clone(),
+            rot: moon_from_blockbench(self.rotation),
+            piv: moon_from_blockbench(*position),
+            vsb: self.visibility.unwrap_or(true),
+            pt: ParentType::for_part(&self.name),
+            ..Default::default()
+        })
+    }
+
+    /// The UVs of a cube's faces from its Box UV offset, in the order north, east, south, west, up,
+    /// down; or [None] if this isn't a cube using Box UV.
+    pub fn box_uv_rects(&self) -> Option<[moon::UvRect; 6]> {
+        let ElementType::Cube { from, to, uv_offset, box_uv, mirror_uv, .. } = &self.extra else { return None };
+        if box_uv != &Some(Value::Bool(true)) {
+            return None;
+        }
+        Some(box_uv_layout(*from, *to, uv_offset.unwrap_or_default(), mirror_uv.unwrap_or(false)))
+    }
+
+    /// Switches a Box UV cube to per-face UV, giving each face the UVs of its place in the
+    /// layout. Returns whether the cube used Box UV.
+    pub fn unwrap_box_uv(&mut self) -> bool {
+        let Some(rects) = self.box_uv_rects() else { return false };
+        let ElementType::Cube { faces, box_uv, .. } = &mut self.extra else { return false };
+        let Faces { north, east, south, west, up, down } = faces;
+        for (face, rect) in [north, east, south, west, up, down].into_iter().zip(rects) {
+            if let Some(face) = face {
+                face.uv = rect;
+                face.rotation = 0;
+            }
+        }
+        *box_uv = Some(Value::Bool(false));
+        true
+    }
+
+    /// The kind of element this is, as Blockbench names it in the editor.
+    pub fn kind(&self) -> &'static str {
+        match self.extra {
+            ElementType::Cube { .. } => "cube",
+            ElementType::Mesh { .. } => "mesh",
+            ElementType::Locator { .. } => "locator",
+            ElementType::NullObject { .. } => "null object",
+            ElementType::TextureMesh { .. } => "texture mesh",
+        }
+    }
+
+    /// The point this element rotates around: its origin, or a locator's or null object's
+    /// position.
+    pub fn pivot(&self) -> [f64; 3] {
+        match &self.extra {
+            ElementType::Locator { position, .. } | ElementType::NullObject { position, .. } => *position,
+            _ => self.origin,
+        }
+    }
+
+    /// Works out the faces this element draws, for exporting to other formats. Faces referring to
+    /// a texture by UUID are [resolved][FaceTexture::resolve] against `textures`, and `sizes` is
+    /// indexed the same way. Faces whose texture has no size in `sizes` are left out, since their
+    /// UVs can't be normalized.
+    fn polygons(&self, textures: &[Texture], sizes: &[Option<(f64, f64)>]) -> Vec<Polygon> {
+        let mut polygons = vec![];
+        let size = |tex: Option<usize>| sizes.get(tex?).copied().flatten();
+        let origin = self.origin;
+        let local = |p: [f64; 3]| -> [f64; 3] { std::array::from_fn(|i| p[i] - origin[i]) };
+        match &self.extra {
+            ElementType::Cube { from, to, faces, inflate, .. } => {
+                let inflate = inflate.unwrap_or(0.0);
+                let [x0, y0, z0] = local(from.map(|c| c - inflate));
+                let [x1, y1, z1] = local(to.map(|c| c + inflate));
+                // the top left, top right, bottom right, and bottom left of each face, seen from
+                // outside the cube
+                let sides = [
+                    (&faces.north, [[x1, y1, z0], [x0, y1, z0], [x0, y0, z0], [x1, y0, z0]]),
+                    (&faces.east,  [[x1, y1, z1], [x1, y1, z0], [x1, y0, z0], [x1, y0, z1]]),
+                    (&faces.south, [[x0, y1, z1], [x1, y1, z1], [x1, y0, z1], [x0, y0, z1]]),
+                    (&faces.west,  [[x0, y1, z0], [x0, y1, z1], [x0, y0, z1], [x0, y0, z0]]),
+                    (&faces.up,    [[x0, y1, z0], [x1, y1, z0], [x1, y1, z1], [x0, y1, z1]]),
+                    (&faces.down,  [[x0, y0, z1], [x1, y0, z1], [x1, y0, z0], [x0, y0, z0]]),
+                ];
+                for (face, corners) in sides {
+                    let Some(Face { uv, texture, rotation }) = face else { continue };
+                    let Some(texture) = texture.resolve(textures) else { continue };
+                    let Some(size) = size(Some(texture)) else { continue };
+                    let [u0, v0, u1, v1] = uv.to_normalized(size);
+                    let uvs = [[u0, v0], [u1, v0], [u1, v1], [u0, v1]];
+                    // rotating a face's UV turns its texture clockwise
+                    let turns = (*rotation as usize / 90) % 4;
+                    let corner = |i: usize| (corners[i], uvs[(i + 4 - turns) % 4]);
+                    polygons.push(Polygon {
+                        texture,
+                        double_sided: false,
+                        corners: vec![corner(0), corner(3), corner(2), corner(1)],
+                    });
+                }
+            }
+            ElementType::Mesh { vertices, faces } => {
+                let mut keys: Vec<_> = faces.keys().collect();
+                keys.sort();
+                for face in keys.into_iter().map(|key| &faces[key]) {
+                    let (Some(texture), Some((width, height))) = (face.texture, size(face.texture)) else { continue };
+                    let mut corners: Vec<_> = face.vertices.iter().filter_map(|key| {
+                        let [u, v] = face.uv.get(key).copied().unwrap_or_default();
+                        Some((*vertices.get(key)?, [u / width, v / height]))
+                    }).collect();
+                    match corners.len() {
+                        3 => {}
+                        4 => {
+                            let positions: Vec<_> = corners.iter().map(|c| c.0).collect();
+                            let mut indexed: Vec<_> = corners.iter().enumerate().map(|(i, c)| (i, c.1)).collect();
+                            order_quad(&mut indexed, &positions);
+                            corners = indexed.into_iter().map(|(i, uv)| (positions[i], uv)).collect();
+                        }
+                        _ => continue,
+                    }
+                    polygons.push(Polygon { texture, double_sided: true, corners });
+                }
+            }
+            ElementType::Locator { .. } | ElementType::NullObject { .. } | ElementType::TextureMesh { .. } => {}
+        }
+        polygons
+    }
+}
+
+/// A face of an element, as exporters see it.
+struct Polygon {
+    /// The index of the face's texture.
+    texture: usize,
+    /// Whether the face should be drawn from both sides. Mesh faces don't have a consistent
+    /// winding, so they always are.
+    double_sided: bool,
+    /// The face's corners, counterclockwise as seen from its front, relative to the element's
+    /// origin. UVs are normalized to the size of the texture.
+    corners: Vec<([f64; 3], [f64; 2])>,
+}
+
+/// Converts mesh data into a mesh element's geometry, with vertices relative to `origin`. Vertices
+/// and faces are keyed by their index.
+fn mesh_from_moon(mesh: &moon::MeshData, origin: [f64; 3]) -> ElementType {
+    let vertices = mesh.vertices().into_iter().enumerate().map(|(i, pos)| {
+        let [x, y, z] = blockbench_from_moon(pos);
+        (format!("v{i}"), [x - origin[0], y - origin[1], z - origin[2]])
+    }).collect();
+    let faces = mesh.faces().enumerate().map(|(i, face)| (format!("f{i}"), MeshFace {
+        uv: face.vertices.iter().map(|v| (format!("v{}", v.index), v.uv)).collect(),
+        vertices: face.vertices.iter().map(|v| format!("v{}", v.index)).collect(),
+        texture: Some(face.tex),
+    })).collect();
+    ElementType::Mesh { vertices, faces }
+}
+
+/// Puts a quad's corners in order around its edge. Blockbench stores a quad's vertices in
+/// whatever order they were created, which can describe a bowtie instead of a quad; Figura
+/// reorders them the same way before rendering.
+fn order_quad(corners: &mut [(usize, [f64; 2])], positions: &[[f64; 3]]) {
+    let pos = |i: usize| positions[corners[i].0];
+    let sub = |a: [f64; 3], b: [f64; 3]| [a[0] - b[0], a[1] - b[1], a[2] - b[2]];
+    let cross = |a: [f64; 3], b: [f64; 3]| [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]];
+    let dot = |a: [f64; 3], b: [f64; 3]| a[0] * b[0] + a[1] * b[1] + a[2] * b[2];
+    let center = (0..4).map(pos).fold([0.0; 3], |c, p| [c[0] + p[0] / 4.0, c[1] + p[1] / 4.0, c[2] + p[2] / 4.0]);
+    let normal = cross(sub(pos(1), pos(0)), sub(pos(2), pos(0)));
+    let x = sub(pos(0), center);
+    let y = cross(normal, x);
+    let angles: Vec<f64> = (0..4).map(|i| {
+        let d = sub(pos(i), center);
+        dot(d, y).atan2(dot(d, x)).rem_euclid(std::f64::consts::TAU)
+    }).collect();
+    let mut order = [0, 1, 2, 3];
+    order.sort_by(|&a, &b| angles[a].total_cmp(&angles[b]));
+    let sorted = order.map(|i| corners[i]);
+    corners.copy_from_slice(&sorted);
+}
+
+/// Lays out a box-UV cube's faces the way Blockbench and Minecraft unwrap a box, returning their
+/// UVs in the order north, east, south, west, up, down. With the cube's size (rounded down to
+/// whole pixels) as `w`×`h`×`d`, the top row holds the up and down faces, each `w`×`d`, after a
+/// gap of `d`; the bottom row holds the east, north, west, and south faces, each `h` tall. The up
+/// face is flipped on both axes, the down face vertically. Mirroring swaps east and west and flips
+/// every face horizontally.
+pub fn box_uv_layout(from: [f64; 3], to: [f64; 3], offset: [f64; 2], mirror: bool) -> [moon::UvRect; 6] {
+    let [w, h, d]: [f64; 3] = std::array::from_fn(|i| (to[i] - from[i] + 1e-7).floor());
+    // each face's starting corner in the layout and its signed size
+    let mut faces = [
+        ([d, d], [w, h]),
+        ([0.0, d], [d, h]),
+        ([2.0 * d + w, d], [w, h]),
+        ([d + w, d], [d, h]),
+        ([d + w, d], [-w, -d]),
+        ([d + 2.0 * w, 0.0], [-w, d]),
+    ];
+    if mirror {
+        (faces[1].0, faces[3].0) = (faces[3].0, faces[1].0);
+        for (corner, size) in &mut faces {
+            corner[0] += size[0];
+            size[0] = -size[0];
+        }
+    }
+    faces.map(|([x, y], [width, height])| {
+        let (x, y) = (offset[0] + x, offset[1] + y);
+        moon::UvRect::pixels(x, y, x + width, y + height)
+    })
+}
+
+/// Works out whether a cube's faces are exactly its Box UV layout for some offset, returning the
+/// offset and whether it's mirrored. Every face must be present, use the same texture, and be
+/// unrotated.
+fn detect_box_uv(from: [f64; 3], to: [f64; 3], faces: &Faces) -> Option<([f64; 2], bool)> {
+    let sides = [&faces.north, &faces.east, &faces.south, &faces.west, &faces.up, &faces.down];
+    let sides: Vec<&Face> = sides.into_iter().map(Option::as_ref).collect::<Option<_>>()?;
+    if sides.iter().any(|f| f.rotation != 0 || f.texture == FaceTexture::None || f.texture != sides[0].texture) {
+        return None;
+    }
+    let close = |a: moon::UvRect, b: moon::UvRect| {
+        [(a.x0, b.x0), (a.y0, b.y0), (a.x1, b.x1), (a.y1, b.y1)].iter().all(|(a, b)| (a - b).abs() < 1e-6)
+    };
+    [false, true].into_iter().find_map(|mirror| {
+        let origin = box_uv_layout(from, to, [0.0; 2], mirror)[0];
+        let offset = [sides[0].uv.x0 - origin.x0, sides[0].uv.y0 - origin.y0];
+        let layout = box_uv_layout(from, to, offset, mirror);
+        sides.iter().zip(layout).all(|(face, rect)| close(face.uv, rect)).then_some((offset, mirror))
+    })
+}
+
+fn convert_face(face: &Option<moon::Face>) -> Option<Face> {
+    Some(match face {
+        Some(face) => {
+            let (uv, rotation) = face.uv.normalize_rotation(face.rot);
+            Face { uv, texture: FaceTexture::Index(face.tex), rotation }
+        }
+        None => Face { uv: Default::default(), texture: FaceTexture::None, rotation: 0 },
+    })
+}
+
+impl Hierarchy {
+    /// Converts the children of a moon [ModelPart] into a hierarchy. In a moon, the root part has
+    /// one child per Blockbench file, and those children's children are the roots of each file's
+    /// outliner; so this is usually called on a child of [Moon::models].
+    ///
+    /// Mesh geometry isn't converted yet; meshes become empty groups. Groups with no children
+    /// become locators, since Figura makes the same part from both.
+    ///
+    /// Marker colors come from each part's [color][ModelPart::color] if the model was compiled by
+    /// [compile]. Otherwise, Figura has thrown them away, so each part is given a color picked
+    /// from its name instead; that way parts with the same name match, and unpacking the same
+    /// moon twice gives the same colors.
+    ///
+    /// [Moon::models]: crate::Moon::models
+    pub fn from_model_part(part: &ModelPart) -> Self {
+        fn any_colored(part: &ModelPart) -> bool {
+            part.color.is_some() || part.chld.iter().any(any_colored)
+        }
+        let colored = any_colored(part);
+        let mut hierarchy = Hierarchy { name: part.name.clone(), ..Default::default() };
+        hierarchy.collections = part.cn.iter().enumerate().map(|(i, name)| Collection {
+            name: name.clone(),
+            uuid: derived_uuid(&format!("{}/collection{i}", part.name)),
+            children: vec![],
+            extra: Map::new(),
+        }).collect();
+        for (i, child) in part.chld.iter().enumerate() {
+            let item = hierarchy.convert_part(child, &format!("{}/{i}", part.name), colored);
+            hierarchy.outliner.push(item);
+        }
+        hierarchy
+    }
+
+    fn convert_part(&mut self, part: &ModelPart, path: &str, colored: bool) -> OutlinerItem {
+        let item = self.convert_part_inner(part, path, colored);
+        let uuid = match &item {
+            OutlinerItem::Element(uuid) => uuid,
+            OutlinerItem::Group(group) => &group.uuid,
+        };
+        for &i in &part.pr {
+            if let Some(collection) = usize::try_from(i).ok().and_then(|i| self.collections.get_mut(i)) {
+                collection.children.push(uuid.clone());
+            }
+        }
+        item
+    }
+
+    fn convert_part_inner(&mut self, part: &ModelPart, path: &str, colored: bool) -> OutlinerItem {
+        let uuid = derived_uuid(path);
+        let color = marker_color(part, colored);
+        let mut children = vec![];
+        // an empty group and a locator make the same part, and empty parts are nearly always
+        // attachment points
+        if matches!(part.data, ModelData::Group {}) && part.chld.is_empty() {
+            self.elements.push(Element {
+                origin: blockbench_from_moon(part.piv),
+                name: part.name.clone(),
+                uuid: uuid.clone(),
+                visibility: Some(part.vsb),
+                locked: false,
+                render_order: None,
+                allow_mirror_modeling: true,
+                export: Some(true),
+                color,
+                rotation: blockbench_from_moon(part.rot),
+                extra: ElementType::Locator { position: blockbench_from_moon(part.piv), ignore_inherited_scale: false },
+            });
+            return OutlinerItem::Element(uuid);
+        }
+        if let ModelData::Cube { cube_data, f, t, inf } = &part.data {
+            // inverted cubes and negative inflates are passed through as-is; see
+            // ModelData::cube_corners
+            let faces = Faces {
+                north: convert_face(&cube_data.n),
+                east:  convert_face(&cube_data.e),
+                south: convert_face(&cube_data.s),
+                west:  convert_face(&cube_data.w),
+                up:    convert_face(&cube_data.u),
+                down:  convert_face(&cube_data.d),
+            };
+            let (from, to) = (blockbench_from_moon(*f), blockbench_from_moon(*t));
+            // keep Box UV on for cubes that were laid out with it, so they stay easy to edit
+            let box_uv = detect_box_uv(from, to, &faces);
+            let element = Element {
+                origin: blockbench_from_moon(part.piv),
+                name: part.name.clone(),
+                uuid: uuid.clone(),
+                visibility: Some(part.vsb),
+                locked: false,
+                render_order: None,
+                allow_mirror_modeling: true,
+                export: Some(true),
+                color,
+                rotation: blockbench_from_moon(part.rot),
+                extra: ElementType::Cube {
+                    from,
+                    to,
+                    uv_offset: box_uv.map(|(offset, _)| offset),
+                    faces,
+                    box_uv: Some(Value::Bool(box_uv.is_some())),
+                    rescale: false,
+                    autouv: 0,
+                    light_emission: None,
+                    mirror_uv: box_uv.map(|(_, mirror)| mirror),
+                    inflate: (*inf != 0.0).then_some(*inf),
+                    shade: None,
+                },
+            };
+            self.elements.push(element);
+            if part.chld.is_empty() {
+                return OutlinerItem::Element(uuid);
+            }
+            // Blockbench elements can't have children, so wrap the cube in a group
+            children.push(OutlinerItem::Element(uuid));
+        }
+        for (i, child) in part.chld.iter().enumerate() {
+            children.push(self.convert_part(child, &format!("{path}/{i}"), colored));
+        }
+        OutlinerItem::Group(Group {
+            name: part.name.clone(),
+            origin: blockbench_from_moon(part.piv),
+            rotation: blockbench_from_moon(part.rot),
+            color,
+            uuid: derived_uuid(&format!("{path}/group")),
+            export: true,
+            visibility: part.vsb,
+            children,
+            extra: Map::new(),
+        })
+    }
+}
+
+/// An error encountered while [compiling][compile] a Blockbench model.
+#[derive(Debug, Error)]
+pub enum CompileError {
+    /// A texture refers to an image file instead of embedding its data.
+    #[error("texture {0:?} isn't embedded in the model")]
+    ExternalTexture(String),
+    /// A texture's embedded data isn't a base64 data URL.
+    #[error("texture {0:?} has malformed data")]
+    MalformedTexture(String),
+    /// A texture's embedded data isn't a valid PNG.
+    #[error("texture {0:?} is not a valid PNG: {1}")]
+    InvalidTexture(String, #[source] ImageError),
+}
+
+/// Converts a point on one pixel grid to another, as [moon::UvRect::rescale] does for rectangles.
+fn rescale_uv([u, v]: [f64; 2], from: (f64, f64), to: (f64, f64)) -> [f64; 2] {
+    [u * to.0 / from.0, v * to.1 / from.1]
+}
+
+/// State shared while compiling one model's outliner.
+struct Compiler<'a> {
+    elements: HashMap<&'a str, &'a Element>,
+    collections: HashMap<String, Vec<i32>>,
+    /// For each texture, the UV resolution faces are given in and the size of the image.
+    uv_sizes: Vec<((f64, f64), (f64, f64))>,
+    textures: &'a [Texture],
+    /// The texture every face is drawn with, in skin projects.
+    skin_texture: Option<usize>,
+    animations: &'a [Animation],
+}
+
+impl Compiler<'_> {
+    fn rescale(&self, tex: usize, uv: moon::UvRect) -> moon::UvRect {
+        match self.uv_sizes.get(tex) {
+            Some(&(from, to)) => uv.rescale(from, to),
+            None => uv,
+        }
+    }
+
+    fn item(&self, item: &OutlinerItem) -> Option<ModelPart> {
+        let (mut part, uuid) = match item {
+            OutlinerItem::Element(uuid) => {
+                let element = self.elements.get(uuid.as_str())?;
+                if element.export == Some(false) {
+                    return None;
+                }
+                (self.element(element)?, uuid)
+            }
+            OutlinerItem::Group(group) => {
+                if !group.export {
+                    return None;
+                }
+                let part = ModelPart {
+                    chld: group.children.iter().filter_map(|child| self.item(child)).collect(),
+                    ..group.to_model_part()
+                };
+                (part, &group.uuid)
+            }
+        };
+        part.pr = self.collections.get(uuid).cloned().unwrap_or_default();
+        let color = match item {
+            OutlinerItem::Element(uuid) => self.elements[uuid.as_str()].color,
+            OutlinerItem::Group(group) => group.color,
+        };
+        part.color = (color != 0).then_some(color);
+        for (index, animation) in self.animations.iter().enumerate() {
+            // animators are keyed by the UUID of the group or element they move
+            if let Some(animator) = animation.animators.get(uuid) {
+                let channels = part_channels(animator);
+                if !channels.is_empty() {
+                    part.anim.insert(index, channels);
+                }
+            }
+        }
+        Some(part)
+    }
+
+    fn element(&self, element: &Element) -> Option<ModelPart> {
+        match &element.extra {
+            ElementType::Cube { .. } => {
+                let mut part = element.cube_part(self.textures, self.skin_texture)?;
+                if let ModelData::Cube { cube_data, .. } = &mut part.data {
+                    for face in [&mut cube_data.n, &mut cube_data.s, &mut cube_data.u, &mut cube_data.d, &mut cube_data.w, &mut cube_data.e] {
+                        if let Some(face) = face {
+                            face.uv = self.rescale(face.tex, face.uv);
+                        }
+                    }
+                }
+                Some(part)
+            }
+            ElementType::Mesh { .. } => {
+                let mut part = element.to_mesh_part()?;
+                if let ModelData::Mesh { mesh_data } = &mut part.data {
+                    mesh_data.map_uvs(|tex, uv| match self.uv_sizes.get(tex) {
+                        Some(&(from, to)) => rescale_uv(uv, from, to),
+                        None => uv,
+                    });
+                }
+                Some(part)
+            }
+            ElementType::Locator { .. } => element.to_locator_part(),
+            ElementType::NullObject { .. } | ElementType::TextureMesh { .. } => None,
+        }
+    }
+}
+
+
+/// Compiles a Blockbench model into the part Figura would make from it, along with its textures.
+/// This is the reverse of [Hierarchy::from_model_part]; the part goes in [Moon::models] as a
+/// child of the root.
+///
+/// Like Figura's loader:
+/// - each outliner group becomes a part with no model data, given the parent type its name
+///   implies, and each cube or mesh becomes a part with the corresponding [ModelData];
+/// - groups and elements that aren't exported are left out along with everything inside them;
+/// - locators become parts with no model data, and null objects and texture meshes, which
+///   Figura doesn't support, are left out ([lint] warns about them);
+/// - skin projects get the player template's bones, [expanded][BBModel::expand_skin_template]
+///   into groups named after Figura's keywords, and every cube face is drawn with the skin;
+/// - collections become the part's [cn][ModelPart::cn] and each member's [pr][ModelPart::pr];
+/// - marker colors are kept in each part's [color][ModelPart::color], which Figura ignores;
+/// - each texture is stored under the model's name and the texture's name without `.png`,
+///   separated by a dot, and UVs are converted from the texture's UV resolution to the size of
+///   the image. Layered textures are [flattened][Texture::flattened_source] first.
+///
+/// Texture IDs are indices into this model's textures; when putting several models into one
+/// moon, move them past the existing textures with [Moon::remap_textures]. Likewise, each part's
+/// [anim][ModelPart::anim] is keyed by the animation's index in this model; [compile_animations]
+/// gives the animations themselves, and [ModelPart::offset_animations] moves the keys past a
+/// moon's existing animations.
+///
+/// [Moon::models]: crate::Moon::models
+/// [Moon::remap_textures]: crate::Moon::remap_textures
+pub fn compile(model: &BBModel) -> Result<(ModelPart, Textures), CompileError> {
+    let name = model.name.clone().unwrap_or_default();
+    let mut textures = Textures::default();
+    let mut data = vec![];
+    let mut uv_sizes = vec![];
+    for texture in &model.textures {
+        let png = texture.flattened_source()?;
+        let (width, height) = image::dimensions(&png)
+            .map_err(|e| CompileError::InvalidTexture(texture.name.clone(), e))?;
+        uv_sizes.push((texture.uv_size(&model.resolution), (width as f64, height as f64)));
+        let key = format!("{name}.{}", texture.name.strip_suffix(".png").unwrap_or(&texture.name));
+        data.push(TextureData { d: key.clone() });
+        textures.src.insert(key, png.into());
+    }
+    textures.data = data.into();
+    let expanded;
+    let outliner = if model.is_skin() {
+        expanded = skin::expand_outliner(&model.outliner);
+        &expanded
+    } else {
+        &model.outliner
+    };
+    let compiler = Compiler {
+        elements: model.elements.iter().map(|e| (e.uuid.as_str(), e)).collect(),
+        collections: collection_indices(&model.collections),
+        uv_sizes,
+        textures: &model.textures,
+        skin_texture: model.is_skin().then(|| skin::skin_texture(&model.textures)).flatten(),
+        animations: &model.animations,
+    };
+    let part = ModelPart {
+        name,
+        vsb: true,
+        cn: model.collections.iter().map(|c| c.name.clone()).collect(),
+        chld: outliner.iter().filter_map(|item| compiler.item(item)).collect(),
+        ..Default::default()
+    };
+    Ok((part, textures))
+}
+
+impl From<&moon::KeyframeValue> for XYZ<SoN> {
+    fn from(value: &moon::KeyframeValue) -> Self {
+        let [x, y, z] = match value {
+            moon::KeyframeValue::Numbers(v) => v.map(|n| SoN::Number(n.into())),
+            moon::KeyframeValue::Expressions(v) => v.clone().map(SoN::String),
+        };
+        XYZ { x, y, z }
+    }
+}
+
+/// Converts the keyframes for one animation on one part.
+fn part_keyframes(channels: &moon::PartChannels, seed: &str) -> Vec<Keyframe> {
+    let mut keyframes = vec![];
+    for (key, channel, list) in [("rot", "rotation", &channels.rot), ("pos", "position", &channels.pos), ("scl", "scale", &channels.scl)] {
+        for (i, keyframe) in list.iter().enumerate() {
+            let widen = |v: Option<[f32; 3]>| v.map(|v| v.map(f64::from));
+            keyframes.push(Keyframe {
+                channel: channel.into(),
+                uniform: None,
+                color: -1,
+                data_points: [Some(&keyframe.pre), keyframe.end.as_ref()].into_iter().flatten().map(|v| DataPoint::Value(v.into())).collect(),
+                interpolation: keyframe.int.name().into(),
+                time: keyframe.time.into(),
+                uuid: derived_uuid(&format!("{seed}/{key}/{i}")),
+                bezier_linked: None,
+                bezier_left_time: widen(keyframe.blt),
+                bezier_left_value: widen(keyframe.bl),
+                bezier_right_time: widen(keyframe.brt),
+                bezier_right_value: widen(keyframe.br),
+            });
+        }
+    }
+    keyframes
+}
+
+fn collect_animators(part: &ModelPart, path: &str, index: usize, animators: &mut HashMap<String, Animator>) {
+    if let Some(channels) = part.anim.get(&index) {
+        let keyframes = part_keyframes(channels, &format!("{path}/anim{index}"));
+        if !keyframes.is_empty() {
+            // animators are keyed by the group's UUID; see Hierarchy::convert_part
+            let uuid = if matches!(part.data, ModelData::Cube { .. }) && part.chld.is_empty() {
+                derived_uuid(path)
+            } else {
+                derived_uuid(&format!("{path}/group"))
+            };
+            animators.insert(uuid, Animator { r#type: "bone".into(), name: part.name.clone(), keyframes });
+        }
+    }
+    for (i, child) in part.chld.iter().enumerate() {
+        collect_animators(child, &format!("{path}/{i}"), index, animators);
+    }
+}
+
+/// Converts one animation's metadata, given its already-converted animators.
+fn animation_from_moon(animation: &moon::Animation, animators: HashMap<String, Animator>, uuid: String) -> Animation {
+    let number = |n: f64| if n == 0.0 { String::new() } else { n.to_string() };
+    Animation {
+        anim_time_update: number(animation.off),
+        animators,
+        blend_weight: animation.bld.map(|n| n.to_string()).unwrap_or_default(),
+        length: animation.len,
+        r#loop: Some(Value::String(match animation.r#loop {
+            Some(moon::Loop::Loop) => "loop",
+            Some(moon::Loop::Hold) => "hold",
+            None => "once",
+        }.into())),
+        loop_delay: number(animation.ldel),
+        name: animation.name.clone(),
+        r#override: animation.ovr != 0,
+        selected: false,
+        snapping: 24,
+        start_delay: number(animation.sdel),
+        uuid,
+        markers: None,
+    }
+}
+
+/// Converts a moon's animations into Blockbench animations for the model stored in `part` (a
+/// child of [Moon::models], as passed to [Hierarchy::from_model_part]). Only animations whose
+/// [mdl][moon::Animation::mdl] matches the part's name are converted, along with their instruction
+/// keyframes.
+///
+/// [Moon::models]: crate::Moon::models
+pub fn animations_from_moon(part: &ModelPart, animations: &[moon::Animation]) -> Vec<Animation> {
+    let mut out = vec![];
+    for (index, animation) in animations.iter().enumerate() {
+        if animation.mdl != part.name {
+            continue;
+        }
+        let mut animators = HashMap::new();
+        for (i, child) in part.chld.iter().enumerate() {
+            collect_animators(child, &format!("{}/{i}", part.name), index, &mut animators);
+        }
+        let seed = format!("{}/animation{index}", part.name);
+        // Blockbench keeps sounds, particles and instructions on an animator of their own
+        if let Some(effects) = effects_animator(&animation.code, &seed) {
+            animators.insert("effects".into(), effects);
+        }
+        let uuid = derived_uuid(&seed);
+        out.push(animation_from_moon(animation, animators, uuid));
+    }
+    out
+}
+
+impl From<&XYZ<SoN>> for moon::KeyframeValue {
+    fn from(XYZ { x, y, z }: &XYZ<SoN>) -> Self {
+        // Blockbench saves numbers typed into keyframes as strings, and blank axes as ""
+        let convert = |v: &SoN| match v {
+            SoN::Number(n) => FiguraValue::Constant(*n),
+            SoN::String(s) => molang::to_figura(s),
+        };
+        match [x, y, z].map(convert) {
+            [FiguraValue::Constant(x), FiguraValue::Constant(y), FiguraValue::Constant(z)] => {
+                moon::KeyframeValue::Numbers([x as f32, y as f32, z as f32])
+            }
+            values => moon::KeyframeValue::Expressions(values.map(|v| match v {
+                FiguraValue::Constant(n) => n.to_string(),
+                FiguraValue::Lua(lua) => lua,
+            })),
+        }
+    }
+}
+
+/// Converts the keyframes of one animator, the reverse of [part_keyframes]. Keyframes on
+/// channels Figura doesn't know are skipped.
+fn part_channels(animator: &Animator) -> moon::PartChannels {
+    let mut channels = moon::PartChannels::default();
+    for keyframe in &animator.keyframes {
+        let list = match keyframe.channel.as_str() {
+            "rotation" => &mut channels.rot,
+            "position" => &mut channels.pos,
+            "scale" => &mut channels.scl,
+            _ => continue,
+        };
+        let Some(DataPoint::Value(pre)) = keyframe.data_points.first() else { continue };
+        let int = Interpolation::from_name(&keyframe.interpolation).unwrap_or_default();
+        // Blockbench keeps handles on every keyframe, but they only mean anything for béziers
+        let handle = |v: Option<[f64; 3]>| v.filter(|_| int == Interpolation::Bezier).map(|v| v.map(|n| n as f32));
+        list.push(moon::PartKeyframe {
+            time: keyframe.time as f32,
+            int,
+            pre: pre.into(),
+            end: match keyframe.data_points.get(1) {
+                Some(DataPoint::Value(end)) => Some(end.into()),
+                _ => None,
+            },
+            bl: handle(keyframe.bezier_left_value),
+            br: handle(keyframe.bezier_right_value),
+            blt: handle(keyframe.bezier_left_time),
+            brt: handle(keyframe.bezier_right_time),
+        });
+    }
+    for list in [&mut channels.rot, &mut channels.pos, &mut channels.scl] {
+        list.sort_by(|a, b| a.time.total_cmp(&b.time));
+    }
+    channels
+}
+
+/// Converts a model's animations into moon animations, the reverse of [animations_from_moon]. The
+/// keyframes themselves go on each part, which [compile] takes care of; the returned animations
+/// are in the same order as the indices it uses. Instruction keyframes become each animation's
+/// [code][moon::Animation::code]; sound and particle keyframes are left out, as Figura ignores them.
+pub fn compile_animations(model: &BBModel) -> Vec<moon::Animation> {
+    let number = |s: &str| s.trim().parse::<f64>().unwrap_or(0.0);
+    model.animations.iter().map(|animation| moon::Animation {
+        r#loop: match &animation.r#loop {
+            Some(Value::String(mode)) if mode == "loop" => Some(moon::Loop::Loop),
+            Some(Value::String(mode)) if mode == "hold" => Some(moon::Loop::Hold),
+            _ => None,
+        },
+        name: animation.name.clone(),
+        ovr: animation.r#override.into(),
+        mdl: model.name.clone().unwrap_or_default(),
+        len: animation.length,
+        off: number(&animation.anim_time_update),
+        bld: animation.blend_weight.trim().parse().ok().filter(|&w| w != 1.0),
+        sdel: number(&animation.start_delay),
+        ldel: number(&animation.loop_delay),
+        code: code_keyframes(animation),
+        ..Default::default()
+    }).collect()
+}
+
+/// The instruction keyframes of an animation's effects animator, in order.
+fn code_keyframes(animation: &Animation) -> Vec<moon::CodeKeyframe> {
+    let mut code: Vec<_> = animation.animators.values()
+        .filter(|animator| animator.r#type == "effect")
+        .flat_map(|animator| &animator.keyframes)
+        .filter(|keyframe| keyframe.channel == "timeline")
+        .filter_map(|keyframe| match keyframe.data_points.first() {
+            Some(DataPoint::Script(Script { script })) => Some(moon::CodeKeyframe { time: keyframe.time, src: script.clone() }),
+            _ => None,
+        })
+        .collect();
+    code.sort_by(|a, b| a.time.total_cmp(&b.time));
+    code
+}
+
+/// The effects animator holding an animation's instruction keyframes, the reverse of
+/// [code_keyframes], or [None] if it has none.
+fn effects_animator(code: &[moon::CodeKeyframe], seed: &str) -> Option<Animator> {
+    let keyframes: Vec<_> = code.iter().enumerate().map(|(i, code)| Keyframe {
+        channel: "timeline".into(),
+        uniform: None,
+        color: -1,
+        data_points: vec![DataPoint::Script(Script { script: code.src.clone() })],
+        interpolation: "linear".into(),
+        time: code.time,
+        uuid: derived_uuid(&format!("{seed}/code/{i}")),
+        ..Default::default()
+    }).collect();
+    (!keyframes.is_empty()).then(|| Animator { r#type: "effect".into(), name: "Effects".into(), keyframes })
+}
+
+/// Checks that each animation's animators move groups or elements that exist, and that their
+/// keyframes are on channels Blockbench knows about.
+fn lint_animations(model: &BBModel, nodes: &HashSet<&str>, lints: &mut Vec<Lint>) {
+    let warn = |code, message| Lint { severity: Severity::Warning, code, message, file: None, part: None };
+    for animation in &model.animations {
+        let mut animators: Vec<_> = animation.animators.iter().collect();
+        animators.sort_by_key(|(key, _)| *key);
+        for (key, animator) in animators {
+            // sounds, particles and instructions are on an animator of their own, not a node
+            let effect = animator.r#type == "effect";
+            let channels: &[&str] = if effect { &["sound", "particle", "timeline"] } else { &["rotation", "position", "scale"] };
+            if !effect && !nodes.contains(key.as_str()) {
+                lints.push(warn(
+                    "missing-animated-part",
+                    format!("animation {} moves {} ({key}), which doesn't exist; its keyframes will be left out", animation.name, animator.name),
+                ));
+            }
+            let mut unknown: Vec<&str> = animator.keyframes.iter().map(|k| k.channel.as_str()).filter(|c| !channels.contains(c)).collect();
+            unknown.sort();
+            unknown.dedup();
+            for channel in unknown {
+                lints.push(warn(
+                    "unknown-channel",
+                    format!("animation {} has keyframes for {} on unknown channel {channel:?}; they will be left out", animation.name, animator.name),
+                ));
+            }
+        }
+    }
+}
+
+/// A problem found by [lint]. These are ordinary [check][crate::check] diagnostics, without a
+/// [file][Diagnostic::file] since the caller knows which model it linted.
+pub type Lint = Diagnostic;
+
+/// Checks a model for mistakes that Blockbench allows but that break or quietly change the model
+/// once Figura loads it:
+/// - `duplicate-uuid`: two groups or elements share a UUID, so only one of them is found;
+/// - `missing-element`: the outliner refers to an element that doesn't exist;
+/// - `missing-texture`: a face uses a texture that doesn't exist;
+/// - `zero-size-cube`: a cube is flat along two or more axes, so nothing of it is visible (cubes
+///   flat along one axis are fine, and commonly used as planes);
+/// - `hidden-export`: a group that isn't exported contains exported nodes, which are left out
+///   along with it;
+/// - `unsupported-element`: an element is of a kind Figura doesn't load;
+/// - `missing-animated-part`: an animation moves a group or element that doesn't exist, usually
+///   after it was deleted or the file was edited by hand, so that part of the animation is lost;
+/// - `unknown-channel`: keyframes are on a channel other than rotation, position, and scale (or
+///   sound, particle, and timeline for effects), so they do nothing.
+pub fn lint(model: &BBModel) -> Vec<Lint> {
+    fn lint_items(items: &[OutlinerItem], path: &str, exported: bool, elements: &HashMap<&str, &Element>, lints: &mut Vec<Lint>) {
+        let warn = |code, message| Lint { severity: Severity::Warning, code, message, file: None, part: None };
+        for item in items {
+            match item {
+                OutlinerItem::Element(uuid) => match elements.get(uuid.as_str()) {
+                    None => lints.push(Lint {
+                        severity: Severity::Error,
+                        code: "missing-element",
+                        message: format!("{path} refers to nonexistent element {uuid}"),
+                        file: None,
+                        part: None,
+                    }),
+                    Some(element) if !exported && element.export != Some(false) => lints.push(warn(
+                        "hidden-export",
+                        format!("{path}/{} is exported, but is inside a group that isn't", element.name),
+                    )),
+                    Some(_) => {}
+                },
+                OutlinerItem::Group(group) => {
+                    let child_path = if path.is_empty() { group.name.clone() } else { format!("{path}/{}", group.name) };
+                    if !exported && group.export {
+                        lints.push(warn("hidden-export", format!("{child_path} is exported, but is inside a group that isn't")));
+                    }
+                    // an exported group inside a hidden one has already been reported, so its own
+                    // children only need checking against it
+                    lint_items(&group.children, &child_path, group.export, elements, lints);
+                }
+            }
+        }
+    }
+    let mut lints = vec![];
+    let mut seen = HashSet::new();
+    let mut check_uuid = |uuid: &str, name: &str, lints: &mut Vec<Lint>| {
+        if !seen.insert(uuid.to_owned()) {
+            lints.push(Lint {
+                severity: Severity::Error,
+                code: "duplicate-uuid",
+                message: format!("{name} has the UUID {uuid}, which is already used"),
+                file: None,
+                part: None,
+            });
+        }
+    };
+    for element in &model.elements {
+        check_uuid(&element.uuid, &element.name, &mut lints);
+    }
+    fn groups<'a>(items: &'a [OutlinerItem], out: &mut Vec<&'a Group>) {
+        for item in items {
+            if let OutlinerItem::Group(group) = item {
+                out.push(group);
+                groups(&group.children, out);
+            }
+        }
+    }
+    let mut all_groups = vec![];
+    groups(&model.outliner, &mut all_groups);
+    for group in &all_groups {
+        check_uuid(&group.uuid, &group.name, &mut lints);
+    }
+    let nodes: HashSet<&str> = all_groups.iter().map(|g| g.uuid.as_str())
+        .chain(model.elements.iter().map(|e| e.uuid.as_str()))
+        .collect();
+    lint_animations(model, &nodes, &mut lints);
+    for element in &model.elements {
+        let used: Vec<usize> = match &element.extra {
+            ElementType::Cube { from, to, faces, .. } => {
+                if (0..3).filter(|&i| from[i] == to[i]).count() >= 2 {
+                    lints.push(Lint {
+                        severity: Severity::Warning,
+                        code: "zero-size-cube",
+                        message: format!("cube {} has no area", element.name),
+                        file: None,
+                        part: None,
+                    });
+                }
+                let sides = [&faces.north, &faces.east, &faces.south, &faces.west, &faces.up, &faces.down];
+                for face in sides.iter().filter_map(|f| f.as_ref()) {
+                    if let FaceTexture::Uuid(uuid) = &face.texture {
+                        if face.texture.resolve(&model.textures).is_none() {
+                            lints.push(Lint {
+                                severity: Severity::Error,
+                                code: "missing-texture",
+                                message: format!("{} uses texture {uuid}, which doesn't exist", element.name),
+                                file: None,
+                                part: None,
+                            });
+                        }
+                    }
+                }
+                sides.into_iter().filter_map(|f| f.as_ref()?.texture.index()).collect()
+            }
+            ElementType::Mesh { faces, .. } => faces.values().filter_map(|f| f.texture).collect(),
+            ElementType::Locator { .. } => vec![],
+            ElementType::NullObject { .. } | ElementType::TextureMesh { .. } => {
+                lints.push(Lint {
+                    severity: Severity::Warning,
+                    code: "unsupported-element",
+                    message: format!("{} is a {}, which Figura doesn't support; it will be left out", element.name, element.kind()),
+                    file: None,
+                    part: None,
+                });
+                vec![]
+            }
+        };
+        let mut missing: Vec<_> = used.into_iter().filter(|&t| t >= model.textures.len()).collect();
+        missing.sort();
+        missing.dedup();
+        for texture in missing {
+            lints.push(Lint {
+                severity: Severity::Error,
+                code: "missing-texture",
+                message: format!("{} uses texture {texture}, but there are only {}", element.name, model.textures.len()),
+                file: None,
+                part: None,
+            });
+        }
+    }
+    let elements = model.elements.iter().map(|e| (e.uuid.as_str(), e)).collect();
+    lint_items(&model.outliner, "", true, &elements, &mut lints);
+    lints
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cube(f: [f64; 3], t: [f64; 3], inf: f64) -> ModelPart {
+        ModelPart {
+            name: "cube".into(),
+            piv: [1.0, 2.0, 3.0],
+            vsb: true,
+            data: ModelData::Cube {
+                cube_data: moon::Sided { n: None, s: None, u: None, d: None, w: None, e: None },
+                f,
+                t,
+                inf,
+            },
+            ..Default::default()
+        }
+    }
+
+    /// Unpacks a cube into a Blockbench file, reads the file back, and compiles it again.
+    fn round_trip(part: ModelPart) -> (Element, ModelPart) {
+        let model = ModelPart { name: "model".into(), vsb: true, chld: vec![part].into(), ..Default::default() };
+        let bbmodel: BBModel = Hierarchy::from_model_part(&model).into();
+        let mut bbmodel = BBModel::from_json(serde_json::to_value(&bbmodel).unwrap()).unwrap();
+        let (compiled, _) = compile(&bbmodel).unwrap();
+        (bbmodel.elements.remove(0), compiled.chld.into_vec().remove(0))
+    }
+
+    fn assert_cube(part: &ModelPart, from: [f64; 3], to: [f64; 3], inflate: f64) {
+        let ModelData::Cube { f, t, inf, .. } = part.data else { panic!("{part:?} isn't a cube") };
+        assert_eq!((f, t, inf), (from, to, inflate));
+    }
+
+    #[test]
+    fn inverted_cube_round_trips() {
+        let (element, part) = round_trip(cube([4.0, 0.0, 2.0], [0.0, 8.0, -2.0], 0.0));
+        let ElementType::Cube { from, to, .. } = element.extra else { panic!("{element:?} isn't a cube") };
+        assert_eq!((from, to), ([4.0, 0.0, 2.0], [0.0, 8.0, -2.0]));
+        assert_cube(&part, [4.0, 0.0, 2.0], [0.0, 8.0, -2.0], 0.0);
+        assert_eq!(part.data.inverted_axes(), [true, false, true]);
+    }
+
+    #[test]
+    fn negative_inflate_round_trips() {
+        let (element, part) = round_trip(cube([0.0, 0.0, 0.0], [4.0, 4.0, 1.0], -1.0));
+        let ElementType::Cube { inflate, .. } = element.extra else { panic!("{element:?} isn't a cube") };
+        assert_eq!(inflate, Some(-1.0));
+        assert_cube(&part, [0.0, 0.0, 0.0], [4.0, 4.0, 1.0], -1.0);
+        // deflating the 1-wide axis by 1 on each side turns it inside out
+        assert_eq!(part.data.cube_corners(), Some(([1.0, 1.0, 1.0], [3.0, 3.0, 0.0])));
+        assert_eq!(part.data.inverted_axes(), [false, false, true]);
+    }
+
+    #[test]
+    fn inflate_shrinks_inverted_axes() {
+        let (_, part) = round_trip(cube([2.0, 0.0, 0.0], [0.0, 2.0, 2.0], 0.5));
+        assert_cube(&part, [2.0, 0.0, 0.0], [0.0, 2.0, 2.0], 0.5);
+        assert_eq!(part.data.cube_corners(), Some(([1.5, -0.5, -0.5], [0.5, 2.5, 2.5])));
+        assert_eq!(part.data.inverted_axes(), [true, false, false]);
+    }
+
+    #[test]
+    fn polygons_resolve_uuid_textures() {
+        let (mut element, _) = round_trip(cube([0.0, 0.0, 0.0], [1.0, 1.0, 1.0], 0.0));
+        let ElementType::Cube { faces, .. } = &mut element.extra else { panic!("{element:?} isn't a cube") };
+        let uv = moon::UvRect::pixels(0.0, 0.0, 1.0, 1.0);
+        faces.north = Some(Face { uv, texture: FaceTexture::Uuid("skin".into()), rotation: 0 });
+        let textures = [Texture::new("cape".into(), 0, "cape".into()), Texture::new("skin".into(), 1, "skin".into())];
+        let sizes = [Some((16.0, 16.0)); 2];
+        let polygons = element.polygons(&textures, &sizes);
+        assert_eq!(polygons.iter().map(|p| p.texture).collect::<Vec<_>>(), [1]);
+        // a UUID that matches nothing draws nothing
+        assert!(element.polygons(&textures[..1], &sizes).is_empty());
+    }
+
+    #[test]
+    fn skin_projects_draw_faces_with_the_skin() {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/pack/basic/model.bbmodel");
+        let mut json: Value = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        json["meta"]["model_format"] = "skin".into();
+        for element in json["elements"].as_array_mut().unwrap() {
+            for face in element["faces"].as_object_mut().unwrap().values_mut() {
+                face["texture"] = Value::Null;
+            }
+        }
+        let mut model = BBModel::from_json(json).unwrap();
+        let (part, _) = compile(&model).unwrap();
+        let names: Vec<_> = part.chld.iter().map(|part| part.name.as_str()).collect();
+        assert_eq!(names, ["Head", "MOVE_RightArm", "Body", "RightArm", "LeftArm", "RightLeg", "LeftLeg"]);
+        let cube = &part.chld[0].chld[0];
+        let ModelData::Cube { cube_data, .. } = &cube.data else { panic!("{cube:?} isn't a cube") };
+        let sides = [&cube_data.n, &cube_data.e, &cube_data.s, &cube_data.w, &cube_data.u, &cube_data.d];
+        assert!(sides.iter().all(|face| face.as_ref().is_some_and(|face| face.tex == 0)), "{cube_data:?}");
+        assert!(model.expand_skin_template());
+        let ElementType::Cube { faces, .. } = &model.elements[0].extra else { panic!() };
+        assert_eq!(faces.north.as_ref().unwrap().texture, FaceTexture::Index(0));
+    }
+}
@@ -0,0 +1,92 @@
+//! [Atlasing][crate::atlas] a Blockbench model's textures, as [Moon::atlas_textures] does for
+//! moons.
+//!
+//! [Moon::atlas_textures]: crate::Moon::atlas_textures
+
+use std::collections::{HashMap, HashSet};
+use crate::atlas::{self, within};
+use super::{BBModel, CompileError, ElementType, FaceTexture, Faces};
+
+impl BBModel {
+    /// Packs the model's textures into one atlas named `name`, and moves every face's UVs to
+    /// match. UVs are first [converted to image pixels][Self::normalize_uvs]. The atlas replaces
+    /// the first texture merged into it, keeping its UUID, and the others are removed. Layered
+    /// textures are [flattened][super::Texture::flattened_source]; textures that aren't embedded,
+    /// or that have faces with UVs outside the image, are left alone. Returns how many textures
+    /// were merged, which is 0 if there weren't at least two that could be.
+    pub fn atlas_textures(&mut self, name: &str) -> Result<usize, CompileError> {
+        self.normalize_uvs();
+        let mut candidates = HashMap::new();
+        for (i, texture) in self.textures.iter().enumerate() {
+            let Some((width, height)) = texture.image_size() else { continue };
+            let Ok(png) = texture.flattened_source() else { continue };
+            candidates.insert(i, ((width as u32, height as u32), png));
+        }
+        let mut excluded = HashSet::new();
+        for element in &self.elements {
+            match &element.extra {
+                ElementType::Cube { faces, .. } => {
+                    let Faces { north, east, south, west, up, down } = faces;
+                    for face in [north, east, south, west, up, down].into_iter().flatten() {
+                        let Some(tex) = face.texture.resolve(&self.textures) else { continue };
+                        let inside = |size| within([face.uv.x0, face.uv.y0], size) && within([face.uv.x1, face.uv.y1], size);
+                        if !candidates.get(&tex).is_some_and(|(size, _)| inside(*size)) {
+                            excluded.insert(tex);
+                        }
+                    }
+                }
+                ElementType::Mesh { faces, .. } => for face in faces.values() {
+                    let Some(tex) = face.texture else { continue };
+                    if !candidates.get(&tex).is_some_and(|(size, _)| face.uv.values().all(|&uv| within(uv, *size))) {
+                        excluded.insert(tex);
+                    }
+                },
+                ElementType::Locator { .. } | ElementType::NullObject { .. } | ElementType::TextureMesh { .. } => {}
+            }
+        }
+        let mut merged: Vec<usize> = candidates.keys().copied().filter(|i| !excluded.contains(i)).collect();
+        merged.sort();
+        if merged.len() < 2 {
+            return Ok(0);
+        }
+        let pngs: Vec<&[u8]> = merged.iter().map(|i| candidates[i].1.as_slice()).collect();
+        let packed = atlas::pack(&pngs).map_err(|e| CompileError::InvalidTexture(name.into(), e))?;
+        let offsets: HashMap<usize, (f64, f64)> = merged.iter().zip(&packed.offsets).map(|(&i, &(x, y))| (i, (x as f64, y as f64))).collect();
+
+        // point every merged face at the first merged texture, which becomes the atlas
+        let first = merged[0];
+        let textures = &self.textures;
+        for element in &mut self.elements {
+            match &mut element.extra {
+                ElementType::Cube { faces, .. } => {
+                    let Faces { north, east, south, west, up, down } = faces;
+                    for face in [north, east, south, west, up, down].into_iter().flatten() {
+                        let Some(&(x, y)) = face.texture.resolve(textures).and_then(|tex| offsets.get(&tex)) else { continue };
+                        face.uv = [face.uv.x0 + x, face.uv.y0 + y, face.uv.x1 + x, face.uv.y1 + y].into();
+                        face.texture = FaceTexture::Index(first);
+                    }
+                }
+                ElementType::Mesh { faces, .. } => for face in faces.values_mut() {
+                    let Some(&(x, y)) = face.texture.and_then(|tex| offsets.get(&tex)) else { continue };
+                    for [u, v] in face.uv.values_mut() {
+                        (*u, *v) = (*u + x, *v + y);
+                    }
+                    face.texture = Some(first);
+                },
+                ElementType::Locator { .. } | ElementType::NullObject { .. } | ElementType::TextureMesh { .. } => {}
+            }
+        }
+
+        let atlas = &mut self.textures[first];
+        atlas.name = format!("{name}.png");
+        atlas.path = String::new();
+        atlas.relative_path = None;
+        atlas.layers = None;
+        atlas.layers_enabled = false;
+        atlas.embed(&packed.png);
+        for &i in merged[1..].iter().rev() {
+            self.remove_texture(i);
+        }
+        Ok(merged.len())
+    }
+}
@@ -0,0 +1,287 @@
+//! A builder for Blockbench models, so that code can produce a `.bbmodel` without filling in
+//! every editor field by hand.
+//!
+//! Groups and elements are placed by the path of their parent group, with names separated by `/`
+//! and `""` meaning the top of the outliner. UUIDs are derived from each node's path, so building
+//! the same model twice gives identical files.
+//!
+//! ```no_run
+//! # use fia::bbmodel::builder::BBModelBuilder;
+//! # use fia::moon::{Face, Sided};
+//! # let png = vec![];
+//! let face = || Some(Face { tex: 0, uv: [0.0, 0.0, 8.0, 8.0].into(), rot: 0.0 });
+//! let model = BBModelBuilder::new("hat")
+//!     .texture("hat", &png)
+//!     .group("", "Head", [0.0, 24.0, 0.0])
+//!     .cube("Head", "brim", [-6.0, 32.0, -6.0], [6.0, 33.0, 6.0], Sided { n: face(), s: face(), u: face(), d: face(), w: face(), e: face() })
+//!     .build()
+//!     .expect("references are valid");
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use serde_json::Map;
+use thiserror::Error;
+use crate::animation::BuiltAnimation;
+use crate::moon::{self, MeshData, Sided};
+use super::{
+    animation_from_moon, convert_face, derived_uuid, mesh_from_moon, part_keyframes, Animator,
+    BBModel, Element, ElementType, Faces, FormatVersion, Group, Meta, OutlinerItem, Resolution,
+    Texture,
+};
+
+/// An error caught by [BBModelBuilder::build].
+#[derive(Debug, Error)]
+pub enum BuildError {
+    /// A node was added to a group that doesn't exist.
+    #[error("no group at {0:?}")]
+    MissingParent(String),
+    /// Two nodes have the same path.
+    #[error("more than one node at {0:?}")]
+    DuplicatePath(String),
+    /// A face uses a texture that wasn't added.
+    #[error("{path:?} uses texture {texture}, but there are only {count}")]
+    MissingTexture {
+        /// The path of the element.
+        path: String,
+        /// The texture index the face refers to.
+        texture: usize,
+        /// How many textures there are.
+        count: usize,
+    },
+    /// An animation moves a node that doesn't exist.
+    #[error("animation {animation:?} moves {path:?}, which doesn't exist")]
+    MissingAnimatedPart {
+        /// The animation's name.
+        animation: String,
+        /// The path of the missing node.
+        path: String,
+    },
+}
+
+/// A node's parent path, its own path, and for groups, the name, origin, rotation, and UUID.
+type Entry = (String, String, Option<(String, [f64; 3], [f64; 3], String)>);
+
+#[derive(Debug)]
+struct Node {
+    parent: String,
+    name: String,
+    origin: [f64; 3],
+    rotation: [f64; 3],
+    /// The element's geometry, or [None] for groups.
+    element: Option<ElementType>,
+}
+
+/// Builds a [BBModel] node by node. Positions and rotations are in
+/// [Blockbench space][crate::moon::space].
+#[derive(Debug)]
+pub struct BBModelBuilder {
+    name: String,
+    resolution: Option<(usize, usize)>,
+    textures: Vec<Texture>,
+    nodes: Vec<Node>,
+    animations: Vec<BuiltAnimation>,
+}
+
+impl BBModelBuilder {
+    /// Starts an empty model with the given name, which becomes its part's name in a moon.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self { name: name.into(), resolution: None, textures: vec![], nodes: vec![], animations: vec![] }
+    }
+
+    /// Sets the project's UV resolution. By default, this is the size of the first texture, or
+    /// 64×64 if there are none. Each texture's UVs are in its own pixels regardless.
+    pub fn resolution(mut self, width: usize, height: usize) -> Self {
+        self.resolution = Some((width, height));
+        self
+    }
+
+    /// Adds a texture, embedding the given PNG. Faces refer to textures by the order they were
+    /// added in, starting from 0.
+    pub fn texture(mut self, name: &str, png: &[u8]) -> Self {
+        let index = self.textures.len();
+        let mut texture = Texture::new(format!("{name}.png"), index, derived_uuid(&format!("{}/texture{index}", self.name)));
+        texture.embed(png);
+        self.textures.push(texture);
+        self
+    }
+
+    /// Adds a group inside the group at `parent`.
+    pub fn group(self, parent: &str, name: &str, origin: [f64; 3]) -> Self {
+        self.rotated_group(parent, name, origin, [0.0; 3])
+    }
+
+    /// Adds a rotated group inside the group at `parent`.
+    pub fn rotated_group(mut self, parent: &str, name: &str, origin: [f64; 3], rotation: [f64; 3]) -> Self {
+        self.nodes.push(Node { parent: parent.into(), name: name.into(), origin, rotation, element: None });
+        self
+    }
+
+    /// Adds a cube inside the group at `parent`, pivoting around its center.
+    pub fn cube(mut self, parent: &str, name: &str, from: [f64; 3], to: [f64; 3], faces: Sided<moon::Face>) -> Self {
+        let origin = std::array::from_fn(|i| (from[i] + to[i]) / 2.0);
+        self.nodes.push(Node {
+            parent: parent.into(),
+            name: name.into(),
+            origin,
+            rotation: [0.0; 3],
+            element: Some(ElementType::Cube {
+                from,
+                to,
+                uv_offset: None,
+                faces: Faces {
+                    north: convert_face(&faces.n),
+                    east:  convert_face(&faces.e),
+                    south: convert_face(&faces.s),
+                    west:  convert_face(&faces.w),
+                    up:    convert_face(&faces.u),
+                    down:  convert_face(&faces.d),
+                },
+                box_uv: Some(false.into()),
+                rescale: false,
+                autouv: 0,
+                light_emission: None,
+                mirror_uv: None,
+                inflate: None,
+                shade: None,
+            }),
+        });
+        self
+    }
+
+    /// Adds a mesh inside the group at `parent`. The mesh's vertices are absolute positions, as in
+    /// a moon; `origin` is only its pivot.
+    pub fn mesh(mut self, parent: &str, name: &str, origin: [f64; 3], mesh: &MeshData) -> Self {
+        self.nodes.push(Node {
+            parent: parent.into(),
+            name: name.into(),
+            origin,
+            rotation: [0.0; 3],
+            element: Some(mesh_from_moon(mesh, origin)),
+        });
+        self
+    }
+
+    /// Adds a locator inside the group at `parent`, marking a point for scripts to attach things
+    /// to.
+    pub fn locator(mut self, parent: &str, name: &str, position: [f64; 3]) -> Self {
+        self.nodes.push(Node {
+            parent: parent.into(),
+            name: name.into(),
+            origin: position,
+            rotation: [0.0; 3],
+            element: Some(ElementType::Locator { position, ignore_inherited_scale: false }),
+        });
+        self
+    }
+
+    /// Adds an animation. Its channels' paths start with the model's name, as with
+    /// [AnimationBuilder::channel][crate::animation::AnimationBuilder::channel].
+    pub fn animation(mut self, animation: BuiltAnimation) -> Self {
+        self.animations.push(animation);
+        self
+    }
+
+    /// Finishes the model, checking that every parent group, texture, and animated node exists.
+    pub fn build(self) -> Result<BBModel, BuildError> {
+        let Self { name, resolution, textures, nodes, animations } = self;
+        let mut uuids = HashMap::new();
+        let mut groups = HashSet::from([String::new()]);
+        let paths: Vec<String> = nodes.iter()
+            .map(|node| if node.parent.is_empty() { node.name.clone() } else { format!("{}/{}", node.parent, node.name) })
+            .collect();
+        for (node, path) in nodes.iter().zip(&paths) {
+            if uuids.insert(path.clone(), derived_uuid(&format!("{name}/{path}"))).is_some() {
+                return Err(BuildError::DuplicatePath(path.clone()));
+            }
+            if node.element.is_none() {
+                groups.insert(path.clone());
+            }
+        }
+        let mut entries: Vec<Entry> = vec![];
+        let mut elements = vec![];
+        for (node, path) in nodes.into_iter().zip(paths) {
+            if !groups.contains(&node.parent) {
+                return Err(BuildError::MissingParent(node.parent));
+            }
+            let uuid = uuids[&path].clone();
+            let Node { parent, name, origin, rotation, element } = node;
+            let Some(extra) = element else {
+                entries.push((parent, path, Some((name, origin, rotation, uuid))));
+                continue;
+            };
+            let used: Vec<usize> = match &extra {
+                ElementType::Cube { faces, .. } => [&faces.north, &faces.east, &faces.south, &faces.west, &faces.up, &faces.down]
+                    .into_iter().filter_map(|f| f.as_ref()?.texture.index()).collect(),
+                ElementType::Mesh { faces, .. } => faces.values().filter_map(|f| f.texture).collect(),
+                ElementType::Locator { .. } | ElementType::NullObject { .. } | ElementType::TextureMesh { .. } => vec![],
+            };
+            if let Some(&texture) = used.iter().find(|&&t| t >= textures.len()) {
+                return Err(BuildError::MissingTexture { path, texture, count: textures.len() });
+            }
+            elements.push(Element {
+                origin,
+                name,
+                uuid: uuid.clone(),
+                visibility: Some(true),
+                locked: false,
+                render_order: None,
+                allow_mirror_modeling: true,
+                export: Some(true),
+                color: 0,
+                rotation,
+                extra,
+            });
+            entries.push((parent, path, None));
+        }
+        fn outliner(entries: &[Entry], uuids: &HashMap<String, String>, parent: &str) -> Vec<OutlinerItem> {
+            entries.iter().filter(|(p, ..)| p == parent).map(|(_, path, group)| match group {
+                None => OutlinerItem::Element(uuids[path].clone()),
+                Some((name, origin, rotation, uuid)) => OutlinerItem::Group(Group {
+                    name: name.clone(),
+                    origin: *origin,
+                    rotation: *rotation,
+                    color: 0,
+                    uuid: uuid.clone(),
+                    export: true,
+                    visibility: true,
+                    children: outliner(entries, uuids, path),
+                    extra: Map::new(),
+                }),
+            }).collect()
+        }
+        let outliner = outliner(&entries, &uuids, "");
+        let prefix = format!("{name}/");
+        let animations = animations.into_iter().enumerate().map(|(i, built)| {
+            let mut animators = HashMap::new();
+            for (part, channels) in &built.parts {
+                let path = part.strip_prefix(&prefix).unwrap_or(part);
+                let uuid = uuids.get(path).ok_or_else(|| BuildError::MissingAnimatedPart {
+                    animation: built.animation.name.clone(),
+                    path: part.clone(),
+                })?;
+                let node_name = path.rsplit('/').next().unwrap_or(path);
+                let keyframes = part_keyframes(channels, &format!("{name}/{path}/anim{i}"));
+                animators.insert(uuid.clone(), Animator { r#type: "bone".into(), name: node_name.into(), keyframes });
+            }
+            Ok(animation_from_moon(&built.animation, animators, derived_uuid(&format!("{name}/animation{i}"))))
+        }).collect::<Result<_, BuildError>>()?;
+        let (width, height) = resolution
+            .or_else(|| textures.iter().find(|t| t.width != 0 && t.height != 0).map(|t| (t.width, t.height)))
+            .unwrap_or((64, 64));
+        Ok(BBModel {
+            meta: Meta {
+                format_version: FormatVersion::default(),
+                model_format: "free".into(),
+                box_uv: false,
+                creation_time: None,
+            },
+            name: Some(name),
+            resolution: Resolution { width, height },
+            textures,
+            elements,
+            outliner,
+            animations,
+            ..Default::default()
+        })
+    }
+}
@@ -0,0 +1,115 @@
+//! Serialization in Blockbench's own key order. [BBModel]'s fields and the `extra` maps on its
+//! parts don't come out in the order Blockbench writes them, so a model saved by fia and then
+//! re-saved by Blockbench (or the other way around) shows every key as moved. Writing each object
+//! with Blockbench's keys first, in its order, and any others sorted after them keeps diffs down
+//! to what actually changed. Like Blockbench, the output has no whitespace.
+
+use std::io::{self, Write};
+use serde_json::Value;
+use super::BBModel;
+
+const ROOT: &[&str] = &[
+    "meta", "name", "model_identifier", "visible_box", "variable_placeholders",
+    "variable_placeholder_buttons", "timeline_setups", "unhandled_root_fields", "resolution",
+    "elements", "outliner", "collections", "textures", "texture_groups", "animations",
+    "animation_variable_placeholders", "reference_images", "export_options", "activity_tracker",
+];
+const META: &[&str] = &["format_version", "model_format", "box_uv", "creation_time"];
+const RESOLUTION: &[&str] = &["width", "height"];
+const ELEMENT: &[&str] = &[
+    "name", "box_uv", "rescale", "locked", "light_emission", "render_order", "allow_mirror_modeling",
+    "from", "to", "autouv", "color", "inflate", "origin", "rotation", "position", "uv_offset",
+    "mirror_uv", "export", "visibility", "vertices", "faces", "type", "uuid",
+];
+const SIDES: &[&str] = &["north", "east", "south", "west", "up", "down"];
+const FACE: &[&str] = &["uv", "vertices", "rotation", "texture"];
+const GROUP: &[&str] = &[
+    "name", "origin", "rotation", "color", "uuid", "export", "mirror_uv", "isOpen", "locked",
+    "visibility", "autouv", "selected", "children",
+];
+const TEXTURE: &[&str] = &[
+    "path", "name", "folder", "namespace", "id", "group", "width", "height", "uv_width",
+    "uv_height", "particle", "use_as_default", "layers_enabled", "sync_to_project", "render_mode",
+    "render_sides", "frame_time", "frame_order_type", "frame_interpolate", "visible", "internal",
+    "saved", "uuid", "relative_path", "source",
+];
+const ANIMATION: &[&str] = &[
+    "uuid", "name", "loop", "override", "length", "snapping", "selected", "anim_time_update",
+    "blend_weight", "start_delay", "loop_delay", "animators",
+];
+const ANIMATOR: &[&str] = &["name", "type", "keyframes"];
+const KEYFRAME: &[&str] = &[
+    "channel", "data_points", "uuid", "time", "color", "interpolation", "bezier_linked",
+    "bezier_left_time", "bezier_left_value", "bezier_right_time", "bezier_right_value",
+];
+const XYZ: &[&str] = &["x", "y", "z"];
+
+/// The keys Blockbench writes first in the object at `path`, in its order.
+fn key_order(path: &[&str]) -> &'static [&'static str] {
+    match path {
+        [] => ROOT,
+        ["meta"] => META,
+        ["resolution"] => RESOLUTION,
+        ["elements", _] => ELEMENT,
+        ["elements", _, "faces"] => SIDES,
+        ["elements", _, "faces", _] => FACE,
+        ["outliner", ..] => GROUP,
+        ["textures", _] => TEXTURE,
+        ["animations", _] => ANIMATION,
+        ["animations", _, "animators", _] => ANIMATOR,
+        ["animations", _, "animators", _, "keyframes", _] => KEYFRAME,
+        [.., "data_points", _] => XYZ,
+        _ => &[],
+    }
+}
+
+fn write_value<'a>(out: &mut impl Write, value: &'a Value, path: &mut Vec<&'a str>) -> io::Result<()> {
+    match value {
+        Value::Object(object) => {
+            let order = key_order(path);
+            let mut keys: Vec<&String> = object.keys().collect();
+            keys.sort_by_key(|key| (order.iter().position(|k| k == key).unwrap_or(order.len()), *key));
+            out.write_all(b"{")?;
+            for (i, key) in keys.into_iter().enumerate() {
+                if i > 0 {
+                    out.write_all(b",")?;
+                }
+                serde_json::to_writer(&mut *out, key)?;
+                out.write_all(b":")?;
+                path.push(key);
+                write_value(out, &object[key], path)?;
+                path.pop();
+            }
+            out.write_all(b"}")
+        }
+        Value::Array(items) => {
+            out.write_all(b"[")?;
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.write_all(b",")?;
+                }
+                // indices don't affect key order, so they all share one placeholder
+                path.push("");
+                write_value(out, item, path)?;
+                path.pop();
+            }
+            out.write_all(b"]")
+        }
+        value => Ok(serde_json::to_writer(out, value)?),
+    }
+}
+
+impl BBModel {
+    /// Serializes this model with Blockbench's key order; see [the module documentation][self].
+    pub fn write_canonical(&self, out: &mut impl Write) -> serde_json::Result<()> {
+        let value = serde_json::to_value(self)?;
+        write_value(out, &value, &mut vec![]).map_err(serde_json::Error::io)
+    }
+
+    /// Like [write_canonical][Self::write_canonical], but returns the bytes.
+    pub fn to_canonical_vec(&self) -> serde_json::Result<Vec<u8>> {
+        let mut out = vec![];
+        self.write_canonical(&mut out)?;
+        Ok(out)
+    }
+}
@@ -0,0 +1,115 @@
+//! Upgrades for `.bbmodel` files saved by Blockbench 3.x, whose JSON doesn't quite match the
+//! structs in [super]. [BBModel::from_json][super::BBModel::from_json] runs [upgrade] before
+//! deserializing, so callers never see the old layouts. Fields that 3.x simply didn't have yet
+//! are defaulted by the structs themselves.
+//!
+//! As with [moon::compat][crate::moon::compat], each upgrade recognizes an old layout by its
+//! shape and leaves current layouts alone.
+
+use serde_json::{Map, Value};
+use super::{BBModel, ElementType};
+
+/// Rewrites any old layouts in a model's JSON into the current one, returning a short description
+/// of each upgrade that changed something.
+pub fn upgrade(json: &mut Value) -> Vec<&'static str> {
+    let mut applied = vec![];
+    let upgrades: [(&'static str, fn(&mut Value) -> bool); 3] = [
+        ("copied the project's box_uv setting to each cube", cube_box_uv),
+        ("renamed animation bones to animators", animation_bones),
+        ("converted boolean loop modes to names", loop_modes),
+    ];
+    for (description, upgrade) in upgrades {
+        if upgrade(json) {
+            applied.push(description);
+        }
+    }
+    applied
+}
+
+/// Whether [upgrade] would have changed the JSON `model` was read from, judging by what each old
+/// layout looks like once deserialized as it is. Layouts that don't deserialize at all, such as
+/// animations with `bones`, need no check here.
+pub fn needs_upgrade(model: &BBModel) -> bool {
+    model.meta.format_version.is_legacy()
+        // cube_box_uv
+        || model.elements.iter().any(|e| matches!(e.extra, ElementType::Cube { box_uv: None, .. }))
+        // loop_modes
+        || model.animations.iter().any(|a| matches!(a.r#loop, Some(Value::Bool(_))))
+}
+
+/// Whether a format version predates Blockbench 4.0. Versions that can't be read as a number are
+/// assumed to be current.
+pub fn is_legacy(format_version: &str) -> bool {
+    format_version.split('.').next().and_then(|major| major.parse::<u32>().ok()).is_some_and(|major| major < 4)
+}
+
+/// 3.x only had a project-wide Box UV setting; 4.0 moved it onto each cube.
+fn cube_box_uv(json: &mut Value) -> bool {
+    let box_uv = json.pointer("/meta/box_uv").cloned().unwrap_or(Value::Bool(false));
+    let Some(Value::Array(elements)) = json.get_mut("elements") else { return false };
+    let mut changed = false;
+    for element in elements {
+        let Value::Object(element) = element else { continue };
+        if element.get("type").and_then(Value::as_str).unwrap_or("cube") == "cube" && !element.contains_key("box_uv") {
+            element.insert("box_uv".into(), box_uv.clone());
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// Early animations keyed their animators by bone name, under `bones`, instead of by the UUID of
+/// the group they move.
+fn animation_bones(json: &mut Value) -> bool {
+    fn group_uuids(items: &[Value], uuids: &mut Map<String, Value>) {
+        for item in items {
+            let Value::Object(group) = item else { continue };
+            if let (Some(Value::String(name)), Some(uuid)) = (group.get("name"), group.get("uuid")) {
+                uuids.entry(name.clone()).or_insert_with(|| uuid.clone());
+            }
+            if let Some(Value::Array(children)) = group.get("children") {
+                group_uuids(children, uuids);
+            }
+        }
+    }
+    let mut uuids = Map::new();
+    if let Some(Value::Array(outliner)) = json.get("outliner") {
+        group_uuids(outliner, &mut uuids);
+    }
+    let Some(Value::Array(animations)) = json.get_mut("animations") else { return false };
+    let mut changed = false;
+    for animation in animations {
+        let Value::Object(animation) = animation else { continue };
+        if animation.contains_key("animators") {
+            continue;
+        }
+        let Some(Value::Object(bones)) = animation.remove("bones") else { continue };
+        let mut animators = Map::new();
+        for (name, mut bone) in bones {
+            if let Value::Object(bone) = &mut bone {
+                bone.entry("name").or_insert_with(|| name.clone().into());
+                bone.entry("type").or_insert_with(|| "bone".into());
+            }
+            // bones without a matching group keep their name as the key, and are ignored later
+            let key = uuids.get(&name).and_then(Value::as_str).map_or(name, str::to_owned);
+            animators.insert(key, bone);
+        }
+        animation.insert("animators".into(), Value::Object(animators));
+        changed = true;
+    }
+    changed
+}
+
+/// Early animations stored whether they loop as a boolean.
+fn loop_modes(json: &mut Value) -> bool {
+    let Some(Value::Array(animations)) = json.get_mut("animations") else { return false };
+    let mut changed = false;
+    for animation in animations {
+        let Some(mode) = animation.get_mut("loop") else { continue };
+        if let Value::Bool(looping) = mode {
+            *mode = Value::String(if *looping { "loop" } else { "once" }.into());
+            changed = true;
+        }
+    }
+    changed
+}
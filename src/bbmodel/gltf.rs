@@ -0,0 +1,245 @@
+//! Exporting models as binary glTF 2.0 (`.glb`) files, which standard 3D viewers and web pages can
+//! show without Blockbench.
+//!
+//! Each group and element becomes a node, placed relative to its parent's pivot and rotated around
+//! its own, so the node tree matches the outliner. Positions are converted from pixels to blocks
+//! (one block is one glTF meter), and otherwise kept as they appear in Blockbench. Every face of
+//! an element is a primitive using its texture's material; textures are embedded as PNGs and
+//! sampled without filtering, as in Minecraft.
+
+use std::collections::BTreeMap;
+use serde_json::{json, Value};
+use crate::image::{self, ImageError};
+use crate::moon::{space, Textures};
+use super::{Element, Hierarchy, OutlinerItem};
+
+const MAGIC: u32 = 0x4654_6C67;
+const JSON_CHUNK: u32 = 0x4E4F_534A;
+const BIN_CHUNK: u32 = 0x004E_4942;
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+const NEAREST: u32 = 9728;
+const CLAMP_TO_EDGE: u32 = 33071;
+
+/// The triangles drawn with one texture.
+#[derive(Default)]
+struct Primitive {
+    positions: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    indices: Vec<u32>,
+}
+
+impl Primitive {
+    /// Adds a convex polygon, given as corners in counterclockwise order as seen from its front.
+    fn polygon(&mut self, corners: &[([f64; 3], [f64; 2])]) {
+        let base = self.positions.len() as u32;
+        for &(pos, uv) in corners {
+            self.positions.push(space::to_blocks(pos).map(|c| c as f32));
+            self.uvs.push(uv.map(|c| c as f32));
+        }
+        for i in 1..corners.len().saturating_sub(1) as u32 {
+            self.indices.extend([base, base + i, base + i + 1]);
+        }
+    }
+}
+
+/// A texture that faces can use: the size of its image, and the indices of its materials.
+#[derive(Clone, Copy)]
+struct Material {
+    size: (f64, f64),
+    culled: usize,
+    double_sided: usize,
+}
+
+#[derive(Default)]
+struct Glb {
+    bin: Vec<u8>,
+    buffer_views: Vec<Value>,
+    accessors: Vec<Value>,
+    meshes: Vec<Value>,
+    nodes: Vec<Value>,
+    materials: Vec<Option<Material>>,
+}
+
+impl Glb {
+    fn view(&mut self, bytes: &[u8], target: Option<u32>) -> usize {
+        while self.bin.len() % 4 != 0 {
+            self.bin.push(0);
+        }
+        let mut view = json!({ "buffer": 0, "byteOffset": self.bin.len(), "byteLength": bytes.len() });
+        if let Some(target) = target {
+            view["target"] = target.into();
+        }
+        self.bin.extend_from_slice(bytes);
+        self.buffer_views.push(view);
+        self.buffer_views.len() - 1
+    }
+
+    fn accessor(&mut self, bytes: &[u8], target: u32, component: u32, count: usize, kind: &str) -> usize {
+        let view = self.view(bytes, Some(target));
+        self.accessors.push(json!({ "bufferView": view, "componentType": component, "count": count, "type": kind }));
+        self.accessors.len() - 1
+    }
+
+    fn mesh(&mut self, name: &str, primitives: BTreeMap<usize, Primitive>) -> Option<usize> {
+        let primitives: Vec<Value> = primitives.into_iter().filter(|(_, p)| !p.indices.is_empty()).map(|(material, p)| {
+            let bytes: Vec<u8> = p.positions.iter().flatten().flat_map(|c| c.to_le_bytes()).collect();
+            let position = self.accessor(&bytes, ARRAY_BUFFER, FLOAT, p.positions.len(), "VEC3");
+            // glTF requires the bounds of positions
+            let min: [f32; 3] = std::array::from_fn(|i| p.positions.iter().map(|v| v[i]).fold(f32::INFINITY, f32::min));
+            let max: [f32; 3] = std::array::from_fn(|i| p.positions.iter().map(|v| v[i]).fold(f32::NEG_INFINITY, f32::max));
+            self.accessors[position]["min"] = json!(min);
+            self.accessors[position]["max"] = json!(max);
+            let bytes: Vec<u8> = p.uvs.iter().flatten().flat_map(|c| c.to_le_bytes()).collect();
+            let uv = self.accessor(&bytes, ARRAY_BUFFER, FLOAT, p.uvs.len(), "VEC2");
+            let bytes: Vec<u8> = p.indices.iter().flat_map(|i| i.to_le_bytes()).collect();
+            let indices = self.accessor(&bytes, ELEMENT_ARRAY_BUFFER, UNSIGNED_INT, p.indices.len(), "SCALAR");
+            json!({ "attributes": { "POSITION": position, "TEXCOORD_0": uv }, "indices": indices, "material": material })
+        }).collect();
+        if primitives.is_empty() {
+            return None;
+        }
+        self.meshes.push(json!({ "name": name, "primitives": primitives }));
+        Some(self.meshes.len() - 1)
+    }
+
+    fn node(&mut self, name: &str, origin: [f64; 3], rotation: [f64; 3], parent_origin: [f64; 3], children: Vec<usize>, mesh: Option<usize>) -> usize {
+        let offset: [f64; 3] = std::array::from_fn(|i| origin[i] - parent_origin[i]);
+        let mut node = json!({ "name": name, "translation": space::to_blocks(offset), "rotation": quaternion(rotation) });
+        if !children.is_empty() {
+            node["children"] = children.into();
+        }
+        if let Some(mesh) = mesh {
+            node["mesh"] = mesh.into();
+        }
+        self.nodes.push(node);
+        self.nodes.len() - 1
+    }
+
+    fn item(&mut self, item: &OutlinerItem, elements: &[Element], parent_origin: [f64; 3]) -> Option<usize> {
+        match item {
+            OutlinerItem::Group(group) => {
+                if !group.export || !group.visibility {
+                    return None;
+                }
+                let children = group.children.iter().filter_map(|child| self.item(child, elements, group.origin)).collect();
+                Some(self.node(&group.name, group.origin, group.rotation, parent_origin, children, None))
+            }
+            OutlinerItem::Element(uuid) => {
+                let element = elements.iter().find(|e| &e.uuid == uuid)?;
+                if element.export == Some(false) || element.visibility == Some(false) {
+                    return None;
+                }
+                let primitives = self.primitives(element);
+                let mesh = self.mesh(&element.name, primitives);
+                Some(self.node(&element.name, element.pivot(), element.rotation, parent_origin, vec![], mesh))
+            }
+        }
+    }
+
+    /// Builds an element's geometry relative to its origin, keyed by material.
+    fn primitives(&self, element: &Element) -> BTreeMap<usize, Primitive> {
+        let sizes: Vec<_> = self.materials.iter().map(|m| m.map(|m| m.size)).collect();
+        let mut primitives = BTreeMap::<usize, Primitive>::new();
+        // elements built from a moon always refer to textures by index
+        for polygon in element.polygons(&[], &sizes) {
+            let Some(material) = self.materials[polygon.texture] else { continue };
+            let material = if polygon.double_sided { material.double_sided } else { material.culled };
+            primitives.entry(material).or_default().polygon(&polygon.corners);
+        }
+        primitives
+    }
+}
+
+/// The quaternion, as `[x, y, z, w]`, for a rotation in [Blockbench space][space].
+fn quaternion(rotation: [f64; 3]) -> [f64; 4] {
+    let [(sx, cx), (sy, cy), (sz, cz)] = rotation.map(|a| (a.to_radians() / 2.0).sin_cos());
+    [
+        sx * cy * cz - cx * sy * sz,
+        cx * sy * cz + sx * cy * sz,
+        cx * cy * sz - sx * sy * cz,
+        cx * cy * cz + sx * sy * sz,
+    ]
+}
+
+/// Exports a model as a binary glTF file, with the moon textures its faces refer to embedded.
+/// Faces whose texture isn't in `textures` are left out, as are hidden and non-exported groups and
+/// elements.
+pub fn export_glb(hierarchy: &Hierarchy, textures: &Textures) -> Result<Vec<u8>, ImageError> {
+    let mut glb = Glb::default();
+    let mut images = vec![];
+    let mut gltf_textures = vec![];
+    let mut materials = vec![];
+    for data in textures.data.iter() {
+        let Some(png) = textures.src.get(&data.d) else {
+            glb.materials.push(None);
+            continue;
+        };
+        let (width, height) = image::dimensions(png.as_ref())?;
+        let view = glb.view(png.as_ref(), None);
+        images.push(json!({ "name": data.d, "bufferView": view, "mimeType": "image/png" }));
+        gltf_textures.push(json!({ "source": images.len() - 1, "sampler": 0 }));
+        let texture = gltf_textures.len() - 1;
+        for double_sided in [false, true] {
+            materials.push(json!({
+                "name": data.d,
+                "pbrMetallicRoughness": { "baseColorTexture": { "index": texture }, "metallicFactor": 0.0 },
+                "alphaMode": "MASK",
+                "doubleSided": double_sided,
+            }));
+        }
+        glb.materials.push(Some(Material {
+            size: (width as f64, height as f64),
+            culled: materials.len() - 2,
+            double_sided: materials.len() - 1,
+        }));
+    }
+    let children = hierarchy.outliner.iter().filter_map(|item| glb.item(item, &hierarchy.elements, [0.0; 3])).collect();
+    let root = glb.node(&hierarchy.name, [0.0; 3], [0.0; 3], [0.0; 3], children, None);
+
+    let Glb { mut bin, buffer_views, accessors, meshes, nodes, .. } = glb;
+    let sampled = !gltf_textures.is_empty();
+    let mut gltf = json!({
+        "asset": { "version": "2.0", "generator": concat!("fia ", env!("CARGO_PKG_VERSION")) },
+        "scene": 0,
+        "scenes": [{ "name": hierarchy.name, "nodes": [root] }],
+        "nodes": nodes,
+    });
+    let mut set = |key: &str, values: Vec<Value>| if !values.is_empty() {
+        gltf[key] = values.into();
+    };
+    set("meshes", meshes);
+    set("materials", materials);
+    set("textures", gltf_textures);
+    set("images", images);
+    set("accessors", accessors);
+    set("bufferViews", buffer_views);
+    if sampled {
+        gltf["samplers"] = json!([{ "magFilter": NEAREST, "minFilter": NEAREST, "wrapS": CLAMP_TO_EDGE, "wrapT": CLAMP_TO_EDGE }]);
+    }
+    while bin.len() % 4 != 0 {
+        bin.push(0);
+    }
+    if !bin.is_empty() {
+        gltf["buffers"] = json!([{ "byteLength": bin.len() }]);
+    }
+
+    let mut json = serde_json::to_vec(&gltf).expect("glTF JSON is always serializable");
+    while json.len() % 4 != 0 {
+        json.push(b' ');
+    }
+    let chunks = [(JSON_CHUNK, json), (BIN_CHUNK, bin)];
+    let length = 12 + chunks.iter().filter(|(_, data)| !data.is_empty()).map(|(_, data)| 8 + data.len()).sum::<usize>();
+    let mut out = Vec::with_capacity(length);
+    out.extend(MAGIC.to_le_bytes());
+    out.extend(2u32.to_le_bytes());
+    out.extend((length as u32).to_le_bytes());
+    for (kind, data) in chunks.into_iter().filter(|(_, data)| !data.is_empty()) {
+        out.extend((data.len() as u32).to_le_bytes());
+        out.extend(kind.to_le_bytes());
+        out.extend(data);
+    }
+    Ok(out)
+}
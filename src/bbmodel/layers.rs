@@ -0,0 +1,152 @@
+//! Texture layers, added in Blockbench 4.9. A texture with layers enabled keeps each layer as its
+//! own image, which Figura knows nothing about; [Texture::flattened_source] composites them into
+//! the single image that ends up in the moon.
+
+use serde::{Serialize, Deserialize};
+use serde_json::{Map, Value};
+use crate::image;
+use super::{png_data_url, decode_data_url, CompileError, Texture};
+
+/// One layer of a [Texture], as Blockbench saves it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TextureLayer {
+    /// The layer's name.
+    pub name: String,
+    /// Where the layer's top left corner is on the texture, in pixels.
+    pub offset: [i64; 2],
+    /// How much the layer is stretched along each axis.
+    pub scale: [f64; 2],
+    /// How opaque the layer is, from 0 to 100.
+    pub opacity: f64,
+    /// Whether the layer is shown. Hidden layers are left out when flattening.
+    pub visible: bool,
+    /// How the layer's colors combine with the ones below it: `default`, `multiply`, `screen`,
+    /// `add`, `difference`, or `set_opacity`.
+    pub blend_mode: String,
+    /// The layer's image, as a `data:` URL.
+    pub image_data: String,
+    /// Fields fia doesn't look at, such as the layer's UUID.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl Default for TextureLayer {
+    fn default() -> Self {
+        TextureLayer {
+            name: String::new(),
+            offset: [0, 0],
+            scale: [1.0, 1.0],
+            opacity: 100.0,
+            visible: true,
+            blend_mode: "default".into(),
+            image_data: String::new(),
+            extra: Map::new(),
+        }
+    }
+}
+
+/// Mixes a layer's color into the one below it, per channel, with colors from 0 to 1.
+fn blend(mode: &str, below: f64, above: f64) -> f64 {
+    match mode {
+        "multiply" => below * above,
+        "screen" => 1.0 - (1.0 - below) * (1.0 - above),
+        "add" => (below + above).min(1.0),
+        "difference" => (below - above).abs(),
+        _ => above,
+    }
+}
+
+/// Draws one pixel of a layer onto a pixel of the canvas.
+fn composite(below: &mut [u8], above: [u8; 4], opacity: f64, mode: &str) {
+    let [cb @ .., ab] = [below[0], below[1], below[2], below[3]].map(|c| c as f64 / 255.0);
+    let [cs @ .., a] = above.map(|c| c as f64 / 255.0);
+    let as_ = a * opacity;
+    if mode == "set_opacity" {
+        // keeps the colors below, but takes the layer's transparency
+        below[3] = (ab * as_ * 255.0).round() as u8;
+        return;
+    }
+    let ao = as_ + ab * (1.0 - as_);
+    if ao == 0.0 {
+        below.copy_from_slice(&[0; 4]);
+        return;
+    }
+    for i in 0..3 {
+        // the blended color only applies where there's something below to blend with
+        let mixed = (1.0 - ab) * cs[i] + ab * blend(mode, cb[i], cs[i]);
+        let co = as_ * mixed + ab * cb[i] * (1.0 - as_);
+        below[i] = (co / ao * 255.0).round() as u8;
+    }
+    below[3] = (ao * 255.0).round() as u8;
+}
+
+impl Texture {
+    /// This texture's layers, bottom first, or none if layers aren't enabled. Layers that can't be
+    /// read are skipped.
+    pub fn layers(&self) -> Vec<TextureLayer> {
+        if !self.layers_enabled {
+            return vec![];
+        }
+        match &self.layers {
+            Some(Value::Array(layers)) => layers.iter().filter_map(|layer| serde_json::from_value(layer.clone()).ok()).collect(),
+            _ => vec![],
+        }
+    }
+
+    /// The image Figura should get for this texture: the [embedded image][Self::decode_source],
+    /// or, if layers are enabled, its visible layers drawn over each other with their offsets,
+    /// scales, opacities, and blend modes.
+    pub fn flattened_source(&self) -> Result<Vec<u8>, CompileError> {
+        let layers = self.layers();
+        if layers.is_empty() {
+            return self.decode_source();
+        }
+        let invalid = |e| CompileError::InvalidTexture(self.name.clone(), e);
+        let (width, height) = match self.image_size() {
+            Some((width, height)) => (width as usize, height as usize),
+            None => {
+                let (width, height) = image::dimensions(&self.decode_source()?).map_err(invalid)?;
+                (width as usize, height as usize)
+            }
+        };
+        let mut canvas = vec![0; width * height * 4];
+        for layer in layers.iter().filter(|layer| layer.visible) {
+            let png = decode_data_url(&layer.image_data).ok_or_else(|| CompileError::MalformedTexture(self.name.clone()))?;
+            let (w, h, pixels) = image::decode_rgba(&png).map_err(invalid)?;
+            let [sx, sy] = layer.scale.map(|s| if s > 0.0 { s } else { 1.0 });
+            let scaled = ((w as f64 * sx).round() as i64, (h as f64 * sy).round() as i64);
+            // only the part of the layer that lands on the canvas, however large its scale
+            let visible = |offset: i64, size: i64, canvas: usize| {
+                offset.saturating_neg().max(0)..size.min((canvas as i64).saturating_sub(offset))
+            };
+            for dy in visible(layer.offset[1], scaled.1, height) {
+                let y = layer.offset[1] + dy;
+                for dx in visible(layer.offset[0], scaled.0, width) {
+                    let x = layer.offset[0] + dx;
+                    // nearest neighbor, as Blockbench draws pixel art
+                    let src_x = ((dx as f64 / sx) as usize).min(w as usize - 1);
+                    let src_y = ((dy as f64 / sy) as usize).min(h as usize - 1);
+                    let src = (src_y * w as usize + src_x) * 4;
+                    let dst = (y as usize * width + x as usize) * 4;
+                    let above = [pixels[src], pixels[src + 1], pixels[src + 2], pixels[src + 3]];
+                    composite(&mut canvas[dst..dst + 4], above, layer.opacity / 100.0, &layer.blend_mode);
+                }
+            }
+        }
+        image::encode_rgba(width as u32, height as u32, &canvas).map_err(invalid)
+    }
+
+    /// Replaces this texture's image with its [flattened layers][Self::flattened_source] and turns
+    /// layers off. Returns whether there were layers to flatten.
+    pub fn flatten_layers(&mut self) -> Result<bool, CompileError> {
+        if self.layers().is_empty() {
+            return Ok(false);
+        }
+        let png = self.flattened_source()?;
+        self.source = png_data_url(&png);
+        self.layers = None;
+        self.layers_enabled = false;
+        Ok(true)
+    }
+}
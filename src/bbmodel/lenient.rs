@@ -0,0 +1,177 @@
+//! Lenient parsing, for `.bbmodel` files that don't quite match the structs in [super]: plugins
+//! add their own fields, and some exporters write `null` for outliner entries or optional keys.
+//!
+//! Rather than mirroring every struct's fields, lenient parsing lets serde find the problems. The
+//! JSON is printed with one key per line, remembering which object each line belongs to, so that
+//! the line of a parse error leads back to the offending key. That key is then taken out of every
+//! object of the same kind, and parsing is retried until it succeeds or fails for some other
+//! reason.
+
+use std::collections::BTreeMap;
+use serde_json::Value;
+use super::{compat, BBModel};
+
+/// How strictly [BBModel::parse] treats files that don't match the format.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Strictness {
+    /// Reject unknown fields and misplaced `null`s, as [BBModel::from_json] does.
+    #[default]
+    Strict,
+    /// Set unknown fields aside, and drop `null` outliner entries and `null`s where a value has a
+    /// default.
+    Lenient,
+}
+
+/// Fields set aside by lenient parsing, keyed by their JSON pointer in the original file, such as
+/// `/elements/3/plugin_data`.
+pub type UnknownFields = BTreeMap<String, Value>;
+
+impl BBModel {
+    /// Deserializes a model, [upgrading][compat::upgrade] old layouts first. With
+    /// [Strictness::Lenient], fields the structs don't know are returned separately instead of
+    /// failing the parse, and can be put back with [restore_unknown].
+    pub fn parse(mut json: Value, strictness: Strictness) -> serde_json::Result<(Self, UnknownFields)> {
+        if strictness == Strictness::Strict {
+            return Ok((Self::from_json(json)?, UnknownFields::new()));
+        }
+        compat::upgrade(&mut json);
+        if let Some(outliner) = json.get_mut("outliner") {
+            strip_null_items(outliner);
+        }
+        let mut unknown = UnknownFields::new();
+        loop {
+            let printed = Printer::print(&json);
+            let error = match serde_json::from_str(&printed.text) {
+                Ok(model) => return Ok((model, unknown)),
+                Err(error) => error,
+            };
+            let Some(Some((pointer, key))) = printed.lines.get(error.line().wrapping_sub(1)) else { return Err(error) };
+            let message = error.to_string();
+            let path: Vec<String> = pointer.split('/').skip(1).map(unescape).collect();
+            let removed = if let Some(field) = message.strip_prefix("unknown field `").and_then(|m| m.split_once('`')) {
+                take_everywhere(&mut json, &path, "", field.0, &|_| true, &mut unknown)
+            } else if let (true, Some(key)) = (message.starts_with("invalid type: null"), key) {
+                take_everywhere(&mut json, &path, "", key, &Value::is_null, &mut UnknownFields::new())
+            } else {
+                false
+            };
+            if !removed {
+                return Err(error);
+            }
+        }
+    }
+}
+
+/// Puts fields set aside by lenient parsing back into a model's JSON, after serializing it. Fields
+/// whose object no longer exists (or has moved, such as elements removed before it) are skipped
+/// or land in the wrong place, so this is best used on models that haven't been restructured.
+pub fn restore_unknown(json: &mut Value, unknown: &UnknownFields) {
+    for (pointer, value) in unknown {
+        let Some((parent, key)) = pointer.rsplit_once('/') else { continue };
+        if let Some(Value::Object(object)) = json.pointer_mut(parent) {
+            object.entry(unescape(key)).or_insert_with(|| value.clone());
+        }
+    }
+}
+
+fn strip_null_items(items: &mut Value) {
+    let Value::Array(items) = items else { return };
+    items.retain(|item| !item.is_null());
+    for item in items {
+        if let Some(children) = item.get_mut("children") {
+            strip_null_items(children);
+        }
+    }
+}
+
+fn escape(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+fn unescape(key: &str) -> String {
+    key.replace("~1", "/").replace("~0", "~")
+}
+
+/// Removes `key` from the object at `path`, and from every object found by taking a different
+/// index wherever `path` goes through an array, if `take` accepts its value. Removed values are
+/// recorded in `out`. Returns whether anything was removed.
+fn take_everywhere(value: &mut Value, path: &[String], pointer: &str, key: &str, take: &dyn Fn(&Value) -> bool, out: &mut UnknownFields) -> bool {
+    match (value, path) {
+        (Value::Object(object), []) => {
+            if !object.get(key).is_some_and(take) {
+                return false;
+            }
+            let removed = object.remove(key).expect("key was just found");
+            out.insert(format!("{pointer}/{}", escape(key)), removed);
+            true
+        }
+        (Value::Array(items), [_, rest @ ..]) => {
+            let mut removed = false;
+            for (i, item) in items.iter_mut().enumerate() {
+                removed |= take_everywhere(item, rest, &format!("{pointer}/{i}"), key, take, out);
+            }
+            removed
+        }
+        (Value::Object(object), [next, rest @ ..]) => match object.get_mut(next) {
+            Some(child) => take_everywhere(child, rest, &format!("{pointer}/{}", escape(next)), key, take, out),
+            None => false,
+        },
+        _ => false,
+    }
+}
+
+/// JSON printed with one object key per line, for finding which key a parse error is about.
+#[derive(Default)]
+struct Printer {
+    text: String,
+    /// For each line, the pointer of the object whose key is on it or which it closes, and the
+    /// key if there is one.
+    lines: Vec<Option<(String, Option<String>)>>,
+    tag: Option<(String, Option<String>)>,
+}
+
+impl Printer {
+    fn print(json: &Value) -> Self {
+        let mut printer = Self::default();
+        printer.value(json, "");
+        printer.newline();
+        printer
+    }
+
+    fn newline(&mut self) {
+        self.text.push('\n');
+        self.lines.push(self.tag.take());
+    }
+
+    fn value(&mut self, value: &Value, pointer: &str) {
+        match value {
+            Value::Object(object) => {
+                self.text.push('{');
+                for (i, (key, value)) in object.iter().enumerate() {
+                    if i > 0 {
+                        self.text.push(',');
+                    }
+                    self.newline();
+                    self.tag = Some((pointer.into(), Some(key.clone())));
+                    self.text.push_str(&Value::String(key.clone()).to_string());
+                    self.text.push(':');
+                    self.value(value, &format!("{pointer}/{}", escape(key)));
+                }
+                self.newline();
+                self.tag = Some((pointer.into(), None));
+                self.text.push('}');
+            }
+            Value::Array(items) => {
+                self.text.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        self.text.push(',');
+                    }
+                    self.value(item, &format!("{pointer}/{i}"));
+                }
+                self.text.push(']');
+            }
+            value => self.text.push_str(&value.to_string()),
+        }
+    }
+}
@@ -0,0 +1,166 @@
+//! Combining several Blockbench models into one project, for avatar kits that ship a model per
+//! accessory.
+
+use std::collections::{HashMap, HashSet};
+use serde_json::Map;
+use super::{derived_uuid, BBModel, ElementType, FaceTexture, Faces, Group, OutlinerItem};
+
+/// Collects every UUID used by a model's groups, elements, textures, texture groups, animations,
+/// collections, and reference images.
+fn uuids(model: &BBModel) -> HashSet<String> {
+    fn visit(items: &[OutlinerItem], out: &mut HashSet<String>) {
+        for item in items {
+            if let OutlinerItem::Group(group) = item {
+                out.insert(group.uuid.clone());
+                visit(&group.children, out);
+            }
+        }
+    }
+    let mut out = HashSet::new();
+    out.extend(model.elements.iter().map(|e| e.uuid.clone()));
+    out.extend(model.textures.iter().map(|t| t.uuid.clone()));
+    out.extend(model.animations.iter().map(|a| a.uuid.clone()));
+    out.extend(model.collections.iter().map(|c| c.uuid.clone()));
+    out.extend(model.reference_images.iter().map(|r| r.uuid.clone()));
+    out.extend(model.texture_groups.iter().map(|g| g.uuid.clone()));
+    visit(&model.outliner, &mut out);
+    out
+}
+
+fn remap_outliner(items: &mut [OutlinerItem], remap: &impl Fn(&mut String)) {
+    for item in items {
+        match item {
+            OutlinerItem::Element(uuid) => remap(uuid),
+            OutlinerItem::Group(group) => {
+                remap(&mut group.uuid);
+                remap_outliner(&mut group.children, remap);
+            }
+        }
+    }
+}
+
+impl BBModel {
+    /// Adds another model's elements, textures and texture groups, outliner, animations,
+    /// collections, and reference images to this one.
+    ///
+    /// If `namespace` isn't empty, the other model's outliner goes inside a new group with that
+    /// name, keeping its parts apart from this model's. UUIDs the other model shares with this one
+    /// are replaced, as are texture names (by prefixing the namespace), and its faces are pointed
+    /// at its textures' new indices. Its textures keep their UV size even if the projects'
+    /// resolutions differ. Animations with the same name are combined into one, so that both
+    /// models' parts move together.
+    pub fn merge(&mut self, mut other: BBModel, namespace: &str) {
+        let mut taken = uuids(self);
+        let mut renamed = HashMap::new();
+        for uuid in uuids(&other) {
+            if taken.contains(&uuid) {
+                let mut new = derived_uuid(&format!("{namespace}/{uuid}"));
+                while taken.contains(&new) {
+                    new = derived_uuid(&new);
+                }
+                taken.insert(new.clone());
+                renamed.insert(uuid, new);
+            }
+        }
+        let remap = |uuid: &mut String| if let Some(new) = renamed.get(uuid) {
+            *uuid = new.clone();
+        };
+
+        let offset = self.textures.len();
+        let resolution = (other.resolution.width, other.resolution.height);
+        let mut names: HashSet<String> = self.textures.iter().map(|t| t.name.clone()).collect();
+        for (i, mut texture) in other.textures.into_iter().enumerate() {
+            remap(&mut texture.uuid);
+            if let Some(group) = &mut texture.group {
+                remap(group);
+            }
+            // Figura looks textures up by name, so a clash would make both models use one image
+            if names.contains(&texture.name) {
+                let prefix = if namespace.is_empty() { "merged" } else { namespace };
+                texture.name = format!("{prefix}_{}", texture.name);
+            }
+            names.insert(texture.name.clone());
+            texture.id = (offset + i).to_string();
+            if texture.uv_width == 0 || texture.uv_height == 0 {
+                (texture.uv_width, texture.uv_height) = resolution;
+            }
+            self.textures.push(texture);
+        }
+        for mut group in other.texture_groups {
+            remap(&mut group.uuid);
+            self.texture_groups.push(group);
+        }
+        for mut element in other.elements {
+            remap(&mut element.uuid);
+            match &mut element.extra {
+                ElementType::Cube { faces, .. } => {
+                    let Faces { north, east, south, west, up, down } = faces;
+                    for face in [north, east, south, west, up, down].into_iter().flatten() {
+                        match &mut face.texture {
+                            FaceTexture::Index(texture) => *texture += offset,
+                            FaceTexture::Uuid(uuid) => remap(uuid),
+                            FaceTexture::None => {}
+                        }
+                    }
+                }
+                ElementType::Mesh { faces, .. } => for face in faces.values_mut() {
+                    if let Some(texture) = &mut face.texture {
+                        *texture += offset;
+                    }
+                },
+                ElementType::Locator { .. } | ElementType::NullObject { .. } | ElementType::TextureMesh { .. } => {}
+            }
+            self.elements.push(element);
+        }
+
+        remap_outliner(&mut other.outliner, &remap);
+        if namespace.is_empty() {
+            self.outliner.extend(other.outliner);
+        } else {
+            let mut uuid = derived_uuid(&format!("{namespace}/group"));
+            while taken.contains(&uuid) {
+                uuid = derived_uuid(&uuid);
+            }
+            self.outliner.push(OutlinerItem::Group(Group {
+                name: namespace.into(),
+                origin: [0.0; 3],
+                rotation: [0.0; 3],
+                color: 0,
+                uuid,
+                export: true,
+                visibility: true,
+                children: other.outliner,
+                extra: Map::new(),
+            }));
+        }
+
+        for mut image in other.reference_images {
+            remap(&mut image.uuid);
+            self.reference_images.push(image);
+        }
+
+        for mut collection in other.collections {
+            remap(&mut collection.uuid);
+            collection.children.iter_mut().for_each(remap);
+            self.collections.push(collection);
+        }
+
+        for mut animation in other.animations {
+            let animators = std::mem::take(&mut animation.animators).into_iter().map(|(mut uuid, animator)| {
+                remap(&mut uuid);
+                (uuid, animator)
+            });
+            match self.animations.iter_mut().find(|a| a.name == animation.name) {
+                Some(existing) => {
+                    existing.animators.extend(animators);
+                    existing.length = existing.length.max(animation.length);
+                }
+                None => {
+                    remap(&mut animation.uuid);
+                    animation.animators = animators.collect();
+                    self.animations.push(animation);
+                }
+            }
+        }
+    }
+}
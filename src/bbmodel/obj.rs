@@ -0,0 +1,132 @@
+//! Exporting models as Wavefront OBJ files, for Blender and other tools that can't read
+//! `.bbmodel` or glTF files.
+//!
+//! OBJ has no hierarchy, so each element becomes an object with its groups' rotations baked into
+//! its vertices. Positions are in blocks, and otherwise kept as they appear in Blockbench.
+
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use crate::image::{self, ImageError};
+use crate::moon::space::{self, Transform};
+use crate::moon::Textures;
+use super::walk::HierarchyVisitor;
+use super::{Element, Group, Hierarchy};
+
+/// A model exported by [export_obj].
+#[derive(Debug, Clone)]
+pub struct ObjExport {
+    /// The geometry, as an OBJ file.
+    pub obj: String,
+    /// The materials, as an MTL file. The OBJ file refers to it as [mtl_name][Self::mtl_name].
+    pub mtl: String,
+    /// The file name the OBJ file expects the MTL file to have.
+    pub mtl_name: String,
+    /// The textures the MTL file refers to, as file names and PNG data. They should be written
+    /// next to the MTL file.
+    pub images: Vec<(String, Vec<u8>)>,
+}
+
+struct Writer {
+    sizes: Vec<Option<(f64, f64)>>,
+    materials: Vec<Option<String>>,
+    /// The textures faces have been written with, which are the only ones the MTL file needs.
+    used: BTreeSet<usize>,
+    obj: String,
+    vertices: usize,
+    /// The transforms of the groups being visited, innermost last.
+    transforms: Vec<Transform>,
+}
+
+impl HierarchyVisitor for Writer {
+    fn enter_group(&mut self, group: &Group, _: &[&Group]) -> bool {
+        if !group.export || !group.visibility {
+            return false;
+        }
+        let outer = self.transforms.last().copied().unwrap_or_default();
+        self.transforms.push(outer.then_inner(&Transform::around(group.rotation, group.origin)));
+        true
+    }
+
+    fn leave_group(&mut self, _: &Group, _: &[&Group]) {
+        self.transforms.pop();
+    }
+
+    fn element(&mut self, element: &Element, _: &[&Group]) {
+        if element.export == Some(false) || element.visibility == Some(false) {
+            return;
+        }
+        let outer = self.transforms.last().copied().unwrap_or_default();
+        let transform = outer.then_inner(&Transform::around(element.rotation, element.origin));
+        // elements built from a moon always refer to textures by index
+        let mut polygons = element.polygons(&[], &self.sizes);
+        if polygons.is_empty() {
+            return;
+        }
+        polygons.sort_by_key(|p| p.texture);
+        writeln!(self.obj, "o {}", element.name.replace(char::is_whitespace, "_")).unwrap();
+        let mut material = None;
+        for polygon in polygons {
+            if material != Some(polygon.texture) {
+                material = Some(polygon.texture);
+                if let Some(Some(name)) = self.materials.get(polygon.texture) {
+                    writeln!(self.obj, "usemtl {name}").unwrap();
+                    self.used.insert(polygon.texture);
+                }
+            }
+            for &(pos, [u, v]) in &polygon.corners {
+                let pos = std::array::from_fn(|i| pos[i] + element.origin[i]);
+                let [x, y, z] = space::to_blocks(transform.apply(pos));
+                writeln!(self.obj, "v {x:.6} {y:.6} {z:.6}").unwrap();
+                // OBJ measures V from the bottom of the image
+                writeln!(self.obj, "vt {u:.6} {:.6}", 1.0 - v).unwrap();
+            }
+            let first = self.vertices + 1;
+            self.vertices += polygon.corners.len();
+            let corners: Vec<String> = (first..=self.vertices).map(|i| format!("{i}/{i}")).collect();
+            writeln!(self.obj, "f {}", corners.join(" ")).unwrap();
+        }
+    }
+}
+
+/// Exports a model as an OBJ file with an MTL file for its textures. Faces whose texture isn't in
+/// `textures` are left out, as are hidden and non-exported groups and elements. Only the textures
+/// the remaining faces use are included, named after their moon keys with `.png` added.
+pub fn export_obj(hierarchy: &Hierarchy, textures: &Textures) -> Result<ObjExport, ImageError> {
+    let stem = hierarchy.name.rsplit('.').next().unwrap_or(&hierarchy.name);
+    let mtl_name = format!("{stem}.mtl");
+    let mut sizes = vec![];
+    let mut materials = vec![];
+    for data in textures.data.iter() {
+        let Some(png) = textures.src.get(&data.d) else {
+            sizes.push(None);
+            materials.push(None);
+            continue;
+        };
+        let (width, height) = image::dimensions(png.as_ref())?;
+        sizes.push(Some((width as f64, height as f64)));
+        materials.push(Some(data.d.replace(char::is_whitespace, "_")));
+    }
+    let mut writer = Writer {
+        sizes,
+        materials,
+        used: BTreeSet::new(),
+        obj: format!("mtllib {mtl_name}\n"),
+        vertices: 0,
+        transforms: vec![],
+    };
+    hierarchy.walk(&mut writer);
+    let mut mtl = String::new();
+    let mut images: Vec<(String, Vec<u8>)> = vec![];
+    for tex in writer.used {
+        let data = &textures.data[tex];
+        let file = format!("{}.png", data.d);
+        // several entries can share a texture, but it only needs one material
+        if images.iter().any(|(existing, _)| *existing == file) {
+            continue;
+        }
+        let name = writer.materials[tex].as_ref().expect("used textures have materials");
+        writeln!(mtl, "newmtl {name}\nKd 1 1 1\nKs 0 0 0\nmap_Kd {file}\nmap_d {file}\n").unwrap();
+        images.push((file, textures.src[&data.d].as_ref().to_vec()));
+    }
+    Ok(ObjExport { obj: writer.obj, mtl, mtl_name, images })
+}
@@ -0,0 +1,149 @@
+//! Path-based editing of a model's outliner, for tools that restructure models. Paths are names
+//! separated by `/`, as in the outliner, with `""` meaning the top level. Elements are named by
+//! looking their UUIDs up in the model's elements; where several siblings share a name, the
+//! first one is used.
+
+use std::collections::HashSet;
+use thiserror::Error;
+use super::{BBModel, Element, Group, Hierarchy, OutlinerItem};
+
+/// An error encountered while editing an outliner.
+#[derive(Debug, Error)]
+pub enum OutlinerError {
+    /// A path doesn't lead to a group or element.
+    #[error("nothing at {0:?}")]
+    Missing(String),
+    /// A path that should lead to a group leads to an element.
+    #[error("{0:?} is not a group")]
+    NotAGroup(String),
+    /// The destination is the item itself or inside it.
+    #[error("cannot move {0:?} into its own subtree")]
+    IntoOwnSubtree(String),
+}
+
+/// Splits a path into its parent's path and its last name.
+fn split(path: &str) -> (&str, &str) {
+    path.rsplit_once('/').unwrap_or(("", path))
+}
+
+fn item_name<'a>(item: &'a OutlinerItem, elements: &'a [Element]) -> Option<&'a str> {
+    match item {
+        OutlinerItem::Group(group) => Some(&group.name),
+        OutlinerItem::Element(uuid) => elements.iter().find(|e| &e.uuid == uuid).map(|e| e.name.as_str()),
+    }
+}
+
+fn find<'a>(items: &'a [OutlinerItem], elements: &[Element], path: &str) -> Option<&'a OutlinerItem> {
+    let (first, rest) = path.split_once('/').unwrap_or((path, ""));
+    let item = items.iter().find(|item| item_name(item, elements) == Some(first))?;
+    match (item, rest) {
+        (_, "") => Some(item),
+        (OutlinerItem::Group(group), rest) => find(&group.children, elements, rest),
+        (OutlinerItem::Element(_), _) => None,
+    }
+}
+
+/// Finds the children of the group at `path`, or the top level for `""`.
+fn children_mut<'a>(items: &'a mut Vec<OutlinerItem>, elements: &[Element], path: &str) -> Result<&'a mut Vec<OutlinerItem>, OutlinerError> {
+    let mut items = items;
+    for (depth, name) in path.split('/').filter(|s| !s.is_empty()).enumerate() {
+        let so_far = || path.split('/').take(depth + 1).collect::<Vec<_>>().join("/");
+        let index = items.iter().position(|item| item_name(item, elements) == Some(name))
+            .ok_or_else(|| OutlinerError::Missing(so_far()))?;
+        items = match &mut items[index] {
+            OutlinerItem::Group(group) => &mut group.children,
+            OutlinerItem::Element(_) => return Err(OutlinerError::NotAGroup(so_far())),
+        };
+    }
+    Ok(items)
+}
+
+/// Takes the item at `path` out of the outliner, leaving its elements in place.
+fn detach(items: &mut Vec<OutlinerItem>, elements: &[Element], path: &str) -> Result<OutlinerItem, OutlinerError> {
+    let (parent, name) = split(path);
+    let siblings = children_mut(items, elements, parent)?;
+    let index = siblings.iter().position(|item| item_name(item, elements) == Some(name))
+        .ok_or_else(|| OutlinerError::Missing(path.into()))?;
+    Ok(siblings.remove(index))
+}
+
+fn element_uuids(item: &OutlinerItem, out: &mut HashSet<String>) {
+    match item {
+        OutlinerItem::Element(uuid) => {
+            out.insert(uuid.clone());
+        }
+        OutlinerItem::Group(group) => for child in &group.children {
+            element_uuids(child, out);
+        },
+    }
+}
+
+fn insert_group(items: &mut Vec<OutlinerItem>, elements: &[Element], parent: &str, group: Group) -> Result<(), OutlinerError> {
+    children_mut(items, elements, parent)?.push(OutlinerItem::Group(group));
+    Ok(())
+}
+
+fn move_item(items: &mut Vec<OutlinerItem>, elements: &[Element], from: &str, to: &str) -> Result<(), OutlinerError> {
+    if to == from || to.starts_with(&format!("{from}/")) {
+        return Err(OutlinerError::IntoOwnSubtree(from.into()));
+    }
+    // check the destination first, so that nothing is lost if it doesn't exist
+    children_mut(items, elements, to)?;
+    let item = detach(items, elements, from)?;
+    children_mut(items, elements, to).expect("destination was found before detaching").push(item);
+    Ok(())
+}
+
+fn remove(items: &mut Vec<OutlinerItem>, elements: &mut Vec<Element>, path: &str) -> Result<OutlinerItem, OutlinerError> {
+    let item = detach(items, elements, path)?;
+    let mut uuids = HashSet::new();
+    element_uuids(&item, &mut uuids);
+    elements.retain(|e| !uuids.contains(&e.uuid));
+    Ok(item)
+}
+
+impl BBModel {
+    /// Finds the group or element at a path.
+    pub fn find_item(&self, path: &str) -> Option<&OutlinerItem> {
+        find(&self.outliner, &self.elements, path)
+    }
+
+    /// Adds a group at the end of the group at `parent`.
+    pub fn insert_group(&mut self, parent: &str, group: Group) -> Result<(), OutlinerError> {
+        insert_group(&mut self.outliner, &self.elements, parent, group)
+    }
+
+    /// Moves the group or element at `from` to the end of the group at `to`.
+    pub fn move_item(&mut self, from: &str, to: &str) -> Result<(), OutlinerError> {
+        move_item(&mut self.outliner, &self.elements, from, to)
+    }
+
+    /// Removes the group or element at a path, along with every element inside it, returning
+    /// the removed outliner item.
+    pub fn remove(&mut self, path: &str) -> Result<OutlinerItem, OutlinerError> {
+        remove(&mut self.outliner, &mut self.elements, path)
+    }
+}
+
+impl Hierarchy {
+    /// Finds the group or element at a path.
+    pub fn find_item(&self, path: &str) -> Option<&OutlinerItem> {
+        find(&self.outliner, &self.elements, path)
+    }
+
+    /// Adds a group at the end of the group at `parent`.
+    pub fn insert_group(&mut self, parent: &str, group: Group) -> Result<(), OutlinerError> {
+        insert_group(&mut self.outliner, &self.elements, parent, group)
+    }
+
+    /// Moves the group or element at `from` to the end of the group at `to`.
+    pub fn move_item(&mut self, from: &str, to: &str) -> Result<(), OutlinerError> {
+        move_item(&mut self.outliner, &self.elements, from, to)
+    }
+
+    /// Removes the group or element at a path, along with every element inside it, returning
+    /// the removed outliner item.
+    pub fn remove(&mut self, path: &str) -> Result<OutlinerItem, OutlinerError> {
+        remove(&mut self.outliner, &mut self.elements, path)
+    }
+}
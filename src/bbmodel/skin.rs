@@ -0,0 +1,103 @@
+//! Blockbench's Minecraft skin format (`model_format: "skin"`), which starts every project from a
+//! player model.
+//!
+//! Skin projects name their bones as Bedrock does (`head`, `rightArm`, ...), which Figura doesn't
+//! recognize as parent types, and may leave out bones the user deleted even though the player
+//! still has them. Expanding the template renames the bones to Figura's keywords and adds the
+//! missing ones as empty groups, so that a skin project compiles into parts that follow the
+//! player.
+//!
+//! Skin projects also have a single texture slot, the skin: Blockbench draws every face with it,
+//! whatever texture the face was saved with (often none at all). Faces are given the
+//! [skin texture][skin_texture] in the same way. Skin projects can't have meshes, so only cubes
+//! are affected.
+
+use serde_json::Map;
+use crate::moon::ParentType;
+use super::{derived_uuid, BBModel, ElementType, FaceTexture, Group, OutlinerItem, Texture};
+
+/// The bones of the player template, with the pivot Blockbench gives each one.
+pub const PLAYER_BONES: [(ParentType, [f64; 3]); 6] = [
+    (ParentType::Head, [0.0, 24.0, 0.0]),
+    (ParentType::Body, [0.0, 24.0, 0.0]),
+    (ParentType::RightArm, [-5.0, 22.0, 0.0]),
+    (ParentType::LeftArm, [5.0, 22.0, 0.0]),
+    (ParentType::RightLeg, [-1.9, 12.0, 0.0]),
+    (ParentType::LeftLeg, [1.9, 12.0, 0.0]),
+];
+
+/// The player bone a group name refers to, ignoring case, spaces, and underscores, so that
+/// `rightArm`, `right_arm`, and `RightArm` are all [ParentType::RightArm].
+pub fn player_bone(name: &str) -> Option<ParentType> {
+    let name: String = name.chars().filter(|c| !matches!(c, ' ' | '_')).flat_map(char::to_lowercase).collect();
+    PLAYER_BONES.iter().map(|(pt, _)| *pt).find(|pt| format!("{pt:?}").to_lowercase() == name)
+}
+
+/// The texture a skin project draws every face with: the one marked as the default, or else the
+/// first, as in Blockbench.
+pub fn skin_texture(textures: &[Texture]) -> Option<usize> {
+    textures.iter().position(|t| t.use_as_default).or((!textures.is_empty()).then_some(0))
+}
+
+/// Expands the player template in a skin project's outliner: top-level groups for player bones
+/// are renamed to Figura's keywords, and bones that aren't there are added as empty groups.
+pub fn expand_outliner(outliner: &[OutlinerItem]) -> Vec<OutlinerItem> {
+    let mut found = vec![];
+    let mut items: Vec<OutlinerItem> = outliner.iter().cloned().map(|mut item| {
+        if let OutlinerItem::Group(group) = &mut item {
+            if let Some(pt) = player_bone(&group.name).filter(|pt| !found.contains(pt)) {
+                group.name = format!("{pt:?}");
+                found.push(pt);
+            }
+        }
+        item
+    }).collect();
+    for (pt, origin) in PLAYER_BONES {
+        if found.contains(&pt) {
+            continue;
+        }
+        items.push(OutlinerItem::Group(Group {
+            name: format!("{pt:?}"),
+            origin,
+            rotation: [0.0; 3],
+            color: 0,
+            uuid: derived_uuid(&format!("skin/{pt:?}")),
+            export: true,
+            visibility: true,
+            children: vec![],
+            extra: Map::new(),
+        }));
+    }
+    items
+}
+
+impl BBModel {
+    /// Whether this is a Minecraft skin project.
+    pub fn is_skin(&self) -> bool {
+        self.meta.model_format == "skin"
+    }
+
+    /// Turns a skin project into a free-form one with the player template spelled out and every
+    /// cube face using the skin texture; see [the module documentation][self]. Returns whether
+    /// this was a skin project.
+    ///
+    /// [compile][super::compile] does this on its own, so this is only needed to edit the
+    /// expanded groups or save them.
+    pub fn expand_skin_template(&mut self) -> bool {
+        if !self.is_skin() {
+            return false;
+        }
+        self.outliner = expand_outliner(&self.outliner);
+        if let Some(skin) = skin_texture(&self.textures) {
+            for element in &mut self.elements {
+                let ElementType::Cube { faces, .. } = &mut element.extra else { continue };
+                let sides = [&mut faces.north, &mut faces.east, &mut faces.south, &mut faces.west, &mut faces.up, &mut faces.down];
+                for face in sides.into_iter().flatten() {
+                    face.texture = FaceTexture::Index(skin);
+                }
+            }
+        }
+        self.meta.model_format = "free".into();
+        true
+    }
+}
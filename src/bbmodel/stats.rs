@@ -0,0 +1,86 @@
+//! A summary of what a Blockbench model contains, for sizing up a model before packing it.
+
+use std::fmt::{self, Display, Formatter};
+use serde::Serialize;
+use crate::image;
+use super::{BBModel, ElementType};
+
+/// Counts of the things in a model, from [BBModel::stats].
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct ModelStats {
+    /// The number of cubes.
+    pub cubes: usize,
+    /// The number of meshes.
+    pub meshes: usize,
+    /// The number of faces across all meshes.
+    pub mesh_faces: usize,
+    /// The number of vertices across all meshes.
+    pub mesh_vertices: usize,
+    /// The number of locators, null objects, and texture meshes, which draw nothing in Figura.
+    pub other_elements: usize,
+    /// The number of outliner groups.
+    pub groups: usize,
+    /// The number of textures.
+    pub textures: usize,
+    /// The memory the textures take up once decoded, at four bytes per pixel. Textures whose size
+    /// can't be worked out aren't counted.
+    pub texture_memory: usize,
+    /// The number of animations.
+    pub animations: usize,
+    /// The name and length in seconds of the longest animation, if there are any.
+    pub longest_animation: Option<(String, f64)>,
+}
+
+impl Display for ModelStats {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        writeln!(fmt, "{} cubes, {} meshes ({} faces, {} vertices)", self.cubes, self.meshes, self.mesh_faces, self.mesh_vertices)?;
+        if self.other_elements > 0 {
+            writeln!(fmt, "{} other elements", self.other_elements)?;
+        }
+        writeln!(fmt, "{} groups", self.groups)?;
+        writeln!(fmt, "{} textures ({} KiB decoded)", self.textures, self.texture_memory.div_ceil(1024))?;
+        write!(fmt, "{} animations", self.animations)?;
+        if let Some((name, length)) = &self.longest_animation {
+            write!(fmt, " (longest: {name}, {length}s)")?;
+        }
+        writeln!(fmt)
+    }
+}
+
+impl BBModel {
+    /// Counts the elements, groups, textures, and animations in this model.
+    pub fn stats(&self) -> ModelStats {
+        fn count_groups(items: &[super::OutlinerItem]) -> usize {
+            items.iter().map(|item| match item {
+                super::OutlinerItem::Group(group) => 1 + count_groups(&group.children),
+                super::OutlinerItem::Element(_) => 0,
+            }).sum()
+        }
+        let mut stats = ModelStats { groups: count_groups(&self.outliner), ..Default::default() };
+        for element in &self.elements {
+            match &element.extra {
+                ElementType::Cube { .. } => stats.cubes += 1,
+                ElementType::Mesh { vertices, faces } => {
+                    stats.meshes += 1;
+                    stats.mesh_faces += faces.len();
+                    stats.mesh_vertices += vertices.len();
+                }
+                ElementType::Locator { .. } | ElementType::NullObject { .. } | ElementType::TextureMesh { .. } => stats.other_elements += 1,
+            }
+        }
+        stats.textures = self.textures.len();
+        stats.texture_memory = self.textures.iter().filter_map(|texture| {
+            // the embedded image is more trustworthy than the saved size, if there is one
+            let (width, height) = match texture.decode_source().ok().and_then(|png| image::dimensions(&png).ok()) {
+                Some((width, height)) => (width as usize, height as usize),
+                None => texture.image_size().map(|(width, height)| (width as usize, height as usize))?,
+            };
+            Some(width * height * 4)
+        }).sum();
+        stats.animations = self.animations.len();
+        stats.longest_animation = self.animations.iter()
+            .max_by(|a, b| a.length.total_cmp(&b.length))
+            .map(|a| (a.name.clone(), a.length));
+        stats
+    }
+}
@@ -0,0 +1,33 @@
+//! Reading models without building a [Value][serde_json::Value] tree of the whole file first.
+//! [BBModel::from_json] takes the whole file as a `Value` so that [compat] can rewrite old
+//! layouts, but for mesh-heavy projects that tree takes several times the memory of the file
+//! itself: every vertex coordinate becomes its own node, and every texture's base64 source is
+//! copied once more on the way into the struct.
+//!
+//! [BBModel::from_reader] instead deserializes straight into the structs. Texture sources go
+//! directly into their [Texture][super::Texture]s, and stay base64 until something calls
+//! [Texture::decode_source][super::Texture::decode_source]. Elements aren't quite streamed: since
+//! an [Element][super::Element] flattens its [ElementType][super::ElementType] into itself, serde
+//! buffers each element (vertices and all) before picking its type. That's one element at a time
+//! rather than the whole file, though. Only files that need upgrading, which are rare and usually
+//! small, are read a second time the old way.
+
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use super::{compat, BBModel};
+
+impl BBModel {
+    /// Reads a model from a file, the same way as [from_json][Self::from_json] but with far less
+    /// memory for large files; see [the module documentation][self]. Files from older Blockbench
+    /// versions are read again from the reader's starting position and upgraded, so the reader
+    /// must be seekable.
+    pub fn from_reader<R: Read + Seek>(mut reader: R) -> serde_json::Result<Self> {
+        let start = reader.stream_position().map_err(serde_json::Error::io)?;
+        match serde_json::from_reader::<_, Self>(BufReader::new(&mut reader)) {
+            Ok(model) if !compat::needs_upgrade(&model) => return Ok(model),
+            // either an old layout or a genuine error; the slow path tells them apart
+            _ => {}
+        }
+        reader.seek(SeekFrom::Start(start)).map_err(serde_json::Error::io)?;
+        Self::from_json(serde_json::from_reader(BufReader::new(reader))?)
+    }
+}
@@ -0,0 +1,75 @@
+//! Walking a model's outliner with its elements looked up, so that tools can act on every group
+//! and element without keeping their own UUID maps.
+
+use std::collections::HashMap;
+use super::{BBModel, Element, Group, Hierarchy, OutlinerItem};
+
+/// Something that looks at the groups and elements of an outliner, in order, as
+/// [Hierarchy::walk] visits them. Every method has a default that does nothing, so implementors
+/// only write the ones they need. `ancestors` holds the groups containing the item, outermost
+/// first.
+pub trait HierarchyVisitor {
+    /// Called on reaching a group, before its children. Returning `false` skips its children, and
+    /// [leave_group][Self::leave_group] isn't called for it.
+    fn enter_group(&mut self, group: &Group, ancestors: &[&Group]) -> bool {
+        let _ = (group, ancestors);
+        true
+    }
+
+    /// Called after a group's children have been visited.
+    fn leave_group(&mut self, group: &Group, ancestors: &[&Group]) {
+        let _ = (group, ancestors);
+    }
+
+    /// Called for each element in the outliner.
+    fn element(&mut self, element: &Element, ancestors: &[&Group]) {
+        let _ = (element, ancestors);
+    }
+
+    /// Called for each outliner entry whose UUID isn't one of the elements'.
+    fn missing_element(&mut self, uuid: &str, ancestors: &[&Group]) {
+        let _ = (uuid, ancestors);
+    }
+}
+
+fn walk<'a>(items: &'a [OutlinerItem], elements: &HashMap<&str, &Element>, ancestors: &mut Vec<&'a Group>, visitor: &mut impl HierarchyVisitor) {
+    for item in items {
+        match item {
+            OutlinerItem::Element(uuid) => match elements.get(uuid.as_str()) {
+                Some(element) => visitor.element(element, ancestors),
+                None => visitor.missing_element(uuid, ancestors),
+            },
+            OutlinerItem::Group(group) => {
+                if !visitor.enter_group(group, ancestors) {
+                    continue;
+                }
+                ancestors.push(group);
+                walk(&group.children, elements, ancestors, visitor);
+                ancestors.pop();
+                visitor.leave_group(group, ancestors);
+            }
+        }
+    }
+}
+
+/// Visits `outliner` depth first, looking elements up in `elements`.
+pub fn walk_outliner(outliner: &[OutlinerItem], elements: &[Element], visitor: &mut impl HierarchyVisitor) {
+    let elements = elements.iter().map(|e| (e.uuid.as_str(), e)).collect();
+    walk(outliner, &elements, &mut vec![], visitor);
+}
+
+impl Hierarchy {
+    /// Visits every group and element in the outliner, depth first and in order; see
+    /// [HierarchyVisitor].
+    pub fn walk(&self, visitor: &mut impl HierarchyVisitor) {
+        walk_outliner(&self.outliner, &self.elements, visitor);
+    }
+}
+
+impl BBModel {
+    /// Visits every group and element in the outliner, depth first and in order; see
+    /// [HierarchyVisitor].
+    pub fn walk(&self, visitor: &mut impl HierarchyVisitor) {
+        walk_outliner(&self.outliner, &self.elements, visitor);
+    }
+}
@@ -0,0 +1,288 @@
+//! An interactive browser for an avatar's contents, as `fia browse` shows it: a list of the
+//! avatar's model parts, scripts, textures, sounds, and resources on the left, and details of
+//! whichever is selected on the right, with scripts shown highlighted in full. Items can be
+//! extracted one at a time, without unpacking the whole avatar.
+//!
+//! | Key                | Action                                  |
+//! |--------------------|-----------------------------------------|
+//! | `↑`/`k`, `↓`/`j`   | Select the previous or next item        |
+//! | `Home`, `End`      | Select the first or last item           |
+//! | `PgUp`, `PgDn`     | Scroll the details                      |
+//! | `e`                | Extract the selected item               |
+//! | `q`, `Esc`         | Quit                                    |
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span, Text};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use crate::image;
+use crate::moon::{relative_path, ModelData, ModelPart};
+use crate::scripts::{self, TokenKind};
+use crate::Moon;
+
+/// How far the details scroll with `PgUp` and `PgDn`.
+const PAGE: u16 = 10;
+
+/// Something listed in the browser.
+enum Item<'a> {
+    /// The title of a section, such as "Scripts".
+    Heading(&'static str),
+    /// A model part, with its path from [Moon::models] and how deep it is.
+    Part { part: &'a ModelPart, path: String, depth: usize },
+    /// A script, by name.
+    Script(&'a str),
+    /// A texture, by name.
+    Texture(&'a str),
+    /// A sound, by name.
+    Sound(&'a str),
+    /// A resource, by path.
+    Resource(&'a str),
+}
+
+/// Sorted keys of a map.
+fn sorted<'a, V>(map: impl IntoIterator<Item = (&'a String, V)>) -> Vec<&'a str> {
+    let mut keys: Vec<&str> = map.into_iter().map(|(key, _)| key.as_str()).collect();
+    keys.sort();
+    keys
+}
+
+/// Adds a part and its descendants to the list.
+fn add_parts<'a>(items: &mut Vec<Item<'a>>, part: &'a ModelPart, path: String, depth: usize) {
+    for child in part.chld.iter() {
+        let path = if path.is_empty() { child.name.clone() } else { format!("{path}.{}", child.name) };
+        items.push(Item::Part { part: child, path: path.clone(), depth });
+        add_parts(items, child, path, depth + 1);
+    }
+}
+
+/// Formats a byte count for people.
+fn size(bytes: usize) -> String {
+    match bytes {
+        0..1024 => format!("{bytes}B"),
+        1024..1048576 => format!("{:.1}KiB", bytes as f64 / 1024.0),
+        _ => format!("{:.1}MiB", bytes as f64 / 1048576.0),
+    }
+}
+
+/// Highlights a script, one line of text per line of source.
+fn highlight(source: &[u8]) -> Vec<Line<'static>> {
+    let mut lines = vec![];
+    let mut spans = vec![];
+    for (kind, range) in scripts::tokens(source) {
+        let style = match kind {
+            TokenKind::Code => Style::new(),
+            TokenKind::Keyword => Style::new().fg(Color::Magenta).add_modifier(Modifier::BOLD),
+            TokenKind::String => Style::new().fg(Color::Green),
+            TokenKind::Comment => Style::new().fg(Color::DarkGray).add_modifier(Modifier::ITALIC),
+            TokenKind::Number => Style::new().fg(Color::Cyan),
+        };
+        let text = String::from_utf8_lossy(&source[range]).replace('\t', "    ");
+        let mut pieces = text.split('\n');
+        if let Some(first) = pieces.next() {
+            spans.push(Span::styled(first.trim_end_matches('\r').to_owned(), style));
+        }
+        for piece in pieces {
+            lines.push(Line::from(std::mem::take(&mut spans)));
+            spans.push(Span::styled(piece.trim_end_matches('\r').to_owned(), style));
+        }
+    }
+    lines.push(Line::from(spans));
+    let width = lines.len().to_string().len();
+    lines.into_iter().enumerate().map(|(i, mut line)| {
+        line.spans.insert(0, Span::styled(format!("{:>width$} ", i + 1), Style::new().fg(Color::DarkGray)));
+        line
+    }).collect()
+}
+
+/// A label and value, for the details pane.
+fn field(label: &str, value: impl ToString) -> Line<'static> {
+    Line::from(vec![
+        Span::styled(format!("{label}: "), Style::new().add_modifier(Modifier::BOLD)),
+        Span::raw(value.to_string()),
+    ])
+}
+
+/// The state of the browser.
+pub struct Browser<'a> {
+    moon: &'a Moon,
+    items: Vec<Item<'a>>,
+    list: ListState,
+    scroll: u16,
+    out: PathBuf,
+    status: String,
+}
+
+impl<'a> Browser<'a> {
+    /// Lists the contents of an avatar. Extracted items are written under `out`.
+    pub fn new(moon: &'a Moon, out: &Path) -> Self {
+        let mut items = vec![];
+        if let Some(models) = &moon.models {
+            items.push(Item::Heading("Models"));
+            add_parts(&mut items, models, String::new(), 0);
+        }
+        let sections: [(&str, Vec<&str>, fn(&'a str) -> Item<'a>); 4] = [
+            ("Scripts", sorted(&moon.scripts), Item::Script),
+            ("Textures", sorted(&moon.textures.src), Item::Texture),
+            ("Sounds", sorted(&moon.sounds), Item::Sound),
+            ("Resources", sorted(&moon.resources), Item::Resource),
+        ];
+        for (heading, names, item) in sections {
+            if !names.is_empty() {
+                items.push(Item::Heading(heading));
+                items.extend(names.into_iter().map(item));
+            }
+        }
+        let mut list = ListState::default();
+        list.select(Some(0));
+        Self { moon, items, list, scroll: 0, out: out.into(), status: String::new() }
+    }
+
+    fn selected(&self) -> Option<&Item<'a>> {
+        self.items.get(self.list.selected()?)
+    }
+
+    /// Selects an item by its index, clamped to the list.
+    fn select(&mut self, index: usize) {
+        self.list.select(Some(index.min(self.items.len().saturating_sub(1))));
+        self.scroll = 0;
+    }
+
+    /// Runs the browser until it's quit.
+    pub fn run(mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            let Event::Key(key) = event::read()? else { continue };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            let selected = self.list.selected().unwrap_or(0);
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Up | KeyCode::Char('k') => self.select(selected.saturating_sub(1)),
+                KeyCode::Down | KeyCode::Char('j') => self.select(selected + 1),
+                KeyCode::Home => self.select(0),
+                KeyCode::End => self.select(usize::MAX),
+                KeyCode::PageUp => self.scroll = self.scroll.saturating_sub(PAGE),
+                KeyCode::PageDown => self.scroll = self.scroll.saturating_add(PAGE),
+                KeyCode::Char('e') => {
+                    self.status = match self.extract() {
+                        Ok(Some(path)) => format!("wrote {}", path.display()),
+                        Ok(None) => "nothing to extract here".into(),
+                        Err(e) => format!("couldn't extract: {e}"),
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Writes the selected item out as a file, returning where, or [None] if it's a heading.
+    fn extract(&self) -> io::Result<Option<PathBuf>> {
+        let moon = self.moon;
+        let (name, data) = match self.selected() {
+            None | Some(Item::Heading(_)) => return Ok(None),
+            Some(Item::Part { part, .. }) => {
+                let model = moon.decompile_model(part).to_canonical_vec()?;
+                (part.name.replace('.', "/") + ".bbmodel", model)
+            }
+            Some(Item::Script(name)) => (name.replace('.', "/") + ".lua", moon.scripts[*name].as_ref().to_vec()),
+            Some(Item::Texture(name)) => (name.replace('.', "/") + ".png", moon.textures.src[*name].as_ref().to_vec()),
+            Some(Item::Sound(name)) => (name.replace('.', "/") + ".ogg", moon.sounds[*name].as_ref().to_vec()),
+            Some(Item::Resource(name)) => (name.to_string(), moon.resources[*name].as_ref().to_vec()),
+        };
+        let path = self.out.join(relative_path(&name).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&path, data)?;
+        Ok(Some(path))
+    }
+
+    /// Describes the selected item, for the details pane.
+    fn details(&self) -> (String, Vec<Line<'static>>) {
+        let moon = self.moon;
+        match self.selected() {
+            None => (String::new(), vec![]),
+            Some(Item::Heading(heading)) => {
+                let metadata = &moon.metadata;
+                (heading.to_string(), vec![
+                    field("avatar", &metadata.name),
+                    field("scripts", moon.scripts.len()),
+                    field("textures", moon.textures.src.len()),
+                    field("sounds", moon.sounds.len()),
+                    field("animations", moon.animations.len()),
+                    field("resources", moon.resources.len()),
+                ])
+            }
+            Some(Item::Part { part, path, .. }) => {
+                let kind = match part.data {
+                    ModelData::Group {} => "group",
+                    ModelData::Cube { .. } => "cube",
+                    ModelData::Mesh { .. } => "mesh",
+                };
+                let mut lines = vec![
+                    field("kind", kind),
+                    field("pivot", format!("{:?}", part.piv)),
+                    field("rotation", format!("{:?}", part.rot)),
+                    field("visible", part.vsb),
+                    field("children", part.chld.len()),
+                    field("complexity", part.complexity()),
+                ];
+                if let Some(pt) = part.pt {
+                    lines.insert(1, field("parent type", format!("{pt:?}")));
+                }
+                if !part.anim.is_empty() {
+                    let animations: Vec<_> = part.anim.keys().filter_map(|&i| moon.animations.get(i)).map(|a| a.name.clone()).collect();
+                    lines.push(field("animations", animations.join(", ")));
+                }
+                (path.clone(), lines)
+            }
+            Some(Item::Script(name)) => (name.to_string(), highlight(moon.scripts[*name].as_ref())),
+            Some(Item::Texture(name)) => {
+                let data = moon.textures.src[*name].as_ref();
+                let users = moon.textures.data.iter().filter(|d| d.d == *name).count();
+                let mut lines = vec![field("size", size(data.len())), field("used by", format!("{users} texture slot(s)"))];
+                match image::dimensions(data) {
+                    Ok((width, height)) => lines.insert(0, field("dimensions", format!("{width}×{height}"))),
+                    Err(e) => lines.insert(0, field("unreadable", e)),
+                }
+                (name.to_string(), lines)
+            }
+            Some(Item::Sound(name)) => (name.to_string(), vec![field("size", size(moon.sounds[*name].as_ref().len()))]),
+            Some(Item::Resource(name)) => (name.to_string(), vec![field("size", size(moon.resources[*name].as_ref().len()))]),
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, status] = Layout::vertical([Constraint::Fill(1), Constraint::Length(1)]).areas(frame.area());
+        let [left, right] = Layout::horizontal([Constraint::Percentage(35), Constraint::Fill(1)]).areas(main);
+        let items: Vec<ListItem> = self.items.iter().map(|item| ListItem::new(match item {
+            Item::Heading(heading) => Line::styled(heading.to_string(), Style::new().add_modifier(Modifier::BOLD | Modifier::UNDERLINED)),
+            Item::Part { part, depth, .. } => Line::raw(format!("{}{}", "  ".repeat(depth + 1), part.name)),
+            Item::Script(name) | Item::Texture(name) | Item::Sound(name) | Item::Resource(name) => Line::raw(format!("  {name}")),
+        })).collect();
+        let list = List::new(items)
+            .block(Block::bordered().title(self.moon.metadata.name.clone()))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, left, &mut self.list);
+        let (title, lines) = self.details();
+        let details = Paragraph::new(Text::from(lines)).block(Block::bordered().title(title)).scroll((self.scroll, 0));
+        frame.render_widget(details, right);
+        let help = "↑↓ select  PgUp/PgDn scroll  e extract  q quit";
+        let status_line = if self.status.is_empty() { help.to_owned() } else { format!("{}  ·  {help}", self.status) };
+        frame.render_widget(Paragraph::new(Line::styled(status_line, Style::new().fg(Color::DarkGray))), status);
+    }
+}
+
+/// Browses an avatar in the terminal until the user quits; see [the module documentation][self].
+pub fn browse(moon: &Moon, out: &Path) -> io::Result<()> {
+    let mut terminal = ratatui::init();
+    let result = Browser::new(moon, out).run(&mut terminal);
+    ratatui::restore();
+    result
+}
@@ -0,0 +1,389 @@
+//! Analysis of an avatar for problems that would stop it from uploading or working, plus a small
+//! assertion language so CI can enforce its own policies (e.g. `total_size < 95KB`) on top of
+//! Figura's.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+use thiserror::Error;
+use crate::limits::Tier;
+use crate::moon::{ModelData, ModelPart};
+use crate::moon::duplicates::DuplicateKey;
+use crate::scripts::RequireGraph;
+use crate::Moon;
+
+/// How serious a [Diagnostic] is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// Something that is probably unintended, but won't stop the avatar from working.
+    Warning,
+    /// Something that will stop the avatar from uploading or working.
+    Error,
+}
+
+/// An error from parsing a [Severity] that isn't `warning` or `error`.
+#[derive(Debug, Error)]
+#[error("unknown severity {0:?} (expected warning or error)")]
+pub struct UnknownSeverity(pub String);
+
+impl FromStr for Severity {
+    type Err = UnknownSeverity;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "warning" => Ok(Severity::Warning),
+            "error" => Ok(Severity::Error),
+            _ => Err(UnknownSeverity(s.into())),
+        }
+    }
+}
+
+impl Display for Severity {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        fmt.write_str(match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+/// A problem found in an avatar.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// How serious the problem is.
+    pub severity: Severity,
+    /// A short identifier for the kind of problem, such as `missing-module`.
+    pub code: &'static str,
+    /// A human-readable description of the problem.
+    pub message: String,
+    /// The path of the file in the avatar (as in [Moon::files]) the problem is in, if any.
+    pub file: Option<String>,
+    /// The path of the model part the problem is on, such as `models/sign/Body`, if any.
+    pub part: Option<String>,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        write!(fmt, "{}: ", self.severity)?;
+        for location in [&self.file, &self.part].into_iter().flatten() {
+            write!(fmt, "{location}: ")?;
+        }
+        fmt.write_str(&self.message)
+    }
+}
+
+/// Checks an avatar for problems.
+pub fn check(moon: &Moon, tier: Tier) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    match moon.check_limits(tier) {
+        Ok(report) if !report.fits() => diagnostics.push(Diagnostic {
+            severity: Severity::Error,
+            code: "size-limit",
+            message: format!("avatar is {}B over the {tier} tier's size limit", -report.headroom()),
+            file: None,
+            part: None,
+        }),
+        Ok(_) => {}
+        Err(e) => diagnostics.push(Diagnostic {
+            severity: Severity::Error,
+            code: "serialize",
+            message: format!("avatar could not be serialized: {e}"),
+            file: None,
+            part: None,
+        }),
+    }
+    let graph = RequireGraph::build(&moon.scripts);
+    for (script, module) in &graph.missing {
+        diagnostics.push(Diagnostic {
+            severity: Severity::Warning,
+            code: "missing-module",
+            message: format!("requires nonexistent module {module}"),
+            file: Some(script.replace('.', "/") + ".lua"),
+            part: None,
+        });
+    }
+    if let Some(cycle) = graph.find_cycle() {
+        diagnostics.push(Diagnostic {
+            severity: Severity::Error,
+            code: "require-cycle",
+            message: format!("require cycle: {}", cycle.join(" → ")),
+            file: Some(cycle[0].replace('.', "/") + ".lua"),
+            part: None,
+        });
+    }
+    for d in moon.textures.data.iter() {
+        if !moon.textures.src.contains_key(&d.d) {
+            diagnostics.push(Diagnostic {
+                severity: Severity::Error,
+                code: "missing-texture",
+                message: format!("texture {} is used but not included", d.d),
+                file: None,
+                part: None,
+            });
+        }
+    }
+    diagnostics.extend(crate::permissions::check(moon));
+    diagnostics
+}
+
+impl Moon {
+    /// Checks that the avatar is consistent with itself, reporting references Figura can't follow:
+    /// - `missing-texture-index`: a face uses a texture index past the end of [Textures::data];
+    /// - `missing-animation`: a part has keyframes for an animation index that doesn't exist;
+    /// - `duplicate-part`: two siblings share a name, so scripts can only reach the first;
+    /// - `missing-auto-script`: `autoScripts` names a script that doesn't exist;
+    /// - `missing-auto-anim`: `autoAnims` names an animation that doesn't exist.
+    ///
+    /// [Textures::data]: crate::moon::Textures::data
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        fn visit(part: &ModelPart, path: &str, moon: &Moon, out: &mut Vec<Diagnostic>) {
+            let error = |code, message| Diagnostic { severity: Severity::Error, code, message, file: None, part: Some(path.into()) };
+            let textures = moon.textures.data.len();
+            let mut faces: Vec<usize> = match &part.data {
+                ModelData::Group {} => vec![],
+                ModelData::Cube { cube_data, .. } => [&cube_data.n, &cube_data.s, &cube_data.u, &cube_data.d, &cube_data.w, &cube_data.e]
+                    .into_iter().flatten().map(|face| face.tex).collect(),
+                ModelData::Mesh { mesh_data } => mesh_data.faces().map(|face| face.tex).collect(),
+            };
+            faces.sort();
+            faces.dedup();
+            for tex in faces.into_iter().filter(|&tex| tex >= textures) {
+                out.push(error("missing-texture-index", format!("uses texture {tex}, but there are only {textures}")));
+            }
+            for &index in part.anim.keys().filter(|&&index| index >= moon.animations.len()) {
+                out.push(error("missing-animation", format!("has keyframes for animation {index}, but there are only {}", moon.animations.len())));
+            }
+            let mut names = HashSet::new();
+            for child in part.chld.iter() {
+                if !names.insert(&child.name) {
+                    out.push(Diagnostic {
+                        severity: Severity::Warning,
+                        code: "duplicate-part",
+                        message: format!("has more than one child named {:?}; scripts can only reach the first", child.name),
+                        file: None,
+                        part: Some(path.into()),
+                    });
+                }
+            }
+            for child in part.chld.iter() {
+                visit(child, &format!("{path}/{}", child.name), moon, out);
+            }
+        }
+        let mut diagnostics = vec![];
+        if let Some(models) = &self.models {
+            visit(models, &models.name, self, &mut diagnostics);
+        }
+        for script in self.metadata.auto_scripts.iter().flatten() {
+            if !self.scripts.contains_key(script) {
+                diagnostics.push(Diagnostic {
+                    severity: Severity::Warning,
+                    code: "missing-auto-script",
+                    message: format!("autoScripts lists {script}, which doesn't exist"),
+                    file: Some("avatar.json".into()),
+                    part: None,
+                });
+            }
+        }
+        for anim in &self.metadata.auto_anims {
+            if !self.animations.iter().any(|a| format!("{}.{}", a.mdl, a.name) == *anim) {
+                diagnostics.push(Diagnostic {
+                    severity: Severity::Warning,
+                    code: "missing-auto-anim",
+                    message: format!("autoAnims lists {anim}, which doesn't exist"),
+                    file: Some("avatar.json".into()),
+                    part: None,
+                });
+            }
+        }
+        diagnostics
+    }
+}
+
+fn count_parts(part: &ModelPart) -> usize {
+    1 + part.chld.iter().map(count_parts).sum::<usize>()
+}
+
+/// Computes the values that [Assertion]s can refer to. Sizes are in bytes; `complexity` is the
+/// number of faces Figura will render.
+pub fn metrics(moon: &Moon) -> BTreeMap<&'static str, f64> {
+    let mut metrics = BTreeMap::new();
+    if let Ok(report) = moon.check_limits(Tier::Default) {
+        metrics.insert("total_size", report.total as f64);
+    }
+    let blobs = [
+        ("textures.count", "textures.size", &moon.textures.src),
+        ("scripts.count", "scripts.size", &moon.scripts),
+        ("sounds.count", "sounds.size", &moon.sounds),
+        ("resources.count", "resources.size", &moon.resources),
+    ];
+    for (count, size, map) in blobs {
+        metrics.insert(count, map.len() as f64);
+        metrics.insert(size, map.values().map(|d| d.as_ref().len()).sum::<usize>() as f64);
+    }
+    metrics.insert("animations.count", moon.animations.len() as f64);
+    let complexity = moon.models.as_ref().map_or_else(Default::default, ModelPart::complexity);
+    metrics.insert("parts.count", moon.models.as_ref().map_or(0, count_parts) as f64);
+    metrics.insert("cubes.count", complexity.cubes as f64);
+    metrics.insert("meshes.count", complexity.meshes as f64);
+    metrics.insert("complexity", complexity.faces() as f64);
+    metrics
+}
+
+/// A comparison operator in an [Assertion].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[allow(missing_docs)]
+pub enum Op { Lt, Le, Gt, Ge, Eq, Ne }
+
+impl Op {
+    const ALL: [(&'static str, Op); 6] = [("<=", Op::Le), (">=", Op::Ge), ("==", Op::Eq), ("!=", Op::Ne), ("<", Op::Lt), (">", Op::Gt)];
+
+    fn symbol(self) -> &'static str {
+        Self::ALL.iter().find(|(_, op)| *op == self).unwrap().0
+    }
+
+    fn apply(self, a: f64, b: f64) -> bool {
+        match self {
+            Op::Lt => a < b,
+            Op::Le => a <= b,
+            Op::Gt => a > b,
+            Op::Ge => a >= b,
+            Op::Eq => a == b,
+            Op::Ne => a != b,
+        }
+    }
+}
+
+/// A condition on one of an avatar's [metrics], such as `scripts.count <= 20`. Values may have a
+/// size suffix: `B`, `KB`/`MB` (powers of 1000), or `KiB`/`MiB` (powers of 1024).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Assertion {
+    /// The name of the metric being checked.
+    pub metric: String,
+    /// How to compare the metric to the value.
+    pub op: Op,
+    /// The value to compare against.
+    pub value: f64,
+    source: String,
+}
+
+/// An error encountered while parsing or evaluating an [Assertion].
+#[derive(Debug, Error)]
+pub enum AssertionError {
+    /// The assertion didn't contain a comparison operator.
+    #[error("no comparison operator in {0:?}")]
+    NoOperator(String),
+    /// The value couldn't be parsed.
+    #[error("invalid value {0:?}")]
+    InvalidValue(String),
+    /// The metric doesn't exist.
+    #[error("unknown metric {0:?}")]
+    UnknownMetric(String),
+}
+
+impl FromStr for Assertion {
+    type Err = AssertionError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (pos, sym, op) = Op::ALL.iter()
+            .filter_map(|&(sym, op)| s.find(sym).map(|pos| (pos, sym, op)))
+            .min_by_key(|&(pos, sym, _)| (pos, usize::MAX - sym.len()))
+            .ok_or_else(|| AssertionError::NoOperator(s.into()))?;
+        let metric = s[..pos].trim().to_string();
+        let raw = s[pos + sym.len()..].trim();
+        let split = raw.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(raw.len());
+        let (number, unit) = raw.split_at(split);
+        let scale = match &*unit.trim().to_ascii_lowercase() {
+            "" | "b" => 1.0,
+            "kb" | "k" => 1e3,
+            "mb" | "m" => 1e6,
+            "kib" => 1024.0,
+            "mib" => 1024.0 * 1024.0,
+            _ => return Err(AssertionError::InvalidValue(raw.into())),
+        };
+        let value: f64 = number.trim().parse().map_err(|_| AssertionError::InvalidValue(raw.into()))?;
+        Ok(Self { metric, op, value: value * scale, source: s.trim().into() })
+    }
+}
+
+impl Display for Assertion {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        if self.source.is_empty() {
+            write!(fmt, "{} {} {}", self.metric, self.op.symbol(), self.value)
+        } else {
+            fmt.write_str(&self.source)
+        }
+    }
+}
+
+impl Assertion {
+    /// Evaluates this assertion, returning whether it held and the metric's actual value.
+    pub fn eval(&self, metrics: &BTreeMap<&'static str, f64>) -> Result<(bool, f64), AssertionError> {
+        let actual = *metrics.get(&*self.metric).ok_or_else(|| AssertionError::UnknownMetric(self.metric.clone()))?;
+        Ok((self.op.apply(actual, self.value), actual))
+    }
+}
+
+impl Diagnostic {
+    /// Turns a failed or invalid [Assertion] into a diagnostic, given the result of
+    /// [Assertion::eval].
+    pub fn from_assertion(assertion: &Assertion, result: Result<(bool, f64), AssertionError>) -> Option<Self> {
+        let message = match result {
+            Ok((true, _)) => return None,
+            Ok((false, actual)) => format!("assertion failed: {assertion} ({} = {actual})", assertion.metric),
+            Err(e) => format!("invalid assertion {assertion}: {e}"),
+        };
+        Some(Diagnostic { severity: Severity::Error, code: "assertion", message, file: None, part: None })
+    }
+
+    /// Turns a key found more than once by [Moon::read_checked] into a warning.
+    pub fn from_duplicate_key(duplicate: &DuplicateKey) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            code: "duplicate-key",
+            message: format!("{} appears more than once; only the last is kept", duplicate.path),
+            file: None,
+            part: None,
+        }
+    }
+}
+
+/// Converts diagnostics into a [SARIF](https://sarifweb.azurewebsites.net/) 2.1.0 log, which code
+/// scanning tools (such as GitHub's) can use to annotate the offending files. File paths are made
+/// relative to the repository root by prepending `prefix`, which should be the avatar's directory
+/// within the repository (or empty if the avatar is the repository). Diagnostics about the avatar
+/// as a whole are attached to its `avatar.json`.
+pub fn to_sarif(diagnostics: &[Diagnostic], prefix: &str) -> serde_json::Value {
+    use serde_json::json;
+    let prefix = if prefix.is_empty() || prefix.ends_with('/') { prefix.to_string() } else { format!("{prefix}/") };
+    let mut rules: Vec<&str> = diagnostics.iter().map(|d| d.code).collect();
+    rules.sort();
+    rules.dedup();
+    let results: Vec<_> = diagnostics.iter().map(|d| json!({
+        "ruleId": d.code,
+        "level": match d.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        },
+        "message": { "text": d.message },
+        "locations": [{
+            "physicalLocation": {
+                "artifactLocation": {
+                    "uri": format!("{prefix}{}", d.file.as_deref().unwrap_or("avatar.json")),
+                },
+            },
+            "logicalLocations": d.part.iter().map(|part| json!({ "fullyQualifiedName": part })).collect::<Vec<_>>(),
+        }],
+    })).collect();
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "fia",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules.iter().map(|id| json!({ "id": id })).collect::<Vec<_>>(),
+                },
+            },
+            "results": results,
+        }],
+    })
+}
@@ -0,0 +1,199 @@
+//! Talking to a Figura backend as a client, the way the mod does when uploading and equipping an
+//! avatar.
+//!
+//! The backend only hands out tokens to players who prove they own their Minecraft account. As
+//! when joining a server, this is done through Mojang's session server: the backend gives out a
+//! random server ID, the client tells the session server it is joining that "server" using its
+//! Minecraft access token, and the backend asks the session server whether that happened. A token
+//! stays valid for a while, so it can be [reused][Client::with_token] instead of logging in each
+//! time.
+
+use serde::Deserialize;
+use thiserror::Error;
+use url::Url;
+use uuid::Uuid;
+use crate::http::{self, HttpError, Response};
+
+/// The host name of the official backend.
+pub const DEFAULT_BACKEND: &str = "figura.moonlight-devs.org";
+
+/// An error encountered while talking to a backend.
+#[derive(Debug, Error)]
+pub enum CloudError {
+    /// The request couldn't be made.
+    #[error("{0}")]
+    Http(#[from] HttpError),
+    /// The backend didn't accept the token, or there wasn't one.
+    #[error("not logged in to the backend, or the token has expired")]
+    Unauthorized,
+    /// Mojang's session server didn't accept the Minecraft access token.
+    #[error("Minecraft's session server rejected the access token (status {0}); it may have expired")]
+    SessionRejected(u16),
+    /// The backend refused an avatar for being over the uploader's size limit.
+    #[error("the backend rejected the avatar for being too large ({0}B)")]
+    TooLarge(usize),
+    /// No Minecraft account has the given username.
+    #[error("no player is named {0}")]
+    UnknownPlayer(String),
+    /// The requested avatar doesn't exist.
+    #[error("{0} has no avatar named {1:?}")]
+    NoAvatar(Uuid, String),
+    /// A response that should have been JSON couldn't be understood.
+    #[error("malformed response: {0}")]
+    Malformed(#[source] serde_json::Error),
+    /// The backend responded with an unexpected status.
+    #[error("the backend responded with status {0}: {1}")]
+    Status(u16, String),
+}
+
+/// Turns unsuccessful responses into errors, recognizing the statuses the backend uses for
+/// authentication failures.
+fn check(response: Response) -> Result<Response, CloudError> {
+    match response.status {
+        _ if response.is_success() => Ok(response),
+        401 | 403 => Err(CloudError::Unauthorized),
+        status => Err(CloudError::Status(status, response.text().trim().to_string())),
+    }
+}
+
+/// Finds the UUID of the Minecraft account with the given username.
+pub fn lookup_player(username: &str) -> Result<Uuid, CloudError> {
+    let mut url = Url::parse("https://api.mojang.com/users/profiles/minecraft/").unwrap();
+    url.path_segments_mut().unwrap().pop_if_empty().push(username);
+    let response = http::get(&url, &[])?;
+    if matches!(response.status, 204 | 404) {
+        return Err(CloudError::UnknownPlayer(username.into()));
+    }
+    #[derive(Deserialize)]
+    struct Profile {
+        id: Uuid,
+    }
+    let profile: Profile = serde_json::from_slice(&check(response)?.body).map_err(CloudError::Malformed)?;
+    Ok(profile.id)
+}
+
+/// Finds a player by UUID, written with or without hyphens, or by username.
+pub fn resolve_player(player: &str) -> Result<Uuid, CloudError> {
+    match Uuid::try_parse(player) {
+        Ok(uuid) => Ok(uuid),
+        Err(_) => lookup_player(player),
+    }
+}
+
+/// An avatar a player has equipped, as listed in [UserInfo::equipped].
+#[derive(Clone, Debug, Deserialize)]
+pub struct EquippedAvatar {
+    /// The avatar's ID among its owner's avatars. Figura always uploads as `avatar`.
+    pub id: String,
+    /// The player who uploaded the avatar.
+    pub owner: Uuid,
+    /// The SHA-256 hash of the avatar file, in hex.
+    pub hash: String,
+}
+
+/// What the backend tells anyone about a player.
+#[derive(Clone, Debug, Deserialize)]
+pub struct UserInfo {
+    /// The player's UUID.
+    pub uuid: Uuid,
+    /// The player's permission tier, such as `default`.
+    #[serde(default)]
+    pub rank: String,
+    /// The avatars the player has equipped, usually one or none.
+    #[serde(default)]
+    pub equipped: Vec<EquippedAvatar>,
+    /// Whether the player is banned from uploading.
+    #[serde(default)]
+    pub banned: bool,
+}
+
+/// A connection to a Figura backend's HTTP API.
+#[derive(Clone, Debug)]
+pub struct Client {
+    api: Url,
+    token: Option<String>,
+}
+
+impl Client {
+    /// Talks to the backend at the given host (and optionally port), without logging in.
+    pub fn new(host: &str) -> Result<Self, url::ParseError> {
+        Ok(Self { api: Url::parse(&format!("https://{host}/api/"))?, token: None })
+    }
+
+    /// Talks to the backend with a token from an earlier [login][Self::login].
+    pub fn with_token(host: &str, token: String) -> Result<Self, url::ParseError> {
+        Ok(Self { token: Some(token), ..Self::new(host)? })
+    }
+
+    /// The token this connection uses, if it has logged in.
+    pub fn token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
+
+    fn url(&self, path: &str) -> Url {
+        self.api.join(path).expect("API paths are valid")
+    }
+
+    fn send(&self, method: &str, path: &str, extra: &[(&str, &str)], body: &[u8]) -> Result<Response, CloudError> {
+        let mut headers = extra.to_vec();
+        if let Some(token) = &self.token {
+            headers.push(("token", token));
+        }
+        Ok(http::request(method, &self.url(path), &headers, body)?)
+    }
+
+    /// Logs in as a player, proving ownership of the account with a Minecraft access token; see
+    /// [the module documentation][self].
+    pub fn login(&mut self, username: &str, uuid: Uuid, access_token: &str) -> Result<(), CloudError> {
+        let mut url = self.url("auth/id");
+        url.query_pairs_mut().append_pair("username", username);
+        let server_id = check(http::get(&url, &[])?)?.text().trim().to_string();
+        let join = serde_json::json!({
+            "accessToken": access_token,
+            "selectedProfile": uuid.simple().to_string(),
+            "serverId": server_id,
+        });
+        let session = Url::parse("https://sessionserver.mojang.com/session/minecraft/join").unwrap();
+        let response = http::request("POST", &session, &[("Content-Type", "application/json")], join.to_string().as_bytes())?;
+        if !response.is_success() {
+            return Err(CloudError::SessionRejected(response.status));
+        }
+        let mut url = self.url("auth/verify");
+        url.query_pairs_mut().append_pair("id", &server_id);
+        self.token = Some(check(http::get(&url, &[])?)?.text().trim().to_string());
+        Ok(())
+    }
+
+    /// Uploads an avatar file as the logged-in player's avatar, replacing the one uploaded before.
+    /// This doesn't [equip][Self::equip] it.
+    pub fn upload(&self, moon: &[u8]) -> Result<(), CloudError> {
+        let response = self.send("PUT", "avatar/avatar", &[("Content-Type", "application/octet-stream")], moon)?;
+        if response.status == 413 {
+            return Err(CloudError::TooLarge(moon.len()));
+        }
+        check(response).map(drop)
+    }
+
+    /// Looks up what the backend knows about a player, including which avatars they have
+    /// equipped.
+    pub fn user(&self, uuid: Uuid) -> Result<UserInfo, CloudError> {
+        let response = check(self.send("GET", &uuid.hyphenated().to_string(), &[], &[])?)?;
+        serde_json::from_slice(&response.body).map_err(CloudError::Malformed)
+    }
+
+    /// Downloads an avatar file by its owner and ID.
+    pub fn download(&self, owner: Uuid, id: &str) -> Result<Vec<u8>, CloudError> {
+        let response = self.send("GET", &format!("{}/{id}", owner.hyphenated()), &[], &[])?;
+        if response.status == 404 {
+            return Err(CloudError::NoAvatar(owner, id.into()));
+        }
+        Ok(check(response)?.body)
+    }
+
+    /// Makes the logged-in player's uploaded avatar the one other players see. `owner` is the
+    /// player's UUID.
+    pub fn equip(&self, owner: Uuid) -> Result<(), CloudError> {
+        let body = serde_json::json!([{ "id": "avatar", "owner": owner.hyphenated().to_string() }]);
+        check(self.send("POST", "equip", &[("Content-Type", "application/json")], body.to_string().as_bytes())?).map(drop)
+    }
+}
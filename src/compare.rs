@@ -0,0 +1,93 @@
+//! Tag-by-tag comparison of two avatars. This is mainly for checking fia's output against what
+//! Figura itself produces from the same source: any [Difference] is a field fia gets wrong (or
+//! that Figura writes nondeterministically).
+
+use std::fmt::{self, Display, Formatter};
+use quartz_nbt::io::NbtIoError;
+use quartz_nbt::{NbtCompound, NbtTag};
+use crate::Moon;
+
+/// One place where two avatars disagree. Paths are written like `models.chld[0].piv`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Difference {
+    /// The expected avatar has a tag the actual one lacks.
+    Missing(String, NbtTag),
+    /// The actual avatar has a tag the expected one lacks.
+    Extra(String, NbtTag),
+    /// Both avatars have the tag, but with different values or types.
+    Changed(String, NbtTag, NbtTag),
+}
+
+/// Describes a tag briefly, so that textures and scripts don't flood the output.
+fn summary(tag: &NbtTag) -> String {
+    match tag {
+        NbtTag::ByteArray(v) => format!("<{} bytes>", v.len()),
+        NbtTag::IntArray(v) => format!("<{} ints>", v.len()),
+        NbtTag::LongArray(v) => format!("<{} longs>", v.len()),
+        NbtTag::List(v) if v.len() > 8 => format!("<list of {}>", v.len()),
+        NbtTag::Compound(v) => format!("<compound of {}>", v.len()),
+        tag => tag.to_string(),
+    }
+}
+
+impl Display for Difference {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        match self {
+            Difference::Missing(path, tag) => write!(fmt, "- {path}: {}", summary(tag)),
+            Difference::Extra(path, tag) => write!(fmt, "+ {path}: {}", summary(tag)),
+            Difference::Changed(path, expected, actual) => write!(fmt, "~ {path}: {} → {}", summary(expected), summary(actual)),
+        }
+    }
+}
+
+/// Whether two tags are equal, allowing floats to differ by rounding.
+fn same_leaf(a: &NbtTag, b: &NbtTag) -> bool {
+    match (a, b) {
+        (NbtTag::Float(a), NbtTag::Float(b)) => (a - b).abs() <= f32::EPSILON * a.abs().max(b.abs()).max(1.0),
+        (NbtTag::Double(a), NbtTag::Double(b)) => (a - b).abs() <= f64::EPSILON * a.abs().max(b.abs()).max(1.0),
+        (a, b) => a == b,
+    }
+}
+
+/// Compares two tags, recording every difference under `path`.
+pub fn diff_tags(path: &str, expected: &NbtTag, actual: &NbtTag, out: &mut Vec<Difference>) {
+    match (expected, actual) {
+        (NbtTag::Compound(e), NbtTag::Compound(a)) => {
+            let mut keys: Vec<&String> = e.inner().keys().chain(a.inner().keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let path = if path.is_empty() { key.clone() } else { format!("{path}.{key}") };
+                match (e.inner().get(key), a.inner().get(key)) {
+                    (Some(e), Some(a)) => diff_tags(&path, e, a, out),
+                    (Some(e), None) => out.push(Difference::Missing(path, e.clone())),
+                    (None, Some(a)) => out.push(Difference::Extra(path, a.clone())),
+                    (None, None) => unreachable!(),
+                }
+            }
+        }
+        (NbtTag::List(e), NbtTag::List(a)) => {
+            let (e, a): (Vec<_>, Vec<_>) = (e.iter().collect(), a.iter().collect());
+            for i in 0..e.len().max(a.len()) {
+                let path = format!("{path}[{i}]");
+                match (e.get(i).copied(), a.get(i).copied()) {
+                    (Some(e), Some(a)) => diff_tags(&path, e, a, out),
+                    (Some(e), None) => out.push(Difference::Missing(path, e.clone())),
+                    (None, Some(a)) => out.push(Difference::Extra(path, a.clone())),
+                    (None, None) => unreachable!(),
+                }
+            }
+        }
+        (e, a) if !same_leaf(e, a) => out.push(Difference::Changed(path.into(), e.clone(), a.clone())),
+        _ => {}
+    }
+}
+
+/// Compares an avatar against the raw NBT of another, returning every difference in the order
+/// the tags sort in. The expected side is left as raw NBT so that tags [Moon] doesn't know about
+/// still show up.
+pub fn diff(expected: &NbtCompound, actual: &Moon) -> Result<Vec<Difference>, NbtIoError> {
+    let mut out = vec![];
+    diff_tags("", &NbtTag::Compound(expected.clone()), &NbtTag::Compound(actual.to_nbt()?), &mut out);
+    Ok(out)
+}
@@ -0,0 +1,133 @@
+//! A breakdown of an avatar's complexity: the number of faces Figura renders for it, which Figura
+//! limits per permission level. When an avatar goes over, [ComplexityReport] shows which groups
+//! are to blame.
+
+use std::fmt::{self, Display, Formatter};
+use std::iter::Sum;
+use std::ops::{Add, AddAssign};
+use serde::Serialize;
+use crate::moon::{ModelData, ModelPart};
+use crate::Moon;
+
+/// How much of an avatar's complexity a part (or group of parts) accounts for.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Complexity {
+    /// The number of cubes.
+    pub cubes: usize,
+    /// The number of meshes.
+    pub meshes: usize,
+    /// The number of cube faces that have a texture, and so are rendered.
+    pub cube_faces: usize,
+    /// The number of mesh faces.
+    pub mesh_faces: usize,
+    /// The number of distinct mesh vertices.
+    pub mesh_vertices: usize,
+}
+
+impl Complexity {
+    /// The number of faces rendered. This is what Figura counts towards its complexity limit.
+    pub fn faces(&self) -> usize {
+        self.cube_faces + self.mesh_faces
+    }
+
+    /// An estimate of the vertices sent to the GPU each frame. Figura draws every face as a quad,
+    /// including triangles (which repeat a vertex).
+    pub fn render_vertices(&self) -> usize {
+        self.faces() * 4
+    }
+}
+
+impl Add for Complexity {
+    type Output = Complexity;
+    fn add(mut self, other: Complexity) -> Complexity {
+        self += other;
+        self
+    }
+}
+
+impl AddAssign for Complexity {
+    fn add_assign(&mut self, other: Complexity) {
+        self.cubes += other.cubes;
+        self.meshes += other.meshes;
+        self.cube_faces += other.cube_faces;
+        self.mesh_faces += other.mesh_faces;
+        self.mesh_vertices += other.mesh_vertices;
+    }
+}
+
+impl Sum for Complexity {
+    fn sum<I: Iterator<Item = Complexity>>(iter: I) -> Complexity {
+        iter.fold(Complexity::default(), Add::add)
+    }
+}
+
+impl Display for Complexity {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        write!(fmt, "{} faces ({} cubes, {} meshes with {} vertices)", self.faces(), self.cubes, self.meshes, self.mesh_vertices)
+    }
+}
+
+impl ModelPart {
+    /// The complexity of this part alone, not counting its children.
+    pub fn own_complexity(&self) -> Complexity {
+        match &self.data {
+            ModelData::Group {} => Complexity::default(),
+            ModelData::Cube { cube_data, .. } => {
+                let faces = [&cube_data.n, &cube_data.s, &cube_data.u, &cube_data.d, &cube_data.w, &cube_data.e];
+                Complexity { cubes: 1, cube_faces: faces.iter().filter(|f| f.is_some()).count(), ..Default::default() }
+            }
+            ModelData::Mesh { mesh_data } => Complexity {
+                meshes: 1,
+                mesh_faces: mesh_data.faces().count(),
+                mesh_vertices: mesh_data.vertices().len(),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// The complexity of this part and all of its descendants.
+    pub fn complexity(&self) -> Complexity {
+        self.own_complexity() + self.chld.iter().map(ModelPart::complexity).sum()
+    }
+}
+
+/// The complexity of every part of an avatar.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ComplexityReport {
+    /// The complexity of the whole avatar.
+    pub total: Complexity,
+    /// The complexity of each part's subtree, by path under [Moon::models], most complex first.
+    /// Parts that render nothing are left out.
+    pub parts: Vec<(String, Complexity)>,
+}
+
+impl Display for ComplexityReport {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        writeln!(fmt, "{}", self.total)?;
+        for (path, complexity) in &self.parts {
+            writeln!(fmt, "• {path}: {complexity}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Moon {
+    /// Measures the complexity of every part of this avatar.
+    pub fn complexity_report(&self) -> ComplexityReport {
+        fn visit(part: &ModelPart, path: &str, out: &mut Vec<(String, Complexity)>) -> Complexity {
+            let mut complexity = part.own_complexity();
+            for child in part.chld.iter() {
+                let path = if path.is_empty() { child.name.clone() } else { format!("{path}/{}", child.name) };
+                complexity += visit(child, &path, out);
+            }
+            if !path.is_empty() && complexity.faces() > 0 {
+                out.push((path.into(), complexity));
+            }
+            complexity
+        }
+        let mut parts = vec![];
+        let total = self.models.as_ref().map_or_else(Complexity::default, |models| visit(models, "", &mut parts));
+        parts.sort_by(|a, b| b.1.faces().cmp(&a.1.faces()).then_with(|| a.0.cmp(&b.0)));
+        ComplexityReport { total, parts }
+    }
+}
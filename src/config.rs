@@ -0,0 +1,120 @@
+//! fia's configuration file, which holds defaults for options that would otherwise have to be
+//! given every time, such as which backend to use. It's read from `config.toml` in fia's
+//! configuration directory (usually `~/.config/fia`) unless `--config` names another file:
+//!
+//! ```toml
+//! backend = "figura.moonlight-devs.org"
+//! token-file = "~/.config/fia/token"
+//! compression = 9
+//! color = "never"
+//!
+//! [unpack]
+//! paths = ["*.lua", "avatar.json"]
+//! exclude = ["old/"]
+//! ```
+//!
+//! Options given on the command line always win over the configuration file.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use serde::Deserialize;
+use thiserror::Error;
+use crate::report::{CliError, ColorChoice};
+
+/// Defaults read from the configuration file. Everything is optional.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    /// Host name of the backend to use, optionally with a port.
+    pub backend: Option<String>,
+    /// A file holding a token from an earlier login, to use instead of logging in. Relative paths
+    /// are relative to the configuration file, and `~/` is the home directory.
+    pub token_file: Option<PathBuf>,
+    /// Compression level for `fia repack`, from 0 to 9.
+    pub compression: Option<u32>,
+    /// Whether to color output.
+    pub color: Option<ColorChoice>,
+    /// Defaults for `fia unpack`.
+    pub unpack: UnpackConfig,
+}
+
+/// Defaults for `fia unpack`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct UnpackConfig {
+    /// Glob patterns of the files to unpack, if not all.
+    pub paths: Option<Vec<String>>,
+    /// Glob patterns of files not to unpack.
+    pub exclude: Vec<String>,
+}
+
+/// An error encountered while loading the configuration file.
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    /// The file couldn't be read.
+    #[error("{0}: {1}")]
+    Io(PathBuf, #[source] io::Error),
+    /// The file isn't valid TOML, or has options that don't exist.
+    #[error("{0}: {1}")]
+    Toml(PathBuf, #[source] toml::de::Error),
+    /// An option has a value it can't take.
+    #[error("{0}: {1}")]
+    Invalid(PathBuf, String),
+}
+
+impl From<ConfigError> for CliError {
+    fn from(error: ConfigError) -> Self {
+        match error {
+            ConfigError::Io(path, e) => CliError::io("read", path, e),
+            ConfigError::Toml(path, e) => CliError::parse("load configuration", path, e),
+            error @ ConfigError::Invalid(..) => CliError::usage(error),
+        }
+    }
+}
+
+/// Where the configuration file is if `--config` isn't given: `fia/config.toml` in
+/// `$XDG_CONFIG_HOME` (or `~/.config`), or in `%APPDATA%` on Windows.
+pub fn default_path() -> Option<PathBuf> {
+    let dir = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()).map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+    dir.map(|dir| dir.join("fia/config.toml"))
+}
+
+/// Expands a path from the configuration file at `file`.
+fn resolve(path: &Path, file: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), std::env::var_os("HOME")) {
+        (Ok(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => file.parent().unwrap_or(Path::new("")).join(path),
+    }
+}
+
+impl Config {
+    /// Reads the configuration file at `path`.
+    pub fn read(path: &Path) -> Result<Self, ConfigError> {
+        let text = fs::read_to_string(path).map_err(|e| ConfigError::Io(path.into(), e))?;
+        let mut config: Config = toml::from_str(&text).map_err(|e| ConfigError::Toml(path.into(), e))?;
+        config.token_file = config.token_file.map(|token_file| resolve(&token_file, path));
+        if let Some(level @ 10..) = config.compression {
+            return Err(ConfigError::Invalid(path.into(), format!("compression must be from 0 to 9, not {level}")));
+        }
+        Ok(config)
+    }
+
+    /// Reads the configuration file at `path`, or at the [default path][default_path] if none is
+    /// given. Only the default configuration file may be missing, in which case every option is
+    /// left unset.
+    pub fn load(path: Option<&Path>) -> Result<Self, ConfigError> {
+        match path {
+            Some(path) => Self::read(path),
+            None => match default_path() {
+                Some(path) if path.exists() => Self::read(&path),
+                _ => Ok(Self::default()),
+            },
+        }
+    }
+}
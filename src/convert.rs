@@ -0,0 +1,198 @@
+//! Converting between the three forms an avatar can take: a moon, a Blockbench model, and an
+//! avatar folder. Every conversion goes through a [Moon]: folders are [packed][crate::pack] and
+//! models compiled on the way in, and on the way out models are decompiled and folders written
+//! out as `fia unpack` does.
+//!
+//! A moon can hold several models while a Blockbench model is only one, so converting to a model
+//! needs one to be picked unless there's only one.
+
+use std::fmt::{self, Display, Formatter};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use quartz_nbt::io::{Flavor, NbtIoError};
+use thiserror::Error;
+use crate::bbmodel::{self, BBModel};
+use crate::moon::{self, ModelPart, UnsafePath};
+use crate::pack::PackError;
+use crate::report::CliError;
+use crate::{glob, Moon};
+
+/// A form an avatar can be stored in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Format {
+    /// A moon, as Figura uploads and caches avatars.
+    Moon,
+    /// A single Blockbench model.
+    BBModel,
+    /// An avatar folder, as found in Figura's wardrobe.
+    Dir,
+}
+
+impl Format {
+    /// Guesses the format of `path` from its extension: `.bbmodel` is a model, any other
+    /// extension a moon, and no extension a folder. Existing folders are always folders.
+    pub fn infer(path: &Path) -> Self {
+        if path.is_dir() {
+            return Format::Dir;
+        }
+        match path.extension() {
+            Some(ext) if ext == "bbmodel" => Format::BBModel,
+            Some(_) => Format::Moon,
+            None => Format::Dir,
+        }
+    }
+}
+
+/// An error from parsing a [Format] that doesn't exist.
+#[derive(Debug, Error)]
+#[error("unknown format {0:?} (expected moon, bbmodel, or dir)")]
+pub struct UnknownFormat(pub String);
+
+impl FromStr for Format {
+    type Err = UnknownFormat;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "moon" => Ok(Format::Moon),
+            "bbmodel" => Ok(Format::BBModel),
+            "dir" => Ok(Format::Dir),
+            _ => Err(UnknownFormat(s.into())),
+        }
+    }
+}
+
+impl Display for Format {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        fmt.write_str(match self {
+            Format::Moon => "moon",
+            Format::BBModel => "bbmodel",
+            Format::Dir => "dir",
+        })
+    }
+}
+
+/// An error encountered while converting an avatar.
+#[derive(Debug, Error)]
+pub enum ConvertError {
+    /// A file couldn't be read or written.
+    #[error("{0}: {1}")]
+    Io(PathBuf, #[source] io::Error),
+    /// A moon couldn't be read or written.
+    #[error("{0}: {1}")]
+    Nbt(PathBuf, #[source] NbtIoError),
+    /// A folder or model couldn't be packed.
+    #[error("{0}")]
+    Pack(#[from] PackError),
+    /// A model or `avatar.json` couldn't be written.
+    #[error("{0}: {1}")]
+    Json(PathBuf, #[source] serde_json::Error),
+    /// The avatar has no models to convert to a Blockbench model.
+    #[error("avatar has no models")]
+    NoModels,
+    /// The avatar has several models, and none was picked.
+    #[error("avatar has several models, so one must be picked: {}", .0.join(", "))]
+    AmbiguousModel(Vec<String>),
+    /// The picked model doesn't exist.
+    #[error("avatar has no model named {0}")]
+    NoSuchModel(String),
+    /// Something in the avatar is named in a way that can't be written to a folder.
+    #[error("{0}")]
+    UnsafePath(#[from] UnsafePath),
+}
+
+impl From<ConvertError> for CliError {
+    fn from(error: ConvertError) -> Self {
+        match error {
+            ConvertError::Io(path, e) => CliError::io("access", path, e),
+            ConvertError::Nbt(path, e) => CliError::parse("load avatar", path, e),
+            ConvertError::Pack(e) => e.into(),
+            ConvertError::Json(_, e) => CliError::failed("serialize avatar", e),
+            ConvertError::UnsafePath(e) => CliError::failed("unpack avatar", e),
+            ConvertError::NoModels | ConvertError::AmbiguousModel(_) | ConvertError::NoSuchModel(_) => CliError::usage(error),
+        }
+    }
+}
+
+/// Reads an avatar in any format.
+pub fn read(path: &Path, format: Format) -> Result<Moon, ConvertError> {
+    match format {
+        Format::Moon => {
+            let file = File::open(path).map_err(|e| ConvertError::Io(path.into(), e))?;
+            Moon::read_auto(file).map(|(moon, _)| moon).map_err(|e| ConvertError::Nbt(path.into(), e))
+        }
+        Format::BBModel => Ok(Moon::pack_bbmodel(path)?),
+        Format::Dir => Ok(Moon::pack_dir(path)?),
+    }
+}
+
+/// Writes an avatar in any format. `model` picks which of the avatar's models to write as a
+/// Blockbench model, and is ignored for other formats.
+pub fn write(moon: &Moon, path: &Path, format: Format, model: Option<&str>) -> Result<(), ConvertError> {
+    match format {
+        Format::Moon => {
+            let data = moon.to_canonical_bytes("", Flavor::GzCompressed).map_err(|e| ConvertError::Nbt(path.into(), e))?;
+            fs::write(path, data).map_err(|e| ConvertError::Io(path.into(), e))
+        }
+        Format::BBModel => {
+            let part = moon.pick_model(model)?;
+            let data = moon.decompile_model(part).to_canonical_vec().map_err(|e| ConvertError::Json(path.into(), e))?;
+            fs::write(path, data).map_err(|e| ConvertError::Io(path.into(), e))
+        }
+        Format::Dir => {
+            for (name, data) in moon.unpacked_files()? {
+                let file = path.join(moon::relative_path(&name)?);
+                if let Some(dir) = file.parent() {
+                    fs::create_dir_all(dir).map_err(|e| ConvertError::Io(dir.into(), e))?;
+                }
+                fs::write(&file, data).map_err(|e| ConvertError::Io(file, e))?;
+            }
+            Ok(())
+        }
+    }
+}
+
+impl Moon {
+    /// Finds the top-level model named `name`, or the only one if no name is given.
+    pub fn pick_model(&self, name: Option<&str>) -> Result<&ModelPart, ConvertError> {
+        let parts = self.models.as_ref().map_or(&[][..], |models| &models.chld);
+        match (name, parts) {
+            (Some(name), _) => parts.iter().find(|part| part.name == name).ok_or_else(|| ConvertError::NoSuchModel(name.into())),
+            (None, []) => Err(ConvertError::NoModels),
+            (None, [part]) => Ok(part),
+            (None, parts) => Err(ConvertError::AmbiguousModel(parts.iter().map(|part| part.name.clone()).collect())),
+        }
+    }
+
+    /// Turns one of this avatar's top-level models back into a Blockbench model, with its
+    /// textures and animations.
+    pub fn decompile_model(&self, part: &ModelPart) -> BBModel {
+        let mut model = bbmodel::Hierarchy::from_model_part(part).into_bbmodel(&self.textures);
+        model.animations = bbmodel::animations_from_moon(part, &self.animations);
+        model
+    }
+
+    /// Lists the files of the folder this avatar would be packed from: its [files][Self::files],
+    /// a Blockbench model for each top-level model, and an `avatar.json` that lists its
+    /// resources. The list is sorted by path.
+    pub fn unpacked_files(&self) -> Result<Vec<(String, Vec<u8>)>, ConvertError> {
+        let mut files: Vec<(String, Vec<u8>)> = self.files()?.into_iter().map(|(path, data)| (path, data.to_vec())).collect();
+        if let Some(models) = &self.models {
+            for part in models.chld.iter() {
+                let name = part.name.replace('.', "/") + ".bbmodel";
+                let model = self.decompile_model(part).to_canonical_vec().map_err(|e| ConvertError::Json(name.clone().into(), e))?;
+                files.push((name, model));
+            }
+        }
+        let mut avatar_json = self.metadata.avatar_json();
+        if !self.resources.is_empty() {
+            let mut names: Vec<_> = self.resources.keys().map(|name| glob::escape(name)).collect();
+            names.sort();
+            avatar_json["resources"] = names.into();
+        }
+        let avatar_json = serde_json::to_vec_pretty(&avatar_json).map_err(|e| ConvertError::Json("avatar.json".into(), e))?;
+        files.push(("avatar.json".into(), avatar_json));
+        files.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(files)
+    }
+}
@@ -0,0 +1,533 @@
+//! Comparing two avatars by what they contain, for reviewing what changed between two versions of
+//! an avatar. Unlike [compare][crate::compare], which lists every differing tag, this groups
+//! changes the way they would be made: scripts (with line diffs), textures, other files, metadata
+//! fields, and model parts.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::{self, Display, Formatter};
+use quartz_nbt::serde::Array;
+use serde::Serialize;
+use crate::image;
+use crate::moon::ModelPart;
+use crate::Moon;
+
+/// How something differs between the old and new avatars.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Change {
+    /// Only the new avatar has it.
+    Added,
+    /// Only the old avatar has it.
+    Removed,
+    /// Both avatars have it, but it differs.
+    Changed,
+}
+
+impl Display for Change {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        fmt.write_str(match self {
+            Change::Added => "+",
+            Change::Removed => "-",
+            Change::Changed => "~",
+        })
+    }
+}
+
+/// A script that differs.
+#[derive(Clone, Debug, Serialize)]
+pub struct ScriptDiff {
+    /// The script's name.
+    pub name: String,
+    /// How it differs.
+    pub change: Change,
+    /// A unified diff from the old script to the new one, with added and removed scripts compared
+    /// against nothing.
+    pub diff: String,
+}
+
+/// What [AvatarDiff] reports about one version of a texture.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct TextureInfo {
+    /// The size of the PNG, in bytes.
+    pub bytes: usize,
+    /// The image's width and height, if it could be read.
+    pub dimensions: Option<(u32, u32)>,
+}
+
+impl Display for TextureInfo {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        write!(fmt, "{}B", self.bytes)?;
+        if let Some((width, height)) = self.dimensions {
+            write!(fmt, " {width}×{height}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A texture image that differs.
+#[derive(Clone, Debug, Serialize)]
+pub struct TextureDiff {
+    /// The texture's name.
+    pub name: String,
+    /// How it differs.
+    pub change: Change,
+    /// The texture in the old avatar, if it has it.
+    pub old: Option<TextureInfo>,
+    /// The texture in the new avatar, if it has it.
+    pub new: Option<TextureInfo>,
+}
+
+/// A sound or resource that differs. Only sizes are compared, since these are opaque.
+#[derive(Clone, Debug, Serialize)]
+pub struct FileDiff {
+    /// The file's name.
+    pub name: String,
+    /// How it differs.
+    pub change: Change,
+    /// The file's size in the old avatar, if it has it.
+    pub old: Option<usize>,
+    /// The file's size in the new avatar, if it has it.
+    pub new: Option<usize>,
+}
+
+/// A field of the [metadata][crate::moon::Metadata] that differs, as it appears in `avatar.json`.
+#[derive(Clone, Debug, Serialize)]
+pub struct MetadataDiff {
+    /// The field's name.
+    pub field: String,
+    /// The old value, or null if it was unset.
+    pub old: serde_json::Value,
+    /// The new value, or null if it is unset.
+    pub new: serde_json::Value,
+}
+
+/// A model part that differs.
+#[derive(Clone, Debug, Serialize)]
+pub struct PartDiff {
+    /// The path to the part, such as `models/player/Head`. Where siblings share a name, the second
+    /// and later are numbered, as in `Head#2`.
+    pub path: String,
+    /// How it differs. Parts under an added or removed part aren't listed separately.
+    pub change: Change,
+    /// For changed parts, which of their properties differ, such as `rot` or `geometry`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub properties: Vec<&'static str>,
+}
+
+/// Everything that differs between two avatars, from [diff].
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct AvatarDiff {
+    /// Scripts that differ, by name.
+    pub scripts: Vec<ScriptDiff>,
+    /// Texture images that differ, by name.
+    pub textures: Vec<TextureDiff>,
+    /// Sounds that differ, by name.
+    pub sounds: Vec<FileDiff>,
+    /// Resources that differ, by name.
+    pub resources: Vec<FileDiff>,
+    /// Metadata fields that differ, by name.
+    pub metadata: Vec<MetadataDiff>,
+    /// Model parts that differ, parents before children.
+    pub parts: Vec<PartDiff>,
+}
+
+impl AvatarDiff {
+    /// Whether the avatars have no differences that are compared.
+    pub fn is_empty(&self) -> bool {
+        self.scripts.is_empty() && self.textures.is_empty() && self.sounds.is_empty()
+            && self.resources.is_empty() && self.metadata.is_empty() && self.parts.is_empty()
+    }
+
+    /// How many differences there are.
+    pub fn len(&self) -> usize {
+        self.scripts.len() + self.textures.len() + self.sounds.len() + self.resources.len() + self.metadata.len() + self.parts.len()
+    }
+}
+
+impl Display for AvatarDiff {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        let size = |size: Option<usize>| size.map_or("nothing".into(), |size| format!("{size}B"));
+        for script in &self.scripts {
+            writeln!(fmt, "{} script {}", script.change, script.name)?;
+            fmt.write_str(&script.diff)?;
+        }
+        for texture in &self.textures {
+            let info = |info: Option<TextureInfo>| info.map_or("nothing".into(), |info| info.to_string());
+            writeln!(fmt, "{} texture {}: {} → {}", texture.change, texture.name, info(texture.old), info(texture.new))?;
+        }
+        for sound in &self.sounds {
+            writeln!(fmt, "{} sound {}: {} → {}", sound.change, sound.name, size(sound.old), size(sound.new))?;
+        }
+        for resource in &self.resources {
+            writeln!(fmt, "{} resource {}: {} → {}", resource.change, resource.name, size(resource.old), size(resource.new))?;
+        }
+        for field in &self.metadata {
+            writeln!(fmt, "~ metadata {}: {} → {}", field.field, field.old, field.new)?;
+        }
+        for part in &self.parts {
+            write!(fmt, "{} part {}", part.change, part.path)?;
+            if !part.properties.is_empty() {
+                write!(fmt, " ({})", part.properties.join(", "))?;
+            }
+            writeln!(fmt)?;
+        }
+        Ok(())
+    }
+}
+
+/// One step of turning the old lines into the new ones.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Edit {
+    Keep,
+    Delete,
+    Insert,
+}
+
+/// Finds a shortest edit script from `a` to `b`, with the linear-space variant of Myers'
+/// algorithm: rather than keeping every step of the search to trace the path back, it finds the
+/// middle of a shortest path and does each half separately.
+fn edits(a: &[&str], b: &[&str]) -> Vec<Edit> {
+    let mut out = Vec::with_capacity(a.len().max(b.len()));
+    push_edits(a, b, &mut out);
+    out
+}
+
+/// Appends a shortest edit script from `a` to `b` to `out`.
+fn push_edits(a: &[&str], b: &[&str], out: &mut Vec<Edit>) {
+    // the common ends don't need searching
+    let prefix = a.iter().zip(b).take_while(|(a, b)| a == b).count();
+    let suffix = a[prefix..].iter().rev().zip(b[prefix..].iter().rev()).take_while(|(a, b)| a == b).count();
+    let (a, b) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+    out.extend(std::iter::repeat(Edit::Keep).take(prefix));
+    if a.is_empty() {
+        out.extend(std::iter::repeat(Edit::Insert).take(b.len()));
+    } else if b.is_empty() {
+        out.extend(std::iter::repeat(Edit::Delete).take(a.len()));
+    } else {
+        // with the ends trimmed, both sides need at least one edit, so each half needs fewer
+        // edits than the whole
+        let (x, y, u, v) = middle_snake(a, b);
+        push_edits(&a[..x], &b[..y], out);
+        out.extend(std::iter::repeat(Edit::Keep).take(u - x));
+        push_edits(&a[u..], &b[v..], out);
+    }
+    out.extend(std::iter::repeat(Edit::Keep).take(suffix));
+}
+
+/// Finds the snake (a run of matching lines) in the middle of a shortest path from `a` to `b`,
+/// by searching forwards from the start and backwards from the end until the searches overlap.
+/// Returns where it starts in `a` and `b` and where it ends, which may be the same place.
+fn middle_snake(a: &[&str], b: &[&str]) -> (usize, usize, usize, usize) {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let delta = n - m;
+    let max = (n + m + 1) / 2;
+    let offset = max + 1;
+    // forward[k + offset] is the furthest x reached on diagonal k = x - y from the start;
+    // backward[c + offset] is the same going back from the end, with x and y counted from the end
+    // and c = delta - k
+    let mut forward = vec![0; 2 * offset as usize + 1];
+    let mut backward = vec![0; 2 * offset as usize + 1];
+    let furthest = |v: &[isize], k: isize, d: isize| {
+        let i = (k + offset) as usize;
+        if k == -d || (k != d && v[i - 1] < v[i + 1]) { v[i + 1] } else { v[i - 1] + 1 }
+    };
+    for d in 0..=max {
+        for k in (-d..=d).step_by(2) {
+            let x0 = furthest(&forward, k, d);
+            let (mut x, mut y) = (x0, x0 - k);
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                (x, y) = (x + 1, y + 1);
+            }
+            forward[(k + offset) as usize] = x;
+            // with an odd delta, the paths can first meet while going forwards
+            let c = delta - k;
+            if delta % 2 != 0 && (1 - d..=d - 1).contains(&c) && x + backward[(c + offset) as usize] >= n {
+                return (x0 as usize, (x0 - k) as usize, x as usize, y as usize);
+            }
+        }
+        for c in (-d..=d).step_by(2) {
+            let x0 = furthest(&backward, c, d);
+            let (mut x, mut y) = (x0, x0 - c);
+            while x < n && y < m && a[(n - x - 1) as usize] == b[(m - y - 1) as usize] {
+                (x, y) = (x + 1, y + 1);
+            }
+            backward[(c + offset) as usize] = x;
+            let k = delta - c;
+            if delta % 2 == 0 && (-d..=d).contains(&k) && x + forward[(k + offset) as usize] >= n {
+                return ((n - x) as usize, (m - y) as usize, (n - x0) as usize, (m - x0 + c) as usize);
+            }
+        }
+    }
+    unreachable!("the searches meet within (n + m) / 2 edits each")
+}
+
+/// Writes a unified diff from `old` to `new`, with `context` unchanged lines around each change,
+/// as `diff -u` would. Returns an empty string if the texts are the same.
+pub fn unified(old: &str, new: &str, old_name: &str, new_name: &str, context: usize) -> String {
+    let (a, b): (Vec<&str>, Vec<&str>) = (old.lines().collect(), new.lines().collect());
+    let edits = edits(&a, &b);
+    // each edit's line in a and b
+    let mut positions = Vec::with_capacity(edits.len());
+    let (mut x, mut y) = (0, 0);
+    for edit in &edits {
+        positions.push((x, y));
+        match edit {
+            Edit::Keep => (x, y) = (x + 1, y + 1),
+            Edit::Delete => x += 1,
+            Edit::Insert => y += 1,
+        }
+    }
+    let changes: Vec<usize> = (0..edits.len()).filter(|&i| edits[i] != Edit::Keep).collect();
+    if changes.is_empty() {
+        return String::new();
+    }
+    let mut out = format!("--- {old_name}\n+++ {new_name}\n");
+    let mut i = 0;
+    while i < changes.len() {
+        // extend the hunk while the next change's context would overlap this one's
+        let mut j = i;
+        while j + 1 < changes.len() && changes[j + 1] - changes[j] <= 2 * context + 1 {
+            j += 1;
+        }
+        let start = changes[i].saturating_sub(context);
+        let end = (changes[j] + context + 1).min(edits.len());
+        let (x, y) = positions[start];
+        let old_len = edits[start..end].iter().filter(|&&e| e != Edit::Insert).count();
+        let new_len = edits[start..end].iter().filter(|&&e| e != Edit::Delete).count();
+        // empty ranges are numbered by the line before them
+        let line = |start: usize, len: usize| if len == 0 { start } else { start + 1 };
+        out += &format!("@@ -{},{old_len} +{},{new_len} @@\n", line(x, old_len), line(y, new_len));
+        for (edit, &(x, y)) in edits[start..end].iter().zip(&positions[start..end]) {
+            match edit {
+                Edit::Keep => out += &format!(" {}\n", a[x]),
+                Edit::Delete => out += &format!("-{}\n", a[x]),
+                Edit::Insert => out += &format!("+{}\n", b[y]),
+            }
+        }
+        i = j + 1;
+    }
+    out
+}
+
+/// Pairs up the entries of two maps by name, in name order.
+fn pair<'a, T>(old: &'a HashMap<String, T>, new: &'a HashMap<String, T>) -> impl Iterator<Item = (&'a String, Option<&'a T>, Option<&'a T>)> {
+    let names: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    names.into_iter().map(|name| (name, old.get(name), new.get(name)))
+}
+
+fn change<T>(old: &Option<T>, new: &Option<T>) -> Change {
+    match (old, new) {
+        (None, _) => Change::Added,
+        (_, None) => Change::Removed,
+        _ => Change::Changed,
+    }
+}
+
+fn diff_files(old: &HashMap<String, Array<Vec<u8>>>, new: &HashMap<String, Array<Vec<u8>>>) -> Vec<FileDiff> {
+    pair(old, new).filter(|(_, old, new)| old != new).map(|(name, old, new)| {
+        let (old, new) = (old.map(|data| data.as_ref().len()), new.map(|data| data.as_ref().len()));
+        FileDiff { name: name.clone(), change: change(&old, &new), old, new }
+    }).collect()
+}
+
+/// The properties of two parts that differ, not counting their children.
+fn changed_properties(old: &ModelPart, new: &ModelPart) -> Vec<&'static str> {
+    let mut out = vec![];
+    let mut check = |name, same: bool| if !same { out.push(name) };
+    check("rot", old.rot == new.rot);
+    check("piv", old.piv == new.piv);
+    check("pt", old.pt == new.pt);
+    check("primary", old.primary == new.primary);
+    check("secondary", old.secondary == new.secondary);
+    check("vsb", old.vsb == new.vsb);
+    check("smo", old.smo == new.smo);
+    check("anim", old.anim == new.anim);
+    if std::mem::discriminant(&old.data) != std::mem::discriminant(&new.data) {
+        out.push("type");
+    } else if format!("{:?}", old.data) != format!("{:?}", new.data) {
+        out.push("geometry");
+    }
+    out
+}
+
+/// Names children by their name, numbering later siblings with the same name.
+fn keyed_children(part: &ModelPart) -> BTreeMap<String, &ModelPart> {
+    let mut seen = HashMap::new();
+    part.chld.iter().map(|child| {
+        let count = seen.entry(&child.name).or_insert(0);
+        *count += 1;
+        let key = if *count == 1 { child.name.clone() } else { format!("{}#{count}", child.name) };
+        (key, child)
+    }).collect()
+}
+
+fn diff_parts(path: &str, old: &ModelPart, new: &ModelPart, out: &mut Vec<PartDiff>) {
+    let properties = changed_properties(old, new);
+    if !properties.is_empty() {
+        out.push(PartDiff { path: path.into(), change: Change::Changed, properties });
+    }
+    let (old, new) = (keyed_children(old), keyed_children(new));
+    let names: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    for name in names {
+        let path = format!("{path}/{name}");
+        match (old.get(name), new.get(name)) {
+            (Some(old), Some(new)) => diff_parts(&path, old, new, out),
+            (old, new) => out.push(PartDiff { path, change: change(&old, &new), properties: vec![] }),
+        }
+    }
+}
+
+/// Compares two avatars; see [the module documentation][self].
+pub fn diff(old: &Moon, new: &Moon) -> AvatarDiff {
+    let scripts = pair(&old.scripts, &new.scripts).filter(|(_, old, new)| old != new).map(|(name, old_script, new_script)| {
+        let text = |script: Option<&Array<Vec<u8>>>| script.map_or_else(String::new, |s| String::from_utf8_lossy(s.as_ref()).into_owned());
+        let (old_name, new_name) = match (old_script, new_script) {
+            (None, _) => ("/dev/null".into(), format!("b/{name}")),
+            (_, None) => (format!("a/{name}"), "/dev/null".into()),
+            _ => (format!("a/{name}"), format!("b/{name}")),
+        };
+        ScriptDiff {
+            name: name.clone(),
+            change: change(&old_script, &new_script),
+            diff: unified(&text(old_script), &text(new_script), &old_name, &new_name, 3),
+        }
+    }).collect();
+
+    let textures = pair(&old.textures.src, &new.textures.src).filter(|(_, old, new)| old != new).map(|(name, old, new)| {
+        let info = |png: Option<&Array<Vec<u8>>>| png.map(|png| TextureInfo {
+            bytes: png.as_ref().len(),
+            dimensions: image::dimensions(png.as_ref()).ok(),
+        });
+        let (old, new) = (info(old), info(new));
+        TextureDiff { name: name.clone(), change: change(&old, &new), old, new }
+    }).collect();
+
+    let mut metadata = vec![];
+    let (serde_json::Value::Object(old_meta), serde_json::Value::Object(new_meta)) = (
+        serde_json::to_value(&old.metadata).expect("metadata serializes to JSON"),
+        serde_json::to_value(&new.metadata).expect("metadata serializes to JSON"),
+    ) else { unreachable!("metadata is a struct") };
+    let fields: BTreeSet<&String> = old_meta.keys().chain(new_meta.keys()).collect();
+    for field in fields {
+        let (old, new) = (old_meta.get(field), new_meta.get(field));
+        if old != new {
+            metadata.push(MetadataDiff {
+                field: field.clone(),
+                old: old.cloned().unwrap_or_default(),
+                new: new.cloned().unwrap_or_default(),
+            });
+        }
+    }
+
+    let mut parts = vec![];
+    match (&old.models, &new.models) {
+        (Some(old), Some(new)) => diff_parts(&old.name, old, new, &mut parts),
+        (None, None) => {}
+        (old, new) => {
+            let path = old.as_ref().or(new.as_ref()).map(|part| part.name.clone()).unwrap_or_default();
+            parts.push(PartDiff { path, change: change(old, new), properties: vec![] });
+        }
+    }
+
+    AvatarDiff {
+        scripts,
+        textures,
+        sounds: diff_files(&old.sounds, &new.sounds),
+        resources: diff_files(&old.resources, &new.resources),
+        metadata,
+        parts,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Edit::*;
+
+    /// Finds the edits between two strings of single-letter lines, checking that they turn one
+    /// into the other.
+    fn check(a: &str, b: &str) -> Vec<Edit> {
+        let (a, b): (Vec<&str>, Vec<&str>) = (a.split_terminator("").skip(1).collect(), b.split_terminator("").skip(1).collect());
+        let edits = edits(&a, &b);
+        let (mut x, mut y) = (0, 0);
+        for edit in &edits {
+            match edit {
+                Keep => {
+                    assert_eq!(a[x], b[y], "{edits:?} keeps different lines");
+                    (x, y) = (x + 1, y + 1);
+                }
+                Delete => x += 1,
+                Insert => y += 1,
+            }
+        }
+        assert_eq!((x, y), (a.len(), b.len()), "{edits:?} doesn't cover both sides");
+        edits
+    }
+
+    fn changes(edits: &[Edit]) -> usize {
+        edits.iter().filter(|&&e| e != Keep).count()
+    }
+
+    #[test]
+    fn diffs_empty_inputs() {
+        assert_eq!(check("", ""), []);
+        assert_eq!(check("", "ab"), [Insert, Insert]);
+        assert_eq!(check("ab", ""), [Delete, Delete]);
+    }
+
+    #[test]
+    fn diffs_equal_inputs() {
+        assert_eq!(check("abc", "abc"), [Keep; 3]);
+        assert_eq!(unified("a\nb\n", "a\nb\n", "old", "new", 3), "");
+    }
+
+    #[test]
+    fn diffs_insertions() {
+        assert_eq!(check("ac", "abc"), [Keep, Insert, Keep]);
+        assert_eq!(check("bd", "abcde"), [Insert, Keep, Insert, Keep, Insert]);
+    }
+
+    #[test]
+    fn diffs_deletions() {
+        assert_eq!(check("abc", "ac"), [Keep, Delete, Keep]);
+        assert_eq!(check("abcde", "bd"), [Delete, Keep, Delete, Keep, Delete]);
+    }
+
+    #[test]
+    fn diffs_interleaved_changes() {
+        // the examples from Myers' paper, which need 5 edits
+        assert_eq!(changes(&check("abcabba", "cbabac")), 5);
+        assert_eq!(changes(&check("cbabac", "abcabba")), 5);
+        assert_eq!(changes(&check("abcdefg", "axcyegz")), 6);
+        assert_eq!(changes(&check("xaxbxcx", "aybycy")), 7);
+        assert_eq!(changes(&check("abab", "baba")), 2);
+    }
+
+    #[test]
+    fn finds_shortest_scripts() {
+        // every pair of strings of up to 5 a's and b's, against the longest common subsequence
+        let strings: Vec<String> = (0..=5u32)
+            .flat_map(|len| (0..1 << len).map(move |bits| (0..len).map(|i| if bits >> i & 1 == 0 { 'a' } else { 'b' }).collect()))
+            .collect();
+        for a in &strings {
+            for b in &strings {
+                let (x, y): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+                let mut lcs = vec![vec![0; y.len() + 1]; x.len() + 1];
+                for i in 0..x.len() {
+                    for j in 0..y.len() {
+                        lcs[i + 1][j + 1] = if x[i] == y[j] { lcs[i][j] + 1 } else { lcs[i][j + 1].max(lcs[i + 1][j]) };
+                    }
+                }
+                let shortest = x.len() + y.len() - 2 * lcs[x.len()][y.len()];
+                assert_eq!(changes(&check(a, b)), shortest, "{a:?} to {b:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn writes_unified_hunks() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\n";
+        let new = "a\nB\nc\nd\ne\nf\ng\nh\ni\n";
+        assert_eq!(unified(old, new, "old", "new", 1), "--- old\n+++ new\n@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n@@ -8,1 +8,2 @@\n h\n+i\n");
+    }
+}
@@ -0,0 +1,349 @@
+//! Documentation for an avatar's scripts, as `fia docgen` generates, so that library authors don't
+//! have to keep it up to date by hand. It's taken from LuaDoc/EmmyLua comments: lines starting
+//! with `---` just above what they describe, with `---@param name type description` and
+//! `---@return type description` for parameters and return values, and `---@private` to leave
+//! something out:
+//!
+//! ```lua
+//! --- Waves with one arm.
+//! ---@param arm string "left" or "right"
+//! function pings.wave(arm)
+//! end
+//! ```
+//!
+//! Three things are documented: functions a script exports (fields of the table it returns, and
+//! global functions), pings, and actions added to the action wheel. Scripts are read line by line
+//! rather than parsed, so definitions have to start their line to be found.
+
+use std::fmt::{self, Display, Formatter};
+use std::sync::LazyLock;
+use regex::Regex;
+use serde::Serialize;
+use crate::Moon;
+
+/// A parameter of a documented function.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Param {
+    /// The parameter's name.
+    pub name: String,
+    /// Its type, if documented.
+    #[serde(rename = "type")]
+    pub ty: Option<String>,
+    /// Whether it may be left out, as marked by a `?` after its name or type in `@param`.
+    pub optional: bool,
+    /// What it's for, if documented.
+    pub description: Option<String>,
+}
+
+/// A return value of a documented function.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Return {
+    /// The value's type.
+    #[serde(rename = "type")]
+    pub ty: String,
+    /// What it is, if documented.
+    pub description: Option<String>,
+}
+
+/// A documented function or ping.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct FunctionDoc {
+    /// The function's name, such as `util.lerp` or `pings.wave`.
+    pub name: String,
+    /// The line it's defined on, counting from 1.
+    pub line: usize,
+    /// Its doc comment, without tags.
+    pub description: String,
+    /// Its parameters, in order.
+    pub params: Vec<Param>,
+    /// Its return values, in order.
+    pub returns: Vec<Return>,
+}
+
+/// An action added to the action wheel.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ActionDoc {
+    /// The action's title, if it's set to a string literal.
+    pub title: Option<String>,
+    /// The item shown for it, if it's set to a string literal.
+    pub item: Option<String>,
+    /// The line it's created on, counting from 1.
+    pub line: usize,
+    /// Its doc comment, without tags.
+    pub description: String,
+    /// What it does, as the name of each callback it's given (such as `onLeftClick`) and what it's
+    /// given as written, or `function` for one defined in place.
+    pub handlers: Vec<(String, String)>,
+}
+
+/// The documentation of one script.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ScriptDoc {
+    /// The script's name.
+    pub script: String,
+    /// The doc comment at the very top of the script, if it has one (separated from whatever comes
+    /// next by a blank line).
+    pub description: String,
+    /// The functions it exports.
+    pub functions: Vec<FunctionDoc>,
+    /// The pings it defines.
+    pub pings: Vec<FunctionDoc>,
+    /// The actions it adds to the action wheel.
+    pub actions: Vec<ActionDoc>,
+}
+
+impl ScriptDoc {
+    /// Whether there's nothing to document.
+    pub fn is_empty(&self) -> bool {
+        self.description.is_empty() && self.functions.is_empty() && self.pings.is_empty() && self.actions.is_empty()
+    }
+}
+
+/// The documentation of an avatar's scripts, from [docgen]. [Displayed][Display] as Markdown.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Docs {
+    /// The avatar's name.
+    pub name: String,
+    /// Each script with anything to document, sorted by name.
+    pub scripts: Vec<ScriptDoc>,
+}
+
+/// A doc comment, split into its description and tags.
+#[derive(Debug, Default)]
+struct Comment {
+    description: String,
+    params: Vec<Param>,
+    returns: Vec<Return>,
+    private: bool,
+}
+
+/// Splits `text` into its first word and the rest, if there's any rest.
+fn first_word(text: &str) -> (&str, Option<String>) {
+    let text = text.trim();
+    match text.split_once(char::is_whitespace) {
+        Some((word, rest)) => (word, Some(rest.trim().to_owned()).filter(|rest| !rest.is_empty())),
+        None => (text, None),
+    }
+}
+
+/// Parses the lines of a doc comment, without their `---`.
+fn parse_comment(lines: &[&str]) -> Comment {
+    let mut comment = Comment::default();
+    let mut description = vec![];
+    for line in lines {
+        let line = line.strip_prefix(' ').unwrap_or(line).trim_end();
+        let Some(tag) = line.strip_prefix('@') else {
+            description.push(line);
+            continue;
+        };
+        let (tag, rest) = first_word(tag);
+        let rest = rest.unwrap_or_default();
+        match tag {
+            "param" => {
+                let (name, rest) = first_word(&rest);
+                let (ty, description) = rest.as_deref().map_or(("", None), first_word);
+                comment.params.push(Param {
+                    name: name.trim_end_matches('?').into(),
+                    ty: Some(ty.trim_end_matches('?').to_owned()).filter(|ty| !ty.is_empty()),
+                    optional: name.ends_with('?') || ty.ends_with('?'),
+                    description,
+                });
+            }
+            "return" => {
+                let (ty, description) = first_word(&rest);
+                comment.returns.push(Return { ty: ty.into(), description });
+            }
+            "private" => comment.private = true,
+            _ => {}
+        }
+    }
+    comment.description = description.join("\n").trim().to_owned();
+    comment
+}
+
+/// A function definition: `function name(params)` or `name = function(params)`, possibly local.
+static FUNCTION: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^\s*(local\s+)?(?:function\s+([\w.:]+)|([\w.:]+)\s*=\s*function)\s*\(([^)]*)\)").expect("the pattern is valid")
+});
+/// A script's last line, if it returns a local table of exports.
+static RETURN: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\s*return\s+([A-Za-z_]\w*)\s*;?\s*$").expect("the pattern is valid"));
+/// An action's title or item, set to a string literal.
+static ACTION_FIELD: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#":(?:set)?([Tt]itle|[Ii]tem)\(\s*(?:"([^"]*)"|'([^']*)')"#).expect("the pattern is valid")
+});
+/// A callback given to an action, such as `:onLeftClick(pings.wave)`.
+static ACTION_HANDLER: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r":(?:set)?([Oo]n[A-Z]\w*)\(\s*(function\b|[\w.:]+)").expect("the pattern is valid")
+});
+
+/// Documents one script.
+fn document(script: &str, source: &str) -> ScriptDoc {
+    let lines: Vec<&str> = source.lines().collect();
+    let module = lines.iter().rev()
+        .map(|line| line.trim())
+        .find(|line| !line.is_empty() && !line.starts_with("--"))
+        .and_then(|line| RETURN.captures(line))
+        .map(|captures| captures[1].to_owned());
+    let mut doc = ScriptDoc { script: script.into(), ..Default::default() };
+    let mut comment: Vec<&str> = vec![];
+    let mut seen_code = false;
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i].trim();
+        i += 1;
+        if let Some(text) = line.strip_prefix("---").filter(|text| !text.starts_with('-')) {
+            comment.push(text);
+            continue;
+        }
+        if line.is_empty() {
+            if !seen_code && doc.description.is_empty() && !comment.is_empty() {
+                doc.description = parse_comment(&comment).description;
+            }
+            comment.clear();
+            continue;
+        }
+        if line.starts_with("--") {
+            continue;
+        }
+        seen_code = true;
+        let parsed = parse_comment(&comment);
+        comment.clear();
+        if parsed.private {
+            continue;
+        }
+        if let Some(captures) = FUNCTION.captures(line) {
+            let local = captures.get(1).is_some();
+            let name = captures.get(2).or(captures.get(3)).expect("one of the names matched").as_str();
+            let exported = match name.split_once(['.', ':']) {
+                Some((table, _)) => table == "pings" || module.as_deref() == Some(table),
+                None => !local,
+            };
+            if !exported {
+                continue;
+            }
+            let params = captures[4].split(',').map(str::trim).filter(|param| !param.is_empty()).map(|name| {
+                parsed.params.iter().find(|param| param.name == name).cloned()
+                    .unwrap_or_else(|| Param { name: name.into(), ..Default::default() })
+            }).collect();
+            let function = FunctionDoc { name: name.into(), line: i, description: parsed.description, params, returns: parsed.returns };
+            if name.starts_with("pings.") {
+                doc.pings.push(function);
+            } else {
+                doc.functions.push(function);
+            }
+        } else if line.contains(":newAction(") {
+            // the rest of the chain is on the lines that follow
+            let start = i;
+            let mut chain = line.to_owned();
+            while i < lines.len() && lines[i].trim_start().starts_with(':') {
+                chain += lines[i].trim();
+                i += 1;
+            }
+            let mut action = ActionDoc { line: start, description: parsed.description, ..Default::default() };
+            for captures in ACTION_FIELD.captures_iter(&chain) {
+                let value = captures.get(2).or(captures.get(3)).map(|value| value.as_str().to_owned());
+                match &captures[1] {
+                    "title" | "Title" => action.title = value,
+                    _ => action.item = value,
+                }
+            }
+            for captures in ACTION_HANDLER.captures_iter(&chain) {
+                let mut callback = captures[1].to_owned();
+                callback[..1].make_ascii_lowercase();
+                action.handlers.push((callback, captures[2].to_owned()));
+            }
+            doc.actions.push(action);
+        }
+    }
+    doc
+}
+
+/// Documents every script of `moon`, leaving out scripts with nothing to document.
+pub fn docgen(moon: &Moon) -> Docs {
+    let mut names: Vec<&String> = moon.scripts.keys().collect();
+    names.sort();
+    let scripts = names.into_iter()
+        .map(|name| document(name, &String::from_utf8_lossy(moon.scripts[name].as_ref())))
+        .filter(|doc| !doc.is_empty())
+        .collect();
+    Docs { name: moon.metadata.name.clone(), scripts }
+}
+
+impl Display for FunctionDoc {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        let params: Vec<&str> = self.params.iter().map(|param| param.name.as_str()).collect();
+        writeln!(fmt, "#### `{}({})`\n", self.name, params.join(", "))?;
+        if !self.description.is_empty() {
+            writeln!(fmt, "{}\n", self.description)?;
+        }
+        if self.params.iter().any(|param| param.ty.is_some() || param.description.is_some()) {
+            writeln!(fmt, "Parameters:\n")?;
+            for param in &self.params {
+                write!(fmt, "- `{}`", param.name)?;
+                if let Some(ty) = &param.ty {
+                    write!(fmt, " (`{ty}`{})", if param.optional { ", optional" } else { "" })?;
+                }
+                match &param.description {
+                    Some(description) => writeln!(fmt, ": {description}")?,
+                    None => writeln!(fmt)?,
+                }
+            }
+            writeln!(fmt)?;
+        }
+        if !self.returns.is_empty() {
+            writeln!(fmt, "Returns:\n")?;
+            for value in &self.returns {
+                match &value.description {
+                    Some(description) => writeln!(fmt, "- `{}`: {description}", value.ty)?,
+                    None => writeln!(fmt, "- `{}`", value.ty)?,
+                }
+            }
+            writeln!(fmt)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for ActionDoc {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        writeln!(fmt, "#### {}\n", self.title.as_deref().unwrap_or("Untitled action"))?;
+        if !self.description.is_empty() {
+            writeln!(fmt, "{}\n", self.description)?;
+        }
+        if let Some(item) = &self.item {
+            writeln!(fmt, "- Item: `{item}`")?;
+        }
+        for (callback, handler) in &self.handlers {
+            writeln!(fmt, "- `{callback}`: `{handler}`")?;
+        }
+        if self.item.is_some() || !self.handlers.is_empty() {
+            writeln!(fmt)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Docs {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        writeln!(fmt, "# {}\n", self.name)?;
+        for script in &self.scripts {
+            writeln!(fmt, "## `{}`\n", script.script)?;
+            if !script.description.is_empty() {
+                writeln!(fmt, "{}\n", script.description)?;
+            }
+            if !script.functions.is_empty() {
+                writeln!(fmt, "### Functions\n")?;
+                script.functions.iter().try_for_each(|function| write!(fmt, "{function}"))?;
+            }
+            if !script.pings.is_empty() {
+                writeln!(fmt, "### Pings\n")?;
+                script.pings.iter().try_for_each(|ping| write!(fmt, "{ping}"))?;
+            }
+            if !script.actions.is_empty() {
+                writeln!(fmt, "### Actions\n")?;
+                script.actions.iter().try_for_each(|action| write!(fmt, "{action}"))?;
+            }
+        }
+        Ok(())
+    }
+}
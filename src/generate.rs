@@ -0,0 +1,113 @@
+//! Built-in generators that create whole avatars from a few parameters. Besides being useful on
+//! their own, they show how to put together a [Moon] from Rust code: building the part tree,
+//! adding textures, and [animating][crate::animation] parts.
+
+use crate::animation::{AnimationBuilder, Channel, Interpolation};
+use crate::image::{self, ImageError};
+use crate::moon::{Face, ModelData, ModelPart, ParentType, Sided, TextureData};
+use crate::scripts::lua_string;
+use crate::Moon;
+
+/// Makes a group, giving it the parent type Figura would infer from its name.
+fn group(name: &str, piv: [f64; 3], children: Vec<ModelPart>) -> ModelPart {
+    ModelPart {
+        name: name.into(),
+        chld: children.into(),
+        piv,
+        pt: ParentType::for_part(name),
+        vsb: true,
+        ..Default::default()
+    }
+}
+
+/// Makes a cube whose faces all use the first texture.
+fn cube(name: &str, f: [f64; 3], t: [f64; 3], uvs: [[f64; 4]; 6]) -> ModelPart {
+    let [n, s, u, d, w, e] = uvs.map(|uv| Some(Face { tex: 0, uv: uv.into(), rot: 0.0 }));
+    ModelPart {
+        name: name.into(),
+        vsb: true,
+        data: ModelData::Cube { cube_data: Sided { n, s, u, d, w, e }, f, t, inf: 0.0 },
+        ..Default::default()
+    }
+}
+
+/// Wraps a model in the root part and gives it a single texture.
+fn finish(model: ModelPart, texture: String, data: Vec<u8>, name: &str) -> Moon {
+    let mut moon = Moon::default();
+    moon.models = Some(group("models", [0.0; 3], vec![model]));
+    moon.textures.data = vec![TextureData { d: texture.clone() }].into();
+    moon.textures.src.insert(texture, data.into());
+    moon.metadata.name = name.into();
+    moon
+}
+
+/// Options for [banner].
+#[derive(Clone, Debug)]
+pub struct BannerOptions {
+    /// The banner's height in pixels (sixteenths of a block); the width follows from the image's
+    /// aspect ratio.
+    pub height: f64,
+    /// Whether the banner should sway back and forth.
+    pub wave: bool,
+}
+
+impl Default for BannerOptions {
+    fn default() -> Self {
+        Self { height: 16.0, wave: false }
+    }
+}
+
+/// Creates an avatar that carries a flag on its back showing the given PNG image.
+pub fn banner(image: Vec<u8>, options: &BannerOptions) -> Result<Moon, ImageError> {
+    let (w, h) = image::dimensions(&image)?;
+    let (w, h) = (w as f64, h as f64);
+    let height = options.height;
+    let width = w * height / h;
+    let top = 24.0;
+    let cloth = cube(
+        "cloth",
+        [-width / 2.0, top - height, 2.0],
+        [width / 2.0, top, 3.0],
+        [
+            [w, 0.0, 0.0, h],
+            [0.0, 0.0, w, h],
+            [0.0, 0.0, w, 1.0],
+            [0.0, h - 1.0, w, h],
+            [w - 1.0, 0.0, w, h],
+            [0.0, 0.0, 1.0, h],
+        ],
+    );
+    let flag = group("flag", [0.0, top, 2.5], vec![cloth]);
+    let body = group("Body", [0.0, 24.0, 0.0], vec![flag]);
+    let mut moon = finish(group("banner", [0.0; 3], vec![body]), "banner.flag".into(), image, "Banner");
+    if options.wave {
+        AnimationBuilder::new("wave")
+            .loops()
+            .channel("banner/Body/flag", Channel::Rotation)
+            .key_with(0.0, [0.0, 0.0, 0.0], Interpolation::CatmullRom)
+            .key_with(1.0, [12.0, 0.0, 0.0], Interpolation::CatmullRom)
+            .key_with(2.0, [0.0, 0.0, 0.0], Interpolation::CatmullRom)
+            .build()
+            .insert_into(&mut moon)
+            .expect("generated part tree contains the flag");
+        moon.scripts.insert("main".into(), b"animations.banner.wave:play()\n".to_vec().into());
+    }
+    Ok(moon)
+}
+
+/// Creates an avatar that carries a wooden sign above its head, showing the given text.
+pub fn sign(text: &str) -> Result<Moon, ImageError> {
+    let wood = image::solid(1, 1, [0x9c, 0x7a, 0x4b, 0xff])?;
+    let uv = [[0.0, 0.0, 1.0, 1.0]; 6];
+    let post = cube("post", [-0.5, 24.0, 3.0], [0.5, 36.0, 4.0], uv);
+    let board = cube("board", [-10.0, 36.0, 3.0], [10.0, 46.0, 4.0], uv);
+    let sign = group("sign", [0.0, 24.0, 3.5], vec![post, board]);
+    let body = group("Body", [0.0, 24.0, 0.0], vec![sign]);
+    let mut moon = finish(group("sign", [0.0; 3], vec![body]), "sign.wood".into(), wood, "Sign");
+    let script = format!(
+        "models.sign.Body.sign:newText(\"text\")\n    :setText({})\n    :setAlignment(\"CENTER\")\n    :setPos(0, 43, 4.1)\n    :setRot(0, 180, 0)\n    :setScale(0.4)\n",
+        lua_string(text),
+    );
+    moon.scripts.insert("main".into(), script.into_bytes().into());
+    Ok(moon)
+}
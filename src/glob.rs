@@ -0,0 +1,170 @@
+//! Glob patterns over `/`-separated paths, following the syntax of Java's `glob:` path matchers,
+//! which Figura uses for the `resources` list in `avatar.json`:
+//!
+//! * `*` matches any run of characters within one path component.
+//! * `**` matches any run of characters, including `/`.
+//! * `?` matches a single character other than `/`.
+//! * `[abc]`, `[a-z]`, and `[!a-z]` match one character in (or not in) a set.
+//! * `{png,ogg}` matches any one of several comma-separated subpatterns.
+//! * `\` makes the next character match literally.
+//!
+//! [expand] applies patterns to a directory the way Figura does when packing an avatar.
+
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Literal(char),
+    AnyChar,
+    AnyInComponent,
+    AnyAcrossComponents,
+    Class { ranges: Vec<(char, char)>, negated: bool },
+    Alternatives(Vec<Vec<Token>>),
+}
+
+/// A compiled glob pattern.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Glob {
+    source: String,
+    tokens: Vec<Token>,
+}
+
+fn parse(chars: &mut std::iter::Peekable<std::str::Chars>, in_braces: bool) -> Vec<Token> {
+    let mut tokens = vec![];
+    while let Some(&c) = chars.peek() {
+        if in_braces && (c == ',' || c == '}') {
+            break;
+        }
+        chars.next();
+        tokens.push(match c {
+            '*' if chars.next_if_eq(&'*').is_some() => Token::AnyAcrossComponents,
+            '*' => Token::AnyInComponent,
+            '?' => Token::AnyChar,
+            '\\' => Token::Literal(chars.next().unwrap_or('\\')),
+            '[' => {
+                let negated = chars.next_if_eq(&'!').is_some();
+                let mut ranges = vec![];
+                while let Some(c) = chars.next_if(|&c| c != ']') {
+                    let end = match chars.next_if_eq(&'-') {
+                        Some(_) => chars.next_if(|&c| c != ']').unwrap_or('-'),
+                        None => c,
+                    };
+                    ranges.push((c, end));
+                }
+                chars.next();
+                Token::Class { ranges, negated }
+            }
+            '{' if !in_braces => {
+                let mut alternatives = vec![parse(chars, true)];
+                while chars.next_if_eq(&',').is_some() {
+                    alternatives.push(parse(chars, true));
+                }
+                chars.next();
+                Token::Alternatives(alternatives)
+            }
+            c => Token::Literal(c),
+        });
+    }
+    tokens
+}
+
+impl Glob {
+    /// Compiles a pattern. Unterminated brackets and braces are closed at the end of the pattern.
+    pub fn new(pattern: &str) -> Self {
+        Self { source: pattern.into(), tokens: parse(&mut pattern.chars().peekable(), false) }
+    }
+
+    /// A pattern that matches exactly `path` and nothing else.
+    pub fn literal(path: &str) -> Self {
+        Self { source: escape(path), tokens: path.chars().map(Token::Literal).collect() }
+    }
+
+    /// Whether the whole of `path` matches this pattern.
+    pub fn matches(&self, path: &str) -> bool {
+        fn go(tokens: &[Token], path: &[char]) -> bool {
+            let Some((token, rest)) = tokens.split_first() else { return path.is_empty() };
+            match token {
+                Token::Literal(c) => path.first() == Some(c) && go(rest, &path[1..]),
+                Token::AnyChar => path.first().is_some_and(|&c| c != '/') && go(rest, &path[1..]),
+                Token::AnyInComponent => {
+                    let run = path.iter().take_while(|&&c| c != '/').count();
+                    (0..=run).any(|n| go(rest, &path[n..]))
+                }
+                Token::AnyAcrossComponents => (0..=path.len()).any(|n| go(rest, &path[n..])),
+                Token::Class { ranges, negated } => path.first().is_some_and(|&c| {
+                    c != '/' && ranges.iter().any(|&(lo, hi)| (lo..=hi).contains(&c)) != *negated
+                }) && go(rest, &path[1..]),
+                Token::Alternatives(alternatives) => alternatives.iter().any(|alt| go(&[alt.as_slice(), rest].concat(), path)),
+            }
+        }
+        go(&self.tokens, &path.chars().collect::<Vec<_>>())
+    }
+
+    /// Whether `path` or one of the directories it's in matches this pattern, so that a pattern
+    /// naming a directory covers everything inside it.
+    pub fn matches_within(&self, path: &str) -> bool {
+        self.matches(path) || path.match_indices('/').any(|(i, _)| self.matches(&path[..i]))
+    }
+}
+
+impl FromStr for Glob {
+    type Err = !;
+
+    fn from_str(pattern: &str) -> Result<Self, !> {
+        Ok(Self::new(pattern))
+    }
+}
+
+impl Display for Glob {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        fmt.write_str(&self.source)
+    }
+}
+
+/// Escapes every special character in `path`, so that it can be written as a pattern matching
+/// only itself.
+pub fn escape(path: &str) -> String {
+    let mut out = String::with_capacity(path.len());
+    for c in path.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '{' | '}' | ',' | '\\') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Whether Figura loads a file in an avatar's folder on its own, regardless of `resources`: scripts
+/// and Blockbench models.
+pub fn loaded_separately(path: &str) -> bool {
+    path.ends_with(".lua") || path.ends_with(".bbmodel")
+}
+
+/// Finds the files under `root` that match any of `patterns`, as Figura does for `resources`.
+/// Paths are relative to `root`, use `/` as the separator, and are sorted. Files that are
+/// [loaded separately][loaded_separately] are left out, as are directories (though their
+/// contents can match).
+pub fn expand(root: &Path, patterns: &[Glob]) -> io::Result<Vec<String>> {
+    fn walk(dir: &Path, prefix: &str, out: &mut Vec<String>) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let path = if prefix.is_empty() { name } else { format!("{prefix}/{name}") };
+            if entry.file_type()?.is_dir() {
+                walk(&entry.path(), &path, out)?;
+            } else {
+                out.push(path);
+            }
+        }
+        Ok(())
+    }
+    let mut files = vec![];
+    walk(root, "", &mut files)?;
+    files.retain(|path| !loaded_separately(path) && patterns.iter().any(|p| p.matches(path)));
+    files.sort();
+    Ok(files)
+}
@@ -0,0 +1,86 @@
+//! Searching an avatar's scripts, as `fia grep` does, without unpacking it first. Scripts aren't
+//! necessarily UTF-8, so they're decoded lossily; invalid bytes can't be matched.
+
+use std::fmt::{self, Display, Formatter};
+use std::ops::Range;
+use regex::Regex;
+use serde::Serialize;
+use crate::Moon;
+
+/// A line printed by [grep]: one that matched, or one around a match.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Line {
+    /// The line's number, counting from 1.
+    pub number: usize,
+    /// The line's text, without its line ending.
+    pub text: String,
+    /// Whether the line matched, rather than being shown as context.
+    pub matched: bool,
+    /// Byte ranges of each non-empty match in [text][Self::text], for highlighting.
+    pub matches: Vec<Range<usize>>,
+}
+
+/// The lines of one script that [grep] found.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ScriptMatches {
+    /// The script's name.
+    pub script: String,
+    /// The lines that matched and their context, in order.
+    pub lines: Vec<Line>,
+}
+
+impl ScriptMatches {
+    /// How many lines matched, not counting context.
+    pub fn count(&self) -> usize {
+        self.lines.iter().filter(|line| line.matched).count()
+    }
+}
+
+/// Lines are printed as `script:line: text`, with `-` instead of `:` for context lines and `--`
+/// between runs of lines that aren't next to each other. Matches are highlighted.
+impl Display for ScriptMatches {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        let mut last = None;
+        for line in &self.lines {
+            if last.is_some_and(|last| last + 1 != line.number) {
+                writeln!(fmt, "--")?;
+            }
+            last = Some(line.number);
+            let separator = if line.matched { ':' } else { '-' };
+            write!(fmt, "\x1b[35m{}\x1b[39m{separator}\x1b[32m{}\x1b[39m{separator} ", self.script, line.number)?;
+            let mut end = 0;
+            for range in &line.matches {
+                write!(fmt, "{}\x1b[1;31m{}\x1b[22;39m", &line.text[end..range.start], &line.text[range.clone()])?;
+                end = range.end;
+            }
+            writeln!(fmt, "{}", &line.text[end..])?;
+        }
+        Ok(())
+    }
+}
+
+/// Searches every script of `moon` for `pattern`, line by line, keeping `before` and `after`
+/// lines of context around each matching line. Scripts without matches are left out, and the
+/// rest are sorted by name.
+pub fn grep(moon: &Moon, pattern: &Regex, before: usize, after: usize) -> Vec<ScriptMatches> {
+    let mut names: Vec<&String> = moon.scripts.keys().collect();
+    names.sort();
+    names.into_iter().filter_map(|name| {
+        let source = String::from_utf8_lossy(moon.scripts[name].as_ref());
+        let lines: Vec<&str> = source.lines().collect();
+        let mut shown = vec![false; lines.len()];
+        for (i, _) in lines.iter().enumerate().filter(|(_, line)| pattern.is_match(line)) {
+            shown[i.saturating_sub(before)..=(i + after).min(lines.len() - 1)].fill(true);
+        }
+        let lines: Vec<Line> = lines.iter().enumerate()
+            .filter(|(i, _)| shown[*i])
+            .map(|(i, text)| Line {
+                number: i + 1,
+                text: text.to_string(),
+                matched: pattern.is_match(text),
+                matches: pattern.find_iter(text).map(|m| m.range()).filter(|range| !range.is_empty()).collect(),
+            })
+            .collect();
+        (!lines.is_empty()).then(|| ScriptMatches { script: name.clone(), lines })
+    }).collect()
+}
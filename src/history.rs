@@ -0,0 +1,108 @@
+//! Access to the backups Figura keeps of previously equipped avatars. Figura saves these as moons
+//! under `figura/backups` in the Minecraft directory; they can be listed chronologically and copied
+//! back into the wardrobe (`figura/avatars`), which is a lifesaver after accidentally overwriting
+//! an avatar's source.
+
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use sha2::{Digest, Sha256};
+use crate::Moon;
+
+/// Guesses the location of the `.minecraft` directory for the current platform.
+pub fn minecraft_dir() -> Option<PathBuf> {
+    if cfg!(windows) {
+        std::env::var_os("APPDATA").map(|p| PathBuf::from(p).join(".minecraft"))
+    } else if cfg!(target_os = "macos") {
+        std::env::var_os("HOME").map(|p| PathBuf::from(p).join("Library/Application Support/minecraft"))
+    } else {
+        std::env::var_os("HOME").map(|p| PathBuf::from(p).join(".minecraft"))
+    }
+}
+
+/// One backed-up avatar.
+#[derive(Debug, Clone)]
+pub struct Backup {
+    /// Where the backup is stored.
+    pub path: PathBuf,
+    /// When the backup was last modified, which is usually when it was made.
+    pub modified: SystemTime,
+    /// The size of the backup file, in bytes.
+    pub size: u64,
+    /// The SHA-256 hash of the backup file.
+    pub hash: [u8; 32],
+    /// The avatar's name, if the backup could be loaded.
+    pub name: Option<String>,
+}
+
+impl Backup {
+    /// Reads information about a backup file.
+    pub fn load(path: PathBuf) -> io::Result<Self> {
+        let modified = fs::metadata(&path)?.modified()?;
+        let mut data = vec![];
+        File::open(&path)?.read_to_end(&mut data)?;
+        let hash = Sha256::digest(&data).into();
+        let name = Moon::read_auto(&*data).ok().map(|(moon, _)| moon.metadata.name);
+        Ok(Self { path, modified, size: data.len() as u64, hash, name })
+    }
+
+    /// The first few bytes of [hash][Self::hash] in hexadecimal, for display.
+    pub fn short_hash(&self) -> String {
+        self.hash[..6].iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    /// [modified][Self::modified] formatted as a UTC `YYYY-MM-DD HH:MM:SS` timestamp.
+    pub fn date(&self) -> String {
+        let secs = self.modified.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        // days-to-civil, from Howard Hinnant's date algorithms
+        let days = (secs / 86400) as i64 + 719468;
+        let era = days.div_euclid(146097);
+        let doe = days.rem_euclid(146097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + (month <= 2) as i64;
+        let time = secs % 86400;
+        format!("{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}", time / 3600, time / 60 % 60, time % 60)
+    }
+
+    /// Copies this backup into a wardrobe directory as a `.moon` file, returning the new path.
+    /// Existing files are never overwritten; a number is appended to the name instead.
+    pub fn restore(&self, avatars_dir: &Path) -> io::Result<PathBuf> {
+        let stem = self.name.as_deref()
+            .filter(|n| !n.is_empty())
+            .map(|n| n.replace(['/', '\\', ':'], "_"))
+            .unwrap_or_else(|| format!("backup-{}", self.short_hash()));
+        fs::create_dir_all(avatars_dir)?;
+        let mut dest = avatars_dir.join(format!("{stem}.moon"));
+        let mut n = 1;
+        while dest.exists() {
+            n += 1;
+            dest = avatars_dir.join(format!("{stem} ({n}).moon"));
+        }
+        fs::copy(&self.path, &dest)?;
+        Ok(dest)
+    }
+}
+
+/// Lists every backup in a directory (recursively), oldest first.
+pub fn list(dir: &Path) -> io::Result<Vec<Backup>> {
+    fn walk(dir: &Path, out: &mut Vec<Backup>) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                walk(&path, out)?;
+            } else if path.extension().is_some_and(|e| e == "moon" || e == "nbt") {
+                out.push(Backup::load(path)?);
+            }
+        }
+        Ok(())
+    }
+    let mut backups = vec![];
+    walk(dir, &mut backups)?;
+    backups.sort_by_key(|b| b.modified);
+    Ok(backups)
+}
@@ -0,0 +1,141 @@
+//! A minimal blocking HTTP/1.1 client, just enough for fetching [assets][crate::asset] and talking
+//! to a Figura backend. Every request uses a fresh connection, which is closed once the response
+//! has been read.
+
+use std::borrow::Cow;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use thiserror::Error;
+use url::Url;
+
+/// The largest response [request] will read, in bytes, so a misbehaving server can't use up all
+/// of memory. It's far more than any avatar or asset needs.
+pub const MAX_RESPONSE: u64 = 64 << 20;
+
+/// An error encountered while making a request. Unsuccessful statuses aren't errors; check
+/// [Response::status].
+#[derive(Debug, Error)]
+pub enum HttpError {
+    /// The connection failed.
+    #[error("{0}")]
+    Io(#[from] io::Error),
+    /// A TLS connection couldn't be set up.
+    #[error("{0}")]
+    Tls(#[from] native_tls::Error),
+    /// The URL isn't an `http:` or `https:` URL with a host.
+    #[error("can't request {0}")]
+    UnsupportedUrl(Url),
+    /// The server's response couldn't be understood.
+    #[error("malformed response from {0}")]
+    Malformed(Url),
+    /// The response was larger than [MAX_RESPONSE].
+    #[error("response from {0} is larger than {} MiB", MAX_RESPONSE >> 20)]
+    TooLarge(Url),
+}
+
+/// A response from a server.
+#[derive(Clone, Debug)]
+pub struct Response {
+    /// The status code, such as 200.
+    pub status: u16,
+    /// The headers, in the order they were sent, with lowercase names.
+    pub headers: Vec<(String, String)>,
+    /// The body, with any chunked encoding removed.
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// Whether the status is in the 2xx range.
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// The value of the first header with the given lowercase name.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    /// The body as text, with invalid UTF-8 replaced.
+    pub fn text(&self) -> Cow<str> {
+        String::from_utf8_lossy(&self.body)
+    }
+}
+
+/// Removes chunked transfer encoding from a body.
+fn dechunk(mut data: &[u8]) -> Option<Vec<u8>> {
+    let mut out = vec![];
+    loop {
+        let line = data.windows(2).position(|w| w == b"\r\n")?;
+        let size = std::str::from_utf8(&data[..line]).ok()?;
+        let size = usize::from_str_radix(size.split(';').next()?.trim(), 16).ok()?;
+        data = &data[line + 2..];
+        if size == 0 {
+            return Some(out);
+        }
+        out.extend_from_slice(data.get(..size)?);
+        data = data.get(size + 2..)?;
+    }
+}
+
+fn exchange(mut stream: impl Read + Write, url: &Url, head: &[u8], body: &[u8]) -> Result<Response, HttpError> {
+    stream.write_all(head)?;
+    stream.write_all(body)?;
+    stream.flush()?;
+    let mut response = vec![];
+    match stream.take(MAX_RESPONSE + 1).read_to_end(&mut response) {
+        Ok(_) => {}
+        // plenty of servers close TLS connections without saying so first
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof && !response.is_empty() => {}
+        Err(e) => return Err(e.into()),
+    }
+    if response.len() as u64 > MAX_RESPONSE {
+        return Err(HttpError::TooLarge(url.clone()));
+    }
+    let malformed = || HttpError::Malformed(url.clone());
+    let split = response.windows(4).position(|w| w == b"\r\n\r\n").ok_or_else(malformed)?;
+    let head = String::from_utf8_lossy(&response[..split]);
+    let mut lines = head.split("\r\n");
+    let status = lines.next().and_then(|line| line.split(' ').nth(1)).and_then(|code| code.parse().ok()).ok_or_else(malformed)?;
+    let headers: Vec<(String, String)> = lines.filter_map(|line| line.split_once(':')).map(|(name, value)| {
+        (name.trim().to_ascii_lowercase(), value.trim().to_string())
+    }).collect();
+    let mut body = response.split_off(split + 4);
+    if headers.iter().any(|(name, value)| name == "transfer-encoding" && value.eq_ignore_ascii_case("chunked")) {
+        body = dechunk(&body).ok_or_else(malformed)?;
+    }
+    Ok(Response { status, headers, body })
+}
+
+/// Sends a request and waits for the whole response, which can be at most [MAX_RESPONSE] bytes
+/// including its head. `headers` are sent along with `Host`,
+/// `User-Agent`, `Content-Length`, and `Connection`, which are filled in automatically.
+pub fn request(method: &str, url: &Url, headers: &[(&str, &str)], body: &[u8]) -> Result<Response, HttpError> {
+    let host = url.host_str().ok_or_else(|| HttpError::UnsupportedUrl(url.clone()))?;
+    let port = url.port_or_known_default().ok_or_else(|| HttpError::UnsupportedUrl(url.clone()))?;
+    let path = &url[url::Position::BeforePath..url::Position::AfterQuery];
+    let mut head = format!("{method} {path} HTTP/1.1\r\nHost: {host}\r\nUser-Agent: fia/{}\r\nConnection: close\r\n", env!("CARGO_PKG_VERSION"));
+    if !body.is_empty() || matches!(method, "POST" | "PUT") {
+        head += &format!("Content-Length: {}\r\n", body.len());
+    }
+    for (name, value) in headers {
+        head += &format!("{name}: {value}\r\n");
+    }
+    head += "\r\n";
+    let stream = TcpStream::connect((host, port))?;
+    match url.scheme() {
+        "http" => exchange(stream, url, head.as_bytes(), body),
+        "https" => {
+            let stream = native_tls::TlsConnector::new()?.connect(host, stream).map_err(|e| match e {
+                native_tls::HandshakeError::Failure(e) => HttpError::Tls(e),
+                native_tls::HandshakeError::WouldBlock(_) => unreachable!("blocking stream would block"),
+            })?;
+            exchange(stream, url, head.as_bytes(), body)
+        }
+        _ => Err(HttpError::UnsupportedUrl(url.clone())),
+    }
+}
+
+/// Sends a `GET` request.
+pub fn get(url: &Url, headers: &[(&str, &str)]) -> Result<Response, HttpError> {
+    request("GET", url, headers, &[])
+}
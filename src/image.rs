@@ -0,0 +1,142 @@
+//! Helpers for the PNG data stored in [Textures::src][crate::moon::Textures::src]. Figura doesn't
+//! care how a texture was encoded, only what pixels come out of it, so these functions are free to
+//! rewrite the encoding as long as the pixels stay the same.
+
+use std::collections::HashSet;
+use thiserror::Error;
+use crate::moon::{ModelData, ModelPart};
+use crate::Moon;
+
+/// An error encountered while re-encoding a PNG.
+#[derive(Debug, Error)]
+pub enum ImageError {
+    /// The texture wasn't a valid PNG.
+    #[error("{0}")]
+    Decoding(#[from] png::DecodingError),
+    /// The texture couldn't be written back out.
+    #[error("{0}")]
+    Encoding(#[from] png::EncodingError),
+}
+
+/// Decodes a PNG and encodes it again with the best compression the `png` crate offers. Ancillary
+/// chunks other than the palette and transparency are dropped. The result is not guaranteed to be
+/// smaller; use [recompress_if_smaller] to keep whichever is.
+pub fn recompress(data: &[u8]) -> Result<Vec<u8>, ImageError> {
+    let mut reader = png::Decoder::new(data).read_info()?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let frame = reader.next_frame(&mut buf)?;
+    buf.truncate(frame.buffer_size());
+    let info = reader.info();
+    let mut out = vec![];
+    let mut encoder = png::Encoder::new(&mut out, info.width, info.height);
+    encoder.set_color(info.color_type);
+    encoder.set_depth(info.bit_depth);
+    if let Some(palette) = &info.palette {
+        encoder.set_palette(palette.clone());
+    }
+    if let Some(trns) = &info.trns {
+        encoder.set_trns(trns.clone());
+    }
+    encoder.set_compression(png::Compression::Best);
+    encoder.set_adaptive_filter(png::AdaptiveFilterType::Adaptive);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&buf)?;
+    writer.finish()?;
+    Ok(out)
+}
+
+/// Like [recompress], but returns the original data if recompressing didn't make it smaller.
+pub fn recompress_if_smaller(data: &[u8]) -> Result<Vec<u8>, ImageError> {
+    let out = recompress(data)?;
+    Ok(if out.len() < data.len() { out } else { data.to_vec() })
+}
+
+/// Reads the width and height of a PNG without decoding its pixels.
+pub fn dimensions(data: &[u8]) -> Result<(u32, u32), ImageError> {
+    let reader = png::Decoder::new(data).read_info()?;
+    Ok(reader.info().size())
+}
+
+/// Decodes a PNG into 8-bit RGBA pixels, row by row, converting from whatever color type and
+/// depth it was stored with. Returns the width, height, and pixels.
+pub fn decode_rgba(data: &[u8]) -> Result<(u32, u32, Vec<u8>), ImageError> {
+    let mut decoder = png::Decoder::new(data);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let frame = reader.next_frame(&mut buf)?;
+    buf.truncate(frame.buffer_size());
+    let pixels = match frame.color_type {
+        png::ColorType::Rgba => buf,
+        png::ColorType::Rgb => buf.chunks_exact(3).flat_map(|p| [p[0], p[1], p[2], 255]).collect(),
+        png::ColorType::GrayscaleAlpha => buf.chunks_exact(2).flat_map(|p| [p[0], p[0], p[0], p[1]]).collect(),
+        png::ColorType::Grayscale => buf.iter().flat_map(|&g| [g, g, g, 255]).collect(),
+        // palettes are expanded by the transformations above
+        png::ColorType::Indexed => unreachable!("indexed PNG wasn't expanded"),
+    };
+    Ok((frame.width, frame.height, pixels))
+}
+
+/// Encodes 8-bit RGBA pixels, row by row, as a PNG.
+pub fn encode_rgba(width: u32, height: u32, pixels: &[u8]) -> Result<Vec<u8>, ImageError> {
+    let mut out = vec![];
+    let mut encoder = png::Encoder::new(&mut out, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(pixels)?;
+    writer.finish()?;
+    Ok(out)
+}
+
+/// Encodes a PNG filled with a single RGBA color.
+pub fn solid(width: u32, height: u32, color: [u8; 4]) -> Result<Vec<u8>, ImageError> {
+    encode_rgba(width, height, &color.repeat((width * height) as usize))
+}
+
+/// What [Moon::replace_texture] does with UVs when the new texture's size differs from the old.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RescaleUvs {
+    /// Scale the UVs of every face using the texture so they cover the same part of the image.
+    Auto,
+    /// Leave UVs alone, such as when the new texture adds space at the right or bottom edge.
+    Keep,
+}
+
+impl Moon {
+    /// Replaces the image of the texture named `name` (a key of
+    /// [Textures::src][crate::moon::Textures::src]), adding it if it doesn't exist. UVs in Figura
+    /// are in pixels, so with [RescaleUvs::Auto], faces using the texture are rescaled when the
+    /// new image's size differs from the old one's. Returns the number of faces rescaled.
+    pub fn replace_texture(&mut self, name: &str, png: Vec<u8>, rescale: RescaleUvs) -> Result<usize, ImageError> {
+        let new = dimensions(&png)?;
+        let old = match self.textures.src.get(name) {
+            Some(data) if rescale == RescaleUvs::Auto => Some(dimensions(data.as_ref())?),
+            _ => None,
+        };
+        self.textures.src.insert(name.into(), png.into());
+        let Some(old) = old.filter(|&old| old != new) else { return Ok(0) };
+        let ids: HashSet<usize> = self.textures.data.iter().enumerate().filter(|(_, d)| d.d == name).map(|(i, _)| i).collect();
+        let (from, to) = ((old.0 as f64, old.1 as f64), (new.0 as f64, new.1 as f64));
+        fn visit(part: &mut ModelPart, ids: &HashSet<usize>, from: (f64, f64), to: (f64, f64)) -> usize {
+            let mut rescaled = 0;
+            match &mut part.data {
+                ModelData::Group {} => {}
+                ModelData::Cube { cube_data, .. } => {
+                    for face in [&mut cube_data.n, &mut cube_data.s, &mut cube_data.u, &mut cube_data.d, &mut cube_data.w, &mut cube_data.e].into_iter().flatten() {
+                        if ids.contains(&face.tex) {
+                            face.uv = face.uv.rescale(from, to);
+                            rescaled += 1;
+                        }
+                    }
+                }
+                ModelData::Mesh { mesh_data } => {
+                    rescaled += mesh_data.faces().filter(|f| ids.contains(&f.tex)).count();
+                    mesh_data.map_uvs(|tex, [u, v]| if ids.contains(&tex) { [u * to.0 / from.0, v * to.1 / from.1] } else { [u, v] });
+                }
+            }
+            rescaled + part.chld.iter_mut().map(|c| visit(c, ids, from, to)).sum::<usize>()
+        }
+        Ok(self.models.as_mut().map_or(0, |models| visit(models, &ids, from, to)))
+    }
+}
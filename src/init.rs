@@ -0,0 +1,112 @@
+//! Starting a new avatar folder, as `fia init` does: an `avatar.json`, a `player.bbmodel` with an
+//! empty group for each body part (named so that Figura attaches them to the player's), and a
+//! `script.lua` with empty event handlers to fill in.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use crate::bbmodel::builder::BBModelBuilder;
+use crate::bbmodel::BBModel;
+use crate::moon::{Authors, Metadata};
+use crate::report::CliError;
+
+/// The body parts in [player_model], with the pivots of the player's own.
+const BODY_PARTS: [(&str, [f64; 3]); 6] = [
+    ("Head", [0.0, 24.0, 0.0]),
+    ("Body", [0.0, 24.0, 0.0]),
+    ("RightArm", [-5.0, 22.0, 0.0]),
+    ("LeftArm", [5.0, 22.0, 0.0]),
+    ("RightLeg", [-1.9, 12.0, 0.0]),
+    ("LeftLeg", [1.9, 12.0, 0.0]),
+];
+
+/// The script a new avatar starts with.
+const SCRIPT: &str = "\
+-- uncomment to hide the vanilla player once the model has something in it
+-- vanilla_model.PLAYER:setVisible(false)
+
+-- runs once the avatar has loaded and the player exists
+function events.entity_init()
+end
+
+-- runs every tick (20 times per second)
+function events.tick()
+end
+
+-- runs every frame; delta is how far into the current tick this frame is
+function events.render(delta, context)
+end
+";
+
+/// What goes in a new avatar's `avatar.json`.
+#[derive(Clone, Debug, Default)]
+pub struct InitOptions {
+    /// The avatar's name.
+    pub name: String,
+    /// The avatar's authors.
+    pub authors: Vec<String>,
+    /// The avatar's color, as a hex code, if it has one.
+    pub color: Option<String>,
+}
+
+/// An error encountered while creating an avatar folder.
+#[derive(Debug, Error)]
+pub enum InitError {
+    /// One of the files already exists, so nothing was written.
+    #[error("{0} already exists")]
+    Exists(PathBuf),
+    /// A file couldn't be written.
+    #[error("{0}: {1}")]
+    Io(PathBuf, #[source] io::Error),
+}
+
+impl From<InitError> for CliError {
+    fn from(error: InitError) -> Self {
+        match error {
+            InitError::Exists(_) => CliError::usage(error),
+            InitError::Io(path, e) => CliError::io("write", path, e),
+        }
+    }
+}
+
+/// Builds an empty model with a group for each of the player's body parts.
+pub fn player_model() -> BBModel {
+    BODY_PARTS.iter()
+        .fold(BBModelBuilder::new("player"), |model, &(name, pivot)| model.group("", name, pivot))
+        .build()
+        .expect("body parts are all top-level groups")
+}
+
+/// Lists the files of a new avatar folder.
+pub fn files(options: &InitOptions) -> serde_json::Result<Vec<(&'static str, Vec<u8>)>> {
+    let metadata = Metadata {
+        name: options.name.clone(),
+        authors: match &options.authors[..] {
+            [author] => Authors::Author(author.clone()),
+            authors => Authors::Authors(authors.to_vec()),
+        },
+        color: options.color.clone().unwrap_or_default(),
+        ..Default::default()
+    };
+    Ok(vec![
+        ("avatar.json", serde_json::to_vec_pretty(&metadata.avatar_json())?),
+        ("player.bbmodel", player_model().to_canonical_vec()?),
+        ("script.lua", SCRIPT.into()),
+    ])
+}
+
+/// Creates a new avatar folder at `dir`, creating `dir` itself if needed. Nothing is written if
+/// any of the files already exist.
+pub fn init(dir: &Path, options: &InitOptions) -> Result<(), InitError> {
+    let files = files(options).map_err(|e| InitError::Io(dir.join("avatar.json"), e.into()))?;
+    if let Some(path) = files.iter().map(|(name, _)| dir.join(name)).find(|path| path.exists()) {
+        return Err(InitError::Exists(path));
+    }
+    fs::create_dir_all(dir).map_err(|e| InitError::Io(dir.into(), e))?;
+    for (name, data) in files {
+        let path = dir.join(name);
+        fs::write(&path, data).map_err(|e| InitError::Io(path, e))?;
+    }
+    Ok(())
+}
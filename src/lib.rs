@@ -14,3 +14,78 @@ pub mod moon;
 pub use moon::Moon;
 
 pub mod bbmodel;
+
+pub mod moons;
+
+pub mod scripts;
+
+pub mod image;
+
+pub mod atlas;
+
+pub mod pipeline;
+
+pub mod limits;
+
+pub mod stats;
+
+#[cfg(feature = "mount")]
+pub mod mount;
+
+pub mod history;
+
+pub mod check;
+
+pub mod asset;
+
+pub mod http;
+
+pub mod cloud;
+
+#[cfg(feature = "backend")]
+pub mod backend;
+#[cfg(feature = "backend")]
+pub use backend::Backend;
+
+pub mod animation;
+
+pub mod generate;
+
+pub mod compare;
+
+pub mod diff;
+
+pub mod glob;
+
+pub mod pack;
+
+#[cfg(feature = "watch")]
+pub mod watch;
+
+#[cfg(feature = "browse")]
+pub mod browse;
+
+pub mod complexity;
+
+pub mod convert;
+
+pub mod init;
+
+pub mod merge;
+
+pub mod permissions;
+
+pub mod molang;
+
+pub mod report;
+
+pub mod config;
+
+pub mod grep;
+
+#[cfg(feature = "run")]
+pub mod runtime;
+
+pub mod profile;
+
+pub mod docgen;
@@ -0,0 +1,131 @@
+//! Checks avatars against the size limits enforced by Figura's backend. The backend rejects any
+//! upload whose file size (that is, after compression) exceeds the limit for the uploader's
+//! permission tier, so it's useful to know ahead of time whether an avatar will fit, and if not,
+//! which part of it is to blame.
+
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::num::ParseIntError;
+use std::str::FromStr;
+use flate2::Compression;
+use quartz_nbt::io::{Flavor, NbtIoError};
+use serde::Serialize;
+use crate::Moon;
+
+/// A backend permission tier, which determines the largest avatar a user may upload.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Tier {
+    /// The limit applied to everyone by the official backend.
+    Default,
+    /// The raised limit given to some users (such as contest winners) by the official backend.
+    Special,
+    /// A limit in bytes, for third-party backends with their own limits.
+    Custom(usize),
+}
+
+impl Tier {
+    /// The largest permitted avatar file size for this tier, in bytes.
+    pub fn limit(self) -> usize {
+        match self {
+            Tier::Default => 100_000,
+            Tier::Special => 250_000,
+            Tier::Custom(n) => n,
+        }
+    }
+}
+
+impl Display for Tier {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        match self {
+            Tier::Default => write!(fmt, "default"),
+            Tier::Special => write!(fmt, "special"),
+            Tier::Custom(n) => write!(fmt, "{n}B"),
+        }
+    }
+}
+
+/// Serialized as it's [displayed][Display], the same way it's written on the command line.
+impl Serialize for Tier {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl FromStr for Tier {
+    type Err = ParseIntError;
+    /// Parses `default`, `special`, or a number of bytes.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(Tier::Default),
+            "special" => Ok(Tier::Special),
+            n => n.parse().map(Tier::Custom),
+        }
+    }
+}
+
+/// How an avatar's size compares to a [Tier]'s limit.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct LimitReport {
+    /// The tier that was checked against.
+    pub tier: Tier,
+    /// The compressed size of the whole avatar, in bytes.
+    pub total: usize,
+    /// The compressed size of each part of the avatar on its own, in bytes, largest first. Since
+    /// compression works better on more data, these add up to a bit more than [total][Self::total].
+    pub components: Vec<(&'static str, usize)>,
+}
+
+impl LimitReport {
+    /// Whether the avatar will be accepted by the backend.
+    pub fn fits(&self) -> bool {
+        self.total <= self.tier.limit()
+    }
+
+    /// How many bytes are left before reaching the limit. This is negative if the avatar is over
+    /// the limit.
+    pub fn headroom(&self) -> isize {
+        self.tier.limit() as isize - self.total as isize
+    }
+}
+
+impl Display for LimitReport {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        let headroom = self.headroom();
+        if headroom < 0 {
+            writeln!(fmt, "{}B of {}B ({} tier): {}B over", self.total, self.tier.limit(), self.tier, -headroom)?;
+        } else {
+            writeln!(fmt, "{}B of {}B ({} tier): {}B left", self.total, self.tier.limit(), self.tier, headroom)?;
+        }
+        for (name, size) in &self.components {
+            writeln!(fmt, "• {name}: {size}B")?;
+        }
+        Ok(())
+    }
+}
+
+/// Measures a value as if it were the only tag in a moon. NBT files must have a compound at the
+/// root, so the value is wrapped in one.
+pub(crate) fn serialized_size(name: &str, value: &impl Serialize, flavor: Flavor) -> Result<usize, NbtIoError> {
+    let wrapped = HashMap::from([(name, value)]);
+    quartz_nbt::serde::serialize(&wrapped, Some(""), flavor).map(|d| d.len())
+}
+
+impl Moon {
+    /// Compares this avatar's compressed size against a tier's limit, as Figura would compress it
+    /// when uploading.
+    pub fn check_limits(&self, tier: Tier) -> Result<LimitReport, NbtIoError> {
+        let flavor = Flavor::GzCompressedWith(Compression::default());
+        let total = quartz_nbt::serde::serialize(self, Some(""), flavor)?.len();
+        let mut components = vec![
+            ("textures", serialized_size("textures", &self.textures, flavor)?),
+            ("scripts", serialized_size("scripts", &self.scripts, flavor)?),
+            ("sounds", serialized_size("sounds", &self.sounds, flavor)?),
+            ("animations", serialized_size("animations", &self.animations, flavor)?),
+            ("models", serialized_size("models", &self.models, flavor)?),
+            ("resources", serialized_size("resources", &self.resources, flavor)?),
+            ("metadata", serialized_size("metadata", &self.metadata, flavor)?),
+        ];
+        components.sort_by(|a, b| b.1.cmp(&a.1));
+        Ok(LimitReport { tier, total, components })
+    }
+}
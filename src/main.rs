@@ -6,18 +6,64 @@
 
 mod bbmodel;
 pub mod moon;
+mod moons;
+mod scripts;
+mod image;
+mod atlas;
+mod pipeline;
+mod limits;
+mod stats;
+#[cfg(feature = "mount")]
+mod mount;
+mod history;
+mod check;
+mod asset;
+mod animation;
+mod generate;
+mod compare;
+mod diff;
+mod glob;
+mod http;
+mod cloud;
+#[cfg(feature = "backend")]
+mod backend;
+mod pack;
+#[cfg(feature = "watch")]
+mod watch;
+#[cfg(feature = "browse")]
+mod browse;
+mod complexity;
+mod convert;
+mod init;
+mod merge;
+mod permissions;
+mod molang;
+mod report;
+mod config;
+mod grep;
+#[cfg(feature = "run")]
+mod runtime;
+mod profile;
+mod docgen;
 
 use std::collections::HashMap;
+use std::ffi::OsStr;
 use std::fmt::Display;
 use std::fs::{File, create_dir_all, canonicalize, read_to_string, write};
 use std::io::{self, stdout, IsTerminal, Read, Write};
 use std::path::{Path, PathBuf};
 use std::process::exit;
 use std::str::FromStr;
+use asset::Asset;
 use base64::{Engine as _, prelude::BASE64_STANDARD};
 use bbmodel::BBModel;
-use clap::{Args, ArgGroup, Parser, Subcommand};
+use bbmodel::lenient::{Strictness, UnknownFields};
+use clap::{Args, ArgGroup, CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::CompleteEnv;
+use clap_complete::engine::{ArgValueCompleter, CompletionCandidate};
 use moon::Moon;
+use moons::Collection;
+use report::{is_stdio, CliError, Reporter};
 use quartz_nbt::{io::NbtIoError, serde::Array};
 use resolve_path::PathResolveExt as _;
 use serde::{Serialize, Deserialize};
@@ -73,7 +119,7 @@ fn opt_equal<K: FromStr, V: FromStr>(pair: &str) -> Result<(Option<K>, V), OptEq
 }
 
 /// Set of modifications to perform to avatar data.
-#[derive(Args, Clone, Debug, PartialEq, Eq)]
+#[derive(Args, Clone, Debug, Default, PartialEq, Eq)]
 #[command(next_help_heading = "Editing Options")]
 pub struct MoonModifications {
     /// Add an avatar author (authors cannot be removed for obvious reasons).
@@ -86,19 +132,52 @@ pub struct MoonModifications {
     #[arg(short = 'k', long, value_name = "\x08[NAME=]<PATH>\x1b[C\x1b", value_parser = equal::<String, PathBuf>)]
     pub add_texture: Vec<(String, PathBuf)>,
     /// Interactively edit a script (leaving a copy in the current working directory).
-    #[arg(short = 'e', long, alias = "edit", value_name = "NAME")]
+    #[arg(short = 'e', long, alias = "edit", value_name = "NAME", add = ArgValueCompleter::new(script_names))]
     pub edit_script: Vec<String>,
     /// Delete a script.
-    #[arg(short = 'r', long, value_name = "NAME")]
+    #[arg(short = 'r', long, value_name = "NAME", add = ArgValueCompleter::new(script_names))]
     pub remove_script: Vec<String>,
     /// Delete a texture.
-    #[arg(short = 's', long, value_name = "NAME")]
+    #[arg(short = 's', long, value_name = "NAME", add = ArgValueCompleter::new(texture_names))]
     pub remove_texture: Vec<String>,
+    /// Remove unused textures and merge identical ones.
+    #[arg(long)]
+    pub gc_textures: bool,
+    /// Combine all scripts into a single script.
+    #[arg(long)]
+    pub bundle_scripts: bool,
+    /// Remove comments, indentation, and blank lines from scripts.
+    #[arg(long)]
+    pub minify_scripts: bool,
+    /// Also rename local variables to short names when minifying scripts.
+    #[arg(long, requires = "minify_scripts")]
+    pub shorten_locals: bool,
+    /// Minify scripts and remove unused parts and resources.
+    #[arg(long)]
+    pub strip: bool,
+    /// Remove model parts whose path (such as `model/Head/hat`) matches a glob.
+    #[arg(long, value_name = "GLOB")]
+    pub remove_parts: Vec<glob::Glob>,
+    /// Remove model parts with a parent type, such as `World`.
+    #[arg(long, value_name = "TYPE")]
+    pub remove_parent_type: Vec<moon::ParentType>,
+    /// Replace bézier keyframes with this many Catmull-Rom keyframes per curve. By default, this
+    /// only happens if --target-version doesn't support béziers.
+    #[arg(long, value_name = "SAMPLES", value_parser = clap::value_parser!(u32).range(1..))]
+    pub sample_beziers: Option<u32>,
+    /// The Figura version that will play the avatar. Keyframes it can't play are converted to ones
+    /// it can.
+    #[arg(long, value_name = "VERSION")]
+    pub target_version: Option<String>,
+    /// Pack textures into a single atlas with this name, moving UVs to match. Scripts that use
+    /// the merged textures by name will no longer find them.
+    #[arg(long, value_name = "NAME")]
+    pub atlas: Option<String>,
 }
 
 impl MoonModifications {
-    fn apply(self, moon: &mut Moon) -> io::Result<()> {
-        let Self { add_author, add_script, add_texture, edit_script, remove_script, remove_texture } = self;
+    fn apply(self, moon: &mut Moon, reporter: &mut Reporter) -> Result<(), CliError> {
+        let Self { add_author, add_script, add_texture, edit_script, remove_script, remove_texture, gc_textures, bundle_scripts, minify_scripts, shorten_locals, strip, remove_parts, remove_parent_type, sample_beziers, target_version, atlas } = self;
         if add_author.len() > 0 {
             let authors: &mut moon::Authors = &mut moon.metadata.authors;
             // normalize
@@ -118,30 +197,164 @@ impl MoonModifications {
         }
         for name in remove_script {
             if let None = moon.scripts.remove(&name) {
-                eprintln!("warning: removing nonexistent script {name}");
+                reporter.warning(format!("removing nonexistent script {name}"));
             }
         }
         for name in remove_texture {
             if let None = moon.textures.src.remove(&name) {
-                eprintln!("warning: removing nonexistent texture {name}");
+                reporter.warning(format!("removing nonexistent texture {name}"));
             }
         }
         for (name, path) in add_script {
-            let mut buf = vec![];
-            File::open(path)?.read_to_end(&mut buf);
-            moon.scripts.insert(name, buf.into());
+            moon.scripts.insert(name, read_file(&path)?.into());
+        }
+        for name in edit_script {
+            if reporter.is_dry_run() {
+                reporter.info(format!("would edit {name}"));
+                continue;
+            }
+            let path = moon::relative_path(&format!("{name}.lua")).map_err(|e| CliError::failed("edit script", e))?;
+            let source = moon.scripts.get(&name).map_or(&[][..], |script| script.as_ref());
+            write(&path, source).map_err(|e| CliError::io("write", &path, e))?;
+            let editor = std::env::var("VISUAL").or_else(|_| std::env::var("EDITOR")).unwrap_or_else(|_| "vi".into());
+            // editors are often given with arguments, as in `code --wait`
+            let mut words = editor.split_whitespace();
+            let program = words.next().unwrap_or("vi");
+            let status = std::process::Command::new(program).args(words).arg(&path).status()
+                .map_err(|e| CliError::io("run editor", program, e))?;
+            if !status.success() {
+                return Err(CliError::failed("edit script", format!("{program} {status}")));
+            }
+            moon.scripts.insert(name, read_file(&path)?.into());
         }
         for (name, path) in add_texture {
-            let mut buf = vec![];
-            File::open(path)?.read_to_end(&mut buf);
-            moon.textures.src.insert(name, buf.into());
+            let buf = read_file(&path)?;
+            match moon.replace_texture(&name, buf.clone(), image::RescaleUvs::Auto) {
+                Ok(0) => {}
+                Ok(n) => reporter.info(format!("rescaled UVs of {n} face{} for {name}", if n == 1 { "" } else { "s" })),
+                Err(e) => {
+                    reporter.warning(format!("could not read {name} as a PNG, so UVs were not rescaled: {e}"));
+                    moon.textures.src.insert(name, buf.into());
+                }
+            }
+        }
+        let mut removed_parts = 0;
+        for glob in &remove_parts {
+            removed_parts += moon.remove_parts_matching(glob);
+        }
+        for pt in remove_parent_type {
+            removed_parts += moon.remove_parent_type(pt);
+        }
+        if removed_parts > 0 {
+            reporter.info(format!("removed {removed_parts} model part{}", if removed_parts == 1 { "" } else { "s" }));
+        }
+        match (sample_beziers, &target_version) {
+            (Some(samples), _) => { moon.sample_beziers(samples); }
+            (None, Some(target)) => if moon.adapt_beziers(target) {
+                reporter.info(format!("sampled bézier keyframes, which Figura {target} doesn't support"));
+            },
+            (None, None) => {}
+        }
+        if let Some(target) = &target_version {
+            if moon.adapt_expressions(target) {
+                reporter.info(format!("replaced expression keyframes with constants, since Figura {target} doesn't support them"));
+            }
+        }
+        if strip {
+            moon.strip();
+        }
+        if gc_textures {
+            let removed = moon.gc_textures();
+            reporter.info(format!("removed {removed} unused or duplicate texture{}", if removed == 1 { "" } else { "s" }));
+        }
+        if let Some(name) = atlas {
+            match moon.atlas_textures(&name) {
+                Ok(0) => reporter.warning("fewer than two textures could be put in an atlas"),
+                Ok(n) => reporter.info(format!("packed {n} textures into {name}")),
+                Err(e) => reporter.warning(format!("could not build an atlas: {e}")),
+            }
+        }
+        if minify_scripts {
+            let mut names: Vec<String> = moon.scripts.keys().cloned().collect();
+            names.sort();
+            for name in names {
+                let script = moon.scripts.get_mut(&name).unwrap();
+                let before = script.as_ref().len();
+                let source = if shorten_locals { scripts::shorten_locals(script.as_ref()) } else { script.as_ref().to_vec() };
+                let minified = scripts::minify(&source);
+                reporter.info(format!("minified {name}: {before} → {} bytes ({} saved)", minified.len(), before.saturating_sub(minified.len())));
+                *script = minified.into();
+            }
+        }
+        if bundle_scripts && !moon.scripts.is_empty() {
+            let graph = scripts::RequireGraph::build(&moon.scripts);
+            for (script, module) in &graph.missing {
+                reporter.warning(format!("{script} requires nonexistent module {module}"));
+            }
+            if let Some(cycle) = graph.find_cycle() {
+                reporter.warning(format!("require cycle: {}", cycle.join(" → ")));
+            }
+            pipeline::Transform::BundleScripts.apply(moon);
         }
         Ok(())
     }
 }
 
-/// Top-level parsing node
+/// Where and how to reach a Figura backend.
+#[derive(Args, Clone, Debug, PartialEq, Eq)]
+#[command(next_help_heading = "Backend Options")]
+pub struct BackendArgs {
+    /// Host name of the backend, optionally with a port. Defaults to Figura's own backend.
+    #[arg(long = "backend", value_name = "HOST")]
+    pub host: Option<String>,
+    /// Token from an earlier login, to use instead of logging in. Other users can see command
+    /// lines while fia runs, so prefer --token-file or $FIA_TOKEN.
+    #[arg(long)]
+    pub token: Option<String>,
+    /// File holding a token from an earlier login, to use instead of logging in.
+    #[arg(long, value_name = "PATH", conflicts_with = "token")]
+    pub token_file: Option<PathBuf>,
+}
+
+impl BackendArgs {
+    /// Fills in options that weren't given from `$FIA_TOKEN` and the configuration file.
+    fn apply_config(&mut self, config: &config::Config) {
+        if self.host.is_none() {
+            self.host = config.backend.clone();
+        }
+        if self.token.is_none() && self.token_file.is_none() {
+            self.token = std::env::var("FIA_TOKEN").ok().filter(|token| !token.is_empty());
+        }
+        if self.token.is_none() && self.token_file.is_none() {
+            self.token_file = config.token_file.clone();
+        }
+    }
+}
+
+/// Various CLI utilities for Figura.
 #[derive(Clone, Debug, Parser)]
+pub struct Cli {
+    /// How to print results and messages: plain text, json (one array of records once the command
+    /// is done), or ndjson (one record per line as they happen).
+    #[arg(long, global = true, value_name = "FORMAT", default_value = "plain")]
+    pub output: report::OutputFormat,
+    /// Show which files would be written, and their sizes, without writing or uploading anything.
+    /// Supported by pack, unpack, repack, push, and commands that only read.
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+    /// When to color output: auto (only on a terminal), always, or never.
+    #[arg(long, global = true, value_name = "WHEN")]
+    pub color: Option<report::ColorChoice>,
+    /// Configuration file to read defaults from, instead of ~/.config/fia/config.toml.
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+    #[command(subcommand)]
+    #[allow(missing_docs)]
+    pub action: Action,
+}
+
+/// A command to run.
+#[derive(Clone, Debug, Subcommand)]
 pub enum Action {
     #[cfg_attr(feature = "unpack", doc = "Upload an avatar or compiled moon to the Figura backend.")]
     #[cfg_attr(not(feature = "unpack"), doc = "Upload an avatar directory to the Figura backend.")]
@@ -153,6 +366,13 @@ pub enum Action {
         #[cfg(feature = "unpack")]
         #[arg(short, long)]
         moon: bool,
+        /// Minecraft username to upload as. Unless a token is given, logging in needs the
+        /// account's access token in $MINECRAFT_ACCESS_TOKEN.
+        #[arg(short, long)]
+        username: String,
+        #[command(flatten)]
+        #[allow(missing_docs)]
+        backend: BackendArgs,
         #[command(flatten)]
         #[allow(missing_docs)]
         modify: MoonModifications,
@@ -160,10 +380,11 @@ pub enum Action {
     /// Download an avatar from the cloud by UUID or player name.
     #[cfg(feature = "pull")]
     Pull {
-        /// String or UUID (or avatar ID with -A) to download.
-        #[arg(required = true)]
+        /// Username or UUID of the player whose equipped avatar to download.
+        #[arg(required_unless_present = "avatar_id")]
         target: Option<String>,
-        /// Treat the target as an avatar ID instead.
+        /// Download an avatar by ID instead, written as OWNER/ID (such as `Steve/avatar`), whether
+        /// or not it's equipped.
         #[arg(short = 'A', long, conflicts_with = "target")]
         avatar_id: Option<String>,
         #[cfg_attr(not(feature = "unpack"), doc = "Path to write the avatar data file to.")]
@@ -174,19 +395,27 @@ pub enum Action {
         #[arg(short = 'C', long, conflicts_with = "out")]
         cem: Option<String>,
         /// Path to the root directory of the resource pack when using --cem.
-        #[arg(short = 'r', long, requires = "cem")]
+        #[arg(short = 'R', long, requires = "cem")]
         pack_root: Option<PathBuf>,
-        /// Extract the downloaded avatar's contents immediately.
+        /// Extract the downloaded avatar's contents immediately. Fails without writing anything if
+        /// any of its files is named so that it would end up outside the output folder.
         #[cfg(feature = "unpack")]
         #[arg(short, long, conflicts_with = "cem")]
         unpack: bool,
+        /// Log in as this Minecraft account, with its access token in $MINECRAFT_ACCESS_TOKEN,
+        /// if the backend needs it and no token is given.
+        #[arg(long, value_name = "USERNAME")]
+        login: Option<String>,
+        #[command(flatten)]
+        #[allow(missing_docs)]
+        backend: BackendArgs,
         #[command(flatten)]
         #[allow(missing_docs)]
         modify: MoonModifications,
     },
     /// Print information about an avatar file.
     Show {
-        /// Path to the avatar file to show.
+        /// Path or URL of the avatar file to show, or `-` for stdin.
         #[arg()]
         file: PathBuf,
         /// Print the internal representation of the avatar file.
@@ -198,16 +427,146 @@ pub enum Action {
         /// Output script content after each script.
         #[arg(short = 'w', long, requires = "verbose")]
         sources: bool,
+        /// Print a JSON document describing the avatar instead.
+        #[arg(short, long, conflicts_with_all = ["parse", "verbose"])]
+        json: bool,
+        /// Backend permission tier to check the avatar's size against: default, special, or a
+        /// size in bytes.
+        #[arg(short, long, default_value = "default")]
+        tier: limits::Tier,
+        #[command(flatten)]
+        #[allow(missing_docs)]
+        modify: MoonModifications,
+    },
+    /// Print the model tree of an avatar file or folder, or of a Blockbench model.
+    Tree {
+        /// Path to the avatar file or folder, or .bbmodel file, to show.
+        #[arg()]
+        file: PathBuf,
+        /// Path to the part to start from, written with dots as in scripts (such as
+        /// `models.player.Head`, or just `player.Head`). Defaults to the whole model.
+        #[arg()]
+        path: Option<String>,
+        #[command(flatten)]
+        #[allow(missing_docs)]
+        options: TreeOptions,
+    },
+    /// Show where an avatar's size goes, compared against the backend's size limit.
+    Stats {
+        /// Path to the avatar file or folder to measure.
+        #[arg()]
+        file: PathBuf,
+        /// Backend permission tier to compare the avatar's size against: default, special, or a
+        /// size in bytes.
+        #[arg(short, long, default_value = "default")]
+        tier: limits::Tier,
+        /// How many of the largest textures and scripts to list.
+        #[arg(short = 'n', long, default_value_t = 5)]
+        top: usize,
+        #[command(flatten)]
+        #[allow(missing_docs)]
+        modify: MoonModifications,
+    },
+    /// Check an avatar file for problems, exiting unsuccessfully if any errors are found.
+    Check {
+        /// Path to the avatar file to check.
+        #[arg()]
+        file: PathBuf,
+        /// Backend permission tier to check the avatar's size against: default, special, or a
+        /// size in bytes.
+        #[arg(short, long, default_value = "default")]
+        tier: limits::Tier,
+        /// Fail if a condition on the avatar doesn't hold, such as `total_size < 95KB`,
+        /// `complexity < 3000`, or `scripts.count <= 20`.
+        #[arg(short, long = "assert", value_name = "CONDITION")]
+        asserts: Vec<check::Assertion>,
+        /// List the metrics available to --assert along with their values.
+        #[arg(short, long)]
+        metrics: bool,
+        /// Break the avatar's complexity down by model part.
+        #[arg(short, long)]
+        complexity: bool,
+        /// How to print the problems found.
+        #[arg(short, long, value_enum, default_value_t = CheckFormat::Text)]
+        format: CheckFormat,
+        /// Path of the avatar's source directory within its repository, used to locate files in
+        /// SARIF output.
+        #[arg(short = 'P', long, default_value = "")]
+        path_prefix: String,
         #[command(flatten)]
         #[allow(missing_docs)]
         modify: MoonModifications,
     },
+    /// Check an avatar folder or file for problems: inconsistencies in the avatar, mistakes in its
+    /// models, missing or cyclic requires, and going over the size limit.
+    Lint {
+        /// Path to the avatar folder or file to check. Defaults to current directory.
+        #[arg(default_value = ".")]
+        path: PathBuf,
+        /// Backend permission tier to check the avatar's size against: default, special, or a
+        /// size in bytes.
+        #[arg(short, long, default_value = "default")]
+        tier: limits::Tier,
+        /// Exit unsuccessfully if any problem is at least this severe: warning or error.
+        #[arg(short, long, value_name = "SEVERITY", default_value = "error")]
+        deny: check::Severity,
+        /// How to print the problems found.
+        #[arg(short, long, value_enum, default_value_t = CheckFormat::Text)]
+        format: CheckFormat,
+        /// Path of the avatar's source directory within its repository, used to locate files in
+        /// SARIF output.
+        #[arg(short = 'P', long, default_value = "")]
+        path_prefix: String,
+    },
+    /// Show what changed between two avatar files: scripts (as unified diffs), textures, sounds,
+    /// resources, metadata, and model parts. Exits with status 1 if they differ.
+    Diff {
+        /// The old avatar file.
+        old: PathBuf,
+        /// The new avatar file.
+        new: PathBuf,
+        /// Print the differences as JSON.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print, as JSON, the permissions an avatar declares and those its scripts appear to need.
+    Permissions {
+        /// Path to the avatar file to inspect.
+        #[arg()]
+        file: PathBuf,
+    },
     /// Parses a .bbmodel file. Mainly useful for internal testing.
     #[command(hide = true)]
     ParseBbmodel {
         /// Path to the Blockbench model to show.
         #[arg()]
         file: PathBuf,
+        /// Check the model for mistakes instead of showing it. Exits with status 1 if any are
+        /// errors.
+        #[arg(short, long)]
+        lint: bool,
+        /// Accept fields added by plugins and stray nulls, listing the fields that were skipped.
+        #[arg(long)]
+        lenient: bool,
+    },
+    /// Create a starter avatar folder: an avatar.json, an empty player model, and a script with
+    /// empty event handlers. Asks for the avatar's details unless they're given.
+    Init {
+        /// Folder to create, which also names the avatar. Defaults to current directory.
+        #[arg()]
+        dir: Option<PathBuf>,
+        /// The avatar's name. Defaults to the folder's name.
+        #[arg(short, long)]
+        name: Option<String>,
+        /// An author of the avatar (may be repeated).
+        #[arg(short, long = "author", value_name = "AUTHOR")]
+        authors: Vec<String>,
+        /// The avatar's color, as a hex code.
+        #[arg(short = 'c', long)]
+        avatar_color: Option<String>,
+        /// Don't ask for anything that wasn't given; use the defaults.
+        #[arg(short, long)]
+        yes: bool,
     },
     /// Create an avatar file from a directory.
     Pack {
@@ -221,35 +580,75 @@ pub enum Action {
         #[allow(missing_docs)]
         modify: MoonModifications,
     },
+    /// Repack an avatar directory whenever it changes, writing or uploading the result. With
+    /// `--output json`, an array of records is printed after each repack.
+    #[cfg(feature = "watch")]
+    Watch {
+        /// Path to the avatar directory to watch. Defaults to current directory.
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+        /// Where to write the packed avatar. Defaults to avatar.nbt.
+        #[arg(short, long, conflicts_with = "push")]
+        out: Option<PathBuf>,
+        /// Upload and equip the avatar as this Minecraft user instead of writing it. Unless a
+        /// token is given, logging in needs the account's access token in
+        /// $MINECRAFT_ACCESS_TOKEN.
+        #[arg(long, value_name = "USERNAME")]
+        push: Option<String>,
+        /// How long to wait for changes to stop before repacking, in milliseconds.
+        #[arg(short, long, default_value_t = 300)]
+        debounce: u64,
+        #[command(flatten)]
+        #[allow(missing_docs)]
+        backend: BackendArgs,
+        #[command(flatten)]
+        #[allow(missing_docs)]
+        modify: MoonModifications,
+    },
     #[cfg(feature = "unpack")]
     /// Unpack the contents of an avatar file.
     Unpack {
-        /// Path to the avatar data to unpack.
+        /// Path or URL of the avatar data to unpack, or `-` for stdin.
         #[arg()]
         file: PathBuf,
-        /// Where to unpack the data to. Defaults to current directory, which may be explosive!
+        /// Where to unpack the data to. Defaults to current directory, which may be explosive! With
+        /// `-`, the only file selected by the paths is written to stdout.
         #[arg(short, long, default_value = ".")]
         out: PathBuf,
         #[command(flatten)]
         #[allow(missing_docs)]
         modify: MoonModifications,
-        /// Which files to unpack, if not all.
-        #[arg()]
-        paths: Option<Vec<String>>,
-        /// Writes the raw model blob to a file.
+        /// Which files to unpack, if not all, as glob patterns such as `scripts/**/util_*.lua` or
+        /// `*.png`. Patterns without a `/` match in any directory, and patterns that match a
+        /// directory unpack everything in it.
+        #[arg(value_parser = path_filter)]
+        paths: Option<Vec<glob::Glob>>,
+        /// Don't unpack files matching this glob pattern (may be repeated), even if they match
+        /// one of the paths.
+        #[arg(short = 'x', long, value_name = "GLOB", value_parser = path_filter)]
+        exclude: Vec<glob::Glob>,
+        /// Writes the raw model blob to a file, relative to the output directory (models.nbt by
+        /// default).
         #[arg(short = 'm', long)]
         dump_models: Option<Option<String>>,
+        /// Also export each model as a binary glTF file, for viewing outside of Blockbench.
+        #[cfg(feature = "gltf")]
+        #[arg(short, long)]
+        gltf: bool,
+        /// Also export each model as an OBJ file, with an MTL file and its textures alongside.
+        #[arg(long)]
+        obj: bool,
     },
     /// Rewrite, recompress, and optionally modify an avatar file.
     Repack {
-        /// File to read avatar data from.
+        /// File to read avatar data from, or `-` for stdin.
         #[arg()]
         file: PathBuf,
-        /// Output path for avatar data. Overwrites the input file by default.
+        /// Output path for avatar data, or `-` for stdout. Overwrites the input file by default.
         #[arg(short, long)]
         out: Option<PathBuf>,
-        /// Set the compression level to the given value or maximum.
-        #[arg(short = 'z', long)]
+        /// Set the compression level to the given value (from 0 to 9) or maximum.
+        #[arg(short = 'z', long, value_parser = clap::value_parser!(u32).range(0..=9))]
         compress: Option<Option<u32>>,
         /// Do not compress the avatar data.
         #[arg(short = 'l', long, conflicts_with = "compress")]
@@ -261,9 +660,238 @@ pub enum Action {
         #[allow(missing_docs)]
         modify: MoonModifications,
     },
+    /// Convert an avatar between a moon, a Blockbench model, and an avatar folder. Formats are
+    /// guessed from the paths: `.bbmodel` files are models, other files moons, and folders (or
+    /// paths without an extension) avatar folders.
+    Convert {
+        /// The avatar to convert.
+        #[arg()]
+        input: PathBuf,
+        /// Where to write the converted avatar.
+        #[arg(value_name = "OUTPUT")]
+        out: PathBuf,
+        /// Format of the input, if it can't be guessed: moon, bbmodel, or dir.
+        #[arg(long, value_name = "FORMAT")]
+        from: Option<convert::Format>,
+        /// Format of the output, if it can't be guessed: moon, bbmodel, or dir.
+        #[arg(long, value_name = "FORMAT")]
+        to: Option<convert::Format>,
+        /// Which of the avatar's models to write, when converting to a Blockbench model. Needed
+        /// if there's more than one.
+        #[arg(short, long)]
+        model: Option<String>,
+        #[command(flatten)]
+        #[allow(missing_docs)]
+        modify: MoonModifications,
+    },
+    /// Apply addons to an avatar: add their models, scripts, textures, sounds, animations, and
+    /// resources to it. Avatars can be given as moons, Blockbench models, or folders. Fails if an
+    /// addon has something the avatar already has, unless told which to keep.
+    Merge {
+        /// The avatar to add to.
+        #[arg()]
+        base: PathBuf,
+        /// The addons to add, in order.
+        #[arg(required = true)]
+        addons: Vec<PathBuf>,
+        /// Where to write the merged avatar file.
+        #[arg(short, long)]
+        out: PathBuf,
+        /// Where both have something, keep the avatar's.
+        #[arg(long, conflicts_with = "prefer_addon")]
+        prefer_base: bool,
+        /// Where both have something, keep the addon's.
+        #[arg(long)]
+        prefer_addon: bool,
+        /// Put each addon's scripts under its file name, so that `hat.moon`'s `main` script
+        /// becomes `hat.main`. Requires between an addon's scripts need to be relative to keep
+        /// working.
+        #[arg(short, long)]
+        namespace: bool,
+        /// Remove unused textures and merge identical ones afterwards.
+        #[arg(long)]
+        dedup_textures: bool,
+        #[command(flatten)]
+        #[allow(missing_docs)]
+        modify: MoonModifications,
+    },
+    /// Search the scripts of an avatar file or folder for a regular expression, printing each
+    /// matching line as `script:line: text`. Exits with status 1 if nothing matched.
+    Grep {
+        /// The regular expression to search for.
+        #[arg()]
+        pattern: String,
+        /// The avatar to search: a moon, a Blockbench model, or an avatar folder.
+        #[arg()]
+        file: PathBuf,
+        /// Ignore case when matching.
+        #[arg(short, long)]
+        ignore_case: bool,
+        /// Show this many lines around each match.
+        #[arg(short = 'C', long, value_name = "LINES", default_value_t = 0)]
+        context: usize,
+        /// Show this many lines before each match, instead of --context.
+        #[arg(short = 'B', long, value_name = "LINES")]
+        before_context: Option<usize>,
+        /// Show this many lines after each match, instead of --context.
+        #[arg(short = 'A', long, value_name = "LINES")]
+        after_context: Option<usize>,
+    },
+    /// Run an avatar's scripts without Minecraft: load them, fire `entity_init`, then fire tick
+    /// and render events, printing whatever the scripts print. Exits with status 1 if a script
+    /// errors.
+    #[cfg(feature = "run")]
+    Run {
+        /// The avatar to run: a moon, a Blockbench model, or an avatar folder.
+        #[arg()]
+        file: PathBuf,
+        /// How many ticks to run for after `entity_init`.
+        #[arg(short, long, default_value_t = 20)]
+        ticks: u32,
+        /// How many frames to render after each tick.
+        #[arg(long, default_value_t = 1)]
+        renders: u32,
+        /// Stop with an error when loading the scripts, or handling one event, takes more than
+        /// this many Lua instructions, so that endless loops don't hang. 0 means no limit.
+        #[arg(long, value_name = "N", default_value_t = 1_000_000)]
+        max_instructions: u32,
+        /// Stop with an error when the scripts allocate more than this many MiB of memory. 0
+        /// means no limit.
+        #[arg(long, value_name = "MIB", default_value_t = 64)]
+        max_memory: usize,
+        #[command(flatten)]
+        #[allow(missing_docs)]
+        modify: MoonModifications,
+    },
+    /// Show how many Lua instructions each event costs per call, and which functions they go to,
+    /// against Figura's instruction limits at the default permission level. Costs are estimated
+    /// from the scripts unless --measure is given. Exits with status 1 if an event goes over.
+    Profile {
+        /// The avatar to profile: a moon, a Blockbench model, or an avatar folder.
+        #[arg()]
+        file: PathBuf,
+        /// Run the avatar, as `fia run` does, and count the instructions actually executed.
+        #[cfg(feature = "run")]
+        #[arg(short, long)]
+        measure: bool,
+        /// How many ticks to run for when measuring.
+        #[cfg(feature = "run")]
+        #[arg(short, long, default_value_t = 20, requires = "measure")]
+        ticks: u32,
+        /// How many frames to render after each tick when measuring.
+        #[cfg(feature = "run")]
+        #[arg(long, default_value_t = 1, requires = "measure")]
+        renders: u32,
+        #[command(flatten)]
+        #[allow(missing_docs)]
+        modify: MoonModifications,
+    },
+    /// Generate documentation for an avatar's scripts from their LuaDoc/EmmyLua comments: the
+    /// functions they export, their pings, and their action wheel actions. Prints Markdown unless
+    /// --json is given.
+    Docgen {
+        /// The avatar to document: a moon, a Blockbench model, or an avatar folder.
+        #[arg()]
+        file: PathBuf,
+        /// Generate JSON instead of Markdown.
+        #[arg(long)]
+        json: bool,
+        /// Write the documentation to this file instead of printing it.
+        #[arg(short, long)]
+        out: Option<PathBuf>,
+    },
+    /// Browse an avatar file's model parts, scripts, textures, and other files in the terminal,
+    /// extracting them one at a time.
+    #[cfg(feature = "browse")]
+    Browse {
+        /// Path to the avatar file to browse.
+        #[arg()]
+        file: PathBuf,
+        /// Directory to extract items to.
+        #[arg(short, long, default_value = ".")]
+        out: PathBuf,
+    },
+    /// Mount an avatar file as a read-only filesystem.
+    #[cfg(feature = "mount")]
+    Mount {
+        /// Path to the avatar file to mount.
+        #[arg()]
+        file: PathBuf,
+        /// Directory to mount the avatar's contents on.
+        #[arg()]
+        dir: PathBuf,
+    },
+    /// List Figura's backups of previously equipped avatars, or restore one.
+    History {
+        /// Path to the `figura` directory. Defaults to the one in the usual Minecraft directory.
+        #[arg(short = 'f', long, global = true)]
+        figura_dir: Option<PathBuf>,
+        #[command(subcommand)]
+        #[allow(missing_docs)]
+        action: Option<HistoryAction>,
+    },
+    /// Bundle several avatar files into a collection, or inspect one.
+    #[command(subcommand)]
+    Collection(CollectionAction),
+    /// Inspect a Blockbench model.
+    #[command(subcommand)]
+    Model(ModelAction),
+    /// List, export, replace, or preview the textures in an avatar file, without unpacking it.
+    #[command(subcommand)]
+    Texture(TextureAction),
+    /// Create an avatar file with one of the built-in generators.
+    Generate {
+        /// Where to write the avatar file.
+        #[arg(short, long, default_value = "avatar.moon", global = true)]
+        out: PathBuf,
+        #[command(subcommand)]
+        #[allow(missing_docs)]
+        generator: Generator,
+    },
+    /// Tools for working on fia itself.
+    #[command(hide = true)]
+    Dev {
+        #[command(subcommand)]
+        #[allow(missing_docs)]
+        command: DevCommand,
+    },
     #[cfg(feature = "backend")]
     /// Run a Figura-compatible backend.
     Backend {
+        /// Transform to apply to uploaded avatars before storing them (may be repeated or
+        /// comma-separated). One of recompress-textures, minify-scripts, strip-sounds,
+        /// gc-textures, or bundle-scripts.
+        #[arg(short = 't', long = "transform", value_name = "TRANSFORM", value_delimiter = ',')]
+        transforms: Vec<pipeline::Transform>,
+        /// Keep a copy of each avatar as it was uploaded alongside the transformed one.
+        #[arg(long, requires = "transforms")]
+        keep_original: bool,
+        /// Address to listen on. Figura only connects over HTTPS, so this should be behind a
+        /// reverse proxy that handles TLS.
+        #[arg(short, long, default_value = "127.0.0.1:8080")]
+        bind: String,
+        /// Directory to store uploaded avatars in.
+        #[arg(short, long, default_value = "backend")]
+        data: PathBuf,
+        /// Permission tier whose size limit uploads are held to: default, special, or a size in
+        /// bytes.
+        #[arg(long, default_value = "default")]
+        tier: limits::Tier,
+        /// Let players log in without checking their account with Mojang, as on an offline-mode
+        /// server. Anyone can then upload avatars as anyone.
+        #[arg(long)]
+        offline: bool,
+        /// Message of the day to show in Figura's wardrobe.
+        #[arg(long, default_value = "")]
+        motd: String,
+    },
+    /// Print a shell completion script. To also complete the names of scripts and textures in
+    /// avatar files, load completions with `source <(COMPLETE=bash fia)` (or the same for your
+    /// shell) instead.
+    Completions {
+        /// The shell to print the script for.
+        #[arg()]
+        shell: clap_complete::Shell,
     },
     /// 🦭
     #[command(hide = true, group = ArgGroup::new("image").multiple(false))]
@@ -280,178 +908,1173 @@ pub enum Action {
     },
 }
 
-fn get_moon_with_name(mut file: impl Read) -> Result<(Moon, String), NbtIoError> {
-    quartz_nbt::serde::deserialize_from(&mut file, quartz_nbt::io::Flavor::GzCompressed)
+impl Action {
+    /// Fills in options that weren't given on the command line from the configuration file.
+    fn apply_config(&mut self, config: &config::Config) {
+        match self {
+            Action::Push { backend, .. } => backend.apply_config(config),
+            #[cfg(feature = "pull")]
+            Action::Pull { backend, .. } => backend.apply_config(config),
+            #[cfg(feature = "watch")]
+            Action::Watch { backend, .. } => backend.apply_config(config),
+            Action::Repack { compress, no_compress, .. } => if compress.is_none() && !*no_compress {
+                *compress = config.compression.map(Some);
+            },
+            #[cfg(feature = "unpack")]
+            Action::Unpack { paths, exclude, .. } => {
+                let globs = |patterns: &[String]| patterns.iter().map(|pattern| path_filter(pattern).unwrap_or_else(|never| never)).collect();
+                if paths.is_none() {
+                    *paths = config.unpack.paths.as_deref().map(globs);
+                }
+                if exclude.is_empty() {
+                    *exclude = globs(&config.unpack.exclude);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Whether `--dry-run` can be used with this command: it either only reads, or can report what
+    /// it would write instead of writing it.
+    fn supports_dry_run(&self) -> bool {
+        match self {
+            Action::Pack { .. } | Action::Repack { .. } | Action::Push { .. } | Action::Docgen { .. } => true,
+            #[cfg(feature = "unpack")]
+            Action::Unpack { .. } => true,
+            #[cfg(feature = "run")]
+            Action::Run { .. } => true,
+            Action::Show { .. } | Action::Grep { .. } | Action::Profile { .. } | Action::Check { .. } | Action::Tree { .. } | Action::Stats { .. } | Action::Lint { .. }
+                | Action::Diff { .. } | Action::Permissions { .. } | Action::ParseBbmodel { .. } | Action::Model(_)
+                | Action::Texture(TextureAction::List { .. } | TextureAction::Preview { .. })
+                | Action::Collection(CollectionAction::Ls { .. }) | Action::History { action: None, .. }
+                | Action::Dev { .. } | Action::Completions { .. } | Action::Fok { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+/// Output formats for `fia check`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum CheckFormat {
+    /// Human-readable messages on stderr.
+    Text,
+    /// A SARIF log on stdout, for code scanning tools.
+    Sarif,
+}
+
+/// Operations on avatar backups.
+#[derive(Clone, Debug, Subcommand)]
+pub enum HistoryAction {
+    /// Copy a backup into the wardrobe, by its number in the list.
+    Restore {
+        /// Which backup to restore, as numbered by `fia history`.
+        #[arg()]
+        n: usize,
+    },
+}
+
+/// Subcommands of `fia dev`.
+#[derive(Clone, Debug, Subcommand)]
+pub enum DevCommand {
+    /// Compare an avatar Figura exported against fia's packing of the same source, listing every
+    /// tag fia gets wrong. Exits unsuccessfully if there are any.
+    CompareExport {
+        /// Path to the moon Figura produced.
+        #[arg()]
+        export: PathBuf,
+        /// Path to the avatar's source directory, or to a moon fia already packed from it.
+        #[arg()]
+        src: PathBuf,
+    },
 }
-fn get_moon(mut file: impl Read) -> Result<Moon, NbtIoError> {
-    get_moon_with_name(file).map(|d| d.0)
+
+/// Avatars that `fia generate` can create.
+#[derive(Clone, Debug, Subcommand)]
+pub enum Generator {
+    /// A flag carried on the avatar's back, showing an image.
+    Banner {
+        /// Path to the PNG image to show on the flag.
+        #[arg()]
+        image: PathBuf,
+        /// Height of the flag in pixels; the width follows from the image.
+        #[arg(long, default_value_t = 16.0)]
+        height: f64,
+        /// Make the flag sway back and forth.
+        #[arg(short, long)]
+        wave: bool,
+    },
+    /// A wooden sign carried above the avatar's head.
+    Sign {
+        /// The text to write on the sign.
+        #[arg()]
+        text: String,
+    },
 }
 
-fn main() -> io::Result<()> {
-    match Action::parse() {
-        Action::Push { avatar, modify, #[cfg(feature = "unpack")] moon } => {
-            todo!()
+/// Operations on moon collections (`.moons` files).
+#[derive(Clone, Debug, Subcommand)]
+pub enum CollectionAction {
+    /// Create a collection from avatar files.
+    Create {
+        /// Where to write the collection.
+        #[arg()]
+        out: PathBuf,
+        /// Avatar files to include. Entries are named after the file unless a name is given.
+        #[arg(required = true, value_name = "\x08[NAME=]<PATH>\x1b[C\x1b", value_parser = opt_equal::<String, PathBuf>)]
+        moons: Vec<(Option<String>, PathBuf)>,
+    },
+    /// List the avatars in a collection.
+    Ls {
+        /// Path to the collection.
+        #[arg()]
+        file: PathBuf,
+    },
+    /// Extract avatar files from a collection.
+    Extract {
+        /// Path to the collection.
+        #[arg()]
+        file: PathBuf,
+        /// Directory to write the extracted avatar files to.
+        #[arg(short, long, default_value = ".")]
+        out: PathBuf,
+        /// Which entries to extract, if not all.
+        #[arg()]
+        names: Vec<String>,
+    },
+}
+
+/// Operations on Blockbench models (`.bbmodel` files).
+#[derive(Clone, Debug, Subcommand)]
+pub enum ModelAction {
+    /// Count a model's elements, textures, and animations.
+    Stats {
+        /// Path to the Blockbench model.
+        #[arg()]
+        file: PathBuf,
+        /// Accept fields added by plugins and stray nulls.
+        #[arg(long)]
+        lenient: bool,
+    },
+}
+
+/// Operations on the textures in an avatar file.
+#[derive(Clone, Debug, Subcommand)]
+pub enum TextureAction {
+    /// List the textures with their dimensions and sizes.
+    #[command(alias = "ls")]
+    List {
+        /// Path to the avatar file.
+        #[arg()]
+        file: PathBuf,
+    },
+    /// Write textures out as PNG files, named as `fia unpack` names them.
+    Export {
+        /// Path to the avatar file.
+        #[arg()]
+        file: PathBuf,
+        /// Directory to write the textures to.
+        #[arg(short, long, default_value = ".")]
+        out: PathBuf,
+        /// Which textures to export, if not all.
+        #[arg(add = ArgValueCompleter::new(texture_names))]
+        names: Vec<String>,
+    },
+    /// Replace textures (or add new ones) from PNG files, rescaling the UVs of faces that use a
+    /// texture whose size changed.
+    Import {
+        /// Path to the avatar file.
+        #[arg()]
+        file: PathBuf,
+        /// PNG files to import. Each replaces the texture named after the file unless a name is
+        /// given.
+        #[arg(required = true, value_name = "\x08[NAME=]<PATH>\x1b[C\x1b", value_parser = opt_equal::<String, PathBuf>)]
+        textures: Vec<(Option<String>, PathBuf)>,
+        /// Output path for the avatar file. Overwrites the input file by default.
+        #[arg(short, long)]
+        out: Option<PathBuf>,
+        /// Leave UVs alone, such as when the new texture only adds space at the right or bottom.
+        #[arg(short, long)]
+        keep_uvs: bool,
+    },
+    /// Show textures in the terminal, using the kitty graphics protocol, with their dimensions and
+    /// sizes.
+    Preview {
+        /// Path to the avatar file.
+        #[arg()]
+        file: PathBuf,
+        /// How many rows of the terminal each texture takes up.
+        #[arg(short, long, default_value_t = 8)]
+        rows: u32,
+        /// Which textures to show, if not all.
+        #[arg(add = ArgValueCompleter::new(texture_names))]
+        names: Vec<String>,
+    },
+}
+
+/// Picks out the textures named in `names` (or all of them, if it's empty), sorted by name,
+/// warning about names that don't exist.
+fn select_textures<'a>(reporter: &mut Reporter, moon: &'a Moon, names: &[String]) -> Vec<(&'a str, &'a [u8])> {
+    for name in names {
+        if !moon.textures.src.contains_key(name) {
+            reporter.warning(format!("no texture named {name}"));
         }
-        #[cfg(feature = "pull")]
-        Action::Pull { target, avatar_id, out, cem, pack_root, modify, #[cfg(feature = "unpack")] unpack } => {
-            todo!()
-        }
-        Action::Show { file, verbose, parse, sources, modify } => {
-            let file = File::open(file)?;
-            // FIXME: don't panic
-            let (mut moon, tag_name) = get_moon_with_name(file).expect("loading moon failed");
-            modify.apply(&mut moon);
-            if parse {
-                println!("{moon:#?}");
-            } else {
-                println!("\x1b[1;4m{}\x1b[21;22;24m", moon.metadata.name);
-                if moon.metadata.description != "" {
-                    let mut desc: &str = (&*moon.metadata.description).into();
-                    if !verbose {
-                        if let Some(size) = desc.find('\n') {
-                            desc = &desc[0..size];
-                            // Safety:
-                            // * Decreasing the length of a string is safe
-                            // * `str::find` always returns a value less than length
-                            // * `str::find` is codepoint-aligned, hopefully
-                            // Rationale: Avoids an allocation
-                            // unsafe {
-                            //     let ptr2: &mut (*const (), usize) = std::mem::transmute(&mut desc);
-                            //     debug_assert!(size <= ptr2.1);
-                            //     ptr2.1 = size;
-                            // }
+    }
+    let mut textures: Vec<_> = moon.textures.src.iter()
+        .filter(|(name, _)| names.is_empty() || names.contains(name))
+        .map(|(name, data)| (name.as_str(), data.as_ref().as_slice()))
+        .collect();
+    textures.sort();
+    textures
+}
+
+/// Reports the dimensions and size of each texture, as `fia texture list` shows them.
+fn texture_summary(reporter: &mut Reporter, textures: &[(&str, &[u8])]) {
+    let mut text = String::new();
+    let mut list = vec![];
+    for &(name, data) in textures {
+        let dimensions = image::dimensions(data).ok();
+        match dimensions {
+            Some((width, height)) => text += &format!("{name}\t{width}×{height}\t{}B\n", data.len()),
+            None => text += &format!("{name}\t\x1b[2m(unreadable)\x1b[22m\t{}B\n", data.len()),
+        }
+        list.push(serde_json::json!({
+            "name": name,
+            "width": dimensions.map(|d| d.0),
+            "height": dimensions.map(|d| d.1),
+            "size": data.len(),
+        }));
+    }
+    reporter.result(text, list);
+}
+
+/// Draws a PNG in the terminal with the kitty graphics protocol, `rows` rows tall. The image is
+/// sent in chunks, since terminals only accept so much in one escape sequence.
+fn print_image(png: &[u8], rows: u32) {
+    let encoded = BASE64_STANDARD.encode(png);
+    let chunks: Vec<_> = encoded.as_bytes().chunks(4096).collect();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(i + 1 < chunks.len());
+        let chunk = std::str::from_utf8(chunk).expect("base64 is ASCII");
+        if i == 0 {
+            print!("\x1b_Gf=100,a=T,r={rows},m={more};{chunk}\x1b\\");
+        } else {
+            print!("\x1b_Gm={more};{chunk}\x1b\\");
+        }
+    }
+    println!();
+}
+
+/// Completes the names of the avatar being worked on: the first argument on the command line that
+/// reads as an avatar file. Dynamic completion passes the whole command line so far as arguments,
+/// which is how the avatar can be found.
+fn complete_names(current: &OsStr, names: impl FnOnce(Moon) -> Vec<String>) -> Vec<CompletionCandidate> {
+    let Some((moon, _)) = std::env::args_os().skip(1)
+        .filter(|arg| Path::new(arg).is_file())
+        .find_map(|arg| Moon::read_auto(File::open(arg).ok()?).ok()) else { return vec![] };
+    let current = current.to_string_lossy();
+    let mut names = names(moon);
+    names.retain(|name| name.starts_with(&*current));
+    names.sort();
+    names.into_iter().map(CompletionCandidate::new).collect()
+}
+
+/// Completes the names of scripts in the avatar being worked on.
+fn script_names(current: &OsStr) -> Vec<CompletionCandidate> {
+    complete_names(current, |moon| moon.scripts.into_keys().collect())
+}
+
+/// Completes the names of textures in the avatar being worked on.
+fn texture_names(current: &OsStr) -> Vec<CompletionCandidate> {
+    complete_names(current, |moon| moon.textures.src.into_keys().collect())
+}
+
+/// Asks a question on the terminal, returning the answer, or `default` if nothing was entered.
+fn prompt(question: &str, default: &str) -> Result<String, CliError> {
+    if default.is_empty() {
+        eprint!("{question}: ");
+    } else {
+        eprint!("{question} [{default}]: ");
+    }
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).map_err(|e| CliError::io("read", "standard input", e))?;
+    let answer = answer.trim();
+    Ok(if answer.is_empty() { default } else { answer }.into())
+}
+
+/// Reads a Blockbench model, without building a JSON tree of the whole file unless fields have to
+/// be set aside.
+fn read_bbmodel(path: &Path, lenient: bool) -> Result<(BBModel, UnknownFields), CliError> {
+    let file = File::open(path).map_err(|e| CliError::io("read", path, e))?;
+    let model = if lenient {
+        serde_json::from_reader(io::BufReader::new(file)).and_then(|json| BBModel::parse(json, Strictness::Lenient))
+    } else {
+        BBModel::from_reader(file).map(|model| (model, UnknownFields::new()))
+    };
+    model.map_err(|e| CliError::parse("load model", path, e))
+}
+
+/// Writes out the files of an avatar, as `fia unpack` does. Exits with the number of files that
+/// couldn't be written.
+#[cfg(feature = "unpack")]
+fn unpack(reporter: &mut Reporter, moon: Moon, out: PathBuf, paths: Option<Vec<glob::Glob>>, exclude: Vec<glob::Glob>, dump_models: Option<Option<String>>, gltf: bool, obj: bool) -> ! {
+    let mut files = moon.unpacked_files().unwrap_or_else(|e| reporter.fail(e.into()));
+    // the dump's path comes from the command line rather than the avatar, so it's used as given
+    let dump = dump_models.zip(moon.models.as_ref()).map(|(path, models)| {
+        use quartz_nbt::serde as qs;
+        use flate2::Compression;
+        use quartz_nbt::io::Flavor;
+        let mut data = vec![];
+        qs::serialize_into(&mut data, models, Some("models"), Flavor::GzCompressedWith(Compression::default()));
+        (path.unwrap_or_else(|| String::from("models.nbt")), data)
+    });
+    if let Some(models) = &moon.models {
+        for part in models.chld.iter() {
+            #[cfg(feature = "gltf")]
+            if gltf {
+                let hierarchy = bbmodel::Hierarchy::from_model_part(part);
+                match bbmodel::gltf::export_glb(&hierarchy, &moon.textures) {
+                    Ok(data) => files.push((part.name.replace('.', "/") + ".glb", data)),
+                    Err(e) => reporter.error(format!("failed to export {} as glTF: {e}", part.name)),
+                }
+            }
+            if obj {
+                let hierarchy = bbmodel::Hierarchy::from_model_part(part);
+                match bbmodel::export_obj(&hierarchy, &moon.textures) {
+                    Ok(export) => {
+                        let path = part.name.replace('.', "/");
+                        let dir = path.rsplit_once('/').map_or(String::new(), |(dir, _)| dir.to_owned() + "/");
+                        files.push((path + ".obj", export.obj.into_bytes()));
+                        files.push((dir.clone() + &export.mtl_name, export.mtl.into_bytes()));
+                        for (file, png) in export.images {
+                            files.push((dir.clone() + &file, png));
                         }
                     }
+                    Err(e) => reporter.error(format!("failed to export {} as OBJ: {e}", part.name)),
                 }
-                // println!("\x1b[1mAuthors:\x1b[21;22m {}");
-                if !moon.textures.src.is_empty() {
-                    if verbose {
-                        println!("");
-                        println!("\x1b[1;4mTextures\x1b[21;22;24m");
-                        for (name, data) in moon.textures.src {
-                            let data = Array::into_inner(data);
-                            println!("• \x1b[1m{name}\x1b[21;22;24m {}B", data.len());
-                        }
+            }
+        }
+    }
+    let mut contents = HashMap::<PathBuf, &[u8]>::new();
+    let mut omitted = 0;
+    let named = files.iter().map(|(name, data)| (name, data, true));
+    for (name, data, from_avatar) in named.chain(dump.iter().map(|(name, data)| (name, data, false))) {
+        let whitelisted = paths.as_ref().is_none_or(|paths| paths.iter().any(|glob| glob.matches_within(name)))
+            && !exclude.iter().any(|glob| glob.matches_within(name));
+        if whitelisted {
+            let path = if from_avatar {
+                moon::relative_path(name).unwrap_or_else(|e| reporter.fail(CliError::failed("unpack avatar", e)))
+            } else {
+                PathBuf::from(name)
+            };
+            contents.insert(out.join(path), data);
+        } else {
+            omitted += 1;
+        }
+    }
+    if is_stdio(&out) {
+        if contents.len() != 1 {
+            reporter.fail(CliError::usage(format!("unpacking to stdout needs paths that select exactly one file, not {}", contents.len())));
+        }
+        let data = contents.into_values().next().unwrap();
+        reporter.write_file(&out, data).unwrap_or_else(|e| reporter.fail(e));
+        reporter.exit(0);
+    }
+    if reporter.is_dry_run() {
+        let mut contents: Vec<_> = contents.into_iter().collect();
+        contents.sort();
+        for (file, data) in &contents {
+            // dry runs never fail to write
+            let _ = reporter.write_file(file, data);
+        }
+        reporter.info(format!("would write {} files{}", contents.len(), if omitted > 0 { format!(" ({omitted} omitted)") } else { "".into() }));
+        reporter.exit(0);
+    }
+    let mut dirs: Vec<_> = contents.keys().filter_map(|p| p.parent().map(PathBuf::from)).collect();
+    dirs.sort();
+    dirs.dedup();
+    let mut written = 0;
+    // the last failure, which decides the exit status
+    let mut failure = None;
+    for dir in dirs {
+        if let Err(e) = create_dir_all(&dir) {
+            let error = CliError::io("create directory", &dir, e);
+            reporter.error(&error);
+            failure = Some(error);
+            let plain = reporter.is_plain();
+            contents.retain(|lost, _| {
+                if lost.starts_with(&dir) {
+                    if plain {
+                        eprintln!("├╴lost file: {}", lost.display());
                     } else {
-                        println!("• \x1b[1m{} texture{}", moon.textures.src.len(), if moon.textures.src.len() == 1 { "" } else { "s" });
+                        reporter.error(format!("lost file: {}", lost.display()));
                     }
+                    false
+                } else {
+                    true
                 }
-                if !moon.scripts.is_empty() {
-                    if verbose {
-                        println!("");
-                        println!("\x1b[1;4mScripts\x1b[21;22;24m");
-                        for (name, data) in moon.scripts {
-                            let data = Array::into_inner(data);
-                            println!("• \x1b[1m{name}\x1b[21;22;24m {}b", data.len());
-                            if sources {
-                                println!("{}", String::from_utf8_lossy(&data));
-                            }
-                        }
-                    } else {
-                        println!("• \x1b[1m{} script{}", moon.scripts.len(), if moon.scripts.len() == 1 { "" } else { "s" });
+            });
+            if plain {
+                eprintln!("\x1b[A└"); // no need to check, as we can't create empty directories
+            }
+        }
+    }
+    for (file, data) in contents {
+        if let Err(e) = write(&file, data) {
+            let error = CliError::io("write", &file, e);
+            reporter.error(&error);
+            failure = Some(error);
+        } else {
+            written += 1;
+        }
+    }
+    reporter.info(format!("wrote {written} files{}", if omitted > 0 { format!(" ({omitted} omitted)") } else { "".into() }));
+    reporter.exit(failure.map_or(0, |error| error.exit_code()))
+}
+
+/// Parses a glob pattern for `fia unpack`, where a trailing `/` is allowed for directories and
+/// patterns without a `/` match in any directory, as in `.gitignore`.
+fn path_filter(pattern: &str) -> Result<glob::Glob, !> {
+    let pattern = pattern.strip_suffix('/').unwrap_or(pattern);
+    Ok(glob::Glob::new(&if pattern.contains('/') { pattern.into() } else { format!("{{,**/}}{pattern}") }))
+}
+
+/// Reports an error talking to a backend and exits.
+fn cloud_failed(reporter: &mut Reporter, e: cloud::CloudError) -> ! {
+    let unauthorized = matches!(e, cloud::CloudError::Unauthorized);
+    let error = CliError::backend(e);
+    reporter.error(&error);
+    if unauthorized {
+        reporter.info("pass --token-file or $FIA_TOKEN, or log in with an access token in $MINECRAFT_ACCESS_TOKEN");
+    }
+    reporter.exit(error.exit_code());
+}
+
+/// Connects to a backend, logging in as `login` (a username and UUID) if no token was given.
+fn connect(reporter: &mut Reporter, backend: BackendArgs, login: Option<(&str, uuid::Uuid)>) -> cloud::Client {
+    let host = backend.host.as_deref().unwrap_or(cloud::DEFAULT_BACKEND);
+    let token = match (backend.token, backend.token_file) {
+        (Some(token), _) => Some(token),
+        (None, Some(path)) => match read_to_string(&path) {
+            Ok(token) => Some(token.trim().to_string()),
+            Err(e) => reporter.fail(CliError::io("read token from", &path, e)),
+        },
+        (None, None) => None,
+    };
+    let client = match token {
+        Some(token) => cloud::Client::with_token(host, token),
+        None => cloud::Client::new(host),
+    };
+    let mut client = client.unwrap_or_else(|e| {
+        reporter.error(format!("invalid backend {host}: {e}"));
+        reporter.exit(2);
+    });
+    if let (None, Some((username, uuid))) = (client.token(), login) {
+        let Ok(access_token) = std::env::var("MINECRAFT_ACCESS_TOKEN") else {
+            reporter.error("logging in needs a Minecraft access token in $MINECRAFT_ACCESS_TOKEN; or pass --token-file or $FIA_TOKEN");
+            reporter.exit(2);
+        };
+        client.login(username, uuid, &access_token).unwrap_or_else(|e| cloud_failed(reporter, e));
+    }
+    client
+}
+
+/// Reads a whole file, or all of stdin if the path is `-`. The file can also be a URL; see
+/// [Asset::locate].
+fn read_file(path: &Path) -> Result<Vec<u8>, CliError> {
+    if is_stdio(path) {
+        let mut data = vec![];
+        io::stdin().lock().read_to_end(&mut data).map_err(|e| CliError::io("read", "standard input", e))?;
+        return Ok(data);
+    }
+    Ok(Asset::locate(path).into_bytes()?)
+}
+
+/// Creates a directory, along with any missing parents.
+fn create_dir(path: &Path) -> Result<(), CliError> {
+    create_dir_all(path).map_err(|e| CliError::io("create", path, e))
+}
+
+/// Loads an avatar file, along with its root tag's name.
+fn load_moon_with_name(reporter: &mut Reporter, path: &Path) -> Result<(Moon, String), CliError> {
+    get_moon_with_name(reporter, &*read_file(path)?).map_err(|e| CliError::parse("load avatar", path, e))
+}
+
+/// Loads an avatar file.
+fn load_moon(reporter: &mut Reporter, path: &Path) -> Result<Moon, CliError> {
+    load_moon_with_name(reporter, path).map(|(moon, _)| moon)
+}
+
+/// Serializes an avatar, to be written or uploaded.
+fn serialize_moon(moon: &Moon, name: &str, flavor: quartz_nbt::io::Flavor) -> Result<Vec<u8>, CliError> {
+    moon.to_canonical_bytes(name, flavor).map_err(|e| CliError::failed("serialize avatar", e))
+}
+
+/// Reads an avatar, reporting any old layouts that had to be upgraded and any tags that were
+/// ignored.
+fn get_moon_with_name(reporter: &mut Reporter, file: impl Read) -> Result<(Moon, String), NbtIoError> {
+    let (moon, name, upgrades) = Moon::read_auto_upgraded(file)?;
+    for upgrade in upgrades.applied {
+        reporter.info(format!("upgraded an old layout: {upgrade}"));
+    }
+    for tag in upgrades.unknown {
+        reporter.warning(format!("ignoring unknown tag {tag}, which won't be kept if the avatar is written again"));
+    }
+    Ok((moon, name))
+}
+fn get_moon(reporter: &mut Reporter, file: impl Read) -> Result<Moon, NbtIoError> {
+    get_moon_with_name(reporter, file).map(|d| d.0)
+}
+
+fn show_moon(reporter: &mut Reporter, moon: Moon, verbose: bool, parse: bool, sources: bool, tier: limits::Tier) {
+    use std::fmt::Write as _;
+    let mut out = String::new();
+    match moon.check_limits(tier) {
+        Ok(report) if verbose => reporter.info(report.to_string().trim_end()),
+        Ok(report) if !report.fits() => reporter.warning(format!("avatar is {}B over the {tier} tier's size limit", -report.headroom())),
+        Ok(_) => {}
+        Err(e) => reporter.warning(format!("could not check size limits: {e}")),
+    }
+    let fingerprint = if verbose { moon.fingerprint().ok() } else { None };
+    if parse {
+        writeln!(out, "{moon:#?}").unwrap();
+    } else {
+        writeln!(out, "\x1b[1;4m{}\x1b[21;22;24m", moon.metadata.name).unwrap();
+        if let Some(fingerprint) = fingerprint {
+            writeln!(out, "\x1b[1mFingerprint:\x1b[21;22m {}", fingerprint.iter().map(|b| format!("{b:02x}")).collect::<String>()).unwrap();
+        }
+        if moon.metadata.description != "" {
+            let mut desc: &str = (&*moon.metadata.description).into();
+            if !verbose {
+                if let Some(size) = desc.find('\n') {
+                    desc = &desc[0..size];
+                    // Safety:
+                    // * Decreasing the length of a string is safe
+                    // * `str::find` always returns a value less than length
+                    // * `str::find` is codepoint-aligned, hopefully
+                    // Rationale: Avoids an allocation
+                    // unsafe {
+                    //     let ptr2: &mut (*const (), usize) = std::mem::transmute(&mut desc);
+                    //     debug_assert!(size <= ptr2.1);
+                    //     ptr2.1 = size;
+                    // }
+                }
+            }
+        }
+        // println!("\x1b[1mAuthors:\x1b[21;22m {}");
+        if !moon.textures.src.is_empty() {
+            if verbose {
+                out.push('\n');
+                writeln!(out, "\x1b[1;4mTextures\x1b[21;22;24m").unwrap();
+                for (name, data) in moon.textures.src {
+                    let data = Array::into_inner(data);
+                    writeln!(out, "• \x1b[1m{name}\x1b[21;22;24m {}B", data.len()).unwrap();
+                }
+            } else {
+                writeln!(out, "• \x1b[1m{} texture{}", moon.textures.src.len(), if moon.textures.src.len() == 1 { "" } else { "s" }).unwrap();
+            }
+        }
+        if !moon.scripts.is_empty() {
+            if verbose {
+                out.push('\n');
+                writeln!(out, "\x1b[1;4mScripts\x1b[21;22;24m").unwrap();
+                for (name, data) in moon.scripts {
+                    let data = Array::into_inner(data);
+                    writeln!(out, "• \x1b[1m{name}\x1b[21;22;24m {}b", data.len()).unwrap();
+                    if sources {
+                        writeln!(out, "{}", String::from_utf8_lossy(&data)).unwrap();
                     }
                 }
+            } else {
+                writeln!(out, "• \x1b[1m{} script{}", moon.scripts.len(), if moon.scripts.len() == 1 { "" } else { "s" }).unwrap();
             }
         }
-        Action::ParseBbmodel { file } => {
-            let file = File::open(file)?;
-            let data: Result<BBModel, _> = serde_json::from_reader(file);
-            println!("{data:#?}");
+        if let Some(models) = moon.models.as_ref().filter(|_| verbose) {
+            out.push('\n');
+            writeln!(out, "\x1b[1;4mModels\x1b[21;22;24m").unwrap();
+            let mut text = String::new();
+            for model in models.chld.iter() {
+                TreeNode::from_part(model, &[]).render(0, TreeOptions::default(), &mut text);
+            }
+            out += &text;
+            if !moon.animations.is_empty() {
+                out.push('\n');
+                writeln!(out, "\x1b[1;4mAnimations\x1b[21;22;24m").unwrap();
+                for (i, animation) in moon.animations.iter().enumerate() {
+                    writeln!(out, "• \x1b[1m{}.{}\x1b[21;22m {}s", animation.mdl, animation.name, animation.len).unwrap();
+                    for (path, channels) in models.animated_parts(i) {
+                        let channels: Vec<String> = channels.channels().map(|c| c.to_string()).collect();
+                        writeln!(out, "  • {path}: {}", channels.join(", ")).unwrap();
+                    }
+                }
+            }
+        }
+    }
+    print!("{}", reporter.styled(&out));
+}
+
+/// Describes an avatar for `fia show --json`.
+fn show_json(moon: &Moon, tier: limits::Tier) -> serde_json::Value {
+    use serde_json::json;
+    let blobs = |map: &HashMap<String, Array<Vec<u8>>>, textures: bool| {
+        let mut names: Vec<&String> = map.keys().collect();
+        names.sort();
+        names.into_iter().map(|name| {
+            let data = map[name].as_ref();
+            let mut entry = json!({ "name": name, "size": data.len() });
+            if textures {
+                entry["dimensions"] = image::dimensions(data).ok().map(|(w, h)| [w, h]).into();
+            }
+            entry
+        }).collect::<Vec<_>>()
+    };
+    let complexity = moon.models.as_ref().map_or_else(Default::default, |models| models.complexity());
+    let limits = moon.check_limits(tier).ok().map(|report| json!({
+        "tier": tier.to_string(),
+        "total": report.total,
+        "limit": tier.limit(),
+        "fits": report.fits(),
+    }));
+    json!({
+        "metadata": moon.metadata,
+        "fingerprint": moon.fingerprint().ok().map(|f| f.iter().map(|b| format!("{b:02x}")).collect::<String>()),
+        "size": limits,
+        "scripts": blobs(&moon.scripts, false),
+        "textures": blobs(&moon.textures.src, true),
+        "sounds": blobs(&moon.sounds, false),
+        "resources": blobs(&moon.resources, false),
+        "models": {
+            "roots": moon.models.iter().flat_map(|models| models.chld.iter().map(|model| &model.name)).collect::<Vec<_>>(),
+            "cubes": complexity.cubes,
+            "meshes": complexity.meshes,
+            "faces": complexity.faces(),
         },
-        Action::Pack { .. } => todo!(),
-        #[cfg(feature = "unpack")]
-        Action::Unpack { file, out, modify, paths, mut dump_models } => {
-            let file = File::open(file)?;
-            // FIXME: don't panic
-            let mut moon = get_moon(file).expect("no opening moon");
-            modify.apply(&mut moon);
-            let Moon { textures: moon::Textures { src, .. }, scripts, animations, models, metadata, resources } = moon;
-            let mut contents = HashMap::<PathBuf, &[u8]>::new();
-            let mut omitted = 0;
-            macro_rules! add_if_whitelisted {
-                ($name:expr => $data:expr) => {
-                    let name: &str = $name;
-                    let data: &[u8] = $data;
-                    'a: {
-                        if let Some(paths) = &paths {
-                            let mut whitelisted = false;
-                            for prefix in paths {
-                                if if prefix.ends_with("/") {
-                                    name.starts_with(prefix)
-                                } else {
-                                    name == *prefix
-                                } {
-                                    contents.insert(out.join(Path::new(&name)), data);
-                                    break 'a
-                                }
-                            }
-                            omitted += 1;
-                        } else {
-                            contents.insert(out.join(Path::new(&name)), data);
-                        }
+        "animations": moon.animations.iter().map(|a| json!({ "model": a.mdl, "name": a.name, "length": a.len })).collect::<Vec<_>>(),
+    })
+}
+
+/// What `fia tree` shows about each part besides its name, kind, and collections.
+#[derive(Args, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[command(next_help_heading = "Display Options")]
+pub struct TreeOptions {
+    /// Show each part's pivot point.
+    #[arg(short, long)]
+    pub pivots: bool,
+    /// Show each part's parent type, if it has one.
+    #[arg(short = 't', long)]
+    pub parent_types: bool,
+    /// Mark parts that are hidden.
+    #[arg(short = 'V', long)]
+    pub visibility: bool,
+    /// Show each group's and element's UUID. Only Blockbench models have these.
+    #[arg(short, long)]
+    pub uuids: bool,
+}
+
+/// A part of a moon, or a group or element of a Blockbench model, as `fia tree` shows it.
+#[derive(Serialize)]
+struct TreeNode {
+    name: String,
+    kind: &'static str,
+    pivot: [f64; 3],
+    parent_type: Option<moon::ParentType>,
+    visible: bool,
+    uuid: Option<String>,
+    collections: Vec<String>,
+    children: Vec<TreeNode>,
+}
+
+impl TreeNode {
+    /// Describes a part and its descendants. `collections` are the names of its model's
+    /// collections, which the part's own are looked up in.
+    fn from_part(part: &moon::ModelPart, collections: &[String]) -> Self {
+        let collections = if part.cn.is_empty() { collections } else { &part.cn };
+        TreeNode {
+            name: part.name.clone(),
+            kind: match part.data {
+                moon::ModelData::Group {} => "",
+                moon::ModelData::Cube { .. } => "cube",
+                moon::ModelData::Mesh { .. } => "mesh",
+            },
+            pivot: part.piv,
+            parent_type: part.pt,
+            visible: part.vsb,
+            uuid: None,
+            collections: part.pr.iter().filter_map(|&i| collections.get(usize::try_from(i).ok()?)).cloned().collect(),
+            children: part.chld.iter().map(|child| TreeNode::from_part(child, collections)).collect(),
+        }
+    }
+
+    /// Describes an item in a Blockbench model's outliner and its descendants, or [None] if it
+    /// refers to an element that doesn't exist.
+    fn from_outliner(item: &bbmodel::OutlinerItem, elements: &HashMap<&str, &bbmodel::Element>) -> Option<Self> {
+        Some(match item {
+            bbmodel::OutlinerItem::Group(group) => TreeNode {
+                name: group.name.clone(),
+                kind: "",
+                pivot: group.origin,
+                parent_type: moon::ParentType::for_part(&group.name),
+                visible: group.visibility,
+                uuid: Some(group.uuid.clone()),
+                collections: vec![],
+                children: group.children.iter().filter_map(|child| TreeNode::from_outliner(child, elements)).collect(),
+            },
+            bbmodel::OutlinerItem::Element(uuid) => {
+                let element = elements.get(uuid.as_str())?;
+                TreeNode {
+                    name: element.name.clone(),
+                    kind: element.kind(),
+                    pivot: element.pivot(),
+                    parent_type: None,
+                    visible: element.visibility.unwrap_or(true),
+                    uuid: Some(uuid.clone()),
+                    collections: vec![],
+                    children: vec![],
+                }
+            }
+        })
+    }
+
+    /// Finds a descendant by its path: the names of the nodes leading to it, separated by dots,
+    /// as scripts write it.
+    fn find(&self, path: &str) -> Option<&TreeNode> {
+        path.split('.').filter(|s| !s.is_empty()).try_fold(self, |node, name| node.children.iter().find(|c| c.name == name))
+    }
+
+    /// Writes this node and its descendants out as text.
+    fn render(&self, depth: usize, options: TreeOptions, out: &mut String) {
+        let kind = if self.kind.is_empty() { String::new() } else { format!(" ({})", self.kind) };
+        *out += &format!("{}• \x1b[1m{}\x1b[21;22m{kind}", "  ".repeat(depth), self.name);
+        if !self.collections.is_empty() {
+            *out += &format!(" [{}]", self.collections.join(", "));
+        }
+        if options.parent_types {
+            if let Some(pt) = self.parent_type {
+                *out += &format!(" <{pt:?}>");
+            }
+        }
+        if options.pivots {
+            *out += &format!(" @ {:?}", self.pivot);
+        }
+        if options.visibility && !self.visible {
+            *out += " \x1b[2m(hidden)\x1b[22m";
+        }
+        if let Some(uuid) = self.uuid.as_ref().filter(|_| options.uuids) {
+            *out += &format!(" \x1b[2m{uuid}\x1b[22m");
+        }
+        *out += "\n";
+        for child in &self.children {
+            child.render(depth + 1, options, out);
+        }
+    }
+}
+
+fn main() {
+    CompleteEnv::with_factory(Cli::command).complete();
+    let mut cli = Cli::parse();
+    let mut reporter = Reporter::new(cli.output, cli.dry_run);
+    let config = match config::Config::load(cli.config.as_deref()) {
+        Ok(config) => config,
+        Err(e) => reporter.fail(e.into()),
+    };
+    reporter.set_color(cli.color.or(config.color).unwrap_or_default());
+    cli.action.apply_config(&config);
+    if cli.dry_run && !cli.action.supports_dry_run() {
+        reporter.fail(CliError::usage("--dry-run isn't supported by this command"));
+    }
+    if let Err(e) = run(cli.action, &mut reporter) {
+        reporter.fail(e);
+    }
+    reporter.finish();
+}
+
+/// Runs a command.
+fn run(action: Action, reporter: &mut Reporter) -> Result<(), CliError> {
+    match action {
+        Action::Push { avatar, username, backend, modify, #[cfg(feature = "unpack")] moon: is_moon } => {
+            let avatar = avatar.unwrap_or_else(|| PathBuf::from("."));
+            #[cfg(not(feature = "unpack"))]
+            let is_moon = false;
+            let mut moon = if is_moon { load_moon(reporter, &avatar)? } else { Moon::pack_dir(&avatar)? };
+            modify.apply(&mut moon, reporter)?;
+            let data = serialize_moon(&moon, "", quartz_nbt::io::Flavor::GzCompressed)?;
+            if reporter.is_dry_run() {
+                reporter.info(format!("would upload and equip {}B as {username}", data.len()));
+                reporter.exit(0);
+            }
+            let owner = cloud::lookup_player(&username).unwrap_or_else(|e| cloud_failed(reporter, e));
+            let client = connect(reporter, backend, Some((&username, owner)));
+            match client.upload(&data) {
+                Ok(()) => {}
+                Err(e @ cloud::CloudError::TooLarge(_)) => {
+                    let error = CliError::backend(e);
+                    reporter.error(&error);
+                    if let Ok(report) = moon.check_limits(limits::Tier::Default) {
+                        reporter.info(report.to_string().trim_end());
                     }
+                    reporter.exit(error.exit_code());
                 }
+                Err(e) => cloud_failed(reporter, e),
+            }
+            client.equip(owner).unwrap_or_else(|e| cloud_failed(reporter, e));
+            reporter.info(format!("uploaded and equipped {}B as {username}", data.len()));
+        }
+        #[cfg(feature = "pull")]
+        Action::Pull { target, avatar_id, out, cem, pack_root, login, backend, modify, #[cfg(feature = "unpack")] unpack: do_unpack } => {
+            let login = login.map(|username| {
+                let uuid = cloud::lookup_player(&username).unwrap_or_else(|e| cloud_failed(reporter, e));
+                (username, uuid)
+            });
+            let client = connect(reporter, backend, login.as_ref().map(|(name, uuid)| (name.as_str(), *uuid)));
+            let (name, owner, id, hash) = match (avatar_id, target) {
+                (Some(spec), _) => {
+                    let Some((player, id)) = spec.split_once('/') else {
+                        return Err(CliError::usage("avatar IDs are written as OWNER/ID"));
+                    };
+                    let owner = cloud::resolve_player(player).unwrap_or_else(|e| cloud_failed(reporter, e));
+                    (player.to_string(), owner, id.to_string(), None)
+                }
+                (None, Some(target)) => {
+                    let uuid = cloud::resolve_player(&target).unwrap_or_else(|e| cloud_failed(reporter, e));
+                    let user = client.user(uuid).unwrap_or_else(|e| cloud_failed(reporter, e));
+                    let Some(avatar) = user.equipped.first() else {
+                        return Err(CliError::backend(format!("{target} has no avatar equipped")));
+                    };
+                    if user.equipped.len() > 1 {
+                        reporter.info(format!("note: {target} has {} avatars equipped; downloading the first", user.equipped.len()));
+                    }
+                    (target, avatar.owner, avatar.id.clone(), Some(avatar.hash.clone()))
+                }
+                (None, None) => unreachable!("clap requires a target or avatar ID"),
             };
-            for (path, data) in &src {
-                add_if_whitelisted!(&(path.replace('.', "/") + ".png") => &data.as_ref());
-            }
-            for (path, data) in &scripts {
-                add_if_whitelisted!(&(path.replace('.', "/") + ".lua") => &data.as_ref());
-            }
-            let mut dump_model_guard: Option<(String, Vec<u8>)> = None;
-            if let Some(path) = dump_models.take() {
-                let path = path.unwrap_or_else(|| String::from("models.nbt"));
-                if let Some(models) = &models {
-                    use quartz_nbt::serde as qs;
-                    use flate2::Compression;
-                    use quartz_nbt::io::Flavor;
-                    let mut data = vec![];
-                    qs::serialize_into(&mut data, &models, Some("models"), Flavor::GzCompressedWith(Compression::default()));
-                    dump_model_guard = Some((path, data));
-                }
-            }
-            if let Some((path, data)) = &dump_model_guard {
-                add_if_whitelisted!(&path => &data);
-            }
-            // if models.chld.len() > 0 {
-                // eprintln!("warning: extracting models not supported yet")
-            // }
-            let mut dirs: Vec<_> = contents.keys().filter_map(|p| p.parent().map(PathBuf::from)).collect();
-            dirs.sort();
-            dirs.dedup();
-            let mut written = 0;
-            let mut fails = std::num::Saturating(0i8);
-            for dir in dirs {
-                if let Err(e) = create_dir_all(&dir) {
-                    fails += 1;
-                    eprintln!("failed to mkdir {}: {e}", dir.display());
-                    contents.retain(|lost, _| {
-                        if lost.starts_with(&dir) {
-                            eprintln!("├╴lost file: {}", lost.display());
-                            false
-                        } else {
-                            true
-                        }
-                    });
-                    eprintln!("\x1b[A└"); // no need to check, as we can't create empty directories
+            let data = client.download(owner, &id).unwrap_or_else(|e| cloud_failed(reporter, e));
+            if let Some(hash) = hash {
+                use sha2::{Digest, Sha256};
+                let actual: String = Sha256::digest(&data).iter().map(|b| format!("{b:02x}")).collect();
+                if !actual.eq_ignore_ascii_case(&hash) {
+                    reporter.warning("the downloaded avatar doesn't match the hash the backend lists for it");
+                }
+            }
+            let mut moon = get_moon(reporter, &*data).map_err(|e| CliError::parse("load downloaded avatar", &id, e))?;
+            #[cfg(feature = "unpack")]
+            if do_unpack {
+                modify.apply(&mut moon, reporter)?;
+                // anyone could have uploaded this, so it's up to unpack to refuse names like
+                // `../x` (which it checks before writing anything)
+                unpack(reporter, moon, out.unwrap_or_else(|| PathBuf::from(&name)), None, vec![], None, false, false);
+            }
+            let path = match cem {
+                Some(entity) => {
+                    // Figura loads entity models from assets/figura/cem/<namespace>/<entity>.moon
+                    let (namespace, entity) = entity.split_once(':').unwrap_or(("minecraft", &entity));
+                    let dir = pack_root.unwrap_or_else(|| PathBuf::from(".")).join("assets/figura/cem").join(namespace);
+                    create_dir(&dir)?;
+                    dir.join(format!("{entity}.moon"))
+                }
+                None => out.unwrap_or_else(|| PathBuf::from(format!("{name}.moon"))),
+            };
+            if modify == MoonModifications::default() {
+                reporter.write_file(&path, &data)?;
+            } else {
+                modify.apply(&mut moon, reporter)?;
+                reporter.write_file(&path, &serialize_moon(&moon, "", quartz_nbt::io::Flavor::GzCompressed)?)?;
+            }
+            reporter.info(format!("wrote {}", path.display()));
+        }
+        Action::Show { file, verbose, parse, sources, json, tier, modify } => {
+            let data = read_file(&file)?;
+            if json || !reporter.is_plain() {
+                let document = if Collection::sniff(&data) {
+                    let collection = Collection::read(&*data).map_err(|e| CliError::parse("load collection", &file, e))?;
+                    let entries: Vec<_> = collection.entries.into_iter().map(|entry| {
+                        let mut moon = entry.moon().map_err(|e| CliError::parse("load avatar", format!("{}:{}", file.display(), entry.name), e))?;
+                        modify.clone().apply(&mut moon, reporter)?;
+                        let mut document = show_json(&moon, tier);
+                        document["entry"] = entry.name.into();
+                        Ok(document)
+                    }).collect::<Result<_, CliError>>()?;
+                    serde_json::Value::from(entries)
+                } else {
+                    let mut moon = get_moon(reporter, &*data).map_err(|e| CliError::parse("load avatar", &file, e))?;
+                    modify.apply(&mut moon, reporter)?;
+                    show_json(&moon, tier)
+                };
+                // --json only changes plain output, as every other format already prints JSON
+                reporter.result(format!("{document:#}\n"), document);
+            } else if Collection::sniff(&data) {
+                let collection = Collection::read(&*data).map_err(|e| CliError::parse("load collection", &file, e))?;
+                for (i, entry) in collection.entries.into_iter().enumerate() {
+                    if i > 0 {
+                        println!("");
+                    }
+                    println!("{}", reporter.styled(&format!("\x1b[2m{}:\x1b[22m", entry.name)));
+                    let mut moon = entry.moon().map_err(|e| CliError::parse("load avatar", format!("{}:{}", file.display(), entry.name), e))?;
+                    modify.clone().apply(&mut moon, reporter)?;
+                    show_moon(reporter, moon, verbose, parse, sources, tier);
                 }
+            } else {
+                let (mut moon, tag_name) = get_moon_with_name(reporter, &*data).map_err(|e| CliError::parse("load avatar", &file, e))?;
+                modify.apply(&mut moon, reporter)?;
+                show_moon(reporter, moon, verbose, parse, sources, tier);
+            }
+        }
+        Action::Check { file, tier, asserts, metrics, complexity, format, path_prefix, modify } => {
+            let data = read_file(&file)?;
+            let (mut moon, _, duplicates) = Moon::read_checked(&*data, moon::sniff_flavor(&data), moon::duplicates::Strictness::Lenient).map_err(|e| CliError::parse("load avatar", &file, e))?;
+            modify.apply(&mut moon, reporter)?;
+            let mut diagnostics: Vec<_> = duplicates.iter().map(check::Diagnostic::from_duplicate_key).collect();
+            diagnostics.extend(check::check(&moon, tier));
+            let values = check::metrics(&moon);
+            if metrics {
+                let lines: String = values.iter().map(|(name, value)| format!("{name} = {value}\n")).collect();
+                reporter.result(lines, serde_json::json!({ "metrics": values }));
+            }
+            if complexity {
+                let report = moon.complexity_report();
+                reporter.result(&report, serde_json::json!({ "complexity": report }));
+            }
+            for assertion in &asserts {
+                diagnostics.extend(check::Diagnostic::from_assertion(assertion, assertion.eval(&values)));
             }
-            for (file, data) in contents {
-                if let Err(e) = write(&file, data) {
-                    fails += 1;
-                    eprintln!("failed to write {}: {e}", file.display());
+            match format {
+                CheckFormat::Text => for diagnostic in &diagnostics {
+                    reporter.diagnostic(diagnostic);
+                }
+                CheckFormat::Sarif => {
+                    let sarif = check::to_sarif(&diagnostics, &path_prefix);
+                    reporter.result(format!("{sarif:#}\n"), sarif);
+                }
+            }
+            if diagnostics.iter().any(|d| d.severity == check::Severity::Error) {
+                reporter.exit(1);
+            }
+        }
+        Action::Tree { file, path, options } => {
+            let root = if file.extension().is_some_and(|ext| ext == "bbmodel") {
+                let (model, _) = read_bbmodel(&file, true)?;
+                let elements = model.elements.iter().map(|e| (e.uuid.as_str(), e)).collect();
+                TreeNode {
+                    name: model.name.clone().unwrap_or_default(),
+                    kind: "",
+                    pivot: [0.0; 3],
+                    parent_type: None,
+                    visible: true,
+                    uuid: None,
+                    collections: vec![],
+                    children: model.outliner.iter().filter_map(|item| TreeNode::from_outliner(item, &elements)).collect(),
+                }
+            } else {
+                let moon = if file.is_dir() {
+                    Moon::pack_dir(&file)?
                 } else {
-                    written += 1;
+                    load_moon(reporter, &file)?
+                };
+                let Some(models) = &moon.models else {
+                    return Err(CliError::usage("avatar has no models"));
+                };
+                TreeNode::from_part(models, &[])
+            };
+            match path {
+                None => {
+                    let mut text = String::new();
+                    for node in &root.children {
+                        node.render(0, options, &mut text);
+                    }
+                    reporter.result(text, &root.children);
+                }
+                Some(path) => {
+                    let relative = match path.strip_prefix(&root.name) {
+                        Some(rest) if !root.name.is_empty() && (rest.is_empty() || rest.starts_with('.')) => rest,
+                        _ => &path,
+                    };
+                    let Some(node) = root.find(relative) else {
+                        return Err(CliError::usage(format!("no part at {path}")));
+                    };
+                    let mut text = String::new();
+                    node.render(0, options, &mut text);
+                    reporter.result(text, node);
+                }
+            }
+        }
+        Action::Stats { file, tier, top, modify } => {
+            let mut moon = if file.is_dir() {
+                Moon::pack_dir(&file)?
+            } else {
+                load_moon(reporter, &file)?
+            };
+            modify.apply(&mut moon, reporter)?;
+            let mut stats = moon.stats(tier).map_err(|e| CliError::failed("serialize avatar", e))?;
+            stats.truncate(top);
+            reporter.result(&stats, &stats);
+        }
+        Action::Lint { path, tier, deny, format, path_prefix } => {
+            let mut diagnostics = vec![];
+            if path.is_dir() {
+                // keep the model lints even if packing then fails
+                match pack::lint_models(&path).map(|lints| diagnostics.extend(lints)).and_then(|()| Moon::pack_dir(&path)) {
+                    Ok(moon) => {
+                        diagnostics.extend(check::check(&moon, tier));
+                        diagnostics.extend(moon.validate());
+                    }
+                    Err(e) => diagnostics.push(check::Diagnostic {
+                        severity: check::Severity::Error,
+                        code: "pack",
+                        message: format!("avatar could not be packed: {e}"),
+                        file: None,
+                        part: None,
+                    }),
+                }
+            } else {
+                let data = read_file(&path)?;
+                let (moon, _, duplicates) = Moon::read_checked(&*data, moon::sniff_flavor(&data), moon::duplicates::Strictness::Lenient)
+                    .map_err(|e| CliError::parse("load avatar", &path, e))?;
+                diagnostics.extend(duplicates.iter().map(check::Diagnostic::from_duplicate_key));
+                diagnostics.extend(check::check(&moon, tier));
+                diagnostics.extend(moon.validate());
+            }
+            match format {
+                CheckFormat::Text => for diagnostic in &diagnostics {
+                    reporter.diagnostic(diagnostic);
+                }
+                CheckFormat::Sarif => {
+                    let sarif = check::to_sarif(&diagnostics, &path_prefix);
+                    reporter.result(format!("{sarif:#}\n"), sarif);
+                }
+            }
+            if diagnostics.iter().any(|d| d.severity >= deny) {
+                reporter.exit(1);
+            }
+        }
+        Action::Diff { old, new, json } => {
+            let old = load_moon(reporter, &old)?;
+            let new = load_moon(reporter, &new)?;
+            let differences = diff::diff(&old, &new);
+            if json {
+                let value = serde_json::to_value(&differences).expect("differences serialize to JSON");
+                reporter.result(format!("{value:#}\n"), value);
+            } else {
+                reporter.result(&differences, &differences);
+            }
+            if !differences.is_empty() {
+                reporter.exit(1);
+            }
+        }
+        Action::Permissions { file } => {
+            let moon = load_moon(reporter, &file)?;
+            let report = serde_json::json!({
+                "declared": moon.metadata.permissions,
+                "inferred": permissions::infer(&moon),
+            });
+            reporter.result(format!("{report:#}\n"), &report);
+        }
+        Action::ParseBbmodel { file, lint, lenient } => {
+            let (model, unknown) = read_bbmodel(&file, lenient)?;
+            for pointer in unknown.keys() {
+                reporter.warning(format!("skipped unknown field {pointer}"));
+            }
+            if lint {
+                let lints = bbmodel::lint(&model);
+                for lint in &lints {
+                    reporter.diagnostic(lint);
+                }
+                if lints.iter().any(|l| l.severity == check::Severity::Error) {
+                    reporter.exit(1);
+                }
+            } else {
+                println!("{model:#?}");
+            }
+        },
+        Action::Init { dir, name, authors, avatar_color: color, yes } => {
+            let dir = dir.unwrap_or_else(|| PathBuf::from("."));
+            let ask = !yes && io::stdin().is_terminal();
+            let default_name = canonicalize(&dir).unwrap_or_else(|_| dir.clone())
+                .file_name().map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+            let name = match name {
+                Some(name) => name,
+                None if ask => prompt("name", &default_name)?,
+                None => default_name,
+            };
+            let authors = if authors.is_empty() && ask {
+                let default_author = std::env::var("USER").unwrap_or_default();
+                prompt("authors, separated by commas", &default_author)?
+                    .split(',').map(str::trim).filter(|a| !a.is_empty()).map(String::from).collect()
+            } else {
+                authors
+            };
+            let color = match color {
+                None if ask => Some(prompt("color (such as #5f9ea0)", "")?).filter(|c| !c.is_empty()),
+                color => color,
+            };
+            init::init(&dir, &init::InitOptions { name, authors, color })?;
+            reporter.info(format!("created an avatar in {}", dir.display()));
+        }
+        Action::Pack { dir, out, modify } => {
+            let mut moon = Moon::pack_dir(&dir)?;
+            modify.apply(&mut moon, reporter)?;
+            let data = serialize_moon(&moon, "", quartz_nbt::io::Flavor::GzCompressed)?;
+            reporter.write_file(&out, &data)?;
+        }
+        #[cfg(feature = "watch")]
+        Action::Watch { dir, out, push, debounce, backend, modify } => {
+            let out = out.unwrap_or_else(|| PathBuf::from("avatar.nbt"));
+            let upload = push.map(|username| {
+                let owner = cloud::lookup_player(&username).unwrap_or_else(|e| cloud_failed(reporter, e));
+                let client = connect(reporter, backend, Some((&username, owner)));
+                (username, owner, client)
+            });
+            let ignored = if upload.is_none() { vec![out.clone()] } else { vec![] };
+            reporter.info(format!("watching {}", dir.display()));
+            // watching never finishes, so JSON output is printed as one array per rebuild
+            reporter.finish();
+            let result = watch::watch(&dir, std::time::Duration::from_millis(debounce), &ignored, || {
+                'rebuild: {
+                    // keep watching after errors, so they can be fixed and saved again
+                    let mut moon = match Moon::pack_dir(&dir) {
+                        Ok(moon) => moon,
+                        Err(e) => break 'rebuild reporter.error(e),
+                    };
+                    if let Err(e) = modify.clone().apply(&mut moon, reporter) {
+                        break 'rebuild reporter.error(e);
+                    }
+                    let data = match moon.to_canonical_bytes("", quartz_nbt::io::Flavor::GzCompressed) {
+                        Ok(data) => data,
+                        Err(e) => break 'rebuild reporter.error(format!("serializing moon failed: {e}")),
+                    };
+                    match &upload {
+                        None => match write(&out, &data) {
+                            Ok(()) => reporter.info(format!("wrote {}B to {}", data.len(), out.display())),
+                            Err(e) => reporter.error(format!("{}: {e}", out.display())),
+                        },
+                        Some((username, owner, client)) => match client.upload(&data).and_then(|()| client.equip(*owner)) {
+                            Ok(()) => reporter.info(format!("uploaded and equipped {}B as {username}", data.len())),
+                            Err(e @ cloud::CloudError::TooLarge(_)) => {
+                                reporter.error(e);
+                                if let Ok(report) = moon.check_limits(limits::Tier::Default) {
+                                    reporter.info(report.to_string().trim_end());
+                                }
+                            }
+                            Err(e) => reporter.error(e),
+                        },
+                    }
                 }
+                reporter.finish();
+            });
+            match result {
+                Ok(never) => never,
+                Err(e) => return Err(CliError::failed("watch for changes", e)),
             }
-            eprintln!("wrote {written} files{}", if omitted > 0 { format!(" ({omitted} omitted)") } else { "".into() });
-            std::process::exit(fails.0.into())
+        }
+        #[cfg(feature = "unpack")]
+        Action::Unpack { file, out, modify, paths, exclude, dump_models, #[cfg(feature = "gltf")] gltf, obj } => {
+            let mut moon = load_moon(reporter, &file)?;
+            modify.apply(&mut moon, reporter)?;
+            #[cfg(not(feature = "gltf"))]
+            let gltf = false;
+            unpack(reporter, moon, out, paths, exclude, dump_models, gltf, obj)
         }
         Action::Repack { file, out, compress, no_compress, if_smaller, modify } => {
-            let mut moon = File::open(&file)?;
-            // FIXME: don't panic
-            let (mut moon, name) = get_moon_with_name(moon).expect("couldn't load moon");
-            modify.apply(&mut moon);
+            let original = read_file(&file)?;
+            let (mut moon, name) = get_moon_with_name(reporter, &*original).map_err(|e| CliError::parse("load avatar", &file, e))?;
+            modify.apply(&mut moon, reporter)?;
             use quartz_nbt::serde as qs;
             use flate2::Compression;
             let compression = if no_compress {
@@ -464,15 +2087,295 @@ fn main() -> io::Result<()> {
                 }
             };
             let flavor = quartz_nbt::io::Flavor::GzCompressedWith(compression);
-            if if_smaller {
-                let data = qs::serialize(&moon, Some(&name), flavor);
+            let data = serialize_moon(&moon, &name, flavor)?;
+            if if_smaller && original.len() <= data.len() {
+                reporter.info("not writing: repacked avatar is no smaller");
             } else {
-                let mut file = File::create(out.as_deref().unwrap_or(&file))?;
-                qs::serialize_into(&mut file, &moon, Some(&name), flavor);
+                reporter.write_file(out.as_deref().unwrap_or(&file), &data)?;
             }
         }
+        Action::Convert { input, out, from, to, model, modify } => {
+            let from = from.unwrap_or_else(|| convert::Format::infer(&input));
+            let to = to.unwrap_or_else(|| convert::Format::infer(&out));
+            let mut moon = convert::read(&input, from)?;
+            modify.apply(&mut moon, reporter)?;
+            convert::write(&moon, &out, to, model.as_deref())?;
+            reporter.info(format!("converted {} ({from}) to {} ({to})", input.display(), out.display()));
+        }
+        Action::Merge { base, addons, out, prefer_base, prefer_addon, namespace, dedup_textures, modify } => {
+            let read = |path: &Path| convert::read(path, convert::Format::infer(path));
+            let mut moon = read(&base)?;
+            let prefer = if prefer_addon { merge::Prefer::Addon } else { merge::Prefer::Base };
+            for path in addons {
+                let addon = read(&path)?;
+                let options = merge::MergeOptions {
+                    prefer,
+                    namespace: namespace.then(|| path.file_stem().map_or_else(String::new, |s| s.to_string_lossy().into_owned())),
+                    dedup_textures,
+                };
+                let collisions = moon.collisions(&addon, options.namespace.as_deref());
+                if !(prefer_base || prefer_addon) && !collisions.is_empty() {
+                    for collision in &collisions {
+                        reporter.error(format!("{}: {collision} is already in the avatar", path.display()));
+                    }
+                    reporter.info("pass --prefer-base or --prefer-addon to pick which to keep");
+                    reporter.exit(1);
+                }
+                for collision in moon.merge(addon, &options) {
+                    let kept = if prefer_addon { "addon's" } else { "avatar's" };
+                    reporter.warning(format!("{}: kept the {kept} {collision}", path.display()));
+                }
+            }
+            modify.apply(&mut moon, reporter)?;
+            let data = serialize_moon(&moon, "", quartz_nbt::io::Flavor::GzCompressed)?;
+            reporter.write_file(&out, &data)?;
+        }
+        Action::Grep { pattern, file, ignore_case, context, before_context, after_context } => {
+            let pattern = regex::RegexBuilder::new(&pattern).case_insensitive(ignore_case).build().map_err(CliError::usage)?;
+            let moon = convert::read(&file, convert::Format::infer(&file))?;
+            let found = grep::grep(&moon, &pattern, before_context.unwrap_or(context), after_context.unwrap_or(context));
+            for script in &found {
+                reporter.result(script, script);
+            }
+            if found.is_empty() {
+                reporter.exit(1);
+            }
+        }
+        #[cfg(feature = "run")]
+        Action::Run { file, ticks, renders, max_instructions, max_memory, modify } => {
+            let mut moon = convert::read(&file, convert::Format::infer(&file))?;
+            modify.apply(&mut moon, reporter)?;
+            let options = runtime::RunOptions {
+                ticks,
+                renders,
+                max_instructions: (max_instructions > 0).then_some(max_instructions),
+                max_memory: (max_memory > 0).then_some(max_memory << 20),
+            };
+            if let Err(e) = runtime::run(&moon, &options, |line| reporter.result(format!("{line}\n"), line)) {
+                reporter.error(e);
+                reporter.exit(1);
+            }
+            reporter.info(format!("ran {ticks} tick{}", if ticks == 1 { "" } else { "s" }));
+        }
+        Action::Profile { file, #[cfg(feature = "run")] measure, #[cfg(feature = "run")] ticks, #[cfg(feature = "run")] renders, modify } => {
+            let mut moon = convert::read(&file, convert::Format::infer(&file))?;
+            modify.apply(&mut moon, reporter)?;
+            #[cfg(feature = "run")]
+            let profile = if measure {
+                // scripts shouldn't run on forever, but going over Figura's limits is worth seeing
+                let options = runtime::RunOptions { ticks, renders, max_instructions: Some(1_000_000), max_memory: Some(64 << 20) };
+                runtime::profile(&moon, &options, |line| reporter.info(line)).unwrap_or_else(|e| {
+                    reporter.error(e);
+                    reporter.exit(1);
+                })
+            } else {
+                profile::estimate(&moon)
+            };
+            #[cfg(not(feature = "run"))]
+            let profile = profile::estimate(&moon);
+            reporter.result(&profile, &profile);
+            if profile.over_limit() {
+                reporter.exit(1);
+            }
+        }
+        Action::Docgen { file, json, out } => {
+            let moon = convert::read(&file, convert::Format::infer(&file))?;
+            let docs = docgen::docgen(&moon);
+            let text = match json {
+                true => format!("{:#}\n", serde_json::to_value(&docs).expect("docs serialize to JSON")),
+                false => docs.to_string(),
+            };
+            match out {
+                Some(out) => reporter.write_file(&out, text.as_bytes())?,
+                None => reporter.result(text, &docs),
+            }
+        }
+        #[cfg(feature = "browse")]
+        Action::Browse { file, out } => {
+            let moon = load_moon(reporter, &file)?;
+            browse::browse(&moon, &out).map_err(|e| CliError::failed("browse avatar", e))?;
+        }
+        #[cfg(feature = "mount")]
+        Action::Mount { file, dir } => {
+            let moon = load_moon(reporter, &file)?;
+            mount::mount(&moon, &dir).map_err(|e| CliError::io("mount on", &dir, e))?;
+        }
+        Action::History { figura_dir, action } => {
+            let Some(figura_dir) = figura_dir.or_else(|| history::minecraft_dir().map(|d| d.join("figura"))) else {
+                return Err(CliError::usage("could not find the Minecraft directory; pass --figura-dir"));
+            };
+            let backups_dir = figura_dir.join("backups");
+            let backups = history::list(&backups_dir).map_err(|e| CliError::io("list backups in", backups_dir, e))?;
+            match action {
+                None => {
+                    let mut text = String::new();
+                    for (i, backup) in backups.iter().enumerate() {
+                        let name = backup.name.as_deref().unwrap_or("\x1b[2m(unreadable)\x1b[22m");
+                        text += &format!("{:>3}  {}  {}  {:>7}B  \x1b[1m{name}\x1b[21;22m\n", i + 1, backup.date(), backup.short_hash(), backup.size);
+                    }
+                    let list: Vec<_> = backups.iter().map(|backup| serde_json::json!({
+                        "path": backup.path,
+                        "date": backup.date(),
+                        "hash": backup.hash.iter().map(|b| format!("{b:02x}")).collect::<String>(),
+                        "size": backup.size,
+                        "name": backup.name,
+                    })).collect();
+                    reporter.result(text, list);
+                }
+                Some(HistoryAction::Restore { n }) => {
+                    let Some(backup) = n.checked_sub(1).and_then(|i| backups.get(i)) else {
+                        return Err(CliError::usage(format!("no backup #{n} (there are {})", backups.len())));
+                    };
+                    let avatars = figura_dir.join("avatars");
+                    let dest = backup.restore(&avatars).map_err(|e| CliError::io("restore to", avatars, e))?;
+                    reporter.info(format!("restored to {}", dest.display()));
+                }
+            }
+        }
+        Action::Dev { command: DevCommand::CompareExport { export, src } } => {
+            let data = read_file(&export)?;
+            let (expected, _) = quartz_nbt::io::read_nbt(&mut &*data, moon::sniff_flavor(&data)).map_err(|e| CliError::parse("load export", &export, e))?;
+            let actual = if src.is_dir() {
+                Moon::pack_dir(&src)?
+            } else {
+                load_moon(reporter, &src)?
+            };
+            let differences = compare::diff(&expected, &actual).map_err(|e| CliError::failed("serialize avatar", e))?;
+            let text: String = differences.iter().map(|difference| format!("{difference}\n")).collect();
+            reporter.result(text, differences.iter().map(|difference| difference.to_string()).collect::<Vec<_>>());
+            if !differences.is_empty() {
+                reporter.info(format!("{} difference{}", differences.len(), if differences.len() == 1 { "" } else { "s" }));
+                reporter.exit(1);
+            }
+        }
+        Action::Generate { out, generator } => {
+            let moon = match generator {
+                Generator::Banner { image, height, wave } => {
+                    let data = read_file(&image)?;
+                    generate::banner(data, &generate::BannerOptions { height, wave }).map_err(|e| CliError::parse("read image", &image, e))?
+                }
+                Generator::Sign { text } => generate::sign(&text).map_err(|e| CliError::failed("create texture", e))?,
+            };
+            let data = serialize_moon(&moon, "", quartz_nbt::io::Flavor::GzCompressed)?;
+            reporter.write_file(&out, &data)?;
+        }
+        Action::Collection(CollectionAction::Create { out, moons }) => {
+            let mut collection = Collection::default();
+            for (name, path) in moons {
+                let name = match name {
+                    Some(name) => name,
+                    None => path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default(),
+                };
+                let data = read_file(&path)?;
+                get_moon(reporter, &*data).map_err(|e| CliError::parse("load avatar", &path, e))?;
+                collection.insert(name, data);
+            }
+            let mut data = vec![];
+            collection.write(&mut data).map_err(|e| CliError::failed("serialize collection", e))?;
+            reporter.write_file(&out, &data)?;
+        }
+        Action::Collection(CollectionAction::Ls { file }) => {
+            let collection = Collection::read(&*read_file(&file)?).map_err(|e| CliError::parse("load collection", &file, e))?;
+            let text: String = collection.entries.iter().map(|entry| format!("{}\t{}B\n", entry.name, entry.data.len())).collect();
+            let list: Vec<_> = collection.entries.iter().map(|entry| serde_json::json!({ "name": entry.name, "size": entry.data.len() })).collect();
+            reporter.result(text, list);
+        }
+        Action::Model(ModelAction::Stats { file, lenient }) => {
+            let (model, _) = read_bbmodel(&file, lenient)?;
+            let stats = model.stats();
+            reporter.result(&stats, &stats);
+        }
+        Action::Texture(TextureAction::List { file }) => {
+            let moon = load_moon(reporter, &file)?;
+            let textures = select_textures(reporter, &moon, &[]);
+            texture_summary(reporter, &textures);
+        }
+        Action::Texture(TextureAction::Export { file, out, names }) => {
+            let moon = load_moon(reporter, &file)?;
+            let mut written = 0;
+            for (name, data) in select_textures(reporter, &moon, &names) {
+                let path = out.join(moon::relative_path(&(name.replace('.', "/") + ".png")).map_err(|e| CliError::failed("export texture", e))?);
+                if let Some(dir) = path.parent() {
+                    create_dir(&dir)?;
+                }
+                reporter.write_file(&path, data)?;
+                written += 1;
+            }
+            reporter.info(format!("wrote {written} files"));
+        }
+        Action::Texture(TextureAction::Import { file, textures, out, keep_uvs }) => {
+            let (mut moon, moon_name) = load_moon_with_name(reporter, &file)?;
+            let rescale = if keep_uvs { image::RescaleUvs::Keep } else { image::RescaleUvs::Auto };
+            for (name, path) in textures {
+                let name = match name {
+                    Some(name) => name,
+                    None => path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default(),
+                };
+                let data = read_file(&path)?;
+                let verb = if moon.textures.src.contains_key(&name) { "replaced" } else { "added" };
+                match moon.replace_texture(&name, data, rescale) {
+                    Ok(0) => reporter.info(format!("{verb} {name}")),
+                    Ok(n) => reporter.info(format!("{verb} {name}, rescaling the UVs of {n} face{}", if n == 1 { "" } else { "s" })),
+                    Err(e) => return Err(CliError::parse("import texture", &path, e)),
+                }
+            }
+            let data = serialize_moon(&moon, &moon_name, quartz_nbt::io::Flavor::GzCompressed)?;
+            reporter.write_file(out.as_deref().unwrap_or(&file), &data)?;
+        }
+        Action::Texture(TextureAction::Preview { file, rows, names }) => {
+            let moon = load_moon(reporter, &file)?;
+            let textures = select_textures(reporter, &moon, &names);
+            if reporter.is_plain() && stdout().is_terminal() {
+                for (name, data) in textures {
+                    match image::dimensions(data) {
+                        Ok((width, height)) => println!("\x1b[1m{name}\x1b[21;22m {width}×{height}, {}B", data.len()),
+                        Err(e) => {
+                            reporter.warning(format!("can't show {name}: {e}"));
+                            continue;
+                        }
+                    }
+                    print_image(data, rows);
+                }
+            } else {
+                texture_summary(reporter, &textures);
+            }
+        }
+        Action::Collection(CollectionAction::Extract { file, out, names }) => {
+            let collection = Collection::read(&*read_file(&file)?).map_err(|e| CliError::parse("load collection", &file, e))?;
+            create_dir(&out)?;
+            let mut written = 0;
+            for name in &names {
+                if collection.get(name).is_none() {
+                    reporter.warning(format!("extracting nonexistent entry {name}"));
+                }
+            }
+            // entry names come from the file, so check them all before writing anything
+            let entries = collection.entries.iter()
+                .filter(|entry| names.is_empty() || names.contains(&entry.name))
+                .map(|entry| moon::relative_path(&format!("{}.moon", entry.name)).map(|path| (path, &entry.data)))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| CliError::parse("load collection", &file, e))?;
+            for (path, data) in entries {
+                reporter.write_file(&out.join(path), data)?;
+                written += 1;
+            }
+            reporter.info(format!("wrote {written} files"));
+        }
         #[cfg(feature = "backend")]
-        Action::Backend { .. } => todo!(),
+        Action::Backend { transforms, keep_original, bind, data, tier, offline, motd } => {
+            let backend = backend::Backend {
+                data_dir: data,
+                pipeline: pipeline::Pipeline { transforms, keep_original },
+                tier,
+                offline,
+                motd,
+            };
+            reporter.info(format!("listening on {bind}"));
+            backend.run(&*bind).map_err(|e| CliError::io("serve on", bind, e))?;
+        }
+        Action::Completions { shell } => {
+            clap_complete::generate(shell, &mut Cli::command(), "fia", &mut stdout());
+        }
         Action::Fok { stock, first, second, third } => {
             let mut path = Vec::<u8>::from(env!("FOKDIR"));
             path.extend_from_slice(b"/"); // needed to concatenate paths
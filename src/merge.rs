@@ -0,0 +1,178 @@
+//! Applying addons to an avatar: adding another avatar's models, scripts, textures, sounds,
+//! animations, and resources to it, such as an accessory from an avatar library. This is the
+//! moon-level counterpart to [BBModel::merge][crate::bbmodel::BBModel::merge], which combines
+//! Blockbench models.
+//!
+//! The two avatars collide wherever both have something with the same name (and, for files,
+//! different contents); a [Prefer] picks which one is kept. Putting the addon's scripts under a
+//! namespace avoids most script collisions, but requires between the addon's own scripts then
+//! only keep working if they're relative (`./util` rather than `util`).
+
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::Entry;
+use std::fmt::{self, Display, Formatter};
+use quartz_nbt::serde::Array;
+use crate::moon::ModelPart;
+use crate::Moon;
+
+/// Which avatar's version of something to keep when both have it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Prefer {
+    /// Keep the avatar being added to.
+    #[default]
+    Base,
+    /// Keep the addon.
+    Addon,
+}
+
+/// How [Moon::merge] combines two avatars.
+#[derive(Clone, Debug, Default)]
+pub struct MergeOptions {
+    /// Which avatar's version of something to keep when both have it.
+    pub prefer: Prefer,
+    /// A prefix for the addon's script names, so that its `main` becomes `namespace.main`.
+    pub namespace: Option<String>,
+    /// Whether to [remove unused and duplicate textures][Moon::gc_textures] afterwards, since
+    /// addons often bring their own copy of the same skin.
+    pub dedup_textures: bool,
+}
+
+/// Something both avatars have, found by [Moon::collisions].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Collision {
+    /// What kind of thing it is: `model`, `script`, `texture`, `sound`, or `resource`.
+    pub kind: &'static str,
+    /// Its name.
+    pub name: String,
+}
+
+impl Display for Collision {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        write!(fmt, "{} {}", self.kind, self.name)
+    }
+}
+
+/// Adds `name` to `namespace`, if there is one.
+fn namespaced(namespace: Option<&str>, name: &str) -> String {
+    match namespace {
+        Some(namespace) => format!("{namespace}.{name}"),
+        None => name.into(),
+    }
+}
+
+/// Adds the files in `addon` to `base`, keeping `base`'s where both have one unless `prefer` says
+/// otherwise.
+fn merge_files(base: &mut HashMap<String, Array<Vec<u8>>>, addon: HashMap<String, Array<Vec<u8>>>, prefer: Prefer) {
+    for (name, data) in addon {
+        match base.entry(name) {
+            Entry::Occupied(mut entry) => if prefer == Prefer::Addon {
+                entry.insert(data);
+            },
+            Entry::Vacant(entry) => {
+                entry.insert(data);
+            }
+        }
+    }
+}
+
+/// Sorted names of every script in an avatar.
+fn script_names(moon: &Moon) -> Vec<String> {
+    let mut names: Vec<String> = moon.scripts.keys().cloned().collect();
+    names.sort();
+    names
+}
+
+impl Moon {
+    /// Lists what both this avatar and `addon` have: top-level models with the same name, and
+    /// scripts (after `namespace` is applied), textures, sounds, and resources with the same name
+    /// but different contents.
+    pub fn collisions(&self, addon: &Moon, namespace: Option<&str>) -> Vec<Collision> {
+        let mut collisions = vec![];
+        if let (Some(base), Some(models)) = (&self.models, &addon.models) {
+            for part in models.chld.iter() {
+                if base.chld.iter().any(|p| p.name == part.name) {
+                    collisions.push(Collision { kind: "model", name: part.name.clone() });
+                }
+            }
+        }
+        let scripts = addon.scripts.iter().map(|(name, data)| (namespaced(namespace, name), data));
+        let files: [(&str, &HashMap<String, Array<Vec<u8>>>, Vec<(String, &Array<Vec<u8>>)>); 4] = [
+            ("script", &self.scripts, scripts.collect()),
+            ("texture", &self.textures.src, addon.textures.src.iter().map(|(n, d)| (n.clone(), d)).collect()),
+            ("sound", &self.sounds, addon.sounds.iter().map(|(n, d)| (n.clone(), d)).collect()),
+            ("resource", &self.resources, addon.resources.iter().map(|(n, d)| (n.clone(), d)).collect()),
+        ];
+        for (kind, base, addon) in files {
+            let mut clashing: Vec<String> = addon.into_iter()
+                .filter(|(name, data)| base.get(name).is_some_and(|ours| ours.as_ref() != data.as_ref()))
+                .map(|(name, _)| name)
+                .collect();
+            clashing.sort();
+            collisions.extend(clashing.into_iter().map(|name| Collision { kind, name }));
+        }
+        collisions
+    }
+
+    /// Adds `addon` to this avatar; see [the module documentation][crate::merge]. The addon's
+    /// scripts are added to `autoScripts` if this avatar has one, and its `autoAnims` to this
+    /// avatar's. Returns the [collisions][Self::collisions] that [MergeOptions::prefer] settled.
+    pub fn merge(&mut self, addon: Moon, options: &MergeOptions) -> Vec<Collision> {
+        let namespace = options.namespace.as_deref();
+        let collisions = self.collisions(&addon, namespace);
+        let addon_auto_scripts: Vec<String> = addon.metadata.auto_scripts.clone()
+            .unwrap_or_else(|| script_names(&addon))
+            .iter().map(|name| namespaced(namespace, name)).collect();
+        match &mut self.metadata.auto_scripts {
+            Some(auto_scripts) => auto_scripts.extend(addon_auto_scripts),
+            // every script already runs, which includes the addon's unless it picked some
+            None if addon.metadata.auto_scripts.is_some() => {
+                self.metadata.auto_scripts = Some(script_names(self).into_iter().chain(addon_auto_scripts).collect());
+            }
+            None => {}
+        }
+        if let Some(auto_scripts) = &mut self.metadata.auto_scripts {
+            let mut seen = HashSet::new();
+            auto_scripts.retain(|name| seen.insert(name.clone()));
+        }
+        for anim in addon.metadata.auto_anims {
+            if !self.metadata.auto_anims.contains(&anim) {
+                self.metadata.auto_anims.push(anim);
+            }
+        }
+
+        let scripts = addon.scripts.into_iter().map(|(name, data)| (namespaced(namespace, &name), data)).collect();
+        merge_files(&mut self.scripts, scripts, options.prefer);
+        merge_files(&mut self.sounds, addon.sounds, options.prefer);
+        merge_files(&mut self.resources, addon.resources, options.prefer);
+        merge_files(&mut self.textures.src, addon.textures.src, options.prefer);
+
+        let texture_offset = self.textures.data.len();
+        let texture_count = addon.textures.data.len();
+        let mut data = std::mem::take(&mut self.textures.data).into_vec();
+        data.extend(addon.textures.data.into_vec());
+        self.textures.data = data.into();
+        let animation_offset = self.animations.len();
+        self.animations.extend(addon.animations);
+
+        if let Some(models) = addon.models {
+            let remap = (0..texture_count).map(|i| (i, texture_offset + i)).collect();
+            let root = self.models.get_or_insert_with(|| ModelPart { name: "models".into(), vsb: true, ..Default::default() });
+            let mut parts = std::mem::take(&mut root.chld).into_vec();
+            for mut part in models.chld.into_vec() {
+                part.remap_textures(&remap);
+                part.offset_animations(animation_offset);
+                match parts.iter().position(|p| p.name == part.name) {
+                    Some(i) if options.prefer == Prefer::Addon => parts[i] = part,
+                    Some(_) => {}
+                    None => parts.push(part),
+                }
+            }
+            root.chld = parts.into();
+        }
+
+        if options.dedup_textures {
+            self.gc_textures();
+        }
+        collisions
+    }
+}
@@ -0,0 +1,574 @@
+//! A parser for the Molang expressions Blockbench allows in keyframes, such as
+//! `math.sin(query.anim_time * 90) * 10`. Figura evaluates keyframe expressions as Lua instead, so
+//! converters [parse] each expression, fold the ones that turn out to be constant with
+//! [Expr::constant], and translate the rest with [Expr::to_lua].
+//!
+//! Only single expressions are supported: no statements, assignments, strings, or loops. Names are
+//! case-insensitive, as in Molang, and are lowercased while parsing.
+
+use std::fmt::Write as _;
+use thiserror::Error;
+
+/// A unary operator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnaryOp {
+    /// `-x`
+    Negate,
+    /// `!x`
+    Not,
+}
+
+/// A binary operator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BinaryOp {
+    /// `a + b`
+    Add,
+    /// `a - b`
+    Sub,
+    /// `a * b`
+    Mul,
+    /// `a / b`
+    Div,
+    /// `a < b`
+    Lt,
+    /// `a <= b`
+    Le,
+    /// `a > b`
+    Gt,
+    /// `a >= b`
+    Ge,
+    /// `a == b`
+    Eq,
+    /// `a != b`
+    Ne,
+    /// `a && b`
+    And,
+    /// `a || b`
+    Or,
+    /// `a ?? b`, which is `a` if it's defined and `b` otherwise
+    Coalesce,
+}
+
+impl BinaryOp {
+    /// How tightly the operator binds; higher binds tighter.
+    fn precedence(self) -> u8 {
+        match self {
+            BinaryOp::Coalesce => 1,
+            BinaryOp::Or => 2,
+            BinaryOp::And => 3,
+            BinaryOp::Eq | BinaryOp::Ne => 4,
+            BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge => 5,
+            BinaryOp::Add | BinaryOp::Sub => 6,
+            BinaryOp::Mul | BinaryOp::Div => 7,
+        }
+    }
+
+    /// Whether the operator produces a truth value rather than a number.
+    fn is_logical(self) -> bool {
+        !matches!(self, BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Coalesce)
+    }
+}
+
+/// A parsed Molang expression.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    /// A number literal.
+    Number(f64),
+    /// A dotted name, such as `query.anim_time` or `math.pi`.
+    Name(String),
+    /// A function call, such as `math.sin(x)`.
+    Call(String, Vec<Expr>),
+    /// A unary operation.
+    Unary(UnaryOp, Box<Expr>),
+    /// A binary operation.
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    /// `condition ? then : otherwise`
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+}
+
+/// An error encountered while parsing an expression.
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("{message} at byte {position}")]
+pub struct ParseError {
+    /// What went wrong.
+    pub message: String,
+    /// The byte offset in the source where it went wrong.
+    pub position: usize,
+}
+
+/// Parses a single Molang expression.
+pub fn parse(source: &str) -> Result<Expr, ParseError> {
+    let mut parser = Parser { source, pos: 0 };
+    let expr = parser.conditional()?;
+    parser.skip_whitespace();
+    if parser.pos < source.len() {
+        return Err(parser.error("expected end of expression"));
+    }
+    Ok(expr)
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> ParseError {
+        ParseError { message: message.into(), position: self.pos }
+    }
+
+    fn rest(&self) -> &str {
+        &self.source[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.pos = self.source.len() - self.rest().trim_start().len();
+    }
+
+    /// Consumes `token` if it comes next.
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        if self.rest().starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn conditional(&mut self) -> Result<Expr, ParseError> {
+        let condition = self.binary(0)?;
+        if !self.eat("?") {
+            return Ok(condition);
+        }
+        let then = self.conditional()?;
+        if !self.eat(":") {
+            return Err(self.error("expected ':'"));
+        }
+        let otherwise = self.conditional()?;
+        Ok(Expr::Conditional(Box::new(condition), Box::new(then), Box::new(otherwise)))
+    }
+
+    fn binary_op(&mut self) -> Option<BinaryOp> {
+        self.skip_whitespace();
+        const OPS: [(&str, BinaryOp); 13] = [
+            ("??", BinaryOp::Coalesce), ("||", BinaryOp::Or), ("&&", BinaryOp::And), ("==", BinaryOp::Eq), ("!=", BinaryOp::Ne),
+            ("<=", BinaryOp::Le), (">=", BinaryOp::Ge), ("<", BinaryOp::Lt), (">", BinaryOp::Gt),
+            ("+", BinaryOp::Add), ("-", BinaryOp::Sub), ("*", BinaryOp::Mul), ("/", BinaryOp::Div),
+        ];
+        OPS.iter().find(|(token, _)| self.rest().starts_with(token)).map(|&(_, op)| op)
+    }
+
+    /// Parses operators binding at least as tightly as `min`, by precedence climbing.
+    fn binary(&mut self, min: u8) -> Result<Expr, ParseError> {
+        let mut left = self.unary()?;
+        while let Some(op) = self.binary_op().filter(|op| op.precedence() > min) {
+            self.pos += match op {
+                BinaryOp::Coalesce | BinaryOp::Or | BinaryOp::And | BinaryOp::Eq | BinaryOp::Ne | BinaryOp::Le | BinaryOp::Ge => 2,
+                _ => 1,
+            };
+            let right = self.binary(op.precedence())?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        if self.eat("-") {
+            Ok(Expr::Unary(UnaryOp::Negate, Box::new(self.unary()?)))
+        } else if self.eat("+") {
+            self.unary()
+        } else if self.eat("!") {
+            Ok(Expr::Unary(UnaryOp::Not, Box::new(self.unary()?)))
+        } else {
+            self.primary()
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, ParseError> {
+        self.skip_whitespace();
+        if self.eat("(") {
+            let expr = self.conditional()?;
+            if !self.eat(")") {
+                return Err(self.error("expected ')'"));
+            }
+            return Ok(expr);
+        }
+        let rest = self.rest();
+        if rest.starts_with(|c: char| c.is_ascii_digit() || c == '.') {
+            let len = rest.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(rest.len());
+            let number = rest[..len].parse().map_err(|_| self.error("malformed number"))?;
+            self.pos += len;
+            // Molang allows a trailing f, as in 1.5f
+            if self.rest().starts_with(['f', 'F']) {
+                self.pos += 1;
+            }
+            return Ok(Expr::Number(number));
+        }
+        if rest.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+            let len = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.')).unwrap_or(rest.len());
+            let name = rest[..len].to_ascii_lowercase();
+            self.pos += len;
+            if !self.eat("(") {
+                return Ok(Expr::Name(name));
+            }
+            let mut args = vec![];
+            if !self.eat(")") {
+                loop {
+                    args.push(self.conditional()?);
+                    if self.eat(")") {
+                        break;
+                    }
+                    if !self.eat(",") {
+                        return Err(self.error("expected ',' or ')'"));
+                    }
+                }
+            }
+            return Ok(Expr::Call(name, args));
+        }
+        Err(self.error(if rest.is_empty() { "unexpected end of expression" } else { "expected a value" }))
+    }
+}
+
+/// Molang's short aliases for namespaces.
+fn expand_namespace(name: &str) -> String {
+    match name.split_once('.') {
+        Some(("q", rest)) => format!("query.{rest}"),
+        Some(("v", rest)) => format!("variable.{rest}"),
+        Some(("t", rest)) => format!("temp.{rest}"),
+        Some(("c", rest)) => format!("context.{rest}"),
+        _ => name.into(),
+    }
+}
+
+fn truth(b: bool) -> f64 {
+    if b { 1.0 } else { 0.0 }
+}
+
+impl Expr {
+    /// Whether this expression or any part of it satisfies `f`.
+    pub fn any(&self, f: &impl Fn(&Expr) -> bool) -> bool {
+        f(self) || match self {
+            Expr::Number(_) | Expr::Name(_) => false,
+            Expr::Call(_, args) => args.iter().any(|arg| arg.any(f)),
+            Expr::Unary(_, x) => x.any(f),
+            Expr::Binary(_, a, b) => a.any(f) || b.any(f),
+            Expr::Conditional(c, a, b) => c.any(f) || a.any(f) || b.any(f),
+        }
+    }
+
+    /// Whether the expression refers to one of Molang's namespaces (`query`, `variable`, `temp`,
+    /// or `context`, or their short forms), which marks it as Molang rather than Lua.
+    pub fn is_molang(&self) -> bool {
+        self.any(&|expr| match expr {
+            Expr::Name(name) | Expr::Call(name, _) => {
+                let namespace = expand_namespace(name);
+                ["query.", "variable.", "temp.", "context."].iter().any(|ns| namespace.starts_with(ns))
+            }
+            _ => false,
+        })
+    }
+
+    /// Evaluates the expression if it doesn't depend on anything that changes, such as the
+    /// animation's time or random numbers. Trigonometry uses degrees, as in Molang.
+    pub fn constant(&self) -> Option<f64> {
+        Some(match self {
+            Expr::Number(n) => *n,
+            Expr::Name(name) => match expand_namespace(name).as_str() {
+                "math.pi" => std::f64::consts::PI,
+                _ => return None,
+            },
+            Expr::Call(name, args) => {
+                let args = args.iter().map(Expr::constant).collect::<Option<Vec<_>>>()?;
+                match (name.as_str(), args.as_slice()) {
+                    ("math.abs", [x]) => x.abs(),
+                    ("math.sin", [x]) => x.to_radians().sin(),
+                    ("math.cos", [x]) => x.to_radians().cos(),
+                    ("math.asin", [x]) => x.asin().to_degrees(),
+                    ("math.acos", [x]) => x.acos().to_degrees(),
+                    ("math.atan", [x]) => x.atan().to_degrees(),
+                    ("math.atan2", [y, x]) => y.atan2(*x).to_degrees(),
+                    ("math.sqrt", [x]) => x.sqrt(),
+                    ("math.exp", [x]) => x.exp(),
+                    ("math.ln", [x]) => x.ln(),
+                    ("math.pow", [x, y]) => x.powf(*y),
+                    ("math.floor", [x]) => x.floor(),
+                    ("math.ceil", [x]) => x.ceil(),
+                    ("math.round", [x]) => x.round(),
+                    ("math.trunc", [x]) => x.trunc(),
+                    ("math.mod", [x, y]) => x % y,
+                    ("math.min", [x, y]) => x.min(*y),
+                    ("math.max", [x, y]) => x.max(*y),
+                    ("math.clamp", [x, min, max]) => x.max(*min).min(*max),
+                    ("math.lerp", [a, b, t]) => a + (b - a) * t,
+                    _ => return None,
+                }
+            }
+            Expr::Unary(UnaryOp::Negate, x) => -x.constant()?,
+            Expr::Unary(UnaryOp::Not, x) => truth(x.constant()? == 0.0),
+            // a constant is always defined, and anything else might not be
+            Expr::Binary(BinaryOp::Coalesce, a, _) => a.constant()?,
+            Expr::Binary(op, a, b) => {
+                let (a, b) = (a.constant()?, b.constant()?);
+                match op {
+                    BinaryOp::Add => a + b,
+                    BinaryOp::Sub => a - b,
+                    BinaryOp::Mul => a * b,
+                    BinaryOp::Div => a / b,
+                    BinaryOp::Lt => truth(a < b),
+                    BinaryOp::Le => truth(a <= b),
+                    BinaryOp::Gt => truth(a > b),
+                    BinaryOp::Ge => truth(a >= b),
+                    BinaryOp::Eq => truth(a == b),
+                    BinaryOp::Ne => truth(a != b),
+                    BinaryOp::And => truth(a != 0.0 && b != 0.0),
+                    BinaryOp::Or => truth(a != 0.0 || b != 0.0),
+                    BinaryOp::Coalesce => a,
+                }
+            }
+            Expr::Conditional(c, a, b) => if c.constant()? != 0.0 { a.constant()? } else { b.constant()? },
+        })
+    }
+
+    /// Translates the expression into a Lua expression for a Figura keyframe, which evaluates to
+    /// a number. `query.anim_time` becomes Figura's `time`, and `math` functions are adjusted for
+    /// Lua's radians. Returns [None] if the expression uses a name or function Figura has no
+    /// equivalent for, such as Molang variables.
+    pub fn to_lua(&self) -> Option<String> {
+        let mut out = String::new();
+        self.write_lua(&mut out, false)?;
+        Some(out)
+    }
+
+    /// Writes the expression as Lua. Molang uses numbers for truth values where Lua uses
+    /// booleans, so `boolean` says which the caller needs, and values are converted as needed.
+    fn write_lua(&self, out: &mut String, boolean: bool) -> Option<()> {
+        let logical = match self {
+            Expr::Unary(UnaryOp::Not, _) => true,
+            Expr::Binary(op, ..) => op.is_logical(),
+            _ => false,
+        };
+        if boolean && !logical {
+            out.push('(');
+            self.write_lua(out, false)?;
+            out.push_str(" ~= 0)");
+            return Some(());
+        }
+        if !boolean && logical {
+            out.push('(');
+            self.write_lua(out, true)?;
+            out.push_str(" and 1 or 0)");
+            return Some(());
+        }
+        match self {
+            Expr::Number(n) => write!(out, "{n}").unwrap(),
+            Expr::Name(name) => out.push_str(match expand_namespace(name).as_str() {
+                "query.anim_time" => "time",
+                "math.pi" => "math.pi",
+                _ => return None,
+            }),
+            Expr::Call(name, args) => {
+                let (prefix, function, suffix) = match (name.as_str(), args.len()) {
+                    ("math.sin" | "math.cos", 1) => ("", name.as_str(), ""),
+                    ("math.asin" | "math.acos" | "math.atan", 1) | ("math.atan2", 2) => ("math.deg(", name.as_str(), ")"),
+                    ("math.abs" | "math.sqrt" | "math.exp" | "math.floor" | "math.ceil" | "math.round", 1) => ("", name.as_str(), ""),
+                    ("math.min" | "math.max" | "math.pow", 2) | ("math.clamp" | "math.lerp", 3) => ("", name.as_str(), ""),
+                    ("math.ln", 1) => ("", "math.log", ""),
+                    ("math.mod", 2) => ("", "math.fmod", ""),
+                    _ => return None,
+                };
+                write!(out, "{prefix}{function}(").unwrap();
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    if matches!(name.as_str(), "math.sin" | "math.cos") {
+                        out.push_str("math.rad(");
+                        arg.write_lua(out, false)?;
+                        out.push(')');
+                    } else {
+                        arg.write_lua(out, false)?;
+                    }
+                }
+                write!(out, "){suffix}").unwrap();
+            }
+            Expr::Unary(UnaryOp::Negate, x) => {
+                out.push_str("(-");
+                x.write_lua(out, false)?;
+                out.push(')');
+            }
+            Expr::Unary(UnaryOp::Not, x) => {
+                out.push_str("(not ");
+                x.write_lua(out, true)?;
+                out.push(')');
+            }
+            Expr::Binary(BinaryOp::Coalesce, a, b) => {
+                // everything with a Figura equivalent is always defined there, so the fallback is
+                // only needed for what has none
+                let mut left = String::new();
+                match a.write_lua(&mut left, false) {
+                    Some(()) => out.push_str(&left),
+                    None => b.write_lua(out, false)?,
+                }
+            }
+            Expr::Binary(op, a, b) => {
+                let (token, operands_boolean) = match op {
+                    BinaryOp::Add => ("+", false),
+                    BinaryOp::Sub => ("-", false),
+                    BinaryOp::Mul => ("*", false),
+                    BinaryOp::Div => ("/", false),
+                    BinaryOp::Lt => ("<", false),
+                    BinaryOp::Le => ("<=", false),
+                    BinaryOp::Gt => (">", false),
+                    BinaryOp::Ge => (">=", false),
+                    BinaryOp::Eq => ("==", false),
+                    BinaryOp::Ne => ("~=", false),
+                    BinaryOp::And => ("and", true),
+                    BinaryOp::Or => ("or", true),
+                    BinaryOp::Coalesce => unreachable!("?? is written above"),
+                };
+                out.push('(');
+                a.write_lua(out, operands_boolean)?;
+                write!(out, " {token} ").unwrap();
+                b.write_lua(out, operands_boolean)?;
+                out.push(')');
+            }
+            Expr::Conditional(c, a, b) => {
+                // both branches are numbers, which Lua always treats as true, so and/or is safe
+                out.push('(');
+                c.write_lua(out, true)?;
+                out.push_str(" and ");
+                a.write_lua(out, false)?;
+                out.push_str(" or ");
+                b.write_lua(out, false)?;
+                out.push(')');
+            }
+        }
+        Some(())
+    }
+}
+
+/// A keyframe value from a `.bbmodel`, converted for Figura by [to_figura].
+#[derive(Clone, Debug, PartialEq)]
+pub enum FiguraValue {
+    /// A value that never changes.
+    Constant(f64),
+    /// A Lua expression for Figura to evaluate.
+    Lua(String),
+}
+
+/// Converts a keyframe expression from a `.bbmodel` for Figura.
+///
+/// Figura reads keyframe text as Lua, and models made for Figura are written with that in mind:
+/// `math.sin(time)` there means radians, not Molang's degrees. So text is only treated as Molang
+/// when it uses a Molang namespace such as `query.`, and translated with [Expr::to_lua];
+/// otherwise it's kept as Lua, except that arithmetic on plain numbers (which means the same in
+/// both languages) is folded into a constant. Text that can't be parsed or translated is kept as
+/// it is.
+pub fn to_figura(source: &str) -> FiguraValue {
+    let source = source.trim();
+    if source.is_empty() {
+        return FiguraValue::Constant(0.0);
+    }
+    let Ok(expr) = parse(source) else { return FiguraValue::Lua(source.into()) };
+    let plain = !expr.any(&|e| matches!(e, Expr::Name(_) | Expr::Call(..)));
+    if expr.is_molang() || plain {
+        if let Some(n) = expr.constant() {
+            return FiguraValue::Constant(n);
+        }
+    }
+    if expr.is_molang() {
+        if let Some(lua) = expr.to_lua() {
+            return FiguraValue::Lua(lua);
+        }
+    }
+    FiguraValue::Lua(source.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn num(n: f64) -> Box<Expr> {
+        Box::new(Expr::Number(n))
+    }
+
+    fn name(name: &str) -> Box<Expr> {
+        Box::new(Expr::Name(name.into()))
+    }
+
+    fn binary(op: BinaryOp, a: Box<Expr>, b: Box<Expr>) -> Box<Expr> {
+        Box::new(Expr::Binary(op, a, b))
+    }
+
+    fn error(source: &str) -> (String, usize) {
+        let error = parse(source).unwrap_err();
+        (error.message, error.position)
+    }
+
+    #[test]
+    fn parses_by_precedence() {
+        use BinaryOp::*;
+        assert_eq!(parse("1 + 2 * 3").unwrap(), *binary(Add, num(1.0), binary(Mul, num(2.0), num(3.0))));
+        assert_eq!(parse("(1 + 2) * 3").unwrap(), *binary(Mul, binary(Add, num(1.0), num(2.0)), num(3.0)));
+        // operators of the same precedence group to the left
+        assert_eq!(parse("1 - 2 - 3").unwrap(), *binary(Sub, binary(Sub, num(1.0), num(2.0)), num(3.0)));
+        assert_eq!(parse("a || b && c == d < e + f").unwrap(), *binary(Or, name("a"), binary(And, name("b"),
+            binary(Eq, name("c"), binary(Lt, name("d"), binary(Add, name("e"), name("f")))))));
+        assert_eq!(parse("-2 * 3").unwrap(), *binary(Mul, Box::new(Expr::Unary(UnaryOp::Negate, num(2.0))), num(3.0)));
+        assert_eq!(parse("!a == b").unwrap(), *binary(Eq, Box::new(Expr::Unary(UnaryOp::Not, name("a"))), name("b")));
+        assert_eq!(parse("1.5f").unwrap(), Expr::Number(1.5));
+    }
+
+    #[test]
+    fn parses_conditionals() {
+        let conditional = |c, a, b| Box::new(Expr::Conditional(c, a, b));
+        assert_eq!(parse("a ? 1 : 2").unwrap(), *conditional(name("a"), num(1.0), num(2.0)));
+        // conditionals nest to the right, and bind more loosely than anything else
+        assert_eq!(parse("a ? b ? 1 : 2 : c ? 3 : 4").unwrap(),
+            *conditional(name("a"), conditional(name("b"), num(1.0), num(2.0)), conditional(name("c"), num(3.0), num(4.0))));
+        assert_eq!(parse("a || b ? 1 + 2 : 3").unwrap(),
+            *conditional(binary(BinaryOp::Or, name("a"), name("b")), binary(BinaryOp::Add, num(1.0), num(2.0)), num(3.0)));
+        assert_eq!(to_figura("1 > 2 ? 3 : 4"), FiguraValue::Constant(4.0));
+    }
+
+    #[test]
+    fn parses_coalescing() {
+        use BinaryOp::*;
+        assert_eq!(parse("v.x ?? 1 + 2").unwrap(), *binary(Coalesce, name("v.x"), binary(Add, num(1.0), num(2.0))));
+        assert_eq!(parse("a ?? b || c").unwrap(), *binary(Coalesce, name("a"), binary(Or, name("b"), name("c"))));
+        assert_eq!(parse("a ?? b ? 1 : 2").unwrap(),
+            Expr::Conditional(binary(Coalesce, name("a"), name("b")), num(1.0), num(2.0)));
+        // Figura has no Molang variables, so only the fallback can be translated
+        assert_eq!(to_figura("v.speed ?? q.anim_time"), FiguraValue::Lua("time".into()));
+        assert_eq!(to_figura("q.anim_time ?? 0"), FiguraValue::Lua("time".into()));
+        assert_eq!(to_figura("2 ?? q.anim_time"), FiguraValue::Constant(2.0));
+    }
+
+    #[test]
+    fn parses_calls() {
+        assert_eq!(parse("Math.Sin(Query.Anim_Time * 90)").unwrap(), Expr::Call("math.sin".into(),
+            vec![*binary(BinaryOp::Mul, name("query.anim_time"), num(90.0))]));
+        assert_eq!(parse("math.clamp(q.x, 0, 1)").unwrap(),
+            Expr::Call("math.clamp".into(), vec![Expr::Name("q.x".into()), Expr::Number(0.0), Expr::Number(1.0)]));
+        assert_eq!(parse("query.time_of_day()").unwrap(), Expr::Call("query.time_of_day".into(), vec![]));
+        assert_eq!(to_figura("math.sin(query.anim_time * 90) * 10"), FiguraValue::Lua("(math.sin(math.rad((time * 90))) * 10)".into()));
+        assert_eq!(parse("math.max(1, math.abs(-3))").unwrap().constant(), Some(3.0));
+        // without a Molang namespace, calls are left to Figura as Lua
+        assert_eq!(to_figura("math.max(1, math.abs(-3))"), FiguraValue::Lua("math.max(1, math.abs(-3))".into()));
+        assert_eq!(to_figura("math.sin(time)"), FiguraValue::Lua("math.sin(time)".into()));
+    }
+
+    #[test]
+    fn reports_error_positions() {
+        assert_eq!(error(""), ("unexpected end of expression".into(), 0));
+        assert_eq!(error("1 +"), ("unexpected end of expression".into(), 3));
+        assert_eq!(error("1 + * 2"), ("expected a value".into(), 4));
+        assert_eq!(error("(1 + 2"), ("expected ')'".into(), 6));
+        assert_eq!(error("math.max(1 2)"), ("expected ',' or ')'".into(), 11));
+        assert_eq!(error("a ? 1"), ("expected ':'".into(), 5));
+        assert_eq!(error("1 2"), ("expected end of expression".into(), 2));
+        assert_eq!(error("q.x + 1..2"), ("malformed number".into(), 6));
+        assert_eq!(parse("q.x; 1").unwrap_err().to_string(), "expected end of expression at byte 3");
+    }
+}
@@ -7,17 +7,32 @@
 //! * Analyze avatar size.
 //! * Create avatars entirely from Rust code.
 //! * Load avatars from the filesystem (e.g. `/figura export avatar`).
-//! * Upload avatars to the backend, when I get around to implementing backend connections.
+//! * Upload avatars to the backend (see [cloud][crate::cloud]).
 
-use std::collections::HashMap;
+pub mod space;
+pub mod compat;
+pub mod canonical;
+pub mod duplicates;
+
+use std::collections::{BTreeMap, HashMap};
 use std::ffi::OsStr;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
 use serde::{Serialize, Deserialize};
-use quartz_nbt::{NbtTag, serde::Array};
+use quartz_nbt::{NbtCompound, NbtTag, serde::Array};
+use quartz_nbt::io::{Flavor, NbtIoError};
+use md5::{Md5, Digest};
+use sha2::Sha256;
+use thiserror::Error;
+use uuid::Uuid;
+use space::Transform;
+use crate::glob::Glob;
+use crate::permissions::Permissions;
 
 /// The top-level of a Figura avatar. This structure contains maps for avatar information, but
 /// since Figura may add more keys at any time, this cannot be exhaustive.
 #[non_exhaustive]
-#[serde(deny_unknown_fields)]
 #[derive(Default, Debug, Serialize, Deserialize)]
 pub struct Moon {
     /// Textures associated with this avatar, found in a bbmodel.
@@ -26,9 +41,11 @@ pub struct Moon {
     /// This avatar's scripts (stored as `u8`s since Lua is not neccessarily UTF-8).
     #[serde(default)]
     pub scripts: HashMap<String, Array<Vec<u8>>>,
-    /// This avatar's animations. I haven't investigated this struct's layout yet.
+    /// This avatar's animations. Only the metadata of each animation is stored here; the keyframes
+    /// are stored on each animated [ModelPart] in [anim][ModelPart::anim], keyed by the
+    /// animation's index in this list.
     #[serde(default)]
-    pub animations: Vec<NbtTag>,
+    pub animations: Vec<Animation>,
     /// The root of the [ModelPart] hierarchy. This can technically be omitted, although I have
     /// always seen it present in practice.
     #[serde(default)]
@@ -40,14 +57,332 @@ pub struct Moon {
     /// [ResourcesAPI]: https://applejuiceyy.github.io/figs/latest/ResourcesAPI/
     #[serde(default)]
     pub resources: HashMap<String, Array<Vec<u8>>>,
+    /// Sounds available to SoundAPI, stored as Ogg Vorbis data and named like scripts.
+    #[serde(default)]
+    pub sounds: HashMap<String, Array<Vec<u8>>>,
     /// Additional metadata loaded from `avatar.json`.
     #[serde(default)]
     pub metadata: Metadata,
 }
 
+/// A name from an avatar that can't safely be used as a path, as [relative_path] rejects.
+#[derive(Debug, Error)]
+#[error("{0:?} isn't a safe relative path")]
+pub struct UnsafePath(pub String);
+
+/// Turns a `/`-separated path from an avatar into a relative path, such as a script's name with its
+/// dots replaced by slashes or a resource's name. Avatars can come from anywhere, so paths that
+/// could lead out of the folder they're written to are rejected: absolute paths, and paths with an
+/// empty, `.` or `..` part.
+pub fn relative_path(path: &str) -> Result<PathBuf, UnsafePath> {
+    let mut relative = PathBuf::new();
+    for part in path.split('/') {
+        // `.`, `..`, and anything the platform treats specially (such as `\` or `C:` on Windows)
+        // turn into something other than one normal component
+        match Path::new(part).components().collect::<Vec<_>>()[..] {
+            [Component::Normal(name)] if name == part => relative.push(part),
+            _ => return Err(UnsafePath(path.into())),
+        }
+    }
+    Ok(relative)
+}
+
+impl Moon {
+    /// Reads a moon stored with the given NBT flavor, returning it along with its root tag's name
+    /// (which is usually empty). Moons from older Figura versions are [upgraded][compat] to the
+    /// current layout.
+    pub fn read(mut reader: impl Read, flavor: Flavor) -> Result<(Moon, String), NbtIoError> {
+        let (root, name) = quartz_nbt::io::read_nbt(&mut reader, flavor)?;
+        Ok((Self::from_nbt(root)?, name))
+    }
+
+    /// Converts an NBT compound, such as one read from a world or a packet, to a moon. Like
+    /// [Moon::read], this [upgrades][compat] old layouts.
+    pub fn from_nbt(mut root: NbtCompound) -> Result<Moon, NbtIoError> {
+        compat::upgrade(&mut root);
+        from_compound(&root)
+    }
+
+    /// Reads a moon like [Moon::read], detecting whether it is gzip-compressed, zlib-compressed,
+    /// or uncompressed with [sniff_flavor].
+    pub fn read_auto(reader: impl Read) -> Result<(Moon, String), NbtIoError> {
+        let mut reader = BufReader::new(reader);
+        let flavor = sniff_flavor(reader.fill_buf()?);
+        Self::read(reader, flavor)
+    }
+
+    /// Reads a moon like [Moon::read_auto], also returning which old layouts were upgraded and
+    /// which tags were ignored. Finding the ignored tags means writing the moon out again, so this
+    /// is slower.
+    pub fn read_auto_upgraded(reader: impl Read) -> Result<(Moon, String, compat::Upgrades), NbtIoError> {
+        let mut reader = BufReader::new(reader);
+        let flavor = sniff_flavor(reader.fill_buf()?);
+        let (mut root, name) = quartz_nbt::io::read_nbt(&mut reader, flavor)?;
+        let applied = compat::upgrade(&mut root);
+        let moon: Moon = from_compound(&root)?;
+        let unknown = compat::unknown_tags(&root, &moon.to_nbt()?);
+        Ok((moon, name, compat::Upgrades { applied, unknown }))
+    }
+
+    /// Lists the files this avatar would have had in its folder, as far as they can be recovered:
+    /// textures as `.png`, scripts as `.lua`, sounds as `.ogg`, and resources under their own
+    /// paths. The list is sorted by path. Fails if any name isn't a safe [relative_path].
+    pub fn files(&self) -> Result<Vec<(String, &[u8])>, UnsafePath> {
+        let mut files = vec![];
+        for (ext, map) in [(".png", &self.textures.src), (".lua", &self.scripts), (".ogg", &self.sounds)] {
+            for (name, data) in map {
+                files.push((name.replace('.', "/") + ext, data.as_ref().as_slice()));
+            }
+        }
+        for (name, data) in &self.resources {
+            files.push((name.clone(), data.as_ref().as_slice()));
+        }
+        for (path, _) in &files {
+            relative_path(path)?;
+        }
+        files.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(files)
+    }
+
+    /// Removes textures in [Textures::src] that no [TextureData] refers to, then merges
+    /// byte-identical textures into one entry (keeping the alphabetically first name) and points
+    /// every [TextureData] at the survivor. [TextureData] entries that end up the same are then
+    /// merged too, and faces are [remapped][Self::remap_textures] to the remaining entries.
+    /// Returns the number of textures removed from [Textures::src].
+    ///
+    /// Exported moons often carry several copies of the same skin, so this can save a significant
+    /// amount of space.
+    pub fn gc_textures(&mut self) -> usize {
+        let Textures { src, data } = &mut self.textures;
+        let before = src.len();
+        src.retain(|name, _| data.iter().any(|d| d.d == *name));
+        let mut names: Vec<String> = src.keys().cloned().collect();
+        names.sort();
+        let mut renames = HashMap::<String, String>::new();
+        for (i, name) in names.iter().enumerate() {
+            if renames.contains_key(name) {
+                continue;
+            }
+            for other in &names[i + 1..] {
+                if !renames.contains_key(other) && src[name].as_ref() == src[other].as_ref() {
+                    renames.insert(other.clone(), name.clone());
+                }
+            }
+        }
+        for old in renames.keys() {
+            src.remove(old);
+        }
+        let removed = before - src.len();
+        let mut kept: Vec<TextureData> = vec![];
+        let mut map = HashMap::new();
+        for (i, mut d) in std::mem::take(data).into_vec().into_iter().enumerate() {
+            if let Some(new) = renames.get(&d.d) {
+                d.d = new.clone();
+            }
+            let new = kept.iter().position(|k| k.d == d.d).unwrap_or_else(|| {
+                kept.push(d);
+                kept.len() - 1
+            });
+            if new != i {
+                map.insert(i, new);
+            }
+        }
+        *data = kept.into();
+        self.remap_textures(&map);
+        removed
+    }
+
+    /// Shrinks the avatar without changing how it behaves, as far as can be told without running
+    /// it:
+    ///
+    /// * Scripts are [minified][crate::scripts::minify].
+    /// * Invisible parts are removed, unless a script mentions their name (and so might make them
+    ///   visible).
+    /// * Empty [anim][ModelPart::anim] entries are removed.
+    /// * Resources are removed unless a script mentions their path.
+    ///
+    /// Names are checked against the scripts before they are minified.
+    pub fn strip(&mut self) {
+        let sources: Vec<&[u8]> = self.scripts.values().map(|s| s.as_ref().as_slice()).collect();
+        let mentioned = |name: &str| !name.is_empty() && sources.iter().any(|s| s.windows(name.len()).any(|w| w == name.as_bytes()));
+        fn strip_part(part: &mut ModelPart, mentioned: &dyn Fn(&str) -> bool) {
+            part.anim.retain(|_, channels| !channels.is_empty());
+            let mut children = std::mem::take(&mut part.chld).into_vec();
+            children.retain(|c| c.vsb || mentioned(&c.name));
+            for child in &mut children {
+                strip_part(child, mentioned);
+            }
+            part.chld = children.into();
+        }
+        if let Some(models) = &mut self.models {
+            strip_part(models, &mentioned);
+        }
+        self.resources.retain(|name, _| mentioned(name));
+        drop(sources);
+        for data in self.scripts.values_mut() {
+            *data = crate::scripts::minify(data.as_ref()).into();
+        }
+    }
+
+    /// Removes every model part for which `keep` returns false, along with everything under it.
+    /// `keep` is given each part's path under [Moon::models] (such as `model/Head/hat`) and the
+    /// part itself; parts under a removed part aren't visited. Collections left without any parts
+    /// are dropped. Returns the number of parts removed, including descendants.
+    pub fn retain_parts(&mut self, mut keep: impl FnMut(&str, &ModelPart) -> bool) -> usize {
+        fn count(part: &ModelPart) -> usize {
+            1 + part.chld.iter().map(count).sum::<usize>()
+        }
+        fn visit(part: &mut ModelPart, path: &str, keep: &mut dyn FnMut(&str, &ModelPart) -> bool) -> usize {
+            let mut removed = 0;
+            let mut children = std::mem::take(&mut part.chld).into_vec();
+            children.retain_mut(|child| {
+                let path = if path.is_empty() { child.name.clone() } else { format!("{path}/{}", child.name) };
+                if keep(&path, child) {
+                    removed += visit(child, &path, keep);
+                    true
+                } else {
+                    removed += count(child);
+                    false
+                }
+            });
+            part.chld = children.into();
+            removed
+        }
+        let Some(models) = &mut self.models else { return 0 };
+        let removed = visit(models, "", &mut keep);
+        for model in models.chld.iter_mut() {
+            model.reindex_collections();
+        }
+        removed
+    }
+
+    /// Removes every part with the given parent type, as in [retain_parts][Self::retain_parts].
+    /// For example, removing [World][ParentType::World] parts leaves only what is attached to the
+    /// player.
+    pub fn remove_parent_type(&mut self, pt: ParentType) -> usize {
+        self.retain_parts(|_, part| part.pt != Some(pt))
+    }
+
+    /// Removes every part whose path matches a glob, as in [retain_parts][Self::retain_parts].
+    pub fn remove_parts_matching(&mut self, glob: &Glob) -> usize {
+        self.retain_parts(|path, _| !glob.matches(path))
+    }
+
+    /// Changes which texture every cube face and mesh face uses, replacing each texture ID (an
+    /// index into [Textures::data]) that is a key of `map` with its value. IDs not in `map` are
+    /// left alone. [Textures::data] itself isn't touched, so it usually needs reordering to
+    /// match.
+    pub fn remap_textures(&mut self, map: &HashMap<usize, usize>) {
+        if let Some(models) = &mut self.models {
+            models.remap_textures(map);
+        }
+    }
+
+    /// Converts this avatar to the NBT compound that would be written to a file.
+    pub fn to_nbt(&self) -> Result<NbtCompound, NbtIoError> {
+        to_compound(self)
+    }
+
+    /// Computes a SHA-256 hash of this avatar's contents. Unlike a hash of the file, this doesn't
+    /// depend on how the avatar was compressed or what order its compounds were written in, so
+    /// identical uploads can be recognized.
+    pub fn fingerprint(&self) -> Result<[u8; 32], NbtIoError> {
+        let mut hasher = Sha256::new();
+        hash_tag(&NbtTag::Compound(self.to_nbt()?), &mut hasher);
+        Ok(hasher.finalize().into())
+    }
+}
+
+/// Converts a value to an NBT compound. quartz_nbt can only serialize to bytes, so this goes
+/// through an uncompressed buffer.
+fn to_compound(value: &impl Serialize) -> Result<NbtCompound, NbtIoError> {
+    let data = quartz_nbt::serde::serialize(value, Some(""), Flavor::Uncompressed)?;
+    Ok(quartz_nbt::io::read_nbt(&mut &*data, Flavor::Uncompressed)?.0)
+}
+
+/// The reverse of [to_compound].
+fn from_compound<T: serde::de::DeserializeOwned>(compound: &NbtCompound) -> Result<T, NbtIoError> {
+    let mut data = vec![];
+    quartz_nbt::io::write_nbt(&mut data, Some(""), compound, Flavor::Uncompressed)?;
+    Ok(quartz_nbt::serde::deserialize_from_buffer(&data)?.0)
+}
+
+/// Guesses how NBT data is compressed from its first two bytes. Gzip streams start with a fixed
+/// magic number and zlib streams with a header whose checksum is a multiple of 31; anything else is
+/// assumed to be uncompressed (in which case it should start with `0x0a`, the compound tag).
+pub fn sniff_flavor(head: &[u8]) -> Flavor {
+    match *head {
+        [0x1f, 0x8b, ..] => Flavor::GzCompressed,
+        [cmf, flg, ..] if cmf & 0x0f == 8 && (u16::from(cmf) << 8 | u16::from(flg)) % 31 == 0 => Flavor::ZlibCompressed,
+        _ => Flavor::Uncompressed,
+    }
+}
+
+/// Feeds a tag into a hasher, with compound keys in sorted order. Every tag is prefixed with its
+/// type and every sequence with its length, so different trees can't produce the same input.
+fn hash_tag(tag: &NbtTag, hasher: &mut Sha256) {
+    hasher.update([match tag {
+        NbtTag::Byte(_) => 1,
+        NbtTag::Short(_) => 2,
+        NbtTag::Int(_) => 3,
+        NbtTag::Long(_) => 4,
+        NbtTag::Float(_) => 5,
+        NbtTag::Double(_) => 6,
+        NbtTag::ByteArray(_) => 7,
+        NbtTag::String(_) => 8,
+        NbtTag::List(_) => 9,
+        NbtTag::Compound(_) => 10,
+        NbtTag::IntArray(_) => 11,
+        NbtTag::LongArray(_) => 12,
+    }]);
+    match tag {
+        NbtTag::Byte(v) => hasher.update(v.to_be_bytes()),
+        NbtTag::Short(v) => hasher.update(v.to_be_bytes()),
+        NbtTag::Int(v) => hasher.update(v.to_be_bytes()),
+        NbtTag::Long(v) => hasher.update(v.to_be_bytes()),
+        NbtTag::Float(v) => hasher.update(v.to_be_bytes()),
+        NbtTag::Double(v) => hasher.update(v.to_be_bytes()),
+        NbtTag::ByteArray(v) => {
+            hasher.update((v.len() as u64).to_be_bytes());
+            hasher.update(v.iter().map(|&b| b as u8).collect::<Vec<_>>());
+        }
+        NbtTag::String(v) => {
+            hasher.update((v.len() as u64).to_be_bytes());
+            hasher.update(v.as_bytes());
+        }
+        NbtTag::List(v) => {
+            hasher.update((v.len() as u64).to_be_bytes());
+            for tag in v.iter() {
+                hash_tag(tag, hasher);
+            }
+        }
+        NbtTag::Compound(v) => {
+            let mut entries: Vec<_> = v.inner().iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            hasher.update((entries.len() as u64).to_be_bytes());
+            for (key, tag) in entries {
+                hasher.update((key.len() as u64).to_be_bytes());
+                hasher.update(key.as_bytes());
+                hash_tag(tag, hasher);
+            }
+        }
+        NbtTag::IntArray(v) => {
+            hasher.update((v.len() as u64).to_be_bytes());
+            for i in v {
+                hasher.update(i.to_be_bytes());
+            }
+        }
+        NbtTag::LongArray(v) => {
+            hasher.update((v.len() as u64).to_be_bytes());
+            for i in v {
+                hasher.update(i.to_be_bytes());
+            }
+        }
+    }
+}
+
 /// Stores the mapping of texture data sources and the list of textures available to modelparts.
 #[derive(Default, Debug, Serialize, Deserialize)]
-#[serde(deny_unknown_fields)]
 pub struct Textures {
     /// Raw texture data. The values of this map are PNG-encoded images, but I'm not masochistic
     /// enough to include PNG deserialization in a Figura avatar parser module.
@@ -61,34 +396,66 @@ pub struct Textures {
 
 /// A set of textures used by modelparts.
 #[derive(Default, Debug, Serialize, Deserialize)]
-#[serde(deny_unknown_fields)]
 #[non_exhaustive]
 pub struct TextureData {
     /// The primary texture, which is not given a name suffix.
     pub d: String,
 }
 
-/// Unused. I don't remember writing this struct.
-#[derive(Default, Debug, Serialize, Deserialize)]
-#[serde(deny_unknown_fields)]
-#[allow(missing_docs)]
+/// Metadata for one of an avatar's animations, created from a Blockbench animation.
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct Animation {
+    /// What happens when the animation reaches its end. If [None], the animation stops.
     #[serde(default)]
     pub r#loop: Option<Loop>,
+    /// The animation's name.
     #[serde(default)]
     pub name: String,
+    /// Whether the animation overrides vanilla animations on parent-typed parts (stored as a
+    /// byte).
     #[serde(default)]
     pub ovr: u8,
+    /// The name of the model (Blockbench file, without extension) the animation belongs to.
     #[serde(default)]
     pub mdl: String,
+    /// The animation's length, in seconds.
     #[serde(default)]
     pub len: f64,
+    /// The time the animation starts playing from, in seconds.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub off: f64,
+    /// The animation's blend weight, if not 1.
+    #[serde(default)]
+    pub bld: Option<f64>,
+    /// How long to wait before starting the animation, in seconds.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub sdel: f64,
+    /// How long to wait before looping the animation, in seconds.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub ldel: f64,
+    /// Instruction keyframes, which run Lua code at a given time.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub code: Vec<CodeKeyframe>,
+    /// Any keys not known to this struct, preserved so that they aren't lost when reserializing.
+    #[serde(flatten)]
+    pub extra: HashMap<String, NbtTag>,
+}
+
+/// An instruction keyframe, which runs a snippet of Lua when an animation reaches it.
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct CodeKeyframe {
+    /// When the code runs, in seconds.
+    pub time: f64,
+    /// The code to run.
+    pub src: String,
 }
 
+fn is_zero(n: &f64) -> bool { *n == 0.0 }
+
 /// A loop mode. This could technically have non-looping, although I have only seen it omitted in
 /// practice. You will usually deal with an [`Option<Loop>`][Option] instead, with [None]
 /// representing non-looping.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Loop {
     /// The animation will return to the beginning when it hits the end.
@@ -101,7 +468,6 @@ pub enum Loop {
 /// Extra avatar data found almost-exactly in `avatar.json`. This is usually safe to dump to JSON
 /// directly (via e.g. [serde_json]).
 #[derive(Default, Debug, Serialize, Deserialize)]
-#[serde(deny_unknown_fields)]
 pub struct Metadata {
     /// Author(s) of the model. If unspecified, is the single author `"?"`.
     #[serde(default)]
@@ -120,6 +486,129 @@ pub struct Metadata {
     /// Target Figura version, if specified.
     #[serde(default)]
     pub ver: String,
+    /// The UUID of the player this avatar belongs to, if known. Hyphenless UUIDs and Minecraft's
+    /// four-int array form are accepted when deserializing.
+    #[serde(default, with = "uuid_serde", skip_serializing_if = "Option::is_none")]
+    pub uuid: Option<Uuid>,
+    /// The scripts to run when the avatar loads, in order, by their key in [Moon::scripts]. If
+    /// unspecified, every script runs.
+    #[serde(default, rename = "autoScripts", skip_serializing_if = "Option::is_none")]
+    pub auto_scripts: Option<Vec<String>>,
+    /// The animations to play when the avatar loads, each as `model.animation`.
+    #[serde(default, rename = "autoAnims", skip_serializing_if = "Vec::is_empty")]
+    pub auto_anims: Vec<String>,
+    /// What the avatar declares it needs from viewers, if it says.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub permissions: Option<Permissions>,
+}
+
+impl Metadata {
+    /// Produces the `avatar.json` this metadata would have been packed from. Script names are
+    /// written as paths relative to the avatar's root, the way they are usually written by hand.
+    pub fn avatar_json(&self) -> serde_json::Value {
+        let mut json = serde_json::Map::new();
+        json.insert("name".into(), self.name.clone().into());
+        match &self.authors {
+            Authors::Author(author) => json.insert("author".into(), author.clone().into()),
+            Authors::Authors(authors) => json.insert("authors".into(), authors.clone().into()),
+        };
+        for (key, value) in [("description", &self.description), ("color", &self.color), ("version", &self.ver)] {
+            if !value.is_empty() {
+                json.insert(key.into(), value.clone().into());
+            }
+        }
+        if let Some(scripts) = &self.auto_scripts {
+            json.insert("autoScripts".into(), scripts.iter().map(|s| s.replace('.', "/") + ".lua").collect());
+        }
+        if !self.auto_anims.is_empty() {
+            json.insert("autoAnims".into(), self.auto_anims.clone().into());
+        }
+        if let Some(permissions) = &self.permissions {
+            json.insert("permissions".into(), serde_json::to_value(permissions).unwrap());
+        }
+        json.into()
+    }
+
+    /// Reads the metadata out of an `avatar.json`. This is the inverse of [avatar_json][Self::avatar_json];
+    /// fields Figura ignores when packing are ignored here too.
+    pub fn from_avatar_json(json: &serde_json::Value) -> Self {
+        let string = |key: &str| json.get(key).and_then(|v| v.as_str()).unwrap_or_default().to_string();
+        let strings = |key: &str| json.get(key).and_then(|v| v.as_array()).map(|a| {
+            a.iter().filter_map(|v| v.as_str()).map(String::from).collect::<Vec<_>>()
+        });
+        let authors = match (json.get("author").and_then(|v| v.as_str()), strings("authors")) {
+            (_, Some(authors)) => Authors::Authors(authors),
+            (Some(author), None) => Authors::Author(author.into()),
+            (None, None) => Authors::default(),
+        };
+        Metadata {
+            authors,
+            color: string("color"),
+            name: string("name"),
+            description: string("description"),
+            ver: string("version"),
+            uuid: None,
+            auto_scripts: strings("autoScripts").map(|s| s.iter().map(|s| script_name(s)).collect()),
+            auto_anims: strings("autoAnims").unwrap_or_default(),
+            permissions: json.get("permissions").and_then(|p| serde_json::from_value(p.clone()).ok()),
+        }
+    }
+}
+
+/// Turns a script's path, as written in `avatar.json`, into its key in [Moon::scripts]: the `.lua`
+/// extension is dropped and path separators become dots.
+pub fn script_name(path: &str) -> String {
+    path.strip_suffix(".lua").unwrap_or(path).replace(['/', '\\'], ".")
+}
+
+/// Computes the UUID an offline-mode server assigns to a player with the given username. This is
+/// the same as Java's `UUID.nameUUIDFromBytes` applied to `"OfflinePlayer:" + username`.
+pub fn offline_uuid(username: &str) -> Uuid {
+    let digest = Md5::digest(format!("OfflinePlayer:{username}").as_bytes());
+    uuid::Builder::from_md5_bytes(digest.into()).into_uuid()
+}
+
+mod uuid_serde {
+    use std::fmt::{self, Formatter};
+    use serde::{Serializer, Deserializer, de::{self, Visitor, SeqAccess}};
+    use uuid::Uuid;
+
+    pub fn serialize<S: Serializer>(uuid: &Option<Uuid>, ser: S) -> Result<S::Ok, S::Error> {
+        match uuid {
+            Some(uuid) => ser.serialize_str(&uuid.hyphenated().to_string()),
+            None => ser.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(de: D) -> Result<Option<Uuid>, D::Error> {
+        struct UuidVisitor;
+        impl<'de> Visitor<'de> for UuidVisitor {
+            type Value = Option<Uuid>;
+            fn expecting(&self, fmt: &mut Formatter) -> fmt::Result {
+                write!(fmt, "a UUID string or an array of four ints")
+            }
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                if v.is_empty() {
+                    return Ok(None);
+                }
+                Uuid::parse_str(v).map(Some).map_err(E::custom)
+            }
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let mut bits = 0u128;
+                for i in 0..4 {
+                    let Some(n) = seq.next_element::<i32>()? else {
+                        return Err(de::Error::invalid_length(i, &self));
+                    };
+                    bits = bits << 32 | n as u32 as u128;
+                }
+                Ok(Some(Uuid::from_u128(bits)))
+            }
+            fn visit_none<E>(self) -> Result<Self::Value, E> {
+                Ok(None)
+            }
+        }
+        de.deserialize_any(UuidVisitor)
+    }
 }
 
 /// Represents the author or authors of an avatar. Figura, for some strange reason, differentiates
@@ -155,12 +644,14 @@ pub struct ModelPart {
     /// This modelpart's children.
     #[serde(default)]
     pub chld: Box<[ModelPart]>,
-    /// Presumably animation-related; unsure.
-    pub anim: Option<NbtTag>,
-    /// Rotation of this model part.
+    /// The keyframes driving this part in each animation, keyed by the animation's index in
+    /// [Moon::animations]. Parts that no animation touches have none.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty", with = "index_keys")]
+    pub anim: BTreeMap<usize, PartChannels>,
+    /// Rotation of this model part, in degrees, in [Blockbench space][space].
     #[serde(default)]
     pub rot: [f64; 3],
-    /// Pivot point of this model part.
+    /// Pivot point of this model part, in [Blockbench space][space].
     #[serde(default)]
     pub piv: [f64; 3],
     /// Primary render type (used for primary texture).
@@ -169,6 +660,13 @@ pub struct ModelPart {
     pub secondary: Option<RenderType>,
     /// Parent type if the name contains one (or it's applied by a customization).
     pub pt: Option<ParentType>,
+    /// The names of the Blockbench collections defined in this part's model. Only the children of
+    /// [Moon::models] (one per Blockbench file) have these.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cn: Vec<String>,
+    /// The collections this part belongs to, as indices into its model's [cn][Self::cn].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pr: Vec<i32>,
     /// Whether this cube is visible.
     #[serde(default = "return_true")]
     pub vsb: bool,
@@ -176,6 +674,13 @@ pub struct ModelPart {
     /// has an effect when the modelpart has vertices, i.e. is not a group.
     #[serde(default)]
     pub smo: bool,
+    /// The Blockbench marker color of the group or element this part was made from, from 0 to 7.
+    /// Figura doesn't store this, and ignores it; [bbmodel::compile] adds it (leaving out the
+    /// default, 0) so that unpacking keeps the creator's color-coding.
+    ///
+    /// [bbmodel::compile]: crate::bbmodel::compile
+    #[serde(default, rename = "fia_color", skip_serializing_if = "Option::is_none")]
+    pub color: Option<u8>,
     /// Extra information that depends on the part type. Since cubes have extra top-level keys,
     /// this can't simply be an externally-tagged enum — instead, the enum is untagged and this
     /// field is flattened.
@@ -183,26 +688,328 @@ pub struct ModelPart {
     pub data: ModelData,
 }
 
-/// Stores extra data for a modelpart depending on what type of model it has, if any.
+/// An error encountered while moving a part with [ModelPart::reparent].
+#[derive(Debug, Error)]
+pub enum ReparentError {
+    /// A path doesn't lead to a part.
+    #[error("no part at {0:?}")]
+    MissingPart(String),
+    /// The new parent is the part itself or one of its descendants.
+    #[error("cannot move {0:?} into its own subtree")]
+    IntoOwnSubtree(String),
+}
+
+fn path_components(path: &str) -> Vec<&str> {
+    path.split('/').filter(|s| !s.is_empty()).collect()
+}
+
+impl ModelPart {
+    /// Finds a descendant by its path: the names of the parts leading to it, separated by `/`.
+    /// The empty path is this part.
+    pub fn find(&self, path: &str) -> Option<&ModelPart> {
+        path_components(path).into_iter().try_fold(self, |part, name| part.chld.iter().find(|c| c.name == name))
+    }
+
+    /// Like [find][Self::find], but mutable.
+    pub fn find_mut(&mut self, path: &str) -> Option<&mut ModelPart> {
+        path_components(path).into_iter().try_fold(self, |part, name| part.chld.iter_mut().find(|c| c.name == name))
+    }
+
+    /// Converts this part and its descendants to NBT, as they appear in [Moon::models].
+    pub fn to_nbt(&self) -> Result<NbtCompound, NbtIoError> {
+        to_compound(self)
+    }
+
+    /// Converts an NBT compound to a part and its descendants.
+    pub fn from_nbt(compound: NbtCompound) -> Result<ModelPart, NbtIoError> {
+        from_compound(&compound)
+    }
+
+    /// This part's own rotation around its pivot.
+    pub fn transform(&self) -> Transform {
+        Transform::around(self.rot, self.piv)
+    }
+
+    /// The combined transform of this part and the parts on the way to a descendant, not
+    /// including the descendant itself.
+    fn transform_to(&self, path: &[&str]) -> Option<Transform> {
+        let mut transform = Transform::default();
+        let mut part = self;
+        for name in path {
+            transform = transform.then_inner(&part.transform());
+            part = part.chld.iter().find(|c| c.name == *name)?;
+        }
+        Some(transform)
+    }
+
+    /// Changes which texture the faces of this part and its descendants use, as in
+    /// [Moon::remap_textures].
+    pub fn remap_textures(&mut self, map: &HashMap<usize, usize>) {
+        match &mut self.data {
+            ModelData::Group {} => {}
+            ModelData::Cube { cube_data, .. } => {
+                for face in [&mut cube_data.n, &mut cube_data.s, &mut cube_data.u, &mut cube_data.d, &mut cube_data.w, &mut cube_data.e].into_iter().flatten() {
+                    if let Some(&new) = map.get(&face.tex) {
+                        face.tex = new;
+                    }
+                }
+            }
+            ModelData::Mesh { mesh_data } => mesh_data.remap_textures(map),
+        }
+        for child in self.chld.iter_mut() {
+            child.remap_textures(map);
+        }
+    }
+
+    /// Moves this part, its descendants, and all their pivots by `delta`. Since pivots move with
+    /// the geometry, rotations look the same relative to the parts they turn.
+    pub fn translate_subtree(&mut self, delta: [f64; 3]) {
+        let add = |v: &mut [f64; 3]| (0..3).for_each(|i| v[i] += delta[i]);
+        add(&mut self.piv);
+        match &mut self.data {
+            ModelData::Group {} => {}
+            ModelData::Cube { f, t, .. } => {
+                add(f);
+                add(t);
+            }
+            ModelData::Mesh { mesh_data } => mesh_data.translate(delta),
+        }
+        for child in self.chld.iter_mut() {
+            child.translate_subtree(delta);
+        }
+    }
+
+    /// Folds every group's rotation into the parts under it, so that no part's appearance depends
+    /// on its ancestors: groups end up unrotated, meshes have their rotation applied to their
+    /// vertices, and cubes (which can't be rotated without a rotation of their own) carry their
+    /// combined rotation themselves. Every pivot moves to where it appeared before, so the model
+    /// looks the same at rest. Animations aren't adjusted.
+    pub fn bake_transforms(&mut self) {
+        fn bake(part: &mut ModelPart, parent: &Transform) {
+            let world = parent.then_inner(&part.transform());
+            for child in part.chld.iter_mut() {
+                bake(child, &world);
+            }
+            let piv = world.apply(part.piv);
+            let delta: [f64; 3] = std::array::from_fn(|i| piv[i] - part.piv[i]);
+            part.rot = [0.0; 3];
+            match &mut part.data {
+                ModelData::Group {} => {}
+                ModelData::Cube { f, t, .. } => {
+                    for v in [f, t] {
+                        (0..3).for_each(|i| v[i] += delta[i]);
+                    }
+                    // tiny angles left over from rounding would otherwise show up as rotations
+                    part.rot = space::euler_angles(&world.rotation).map(|a| if a.abs() < 1e-9 { 0.0 } else { a });
+                }
+                ModelData::Mesh { mesh_data } => mesh_data.map_vertices(|v| world.apply(v)),
+            }
+            part.piv = piv;
+        }
+        bake(self, &Transform::default());
+    }
+
+    /// Moves the descendant at `child` to be the last child of the descendant at `new_parent`
+    /// (paths as in [find][Self::find]). The moved part's pivot and rotation, and the positions
+    /// of everything under it, are adjusted to cancel out the change in its ancestors' rotations,
+    /// so it looks the same at rest. Animations on the moved part aren't adjusted, and will look
+    /// different if its ancestors' rotations changed.
+    pub fn reparent(&mut self, child: &str, new_parent: &str) -> Result<(), ReparentError> {
+        let child_path = path_components(child);
+        let parent_path = path_components(new_parent);
+        let Some((name, old_parent_path)) = child_path.split_last() else {
+            return Err(ReparentError::MissingPart(child.into()));
+        };
+        if parent_path.starts_with(&child_path) {
+            return Err(ReparentError::IntoOwnSubtree(child.into()));
+        }
+        let old = self.transform_to(&child_path).ok_or_else(|| ReparentError::MissingPart(child.into()))?;
+        let new = self.find(new_parent)
+            .and_then(|parent| Some(self.transform_to(&parent_path)?.then_inner(&parent.transform())))
+            .ok_or_else(|| ReparentError::MissingPart(new_parent.into()))?;
+        let change = new.inverse().then_inner(&old);
+        let old_parent = self.find_mut(&old_parent_path.join("/")).unwrap();
+        let mut siblings = std::mem::take(&mut old_parent.chld).into_vec();
+        let index = siblings.iter().position(|c| c.name == *name).unwrap();
+        let mut part = siblings.remove(index);
+        old_parent.chld = siblings.into();
+        let piv = change.apply(part.piv);
+        part.translate_subtree(std::array::from_fn(|i| piv[i] - part.piv[i]));
+        part.rot = space::euler_angles(&change.then_inner(&Transform::around(part.rot, [0.0; 3])).rotation);
+        let parent = self.find_mut(new_parent).unwrap();
+        let mut children = std::mem::take(&mut parent.chld).into_vec();
+        children.push(part);
+        parent.chld = children.into();
+        Ok(())
+    }
+
+    /// Cleans up the collections of a model (a child of [Moon::models]) after parts have been
+    /// added or removed: collections no part belongs to are dropped from [cn][Self::cn], and
+    /// every [pr][Self::pr] in the subtree is rewritten to match. Indices that don't refer to a
+    /// collection are removed. Returns the number of collections dropped.
+    pub fn reindex_collections(&mut self) -> usize {
+        fn visit(part: &ModelPart, used: &mut Vec<bool>) {
+            for &i in &part.pr {
+                if let Some(used) = usize::try_from(i).ok().and_then(|i| used.get_mut(i)) {
+                    *used = true;
+                }
+            }
+            part.chld.iter().for_each(|c| visit(c, used));
+        }
+        fn remap(part: &mut ModelPart, map: &[Option<i32>]) {
+            let mut pr: Vec<i32> = part.pr.iter().filter_map(|&i| *map.get(usize::try_from(i).ok()?)?).collect();
+            pr.sort();
+            pr.dedup();
+            part.pr = pr;
+            part.chld.iter_mut().for_each(|c| remap(c, map));
+        }
+        let mut used = vec![false; self.cn.len()];
+        visit(self, &mut used);
+        let mut next = 0;
+        let map: Vec<Option<i32>> = used.iter().map(|&u| u.then(|| { next += 1; next - 1 })).collect();
+        let before = self.cn.len();
+        let mut keep = used.into_iter();
+        self.cn.retain(|_| keep.next().unwrap());
+        remap(self, &map);
+        before - self.cn.len()
+    }
+}
+
+/// The keyframes for one part in one animation, split by the property they change. Figura stores
+/// these on the part rather than with the [Animation].
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PartChannels {
+    /// Rotation keyframes, in degrees.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rot: Vec<PartKeyframe>,
+    /// Position keyframes, as offsets from the part's resting position.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pos: Vec<PartKeyframe>,
+    /// Scale keyframes, where 1 is unchanged.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scl: Vec<PartKeyframe>,
+    /// Any channels not known to this struct, preserved so that they aren't lost when
+    /// reserializing.
+    #[serde(flatten)]
+    pub extra: HashMap<String, NbtTag>,
+}
+
+impl PartChannels {
+    /// Whether there are no keyframes in any channel.
+    pub fn is_empty(&self) -> bool {
+        self.rot.is_empty() && self.pos.is_empty() && self.scl.is_empty() && self.extra.is_empty()
+    }
+}
+
+/// One keyframe in a [PartChannels] channel. Figura writes every number here as a float.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PartKeyframe {
+    /// When the keyframe happens, in seconds.
+    pub time: f32,
+    /// How values are interpolated between this keyframe and the next.
+    #[serde(default)]
+    pub int: Interpolation,
+    /// The keyframe's value, or the value coming into it if [end][Self::end] is set.
+    pub pre: KeyframeValue,
+    /// The value going out of the keyframe, for keyframes that jump from one value to another.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<KeyframeValue>,
+    /// Left Bézier handle value, for [Interpolation::Bezier].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bl: Option<[f32; 3]>,
+    /// Right Bézier handle value, for [Interpolation::Bezier].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub br: Option<[f32; 3]>,
+    /// Left Bézier handle time, for [Interpolation::Bezier].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blt: Option<[f32; 3]>,
+    /// Right Bézier handle time, for [Interpolation::Bezier].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub brt: Option<[f32; 3]>,
+}
+
+/// The value of a [PartKeyframe] on each axis.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum KeyframeValue {
+    /// Constant values.
+    Numbers([f32; 3]),
+    /// Lua expressions evaluated each frame. Figura stores every axis as an expression if any of
+    /// them is one.
+    Expressions([String; 3]),
+}
+
+/// How values are interpolated between a keyframe and the next one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Interpolation {
+    /// Straight lines between keyframes.
+    #[default]
+    Linear,
+    /// Jump to the next keyframe's value when it is reached.
+    Step,
+    /// A smooth curve through the surrounding keyframes.
+    CatmullRom,
+    /// A curve controlled by the keyframe's handles.
+    Bezier,
+}
+
+impl Interpolation {
+    /// The name Figura and Blockbench store this interpolation under.
+    pub fn name(self) -> &'static str {
+        match self {
+            Interpolation::Linear => "linear",
+            Interpolation::Step => "step",
+            Interpolation::CatmullRom => "catmullrom",
+            Interpolation::Bezier => "bezier",
+        }
+    }
+
+    /// Looks up an interpolation by the name Blockbench stores it under.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "linear" => Interpolation::Linear,
+            "step" => Interpolation::Step,
+            "catmullrom" => Interpolation::CatmullRom,
+            "bezier" => Interpolation::Bezier,
+            _ => return None,
+        })
+    }
+}
+
+/// (De)serializes maps keyed by index as NBT compounds, whose keys must be strings.
+mod index_keys {
+    use std::collections::BTreeMap;
+    use serde::{Serialize, Serializer, Deserialize, Deserializer, de};
+
+    pub fn serialize<S: Serializer, V: Serialize>(map: &BTreeMap<usize, V>, ser: S) -> Result<S::Ok, S::Error> {
+        ser.collect_map(map.iter().map(|(k, v)| (k.to_string(), v)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, V: Deserialize<'de>>(de: D) -> Result<BTreeMap<usize, V>, D::Error> {
+        BTreeMap::<String, V>::deserialize(de)?.into_iter()
+            .map(|(k, v)| k.parse().map(|k| (k, v)).map_err(|_| de::Error::custom(format!("invalid animation index {k:?}"))))
+            .collect()
+    }
+}
+
+/// Stores extra data for a modelpart depending on what type of model it has, if any. Being
+/// untagged, the variant is picked by which keys are present: a part with neither cube nor mesh
+/// data is a group, so [Group][Self::Group] has to come last.
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(untagged)]
-#[serde(deny_unknown_fields)]
 pub enum ModelData {
-    /// A group, with no model data.
-    Group {},
     /// A cube, which is not a cube (more generally, it's a rectangular prism).
     Cube {
         /// Maps each side of the cube to its UV and texture data.
         cube_data: Sided<Face>,
-        /// The point where the cube begins. I'm unsure of what coordinate space this location is
-        /// in.
+        /// The point where the cube begins, in [Blockbench space][space].
         f: [f64; 3],
-        /// The point where the cube begins. May be less than [f][Self::f] for inverted cubes. This
-        /// is probably in the same coordinate space as [f][Self::f].
+        /// The point where the cube ends, in [Blockbench space][space]. May be less than
+        /// [f][Self::f] on some axes for inverted cubes.
         t: [f64; 3],
-        /// The cube's inflate scale. This is equivalent to subtracting this value from each number
-        /// in [f][Self::f] and adding it to each value in [t][Self::t], except it doesn't affect
-        /// Blockbench's generated UVs.
+        /// The cube's inflate scale, which may be negative. See [ModelData::cube_corners] for how
+        /// it is applied.
         #[serde(default)]
         inf: f64,
     },
@@ -211,11 +1018,36 @@ pub enum ModelData {
         /// Data for meshes. To be honest, I'm surprised that Figura didn't flatten this struct.
         mesh_data: MeshData,
     },
+    /// A group, with no model data.
+    Group {},
+}
+
+impl ModelData {
+    /// The corners a cube is drawn between, after inflating, or [None] if this isn't a cube.
+    ///
+    /// Figura and Blockbench both subtract the inflate from every coordinate of `f` and add it to
+    /// every coordinate of `t`, without checking which is larger. So on an axis where a cube is
+    /// inverted (`t < f`), a positive inflate shrinks it rather than growing it, and a negative
+    /// inflate larger than half the cube's size turns it inside out. Since both programs agree,
+    /// converters pass `f`, `t`, and the inflate through untouched rather than normalizing them.
+    pub fn cube_corners(&self) -> Option<([f64; 3], [f64; 3])> {
+        let ModelData::Cube { f, t, inf, .. } = self else { return None };
+        Some((f.map(|c| c - inf), t.map(|c| c + inf)))
+    }
+
+    /// The axes on which a cube is drawn inside out, because its [inflated
+    /// corners][Self::cube_corners] are reversed. Faces perpendicular to such an axis are visible
+    /// from the inside instead of the outside. Always all `false` for non-cubes.
+    pub fn inverted_axes(&self) -> [bool; 3] {
+        match self.cube_corners() {
+            Some((from, to)) => [0, 1, 2].map(|i| to[i] < from[i]),
+            None => [false; 3],
+        }
+    }
 }
 
 /// Maps each side of something (such as a cube) to an object.
 #[derive(Debug, Serialize, Deserialize)]
-#[serde(deny_unknown_fields)]
 pub struct Sided<S> {
     /// The north face.
     pub n: Option<S>,
@@ -232,36 +1064,345 @@ pub struct Sided<S> {
 }
 
 /// Texture and UV information for each face of a cube.
-#[serde(deny_unknown_fields)]
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Face {
     /// The texture ID in [Textures::data].
+    #[serde(serialize_with = "serialize_tex")]
     pub tex: usize,
-    /// The UV information (presumably `[x0, y0, x1, y1]`, but I haven't confirmed this).
-    pub uv: [f64; 4],
+    /// The area of the texture shown on the face, in the texture's pixels.
+    pub uv: UvRect,
     /// How the face is rotated.
     #[serde(default)]
     pub rot: f64,
 }
 
-/// Texture and vertex information for meshes. I'm not even going to try documenting this right
-/// now; ping me in a few hours maybe?
+/// A rectangle on a texture, stored as `[x0, y0, x1, y1]` where `(x0, y0)` is the corner shown at
+/// the face's top left. A rectangle whose second corner is left of or above its first is
+/// mirrored.
+///
+/// UVs come in two spaces: *pixel* space, in units of some grid laid over the texture, and
+/// *normalized* space, where the whole texture spans 0 to 1. Moons use the pixels of the actual
+/// texture image; Blockbench uses the project's (or texture's) UV resolution, which needn't match
+/// the image. Converting between grids goes through [rescale][Self::rescale].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(from = "[f64; 4]", into = "[f64; 4]")]
+pub struct UvRect {
+    /// The horizontal coordinate of the first corner.
+    pub x0: f64,
+    /// The vertical coordinate of the first corner.
+    pub y0: f64,
+    /// The horizontal coordinate of the second corner.
+    pub x1: f64,
+    /// The vertical coordinate of the second corner.
+    pub y1: f64,
+}
+
+impl UvRect {
+    /// Makes a rectangle from pixel coordinates.
+    pub fn pixels(x0: f64, y0: f64, x1: f64, y1: f64) -> Self {
+        Self { x0, y0, x1, y1 }
+    }
+
+    /// Makes a rectangle from normalized coordinates, converting them to pixels on a grid of the
+    /// given width and height.
+    pub fn from_normalized(rect: [f64; 4], (width, height): (f64, f64)) -> Self {
+        Self::pixels(rect[0] * width, rect[1] * height, rect[2] * width, rect[3] * height)
+    }
+
+    /// Converts this rectangle to normalized coordinates, given the width and height of the grid
+    /// it's in.
+    pub fn to_normalized(self, (width, height): (f64, f64)) -> [f64; 4] {
+        [self.x0 / width, self.y0 / height, self.x1 / width, self.y1 / height]
+    }
+
+    /// Moves this rectangle from one pixel grid to another, such as from a Blockbench project's
+    /// UV resolution to the size of the texture image.
+    pub fn rescale(self, from: (f64, f64), to: (f64, f64)) -> Self {
+        Self::from_normalized(self.to_normalized(from), to)
+    }
+
+    /// The rectangle's width, which is negative if it's mirrored horizontally.
+    pub fn width(self) -> f64 {
+        self.x1 - self.x0
+    }
+
+    /// The rectangle's height, which is negative if it's mirrored vertically.
+    pub fn height(self) -> f64 {
+        self.y1 - self.y0
+    }
+
+    /// Rewrites a face's rectangle and UV rotation into a canonical form, with the rotation
+    /// either 0 or 90 degrees. Blockbench only allows multiples of 90, so the rotation is rounded
+    /// to the nearest quarter turn first. Turning a face's texture halfway is the same as swapping
+    /// the rectangle's corners, so rotations of 180 and 270 degrees become 0 and 90 with the
+    /// corners swapped. The face looks the same either way, but two faces that look the same now
+    /// compare equal.
+    pub fn normalize_rotation(self, rotation: f64) -> (Self, u16) {
+        let quarters = ((rotation / 90.0).round() as i64).rem_euclid(4) as u16;
+        let rect = if quarters >= 2 { Self::pixels(self.x1, self.y1, self.x0, self.y0) } else { self };
+        (rect, quarters % 2 * 90)
+    }
+}
+
+impl From<[f64; 4]> for UvRect {
+    fn from([x0, y0, x1, y1]: [f64; 4]) -> Self {
+        Self { x0, y0, x1, y1 }
+    }
+}
+
+impl From<UvRect> for [f64; 4] {
+    fn from(rect: UvRect) -> Self {
+        [rect.x0, rect.y0, rect.x1, rect.y1]
+    }
+}
+
+/// NBT has no unsigned types, so texture IDs are written as ints.
+fn serialize_tex<S: serde::Serializer>(tex: &usize, ser: S) -> Result<S::Ok, S::Error> {
+    ser.serialize_i32(*tex as i32)
+}
+
+/// Texture and vertex information for meshes, packed as compactly as Figura could manage. Each
+/// field is a list; [faces][Self::faces] and [MeshBuilder] convert to and from something more
+/// pleasant.
 #[derive(Debug, Serialize, Deserialize)]
-#[serde(deny_unknown_fields)]
 pub struct MeshData {
-    /// Vertices.
+    /// Vertex positions, as a flat list of `x, y, z` numbers.
     pub vtx: NbtTag,
-    /// Textures, see [Textures::data].
+    /// One short per face: the face's texture ID (see [Textures::data]) shifted left by 4 bits,
+    /// plus its number of vertices (3 or 4).
     pub tex: NbtTag,
-    /// Faces.
+    /// The index into [vtx][Self::vtx] (counting whole vertices) of each face's vertices, in
+    /// order. Stored as bytes, shorts, or ints depending on how many vertices there are.
     pub fac: NbtTag,
-    /// UVs, aka hell.
+    /// The `u, v` coordinates of each face's vertices, in the same order as [fac][Self::fac].
     pub uvs: NbtTag,
     /// Extraneous keys not matched.
     #[serde(flatten)]
     excess: NbtTag,
 }
 
+/// Reads a numeric tag as an [f64], whatever its width.
+pub(crate) fn nbt_f64(tag: &NbtTag) -> Option<f64> {
+    match *tag {
+        NbtTag::Byte(n) => Some(n.into()),
+        NbtTag::Short(n) => Some(n.into()),
+        NbtTag::Int(n) => Some(n.into()),
+        NbtTag::Long(n) => Some(n as f64),
+        NbtTag::Float(n) => Some(n.into()),
+        NbtTag::Double(n) => Some(n),
+        _ => None,
+    }
+}
+
+/// Reads a list or array tag of numbers.
+fn nbt_numbers(tag: &NbtTag) -> Vec<f64> {
+    match tag {
+        NbtTag::List(list) => list.iter().filter_map(nbt_f64).collect(),
+        NbtTag::ByteArray(v) => v.iter().map(|&n| n.into()).collect(),
+        NbtTag::IntArray(v) => v.iter().map(|&n| n.into()).collect(),
+        NbtTag::LongArray(v) => v.iter().map(|&n| n as f64).collect(),
+        _ => vec![],
+    }
+}
+
+/// Reads [MeshData::fac], treating bytes as unsigned.
+fn nbt_indices(tag: &NbtTag) -> Vec<usize> {
+    let index = |tag: &NbtTag| match *tag {
+        NbtTag::Byte(n) => n as u8 as usize,
+        NbtTag::Short(n) => n as u16 as usize,
+        ref tag => nbt_f64(tag).unwrap_or(0.0) as usize,
+    };
+    match tag {
+        NbtTag::List(list) => list.iter().map(index).collect(),
+        NbtTag::ByteArray(v) => v.iter().map(|&n| n as u8 as usize).collect(),
+        NbtTag::IntArray(v) => v.iter().map(|&n| n as usize).collect(),
+        _ => vec![],
+    }
+}
+
+/// One corner of a [MeshFace].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MeshVertex {
+    /// The index of this vertex's position in the mesh, shared with other faces using it.
+    pub index: usize,
+    /// This vertex's position.
+    pub pos: [f64; 3],
+    /// This vertex's texture coordinates on this face.
+    pub uv: [f64; 2],
+}
+
+/// One face of a mesh, as read from [MeshData::faces].
+#[derive(Clone, Debug, PartialEq)]
+pub struct MeshFace {
+    /// The texture ID in [Textures::data].
+    pub tex: usize,
+    /// The face's corners, of which there are 3 or 4.
+    pub vertices: Vec<MeshVertex>,
+}
+
+impl MeshData {
+    /// Changes which texture each face uses, as in [Moon::remap_textures]. The vertex counts
+    /// packed alongside each texture ID are kept.
+    pub fn remap_textures(&mut self, map: &HashMap<usize, usize>) {
+        let NbtTag::List(list) = &mut self.tex else { return };
+        for tag in list.inner_mut() {
+            let Some(info) = nbt_f64(tag).map(|n| n as i64) else { continue };
+            if let Some(&new) = map.get(&((info >> 4) as usize)) {
+                let info = (new as i64) << 4 | info & 0xf;
+                *tag = match tag {
+                    NbtTag::Int(_) => NbtTag::Int(info as i32),
+                    _ => NbtTag::Short(info as i16),
+                };
+            }
+        }
+    }
+
+    /// Moves every vertex of the mesh by `delta`.
+    pub fn translate(&mut self, delta: [f64; 3]) {
+        self.map_vertices(|v| std::array::from_fn(|i| v[i] + delta[i]));
+    }
+
+    /// Replaces the position of every vertex of the mesh with the result of `f`.
+    pub fn map_vertices(&mut self, mut f: impl FnMut([f64; 3]) -> [f64; 3]) {
+        let NbtTag::List(list) = &mut self.vtx else { return };
+        for chunk in list.inner_mut().chunks_exact_mut(3) {
+            let Some(v) = chunk.iter().map(nbt_f64).collect::<Option<Vec<_>>>() else { continue };
+            let v = f([v[0], v[1], v[2]]);
+            for (tag, n) in chunk.iter_mut().zip(v) {
+                *tag = match tag {
+                    NbtTag::Double(_) => NbtTag::Double(n),
+                    _ => NbtTag::Float(n as f32),
+                };
+            }
+        }
+    }
+
+    /// Replaces the texture coordinates of every face corner with the result of `f`, which is
+    /// given the face's texture ID and the corner's current coordinates.
+    pub fn map_uvs(&mut self, mut f: impl FnMut(usize, [f64; 2]) -> [f64; 2]) {
+        let tex = nbt_numbers(&self.tex);
+        let NbtTag::List(uvs) = &mut self.uvs else { return };
+        let mut corners = uvs.inner_mut().chunks_exact_mut(2);
+        for info in tex {
+            let info = info as i64;
+            for corner in corners.by_ref().take((info & 0xf) as usize) {
+                let (Some(u), Some(v)) = (nbt_f64(&corner[0]), nbt_f64(&corner[1])) else { continue };
+                let uv = f((info >> 4) as usize, [u, v]);
+                for (tag, n) in corner.iter_mut().zip(uv) {
+                    *tag = match tag {
+                        NbtTag::Double(_) => NbtTag::Double(n),
+                        _ => NbtTag::Float(n as f32),
+                    };
+                }
+            }
+        }
+    }
+
+    /// Unpacks the positions of the mesh's vertices.
+    pub fn vertices(&self) -> Vec<[f64; 3]> {
+        nbt_numbers(&self.vtx).chunks_exact(3).map(|c| [c[0], c[1], c[2]]).collect()
+    }
+
+    /// Unpacks the mesh's faces. Iteration stops early if the data is truncated; vertex indices
+    /// that are out of range get the position `[0, 0, 0]`.
+    pub fn faces(&self) -> impl Iterator<Item = MeshFace> {
+        let positions = self.vertices();
+        let tex = nbt_numbers(&self.tex);
+        let fac = nbt_indices(&self.fac);
+        let uvs = nbt_numbers(&self.uvs);
+        let mut corner = 0;
+        tex.into_iter().map_while(move |info| {
+            let info = info as i64;
+            let count = (info & 0xf) as usize;
+            let end = corner + count;
+            if end > fac.len() || end * 2 > uvs.len() {
+                return None;
+            }
+            let vertices = (corner..end).map(|i| {
+                let index = fac[i];
+                MeshVertex {
+                    index,
+                    pos: positions.get(index).copied().unwrap_or_default(),
+                    uv: [uvs[i * 2], uvs[i * 2 + 1]],
+                }
+            }).collect();
+            corner = end;
+            Some(MeshFace { tex: (info >> 4) as usize, vertices })
+        })
+    }
+}
+
+/// Packs vertices and faces into [MeshData].
+#[derive(Clone, Debug, Default)]
+pub struct MeshBuilder {
+    vertices: Vec<[f64; 3]>,
+    faces: Vec<(usize, Vec<(usize, [f64; 2])>)>,
+}
+
+impl MeshBuilder {
+    /// Starts an empty mesh.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts with an existing mesh's vertices and faces, for editing.
+    pub fn from_mesh(mesh: &MeshData) -> Self {
+        Self {
+            vertices: mesh.vertices(),
+            faces: mesh.faces().map(|f| (f.tex, f.vertices.iter().map(|v| (v.index, v.uv)).collect())).collect(),
+        }
+    }
+
+    /// Adds a vertex, returning its index for use in [face][Self::face].
+    pub fn vertex(&mut self, pos: [f64; 3]) -> usize {
+        self.vertices.push(pos);
+        self.vertices.len() - 1
+    }
+
+    /// Mutable access to the vertex positions, indexed as returned by [vertex][Self::vertex].
+    pub fn vertices_mut(&mut self) -> &mut [[f64; 3]] {
+        &mut self.vertices
+    }
+
+    /// Adds a face using the given texture, made of 3 or 4 `(vertex index, uv)` corners.
+    ///
+    /// # Panics
+    /// Panics if there aren't 3 or 4 corners.
+    pub fn face(&mut self, tex: usize, corners: &[(usize, [f64; 2])]) -> &mut Self {
+        assert!(matches!(corners.len(), 3 | 4), "mesh faces must have 3 or 4 vertices");
+        self.faces.push((tex, corners.to_vec()));
+        self
+    }
+
+    /// Removes faces that don't satisfy a predicate, which is given each face's texture and
+    /// corners.
+    pub fn retain_faces(&mut self, mut keep: impl FnMut(usize, &[(usize, [f64; 2])]) -> bool) -> &mut Self {
+        self.faces.retain(|(tex, corners)| keep(*tex, corners));
+        self
+    }
+
+    /// Packs the mesh, using the narrowest integer type that fits for vertex indices as Figura
+    /// does.
+    pub fn build(&self) -> MeshData {
+        let floats = |values: Vec<f64>| NbtTag::List(values.into_iter().map(|v| NbtTag::Float(v as f32)).collect());
+        let indices = self.faces.iter().flat_map(|(_, c)| c.iter().map(|(i, _)| *i));
+        let fac = if self.vertices.len() <= 256 {
+            indices.map(|i| NbtTag::Byte(i as u8 as i8)).collect()
+        } else if self.vertices.len() < 32767 {
+            indices.map(|i| NbtTag::Short(i as i16)).collect()
+        } else {
+            indices.map(|i| NbtTag::Int(i as i32)).collect()
+        };
+        MeshData {
+            vtx: floats(self.vertices.iter().flatten().copied().collect()),
+            tex: NbtTag::List(self.faces.iter().map(|(tex, c)| NbtTag::Short((*tex << 4 | c.len()) as i16)).collect()),
+            fac: NbtTag::List(fac),
+            uvs: floats(self.faces.iter().flat_map(|(_, c)| c.iter().flat_map(|(_, uv)| *uv)).collect()),
+            excess: NbtTag::Compound(Default::default()),
+        }
+    }
+}
+
 impl Default for ModelData {
     fn default() -> Self {
         Self::Group {}
@@ -271,7 +1412,7 @@ impl Default for ModelData {
 /// A parent type determined by Figura. Although usually the parent type can be determined based on
 /// the [ModelPart]'s name, Figura for some reason stores a copy anyway. This enum documents each
 /// possible parent type.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[allow(missing_docs)]
 pub enum ParentType {
     /// No parent type — follows parent's rotations.
@@ -320,3 +1461,192 @@ pub enum ParentType {
     LeftElytraPivot,
     RightElytraPivot,
 }
+
+impl ParentType {
+    /// Each parent type (other than [None][ParentType::None]) along with the keywords Figura
+    /// recognizes at the start of a part's name.
+    pub const KEYWORDS: &'static [(ParentType, &'static [&'static str])] = &[
+        (ParentType::Head, &["Head"]),
+        (ParentType::Body, &["Body"]),
+        (ParentType::LeftArm, &["LeftArm"]),
+        (ParentType::RightArm, &["RightArm"]),
+        (ParentType::LeftLeg, &["LeftLeg"]),
+        (ParentType::RightLeg, &["RightLeg"]),
+        (ParentType::LeftElytra, &["LeftElytra", "LeftElytron"]),
+        (ParentType::RightElytra, &["RightElytra", "RightElytron"]),
+        (ParentType::Cape, &["Cape"]),
+        (ParentType::World, &["World"]),
+        (ParentType::Hud, &["Hud", "HUD", "Gui", "GUI"]),
+        (ParentType::Camera, &["Camera"]),
+        (ParentType::Skull, &["Skull", "\u{2620}"]),
+        (ParentType::Portrait, &["Portrait"]),
+        (ParentType::Arrow, &["Arrow"]),
+        (ParentType::Trident, &["Trident"]),
+        (ParentType::Item, &["Item"]),
+        (ParentType::LeftItemPivot, &["LeftItemPivot"]),
+        (ParentType::RightItemPivot, &["RightItemPivot"]),
+        (ParentType::LeftSpyglassPivot, &["LeftSpyglassPivot"]),
+        (ParentType::RightSpyglassPivot, &["RightSpyglassPivot"]),
+        (ParentType::LeftParrotPivot, &["LeftParrotPivot"]),
+        (ParentType::RightParrotPivot, &["RightParrotPivot"]),
+        (ParentType::HelmetItemPivot, &["HelmetItemPivot"]),
+        (ParentType::HelmetPivot, &["HelmetPivot"]),
+        (ParentType::ChestplatePivot, &["ChestplatePivot"]),
+        (ParentType::LeftShoulderPivot, &["LeftShoulderPivot"]),
+        (ParentType::RightShoulderPivot, &["RightShoulderPivot"]),
+        (ParentType::LeggingsPivot, &["LeggingsPivot"]),
+        (ParentType::LeftLeggingPivot, &["LeftLeggingPivot"]),
+        (ParentType::RightLeggingPivot, &["RightLeggingPivot"]),
+        (ParentType::LeftBootPivot, &["LeftBootPivot"]),
+        (ParentType::RightBootPivot, &["RightBootPivot"]),
+        (ParentType::LeftElytraPivot, &["LeftElytraPivot"]),
+        (ParentType::RightElytraPivot, &["RightElytraPivot"]),
+    ];
+
+    /// Determines the parent type Figura gives a part based on its name. Figura checks whether the
+    /// name starts with one of each type's [keywords][Self::KEYWORDS]; where several keywords
+    /// match (such as `LeftElytra` and `LeftElytraPivot`), the longest one wins. Names without a
+    /// keyword get [None][ParentType::None].
+    pub fn from_part_name(name: &str) -> ParentType {
+        Self::KEYWORDS.iter()
+            .flat_map(|(pt, keywords)| keywords.iter().map(move |k| (*pt, *k)))
+            .filter(|(_, k)| name.starts_with(k))
+            .max_by_key(|(_, k)| k.len())
+            .map_or(ParentType::None, |(pt, _)| pt)
+    }
+
+    /// Like [from_part_name][Self::from_part_name], but also looks past prefixes of capital
+    /// letters and an underscore, such as the `MOVE_` and `MIMIC_` that older Figura versions and
+    /// some exporters put before a keyword, to find which part `MOVE_Head` was meant to follow.
+    /// Figura itself doesn't do this, so packing doesn't either.
+    pub fn infer(name: &str) -> ParentType {
+        let mut rest = name;
+        loop {
+            let pt = Self::from_part_name(rest);
+            if pt != ParentType::None {
+                return pt;
+            }
+            let Some((prefix, after)) = rest.split_once('_') else { return ParentType::None };
+            if prefix.is_empty() || !prefix.chars().all(|c| c.is_ascii_uppercase()) {
+                return ParentType::None;
+            }
+            rest = after;
+        }
+    }
+
+    /// The parent type Figura stores for a part with this name: the one [its name
+    /// gives][Self::from_part_name], or [None][Option::None] if there isn't one.
+    pub fn for_part(name: &str) -> Option<ParentType> {
+        Some(Self::from_part_name(name)).filter(|&pt| pt != ParentType::None)
+    }
+}
+
+/// An error returned when parsing a [ParentType] that doesn't exist.
+#[derive(Debug, Error)]
+#[error("unknown parent type {0:?}")]
+pub struct UnknownParentType(pub String);
+
+impl FromStr for ParentType {
+    type Err = UnknownParentType;
+    /// Parses one of the [keywords][Self::KEYWORDS] Figura recognizes, or `None`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "None" {
+            return Ok(ParentType::None);
+        }
+        Self::KEYWORDS.iter()
+            .find(|(_, keywords)| keywords.contains(&s))
+            .map(|(pt, _)| *pt)
+            .ok_or_else(|| UnknownParentType(s.into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn textured_cube(name: &str, tex: usize) -> ModelPart {
+        let face = || Some(Face { tex, uv: UvRect::pixels(0.0, 0.0, 1.0, 1.0), rot: 0.0 });
+        ModelPart {
+            name: name.into(),
+            data: ModelData::Cube {
+                cube_data: Sided { n: face(), s: face(), u: face(), d: face(), w: face(), e: face() },
+                f: [0.0; 3],
+                t: [1.0; 3],
+                inf: 0.0,
+            },
+            ..Default::default()
+        }
+    }
+
+    fn cube_tex(part: &ModelPart) -> usize {
+        let ModelData::Cube { cube_data, .. } = &part.data else { panic!("{} isn't a cube", part.name) };
+        cube_data.n.as_ref().unwrap().tex
+    }
+
+    #[test]
+    fn gc_textures_merges_data_and_remaps_faces() {
+        let mut moon = Moon::default();
+        moon.textures.src = HashMap::from([
+            ("a".into(), vec![1].into()),
+            ("b".into(), vec![1].into()),
+            ("c".into(), vec![2].into()),
+        ]);
+        // b is a copy of a, so the middle entry goes and c moves down to take its place
+        moon.textures.data = ["a", "b", "c"].map(|d| TextureData { d: d.into() }).into();
+        moon.models = Some(ModelPart {
+            chld: vec![textured_cube("a", 0), textured_cube("b", 1), textured_cube("c", 2)].into(),
+            ..Default::default()
+        });
+        assert_eq!(moon.gc_textures(), 1);
+        assert_eq!(moon.textures.data.iter().map(|d| d.d.as_str()).collect::<Vec<_>>(), ["a", "c"]);
+        let textures: Vec<_> = moon.models.as_ref().unwrap().chld.iter().map(cube_tex).collect();
+        assert_eq!(textures, [0, 0, 1]);
+    }
+
+    /// The texture coordinates Figura gives each corner of a face, starting at the top left and
+    /// going clockwise: the rectangle's corners, shifted along by one for each quarter turn.
+    fn corner_uvs(rect: UvRect, rotation: f64) -> [[f64; 2]; 4] {
+        let corners = [[rect.x0, rect.y0], [rect.x1, rect.y0], [rect.x1, rect.y1], [rect.x0, rect.y1]];
+        let quarters = ((rotation / 90.0).round() as i64).rem_euclid(4) as usize;
+        std::array::from_fn(|i| corners[(i + quarters) % 4])
+    }
+
+    #[test]
+    fn normalize_rotation_keeps_corners() {
+        let rect = UvRect::pixels(0.0, 0.0, 4.0, 8.0);
+        for rotation in [0.0, 90.0, 180.0, 270.0, 360.0, -90.0, 450.0, 89.0] {
+            let (normalized, rot) = rect.normalize_rotation(rotation);
+            assert!(rot == 0 || rot == 90, "{rotation} became {rot}");
+            assert_eq!(corner_uvs(normalized, rot.into()), corner_uvs(rect, rotation), "{rotation}");
+        }
+    }
+
+    #[test]
+    fn normalize_rotation_is_idempotent() {
+        let rect = UvRect::pixels(2.0, 6.0, 5.0, 1.0);
+        for rotation in [0.0, 90.0, 180.0, 270.0] {
+            let (once, rot) = rect.normalize_rotation(rotation);
+            assert_eq!(once.normalize_rotation(rot.into()), (once, rot));
+        }
+    }
+
+    /// Faces shaped like the ones in typical avatars (as Figura packs them, with the rotation
+    /// Blockbench saved), with the canonical form of each.
+    #[test]
+    fn normalize_rotation_matches_known_faces() {
+        let faces = [
+            // a signpost's front, unrotated
+            ([0.0, 0.0, 24.0, 12.0], 0.0, [0.0, 0.0, 24.0, 12.0], 0),
+            // the top of a hat brim, turned to face forward
+            ([16.0, 0.0, 24.0, 8.0], 90.0, [16.0, 0.0, 24.0, 8.0], 90),
+            // the bottom of a head, which Blockbench flips by turning it halfway
+            ([24.0, 8.0, 16.0, 0.0], 180.0, [16.0, 0.0, 24.0, 8.0], 0),
+            // the side of an ear, mirrored and turned back a quarter
+            ([8.0, 0.0, 0.0, 4.0], 270.0, [0.0, 4.0, 8.0, 0.0], 90),
+        ];
+        for (uv, rotation, expected, expected_rotation) in faces {
+            let face = Face { tex: 0, uv: uv.into(), rot: rotation };
+            assert_eq!(face.uv.normalize_rotation(face.rot), (expected.into(), expected_rotation), "{uv:?} at {rotation}");
+        }
+    }
+}
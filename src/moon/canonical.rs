@@ -0,0 +1,128 @@
+//! Byte-for-byte reproducible serialization. Moons hold several [HashMap][std::collections::HashMap]s,
+//! whose iteration order (and so the order quartz_nbt writes their keys in) changes from run to
+//! run, so serializing the same avatar twice usually gives different bytes. Writing compounds
+//! with their keys sorted fixes that, which makes caching, size comparisons, and reproducible
+//! builds possible. Lists, including [ModelPart::chld][super::ModelPart::chld], keep their order.
+
+use std::io::{self, Write};
+use flate2::Compression;
+use flate2::write::{GzEncoder, ZlibEncoder};
+use quartz_nbt::io::{Flavor, NbtIoError};
+use quartz_nbt::{NbtCompound, NbtTag};
+use super::Moon;
+
+fn tag_id(tag: &NbtTag) -> u8 {
+    match tag {
+        NbtTag::Byte(_) => 1,
+        NbtTag::Short(_) => 2,
+        NbtTag::Int(_) => 3,
+        NbtTag::Long(_) => 4,
+        NbtTag::Float(_) => 5,
+        NbtTag::Double(_) => 6,
+        NbtTag::ByteArray(_) => 7,
+        NbtTag::String(_) => 8,
+        NbtTag::List(_) => 9,
+        NbtTag::Compound(_) => 10,
+        NbtTag::IntArray(_) => 11,
+        NbtTag::LongArray(_) => 12,
+    }
+}
+
+/// Writes a string in Java's modified UTF-8, as NBT requires: NUL is written as two bytes, and
+/// characters outside the Basic Multilingual Plane as two three-byte surrogates. Strings that
+/// come to more than 65535 bytes can't be stored, and are an error.
+fn write_string(out: &mut impl Write, s: &str) -> io::Result<()> {
+    let mut bytes = Vec::with_capacity(s.len());
+    for unit in s.encode_utf16() {
+        match unit {
+            0x01..=0x7f => bytes.push(unit as u8),
+            0x00 | 0x80..=0x7ff => bytes.extend([0xc0 | (unit >> 6) as u8, 0x80 | (unit & 0x3f) as u8]),
+            _ => bytes.extend([0xe0 | (unit >> 12) as u8, 0x80 | (unit >> 6 & 0x3f) as u8, 0x80 | (unit & 0x3f) as u8]),
+        }
+    }
+    let len = u16::try_from(bytes.len()).map_err(|_| io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("a string of {} bytes is too long for NBT, which allows at most {}", bytes.len(), u16::MAX),
+    ))?;
+    out.write_all(&len.to_be_bytes())?;
+    out.write_all(&bytes)
+}
+
+fn write_payload(out: &mut impl Write, tag: &NbtTag) -> io::Result<()> {
+    match tag {
+        NbtTag::Byte(v) => out.write_all(&v.to_be_bytes()),
+        NbtTag::Short(v) => out.write_all(&v.to_be_bytes()),
+        NbtTag::Int(v) => out.write_all(&v.to_be_bytes()),
+        NbtTag::Long(v) => out.write_all(&v.to_be_bytes()),
+        NbtTag::Float(v) => out.write_all(&v.to_be_bytes()),
+        NbtTag::Double(v) => out.write_all(&v.to_be_bytes()),
+        NbtTag::ByteArray(v) => {
+            out.write_all(&(v.len() as i32).to_be_bytes())?;
+            out.write_all(&v.iter().map(|&b| b as u8).collect::<Vec<_>>())
+        }
+        NbtTag::String(v) => write_string(out, v),
+        NbtTag::List(v) => {
+            out.write_all(&[v.iter().next().map_or(0, tag_id)])?;
+            out.write_all(&(v.len() as i32).to_be_bytes())?;
+            v.iter().try_for_each(|tag| write_payload(out, tag))
+        }
+        NbtTag::Compound(v) => write_compound(out, v),
+        NbtTag::IntArray(v) => {
+            out.write_all(&(v.len() as i32).to_be_bytes())?;
+            v.iter().try_for_each(|n| out.write_all(&n.to_be_bytes()))
+        }
+        NbtTag::LongArray(v) => {
+            out.write_all(&(v.len() as i32).to_be_bytes())?;
+            v.iter().try_for_each(|n| out.write_all(&n.to_be_bytes()))
+        }
+    }
+}
+
+fn write_compound(out: &mut impl Write, compound: &NbtCompound) -> io::Result<()> {
+    let mut entries: Vec<_> = compound.inner().iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    for (key, tag) in entries {
+        out.write_all(&[tag_id(tag)])?;
+        write_string(out, key)?;
+        write_payload(out, tag)?;
+    }
+    out.write_all(&[0])
+}
+
+/// Writes an NBT file with every compound's keys in sorted order.
+pub fn write_nbt(out: &mut impl Write, root_name: &str, root: &NbtCompound, flavor: Flavor) -> io::Result<()> {
+    fn raw(out: &mut impl Write, root_name: &str, root: &NbtCompound) -> io::Result<()> {
+        out.write_all(&[10])?;
+        write_string(out, root_name)?;
+        write_compound(out, root)
+    }
+    match flavor {
+        Flavor::Uncompressed => raw(out, root_name, root),
+        Flavor::ZlibCompressed | Flavor::ZlibCompressedWith(_) => {
+            let level = if let Flavor::ZlibCompressedWith(level) = flavor { level } else { Compression::default() };
+            let mut encoder = ZlibEncoder::new(out, level);
+            raw(&mut encoder, root_name, root)?;
+            encoder.finish().map(drop)
+        }
+        Flavor::GzCompressed | Flavor::GzCompressedWith(_) => {
+            let level = if let Flavor::GzCompressedWith(level) = flavor { level } else { Compression::default() };
+            let mut encoder = GzEncoder::new(out, level);
+            raw(&mut encoder, root_name, root)?;
+            encoder.finish().map(drop)
+        }
+    }
+}
+
+impl Moon {
+    /// Serializes this avatar [canonically][self]: the same avatar always gives the same bytes.
+    pub fn write_canonical(&self, out: &mut impl Write, root_name: &str, flavor: Flavor) -> Result<(), NbtIoError> {
+        Ok(write_nbt(out, root_name, &self.to_nbt()?, flavor)?)
+    }
+
+    /// Like [write_canonical][Self::write_canonical], but returns the bytes.
+    pub fn to_canonical_bytes(&self, root_name: &str, flavor: Flavor) -> Result<Vec<u8>, NbtIoError> {
+        let mut out = vec![];
+        self.write_canonical(&mut out, root_name, flavor)?;
+        Ok(out)
+    }
+}
@@ -0,0 +1,245 @@
+//! Upgrades for moons written by older Figura versions, whose tags don't quite match the structs
+//! in [super]. [Moon::read][super::Moon::read] runs [upgrade] on the raw NBT before deserializing,
+//! so callers never see the old layouts.
+//!
+//! Early exports don't reliably record which Figura version wrote them, so each upgrade recognizes
+//! an old layout by its shape instead, and leaves current layouts alone.
+//!
+//! Tags that no layout fia knows has, such as ones a newer Figura version added, are ignored
+//! rather than refusing the moon. [Moon::read_auto_upgraded][super::Moon::read_auto_upgraded]
+//! lists them, along with the upgrades applied, so they can be reported.
+
+use std::collections::HashMap;
+use quartz_nbt::{NbtCompound, NbtTag};
+
+/// What had to be done to read a moon, from
+/// [Moon::read_auto_upgraded][super::Moon::read_auto_upgraded].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Upgrades {
+    /// A short description of each [upgrade] that changed something.
+    pub applied: Vec<&'static str>,
+    /// The [unknown tags][unknown_tags], which writing the moon again would drop.
+    pub unknown: Vec<String>,
+}
+
+/// Rewrites any old layouts in a moon's root compound into the current one, returning a short
+/// description of each upgrade that changed something.
+pub fn upgrade(root: &mut NbtCompound) -> Vec<&'static str> {
+    let mut applied = vec![];
+    let upgrades: [(&'static str, fn(&mut NbtCompound) -> bool); 3] = [
+        ("renamed metadata.version to ver", metadata_version),
+        ("wrapped bare texture names in textures.data", texture_data_strings),
+        ("re-keyed part animations by index", part_anim_keys),
+    ];
+    for (description, upgrade) in upgrades {
+        if upgrade(root) {
+            applied.push(description);
+        }
+    }
+    applied
+}
+
+/// Older metadata spelled `ver` out in full.
+fn metadata_version(root: &mut NbtCompound) -> bool {
+    let Some(NbtTag::Compound(metadata)) = root.inner_mut().get_mut("metadata") else { return false };
+    let metadata = metadata.inner_mut();
+    if metadata.contains_key("ver") {
+        return false;
+    }
+    let Some(version) = metadata.remove("version") else { return false };
+    metadata.insert("ver".into(), version);
+    true
+}
+
+/// Older texture lists held each texture's name directly instead of a compound of names.
+fn texture_data_strings(root: &mut NbtCompound) -> bool {
+    let Some(NbtTag::Compound(textures)) = root.inner_mut().get_mut("textures") else { return false };
+    let Some(NbtTag::List(data)) = textures.inner_mut().get_mut("data") else { return false };
+    let mut changed = false;
+    for tag in data.inner_mut() {
+        if let NbtTag::String(name) = tag {
+            let mut compound = NbtCompound::new();
+            compound.insert("d", std::mem::take(name));
+            *tag = NbtTag::Compound(compound);
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// Older parts stored their keyframes as a list with one entry per animation, or keyed by the
+/// animation's name; both become a compound keyed by the animation's index.
+fn part_anim_keys(root: &mut NbtCompound) -> bool {
+    let mut indices: HashMap<(String, String), usize> = HashMap::new();
+    if let Some(NbtTag::List(animations)) = root.inner().get("animations") {
+        for (i, animation) in animations.iter().enumerate() {
+            let NbtTag::Compound(animation) = animation else { continue };
+            let field = |key: &str| match animation.inner().get(key) {
+                Some(NbtTag::String(s)) => s.clone(),
+                _ => String::new(),
+            };
+            indices.entry((field("mdl"), field("name"))).or_insert(i);
+        }
+    }
+    fn visit(part: &mut NbtCompound, model: &str, indices: &HashMap<(String, String), usize>) -> bool {
+        let mut changed = false;
+        let part = part.inner_mut();
+        match part.get_mut("anim") {
+            Some(NbtTag::List(list)) => {
+                let mut anim = NbtCompound::new();
+                for (i, channels) in std::mem::take(list).into_iter().enumerate() {
+                    if matches!(&channels, NbtTag::Compound(c) if !c.is_empty()) {
+                        anim.insert(i.to_string(), channels);
+                    }
+                }
+                part.insert("anim".into(), NbtTag::Compound(anim));
+                changed = true;
+            }
+            Some(NbtTag::Compound(anim)) => {
+                let named: Vec<String> = anim.inner().keys().filter(|k| k.parse::<usize>().is_err()).cloned().collect();
+                for name in named {
+                    if let Some(index) = indices.get(&(model.into(), name.clone())) {
+                        let channels = anim.inner_mut().remove(&name).unwrap();
+                        anim.inner_mut().insert(index.to_string(), channels);
+                        changed = true;
+                    }
+                }
+            }
+            _ => {}
+        }
+        if let Some(NbtTag::List(children)) = part.get_mut("chld") {
+            for child in children.inner_mut() {
+                if let NbtTag::Compound(child) = child {
+                    changed |= visit(child, model, indices);
+                }
+            }
+        }
+        changed
+    }
+    let Some(NbtTag::Compound(models)) = root.inner_mut().get_mut("models") else { return false };
+    let Some(NbtTag::List(children)) = models.inner_mut().get_mut("chld") else { return false };
+    let mut changed = false;
+    for child in children.inner_mut() {
+        let NbtTag::Compound(child) = child else { continue };
+        let model = match child.inner().get("name") {
+            Some(NbtTag::String(name)) => name.clone(),
+            _ => String::new(),
+        };
+        changed |= visit(child, &model, &indices);
+    }
+    changed
+}
+
+/// Lists the tags in `original` that are missing from `read`, the same moon after being read and
+/// written again, as dotted paths such as `models.chld.0.foo`. Tags whose value is empty or zero
+/// are left out, since fia doesn't write those back even when it knows them.
+pub fn unknown_tags(original: &NbtCompound, read: &NbtCompound) -> Vec<String> {
+    fn is_default(tag: &NbtTag) -> bool {
+        match tag {
+            NbtTag::Compound(c) => c.is_empty(),
+            NbtTag::List(l) => l.is_empty(),
+            NbtTag::String(s) => s.is_empty(),
+            NbtTag::ByteArray(a) => a.is_empty(),
+            NbtTag::IntArray(a) => a.is_empty(),
+            NbtTag::LongArray(a) => a.is_empty(),
+            NbtTag::Byte(n) => *n == 0,
+            NbtTag::Short(n) => *n == 0,
+            NbtTag::Int(n) => *n == 0,
+            NbtTag::Long(n) => *n == 0,
+            NbtTag::Float(n) => *n == 0.0,
+            NbtTag::Double(n) => *n == 0.0,
+        }
+    }
+    fn visit(original: &NbtCompound, read: &NbtCompound, path: &str, out: &mut Vec<String>) {
+        for (key, tag) in original.inner() {
+            let path = if path.is_empty() { key.clone() } else { format!("{path}.{key}") };
+            match (tag, read.inner().get(key)) {
+                (tag, None) if !is_default(tag) => out.push(path),
+                (NbtTag::Compound(tag), Some(NbtTag::Compound(read))) => visit(tag, read, &path, out),
+                (NbtTag::List(tags), Some(NbtTag::List(read))) => for (i, (tag, read)) in tags.iter().zip(read.iter()).enumerate() {
+                    if let (NbtTag::Compound(tag), NbtTag::Compound(read)) = (tag, read) {
+                        visit(tag, read, &format!("{path}.{i}"), out);
+                    }
+                },
+                _ => {}
+            }
+        }
+    }
+    let mut out = vec![];
+    visit(original, read, "", &mut out);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use quartz_nbt::io::Flavor;
+    use crate::Moon;
+    use super::*;
+
+    /// Reads a moon from `tests/fixtures/compat`. The fixtures are written by hand in the layouts
+    /// the upgrades recognize, rather than exported from the versions that wrote them.
+    fn read_fixture(name: &str) -> (Moon, Upgrades) {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/compat").join(format!("{name}.snbt"));
+        let root = quartz_nbt::snbt::parse(&std::fs::read_to_string(path).unwrap()).unwrap();
+        let mut data = vec![];
+        quartz_nbt::io::write_nbt(&mut data, None, &root, Flavor::Uncompressed).unwrap();
+        let (moon, _, upgrades) = Moon::read_auto_upgraded(&*data).unwrap();
+        (moon, upgrades)
+    }
+
+    fn head(moon: &Moon) -> &crate::moon::ModelPart {
+        moon.models.as_ref().unwrap().find("model/Head").unwrap()
+    }
+
+    #[test]
+    fn renames_metadata_version() {
+        // quartz_nbt reads quoted SNBT strings that start with a digit as numbers, so this one is
+        // built here instead of being a fixture
+        let mut metadata = NbtCompound::new();
+        metadata.insert("version", "0.1.0");
+        let mut root = NbtCompound::new();
+        root.insert("metadata", metadata);
+        assert_eq!(upgrade(&mut root), ["renamed metadata.version to ver"]);
+        let moon = Moon::from_nbt(root).unwrap();
+        assert_eq!(moon.metadata.ver, "0.1.0");
+    }
+
+    #[test]
+    fn wraps_texture_names() {
+        let (moon, upgrades) = read_fixture("texture-names");
+        assert_eq!(upgrades, Upgrades { applied: vec!["wrapped bare texture names in textures.data"], unknown: vec![] });
+        assert_eq!(moon.textures.data[0].d, "skin");
+    }
+
+    #[test]
+    fn rekeys_animation_lists() {
+        let (moon, upgrades) = read_fixture("anim-list");
+        assert_eq!(upgrades, Upgrades { applied: vec!["re-keyed part animations by index"], unknown: vec![] });
+        assert_eq!(head(&moon).anim.keys().collect::<Vec<_>>(), [&1]);
+    }
+
+    #[test]
+    fn rekeys_animation_names() {
+        let (moon, upgrades) = read_fixture("anim-names");
+        assert_eq!(upgrades, Upgrades { applied: vec!["re-keyed part animations by index"], unknown: vec![] });
+        assert_eq!(head(&moon).anim.keys().collect::<Vec<_>>(), [&1]);
+    }
+
+    #[test]
+    fn reports_unknown_tags() {
+        let (moon, upgrades) = read_fixture("unknown-tags");
+        let mut unknown = upgrades.unknown;
+        unknown.sort();
+        assert_eq!(unknown, ["metadata.badges", "models.chld.0.glint"]);
+        assert_eq!(moon.metadata.name, "Future");
+    }
+
+    #[test]
+    fn leaves_current_moons_alone() {
+        let (moon, _) = read_fixture("texture-names");
+        let mut root = moon.to_nbt().unwrap();
+        assert!(upgrade(&mut root).is_empty());
+        assert!(unknown_tags(&root, &moon.to_nbt().unwrap()).is_empty());
+    }
+}
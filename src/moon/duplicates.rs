@@ -0,0 +1,131 @@
+//! Detection of duplicate keys in NBT compounds. Nothing stops hand-crafted NBT from naming two
+//! scripts or textures the same, and since quartz_nbt reads compounds into maps, all but the last
+//! of each would silently disappear. [Moon::read_checked] looks for them first.
+
+use std::collections::HashSet;
+use std::io::Read;
+use flate2::read::{GzDecoder, ZlibDecoder};
+use quartz_nbt::io::{Flavor, NbtIoError};
+use thiserror::Error;
+use super::Moon;
+
+/// What [Moon::read_checked] does when it finds duplicate keys.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Strictness {
+    /// Fail with [LoadError::DuplicateKeys].
+    Strict,
+    /// Keep the last value for each key, as [Moon::read] does, and return the duplicates
+    /// alongside the moon.
+    Lenient,
+}
+
+/// A key that appears more than once in the same compound, of which only the last value is kept.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DuplicateKey {
+    /// The key's path, such as `scripts.main`.
+    pub path: String,
+}
+
+/// An error encountered while loading a moon with [Moon::read_checked].
+#[derive(Debug, Error)]
+pub enum LoadError {
+    /// The data isn't a valid moon.
+    #[error("{0}")]
+    Nbt(#[from] NbtIoError),
+    /// Some compounds have the same key more than once; these are their paths.
+    #[error("duplicate keys: {}", .0.join(", "))]
+    DuplicateKeys(Vec<String>),
+}
+
+struct Scanner<'a> {
+    data: &'a [u8],
+    pos: usize,
+    duplicates: Vec<String>,
+}
+
+impl<'a> Scanner<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.pos..self.pos.checked_add(n)?)?;
+        self.pos += n;
+        Some(bytes)
+    }
+
+    fn int(&mut self) -> Option<usize> {
+        let n = i32::from_be_bytes(self.take(4)?.try_into().unwrap());
+        usize::try_from(n).ok()
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let len = u16::from_be_bytes(self.take(2)?.try_into().unwrap());
+        Some(String::from_utf8_lossy(self.take(len.into())?).into_owned())
+    }
+
+    fn payload(&mut self, id: u8, path: &str) -> Option<()> {
+        match id {
+            1 => drop(self.take(1)?),
+            2 => drop(self.take(2)?),
+            3 | 5 => drop(self.take(4)?),
+            4 | 6 => drop(self.take(8)?),
+            7 => drop({ let n = self.int()?; self.take(n)? }),
+            8 => drop(self.string()?),
+            9 => {
+                let id = self.take(1)?[0];
+                for i in 0..self.int()? {
+                    self.payload(id, &format!("{path}[{i}]"))?;
+                }
+            }
+            10 => self.compound(path)?,
+            11 => drop({ let n = self.int()?; self.take(n.checked_mul(4)?)? }),
+            12 => drop({ let n = self.int()?; self.take(n.checked_mul(8)?)? }),
+            _ => return None,
+        }
+        Some(())
+    }
+
+    fn compound(&mut self, path: &str) -> Option<()> {
+        let mut seen = HashSet::new();
+        loop {
+            let id = self.take(1)?[0];
+            if id == 0 {
+                return Some(());
+            }
+            let key = self.string()?;
+            let path = if path.is_empty() { key.clone() } else { format!("{path}.{key}") };
+            if !seen.insert(key) {
+                self.duplicates.push(path.clone());
+            }
+            self.payload(id, &path)?;
+        }
+    }
+}
+
+/// Lists the paths (like `scripts.main`) of keys that appear more than once in the same compound
+/// in uncompressed NBT data. Scanning stops quietly at malformed data, which reading the moon
+/// will report anyway.
+pub fn find_duplicate_keys(data: &[u8]) -> Vec<String> {
+    let mut scanner = Scanner { data, pos: 0, duplicates: vec![] };
+    if scanner.take(1) == Some(&[10]) && scanner.string().is_some() {
+        scanner.compound("");
+    }
+    scanner.duplicates
+}
+
+impl Moon {
+    /// Reads a moon like [Moon::read], first checking for keys that appear more than once in the
+    /// same compound, such as two scripts with the same name. Depending on `strictness`, these
+    /// are either an error or returned alongside the moon.
+    pub fn read_checked(mut reader: impl Read, flavor: Flavor, strictness: Strictness) -> Result<(Moon, String, Vec<DuplicateKey>), LoadError> {
+        let mut data = vec![];
+        match flavor {
+            Flavor::Uncompressed => reader.read_to_end(&mut data),
+            Flavor::ZlibCompressed | Flavor::ZlibCompressedWith(_) => ZlibDecoder::new(reader).read_to_end(&mut data),
+            Flavor::GzCompressed | Flavor::GzCompressedWith(_) => GzDecoder::new(reader).read_to_end(&mut data),
+        }.map_err(NbtIoError::from)?;
+        let duplicates = find_duplicate_keys(&data);
+        if strictness == Strictness::Strict && !duplicates.is_empty() {
+            return Err(LoadError::DuplicateKeys(duplicates));
+        }
+        let (moon, name) = Moon::read(&*data, Flavor::Uncompressed)?;
+        Ok((moon, name, duplicates.into_iter().map(|path| DuplicateKey { path }).collect()))
+    }
+}
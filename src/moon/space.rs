@@ -0,0 +1,139 @@
+//! The coordinate spaces that model data passes through, and conversions between them. Getting
+//! these wrong produces exports that are subtly offset or mirrored, so every converter should go
+//! through here rather than adjusting coordinates itself.
+//!
+//! # Blockbench space
+//! Positions are in pixels (sixteenths of a block), with +Y up, +Z south and +X west. A cube's
+//! `from`/`to` and a group's or element's `origin` (pivot) are absolute — not relative to the
+//! parent group. Rotations are Euler angles in degrees around the pivot; the X rotation is applied
+//! first and the Z rotation last, so the rotation matrix is `Rz · Ry · Rx`.
+//!
+//! # Moon space
+//! Figura copies positions and rotations out of the `.bbmodel` unchanged: [f][super::ModelData::Cube::f],
+//! [t][super::ModelData::Cube::t], [piv][super::ModelPart::piv], and [rot][super::ModelPart::rot]
+//! are all in Blockbench space. (Figura leaves out `piv` and `rot` when they are zero, which is why
+//! they default to zero here.) The only conversions happen at render time, which is also when the
+//! model's scale of one unit per pixel comes into play. So [moon_from_blockbench] and
+//! [blockbench_from_moon] are the identity; they exist to document that fact at each call site.
+//!
+//! # Entity model space
+//! Minecraft's own entity models (and formats derived from them, such as Java entity code)
+//! measure pivots from the top of the model with +Y down and +X east, and rotate in radians with
+//! the X and Y axes flipped. [entity_pivot] and [entity_rotation] convert into this space.
+
+/// A point or vector in some coordinate space.
+pub type Vec3 = [f64; 3];
+
+/// How many model units (pixels) make up one block.
+pub const PIXELS_PER_BLOCK: f64 = 16.0;
+
+/// The height, in pixels, of the origin of entity model space above Blockbench's origin.
+pub const ENTITY_ORIGIN_HEIGHT: f64 = 24.0;
+
+/// Converts a Blockbench position, pivot, or rotation into moon space.
+pub fn moon_from_blockbench(v: Vec3) -> Vec3 {
+    v
+}
+
+/// Converts a moon position, pivot, or rotation into Blockbench space.
+pub fn blockbench_from_moon(v: Vec3) -> Vec3 {
+    v
+}
+
+/// Converts a length or position in pixels to blocks.
+pub fn to_blocks(v: Vec3) -> Vec3 {
+    v.map(|c| c / PIXELS_PER_BLOCK)
+}
+
+/// Converts a length or position in blocks to pixels.
+pub fn from_blocks(v: Vec3) -> Vec3 {
+    v.map(|c| c * PIXELS_PER_BLOCK)
+}
+
+/// Converts a Blockbench or moon pivot into entity model space.
+pub fn entity_pivot([x, y, z]: Vec3) -> Vec3 {
+    [-x, ENTITY_ORIGIN_HEIGHT - y, z]
+}
+
+/// Converts a Blockbench or moon rotation (in degrees) into entity model space (in radians).
+pub fn entity_rotation([x, y, z]: Vec3) -> Vec3 {
+    [-x.to_radians(), -y.to_radians(), z.to_radians()]
+}
+
+/// A 3×3 matrix, as rows.
+pub type Mat3 = [[f64; 3]; 3];
+
+fn mul(a: &Mat3, b: &Mat3) -> Mat3 {
+    std::array::from_fn(|i| std::array::from_fn(|j| (0..3).map(|k| a[i][k] * b[k][j]).sum()))
+}
+
+fn transpose(m: &Mat3) -> Mat3 {
+    std::array::from_fn(|i| std::array::from_fn(|j| m[j][i]))
+}
+
+fn apply(m: &Mat3, v: Vec3) -> Vec3 {
+    std::array::from_fn(|i| (0..3).map(|k| m[i][k] * v[k]).sum())
+}
+
+/// The matrix for a rotation given as Euler angles in degrees.
+pub fn rotation_matrix(rot: Vec3) -> Mat3 {
+    let [(sx, cx), (sy, cy), (sz, cz)] = rot.map(|a| a.to_radians().sin_cos());
+    let x = [[1.0, 0.0, 0.0], [0.0, cx, -sx], [0.0, sx, cx]];
+    let y = [[cy, 0.0, sy], [0.0, 1.0, 0.0], [-sy, 0.0, cy]];
+    let z = [[cz, -sz, 0.0], [sz, cz, 0.0], [0.0, 0.0, 1.0]];
+    mul(&z, &mul(&y, &x))
+}
+
+/// The Euler angles in degrees of a rotation matrix; the inverse of [rotation_matrix]. At gimbal
+/// lock (a Y rotation of ±90°), the Z rotation is taken to be zero.
+pub fn euler_angles(m: &Mat3) -> Vec3 {
+    let y = (-m[2][0]).clamp(-1.0, 1.0).asin();
+    let [x, z] = if m[2][0].abs() < 1.0 - 1e-9 {
+        [m[2][1].atan2(m[2][2]), m[1][0].atan2(m[0][0])]
+    } else {
+        [(-m[1][2]).atan2(m[1][1]), 0.0]
+    };
+    [x, y, z].map(f64::to_degrees)
+}
+
+/// A rotation followed by a translation, such as a part's rotation around its pivot.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    /// The rotation, applied first.
+    pub rotation: Mat3,
+    /// The translation, applied second.
+    pub offset: Vec3,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self { rotation: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]], offset: [0.0; 3] }
+    }
+}
+
+impl Transform {
+    /// The transform of a part with the given rotation and pivot: a rotation around the pivot.
+    pub fn around(rot: Vec3, piv: Vec3) -> Self {
+        let rotation = rotation_matrix(rot);
+        let turned = apply(&rotation, piv);
+        Self { rotation, offset: std::array::from_fn(|i| piv[i] - turned[i]) }
+    }
+
+    /// Transforms a point.
+    pub fn apply(&self, v: Vec3) -> Vec3 {
+        let turned = apply(&self.rotation, v);
+        std::array::from_fn(|i| turned[i] + self.offset[i])
+    }
+
+    /// The transform that applies `inner` and then this one; this is how a parent's transform
+    /// combines with its child's.
+    pub fn then_inner(&self, inner: &Transform) -> Self {
+        Self { rotation: mul(&self.rotation, &inner.rotation), offset: self.apply(inner.offset) }
+    }
+
+    /// The transform that undoes this one.
+    pub fn inverse(&self) -> Self {
+        let rotation = transpose(&self.rotation);
+        Self { rotation, offset: apply(&rotation, self.offset).map(|c| -c) }
+    }
+}
@@ -0,0 +1,183 @@
+//! This module implements a small container format (`.moons`) for distributing several moons as a
+//! single file, such as an NPC pack for a server. A collection is laid out as:
+//!
+//! * The magic bytes `b"MOONS"`, followed by a format version byte (currently `1`).
+//! * A big-endian `u32` entry count.
+//! * The index: for each entry, a big-endian `u16` name length, the UTF-8 name, and big-endian
+//!   `u64` offset and length of the entry's data (relative to the end of the index).
+//! * The entries' data, which are moons exactly as they would be stored on disk (usually
+//!   gzip-compressed NBT).
+//!
+//! Entries are kept as raw bytes, so extracting a moon from a collection yields the exact file that
+//! was put in, and listing a collection doesn't need to decode any NBT.
+
+use std::io::{self, Read, Write};
+use quartz_nbt::io::{Flavor, NbtIoError};
+use thiserror::Error;
+use crate::Moon;
+
+/// The magic bytes at the start of every collection.
+pub const MAGIC: &[u8; 5] = b"MOONS";
+/// The version of the collection format written by this module.
+pub const VERSION: u8 = 1;
+
+/// An error encountered while reading or writing a collection.
+#[derive(Debug, Error)]
+pub enum CollectionError {
+    /// The underlying reader or writer failed.
+    #[error("{0}")]
+    Io(#[from] io::Error),
+    /// An entry could not be encoded or decoded as a moon.
+    #[error("{0}")]
+    Nbt(#[from] NbtIoError),
+    /// The file did not start with [MAGIC].
+    #[error("not a moon collection")]
+    BadMagic,
+    /// The file was written with a newer version of the format.
+    #[error("unsupported collection version {0}")]
+    UnsupportedVersion(u8),
+    /// An index entry's name was not valid UTF-8.
+    #[error("entry name is not valid UTF-8")]
+    BadName,
+    /// An index entry pointed outside of the data section.
+    #[error("entry {0} is out of bounds")]
+    OutOfBounds(String),
+    /// An entry name was longer than the index can store.
+    #[error("entry name {0} is too long")]
+    NameTooLong(String),
+}
+
+/// One moon in a [Collection].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// The entry's name, usually the moon's file name without an extension.
+    pub name: String,
+    /// The moon's data, as it would be stored in a standalone file.
+    pub data: Vec<u8>,
+}
+
+impl Entry {
+    /// Decodes this entry's data into a [Moon].
+    pub fn moon(&self) -> Result<Moon, NbtIoError> {
+        Moon::read_auto(&*self.data).map(|d| d.0)
+    }
+}
+
+/// An ordered list of named moons, stored as a `.moons` file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Collection {
+    /// The entries in this collection, in the order they are stored.
+    pub entries: Vec<Entry>,
+}
+
+impl Collection {
+    /// Checks whether the given bytes look like the start of a collection.
+    pub fn sniff(head: &[u8]) -> bool {
+        head.starts_with(MAGIC)
+    }
+
+    /// Reads an entire collection from a reader.
+    pub fn read(mut r: impl Read) -> Result<Self, CollectionError> {
+        let mut magic = [0; 5];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(CollectionError::BadMagic);
+        }
+        let version = read_u8(&mut r)?;
+        if version > VERSION {
+            return Err(CollectionError::UnsupportedVersion(version));
+        }
+        let count = read_u32(&mut r)?;
+        let mut index = Vec::with_capacity(count.min(1024) as usize);
+        for _ in 0..count {
+            let len = read_u16(&mut r)?;
+            let mut name = vec![0; len.into()];
+            r.read_exact(&mut name)?;
+            let name = String::from_utf8(name).map_err(|_| CollectionError::BadName)?;
+            let offset = read_u64(&mut r)?;
+            let len = read_u64(&mut r)?;
+            index.push((name, offset, len));
+        }
+        let mut data = vec![];
+        r.read_to_end(&mut data)?;
+        let mut entries = Vec::with_capacity(index.len());
+        for (name, offset, len) in index {
+            let Some(end) = offset.checked_add(len).filter(|&end| end <= data.len() as u64) else {
+                return Err(CollectionError::OutOfBounds(name));
+            };
+            let data = data[offset as usize..end as usize].to_vec();
+            entries.push(Entry { name, data });
+        }
+        Ok(Self { entries })
+    }
+
+    /// Writes this collection to a writer.
+    pub fn write(&self, mut w: impl Write) -> Result<(), CollectionError> {
+        w.write_all(MAGIC)?;
+        w.write_all(&[VERSION])?;
+        w.write_all(&(self.entries.len() as u32).to_be_bytes())?;
+        let mut offset = 0u64;
+        for Entry { name, data } in &self.entries {
+            let Ok(len) = u16::try_from(name.len()) else {
+                return Err(CollectionError::NameTooLong(name.clone()));
+            };
+            w.write_all(&len.to_be_bytes())?;
+            w.write_all(name.as_bytes())?;
+            w.write_all(&offset.to_be_bytes())?;
+            w.write_all(&(data.len() as u64).to_be_bytes())?;
+            offset += data.len() as u64;
+        }
+        for Entry { data, .. } in &self.entries {
+            w.write_all(data)?;
+        }
+        Ok(())
+    }
+
+    /// Finds an entry by name.
+    pub fn get(&self, name: &str) -> Option<&Entry> {
+        self.entries.iter().find(|e| e.name == name)
+    }
+
+    /// Adds raw moon data to the collection, replacing any entry with the same name.
+    pub fn insert(&mut self, name: String, data: Vec<u8>) {
+        if let Some(entry) = self.entries.iter_mut().find(|e| e.name == name) {
+            entry.data = data;
+        } else {
+            self.entries.push(Entry { name, data });
+        }
+    }
+
+    /// Serializes a moon and adds it to the collection, replacing any entry with the same name.
+    pub fn insert_moon(&mut self, name: String, moon: &Moon) -> Result<(), NbtIoError> {
+        let data = moon.to_canonical_bytes("", Flavor::GzCompressed)?;
+        self.insert(name, data);
+        Ok(())
+    }
+
+    /// Removes an entry by name, returning it if it existed.
+    pub fn remove(&mut self, name: &str) -> Option<Entry> {
+        let i = self.entries.iter().position(|e| e.name == name)?;
+        Some(self.entries.remove(i))
+    }
+}
+
+fn read_u8(r: &mut impl Read) -> io::Result<u8> {
+    let mut buf = [0; 1];
+    r.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+fn read_u16(r: &mut impl Read) -> io::Result<u16> {
+    let mut buf = [0; 2];
+    r.read_exact(&mut buf)?;
+    Ok(u16::from_be_bytes(buf))
+}
+fn read_u32(r: &mut impl Read) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
+fn read_u64(r: &mut impl Read) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_be_bytes(buf))
+}
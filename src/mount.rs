@@ -0,0 +1,149 @@
+//! A read-only FUSE filesystem exposing the [files][Moon::files] inside a moon, so they can be
+//! browsed with normal tools without extracting anything. Directories are synthesized from the
+//! file paths, and an `avatar.json` is generated from the moon's metadata.
+
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use fuser::{FileAttr, FileType, Filesystem, MountOption, ReplyAttr, ReplyData, ReplyDirectory, ReplyEntry, Request};
+use crate::moon::UnsafePath;
+use crate::Moon;
+
+const TTL: Duration = Duration::from_secs(60);
+const ROOT: u64 = 1;
+
+enum Node {
+    Dir { parent: u64, children: BTreeMap<String, u64> },
+    File { data: Vec<u8> },
+}
+
+/// A moon's files, laid out as a directory tree of inodes.
+pub struct MoonFs {
+    /// Indexed by inode number minus one.
+    nodes: Vec<Node>,
+    time: SystemTime,
+}
+
+impl MoonFs {
+    /// Builds the directory tree for a moon, failing if any of its files has an unsafe name.
+    pub fn new(moon: &Moon) -> Result<Self, UnsafePath> {
+        let mut fs = Self {
+            nodes: vec![Node::Dir { parent: ROOT, children: BTreeMap::new() }],
+            time: SystemTime::now(),
+        };
+        let mut files: Vec<(String, Vec<u8>)> = moon.files()?.into_iter().map(|(p, d)| (p, d.to_vec())).collect();
+        if let Ok(json) = serde_json::to_vec_pretty(&moon.metadata) {
+            files.push(("avatar.json".into(), json));
+        }
+        for (path, data) in files {
+            let mut dir = ROOT;
+            let mut parts: Vec<&str> = path.split('/').filter(|p| !p.is_empty()).collect();
+            let Some(name) = parts.pop() else { continue };
+            for part in parts {
+                dir = match fs.child(dir, part) {
+                    Some(ino) => ino,
+                    None => fs.insert(dir, part, Node::Dir { parent: dir, children: BTreeMap::new() }),
+                };
+            }
+            fs.insert(dir, name, Node::File { data });
+        }
+        Ok(fs)
+    }
+
+    fn child(&self, dir: u64, name: &str) -> Option<u64> {
+        match self.nodes.get(dir as usize - 1)? {
+            Node::Dir { children, .. } => children.get(name).copied(),
+            Node::File { .. } => None,
+        }
+    }
+
+    fn insert(&mut self, dir: u64, name: &str, node: Node) -> u64 {
+        self.nodes.push(node);
+        let ino = self.nodes.len() as u64;
+        if let Node::Dir { children, .. } = &mut self.nodes[dir as usize - 1] {
+            children.insert(name.into(), ino);
+        }
+        ino
+    }
+
+    fn attr(&self, ino: u64) -> Option<FileAttr> {
+        let (kind, size, perm, nlink) = match self.nodes.get((ino as usize).checked_sub(1)?)? {
+            Node::Dir { .. } => (FileType::Directory, 0, 0o555, 2),
+            Node::File { data } => (FileType::RegularFile, data.len() as u64, 0o444, 1),
+        };
+        Some(FileAttr {
+            ino,
+            size,
+            blocks: size.div_ceil(512),
+            atime: self.time,
+            mtime: self.time,
+            ctime: self.time,
+            crtime: self.time,
+            kind,
+            perm,
+            nlink,
+            uid: unsafe { libc::getuid() },
+            gid: unsafe { libc::getgid() },
+            rdev: 0,
+            blksize: 512,
+            flags: 0,
+        })
+    }
+}
+
+impl Filesystem for MoonFs {
+    fn lookup(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        match name.to_str().and_then(|name| self.child(parent, name)).and_then(|ino| self.attr(ino)) {
+            Some(attr) => reply.entry(&TTL, &attr, 0),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+
+    fn getattr(&mut self, _req: &Request, ino: u64, reply: ReplyAttr) {
+        match self.attr(ino) {
+            Some(attr) => reply.attr(&TTL, &attr),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+
+    fn read(&mut self, _req: &Request, ino: u64, _fh: u64, offset: i64, size: u32, _flags: i32, _lock: Option<u64>, reply: ReplyData) {
+        match self.nodes.get((ino as usize).wrapping_sub(1)) {
+            Some(Node::File { data }) => {
+                let start = (offset as usize).min(data.len());
+                let end = start.saturating_add(size as usize).min(data.len());
+                reply.data(&data[start..end]);
+            }
+            Some(Node::Dir { .. }) => reply.error(libc::EISDIR),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+
+    fn readdir(&mut self, _req: &Request, ino: u64, _fh: u64, offset: i64, mut reply: ReplyDirectory) {
+        let Some(Node::Dir { parent, children }) = self.nodes.get((ino as usize).wrapping_sub(1)) else {
+            return reply.error(libc::ENOTDIR);
+        };
+        let entries = [(ino, FileType::Directory, "."), (*parent, FileType::Directory, "..")].into_iter()
+            .chain(children.iter().map(|(name, &child)| {
+                let kind = match self.nodes[child as usize - 1] {
+                    Node::Dir { .. } => FileType::Directory,
+                    Node::File { .. } => FileType::RegularFile,
+                };
+                (child, kind, &**name)
+            }));
+        for (i, (ino, kind, name)) in entries.enumerate().skip(offset as usize) {
+            if reply.add(ino, i as i64 + 1, kind, name) {
+                break;
+            }
+        }
+        reply.ok();
+    }
+}
+
+/// Mounts a moon read-only at the given directory, blocking until it is unmounted.
+pub fn mount(moon: &Moon, dir: &Path) -> io::Result<()> {
+    let options = [MountOption::RO, MountOption::FSName("fia".into()), MountOption::DefaultPermissions];
+    let fs = MoonFs::new(moon).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    fuser::mount2(fs, dir, &options)
+}
@@ -0,0 +1,382 @@
+//! Packing an avatar folder into a [Moon], the way Figura does when an avatar is equipped from the
+//! wardrobe:
+//!
+//! * `avatar.json` becomes the [metadata][Moon::metadata], and its `resources` patterns pick the
+//!   [resources][Moon::resources]. If it doesn't give a `version`, the metadata's
+//!   [ver][Metadata::ver] is [FIGURA_VERSION].
+//! * Every `.lua` file becomes a script and every `.ogg` file a sound, keyed by its path with the
+//!   extension dropped and separators turned into dots.
+//! * Every `.bbmodel` file is [compiled][crate::bbmodel::compile] into a child of the `models`
+//!   root. Folders containing models become groups, named after the folder and given the parent
+//!   type their name implies, and the folder path is included in texture names.
+//! * The `customizations` in `avatar.json` are applied to the compiled parts, each keyed by a
+//!   part's path with dots between names (optionally starting with `models`). A customization
+//!   can set `primaryRenderType`, `secondaryRenderType`, `parentType`, `visible` and `smooth`,
+//!   move the part into another with `moveTo`, or `remove` it.
+//! * Textures named in `ignoredTextures`, by their full name (such as `folder.model.skin`), are
+//!   left out of the moon. Faces that use them keep pointing at them, as Figura's do.
+//!
+//! Hidden files and folders (those starting with `.`) are skipped, as Figura skips them. Figura
+//! reads folders in whatever order the filesystem gives; fia sorts them, so that packing the same
+//! folder always gives the same moon.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Seek};
+use std::path::{Path, PathBuf};
+use serde::Deserialize;
+use thiserror::Error;
+use crate::asset::{Asset, AssetError, LazyMoon};
+use crate::bbmodel::{self, BBModel, CompileError};
+use crate::bbmodel::lenient::Strictness;
+use crate::check::Diagnostic;
+use crate::glob::{self, Glob};
+use crate::moon::{self, Metadata, ModelPart, ParentType, RenderType};
+use crate::report::CliError;
+use crate::Moon;
+
+/// An error encountered while packing an avatar folder.
+#[derive(Debug, Error)]
+pub enum PackError {
+    /// The folder has no `avatar.json`, so Figura wouldn't consider it an avatar.
+    #[error("{0} has no avatar.json")]
+    NotAnAvatar(PathBuf),
+    /// A file couldn't be read.
+    #[error("{0}: {1}")]
+    Io(PathBuf, #[source] io::Error),
+    /// `avatar.json` or a model isn't valid JSON, or isn't shaped like one.
+    #[error("{0}: {1}")]
+    Json(PathBuf, #[source] serde_json::Error),
+    /// A model couldn't be compiled.
+    #[error("{0}: {1}")]
+    Model(PathBuf, #[source] CompileError),
+    /// A script, sound, or resource couldn't be read once it was needed.
+    #[error(transparent)]
+    Asset(#[from] AssetError),
+    /// A customization in `avatar.json` names a part that doesn't exist or an unknown parent
+    /// type, or moves a part into itself.
+    #[error("{0}: {1}")]
+    Customization(PathBuf, String),
+}
+
+/// The Figura version whose packing fia follows, which is recorded as the
+/// [ver][Metadata::ver] of avatars whose `avatar.json` doesn't give one.
+pub const FIGURA_VERSION: &str = "0.1.5";
+
+impl From<PackError> for CliError {
+    fn from(error: PackError) -> Self {
+        match error {
+            PackError::NotAnAvatar(_) => CliError::usage(error),
+            PackError::Io(path, e) => CliError::io("read", path, e),
+            PackError::Json(path, e) => CliError::parse("load", path, e),
+            PackError::Model(path, e) => CliError::parse("compile", path, e),
+            PackError::Customization(..) => CliError::failed("pack avatar", error),
+            PackError::Asset(e) => e.into(),
+        }
+    }
+}
+
+/// The files in one folder, sorted by name, without hidden ones.
+fn list(dir: &Path) -> Result<Vec<(String, PathBuf, bool)>, PackError> {
+    let io_error = |e| PackError::Io(dir.into(), e);
+    let mut entries = vec![];
+    for entry in fs::read_dir(dir).map_err(io_error)? {
+        let entry = entry.map_err(io_error)?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') {
+            continue;
+        }
+        let is_dir = entry.file_type().map_err(io_error)?.is_dir();
+        entries.push((name, entry.path(), is_dir));
+    }
+    entries.sort();
+    Ok(entries)
+}
+
+/// Reads a model as Figura does, accepting anything Blockbench writes, including plugin fields.
+/// Models are [streamed][BBModel::from_reader] so that large ones don't need a copy in memory as a
+/// JSON tree; only those that don't fit the structs are read again leniently.
+fn read_model(path: &Path) -> Result<BBModel, PackError> {
+    let io_error = |e| PackError::Io(path.into(), e);
+    let mut file = fs::File::open(path).map_err(io_error)?;
+    if let Ok(model) = BBModel::from_reader(&mut file) {
+        return Ok(model);
+    }
+    file.rewind().map_err(io_error)?;
+    serde_json::from_reader(io::BufReader::new(file))
+        .and_then(|json| BBModel::parse(json, Strictness::Lenient))
+        .map(|(model, _)| model)
+        .map_err(|e| PackError::Json(path.into(), e))
+}
+
+/// Embeds textures that the model links to a file instead of embedding, as Figura reads them from
+/// next to the model when packing. Textures whose file can't be found are left alone, and fail to
+/// compile.
+fn embed_linked_textures(model: &mut BBModel, dir: &Path) {
+    for texture in &mut model.textures {
+        if texture.decode_source().is_ok() {
+            continue;
+        }
+        let Some(path) = texture.relative_path() else { continue };
+        if let Ok(png) = fs::read(dir.join(path)) {
+            texture.set_source(&png);
+        }
+    }
+}
+
+/// Adds the scripts and sounds under `dir`, whose path from the avatar's root is `prefix`, to
+/// `files` without reading them.
+fn collect_files(files: &mut LazyMoon, dir: &Path, prefix: &str) -> Result<(), PackError> {
+    for (name, path, is_dir) in list(dir)? {
+        if is_dir {
+            collect_files(files, &path, &format!("{prefix}{name}/"))?;
+            continue;
+        }
+        let Some((stem, ext)) = name.rsplit_once('.') else { continue };
+        let map = match ext {
+            "lua" => &mut files.scripts,
+            "ogg" => &mut files.sounds,
+            _ => continue,
+        };
+        map.insert(moon::script_name(&format!("{prefix}{stem}")), Asset::OnDisk(path));
+    }
+    Ok(())
+}
+
+/// One entry of the `customizations` in `avatar.json`.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Customization {
+    primary_render_type: Option<RenderType>,
+    secondary_render_type: Option<RenderType>,
+    parent_type: Option<String>,
+    move_to: Option<String>,
+    visible: Option<bool>,
+    remove: Option<bool>,
+    smooth: Option<bool>,
+}
+
+/// Turns a part path as `avatar.json` writes it, such as `models.model.Head`, into one
+/// [ModelPart::find] takes when given [Moon::models].
+fn customization_path(path: &str) -> String {
+    let path = path.strip_prefix("models").filter(|rest| rest.is_empty() || rest.starts_with('.')).unwrap_or(path);
+    path.split('.').filter(|name| !name.is_empty()).collect::<Vec<_>>().join("/")
+}
+
+/// Removes the descendant at `path` (as in [ModelPart::find]) from its parent and returns it.
+fn take_part(root: &mut ModelPart, path: &str) -> Option<ModelPart> {
+    let (parent, name) = path.rsplit_once('/').unwrap_or(("", path));
+    let parent = root.find_mut(parent)?;
+    let mut children = std::mem::take(&mut parent.chld).into_vec();
+    let part = children.iter().position(|child| child.name == name).map(|i| children.remove(i));
+    parent.chld = children.into();
+    part
+}
+
+impl Customization {
+    /// Applies this customization to the part at `path` under `models`. Like Figura, this moves
+    /// parts without adjusting their pivots.
+    fn apply(self, models: &mut ModelPart, path: &str) -> Result<(), String> {
+        let target = customization_path(path);
+        let missing = |path: &str| format!("no part at {path:?}");
+        if self.remove == Some(true) {
+            return take_part(models, &target).map(drop).ok_or_else(|| missing(path));
+        }
+        let part = models.find_mut(&target).filter(|_| !target.is_empty()).ok_or_else(|| missing(path))?;
+        if let Some(render_type) = self.primary_render_type {
+            part.primary = Some(render_type);
+        }
+        if let Some(render_type) = self.secondary_render_type {
+            part.secondary = Some(render_type);
+        }
+        if let Some(pt) = self.parent_type {
+            part.pt = Some(pt.parse().map_err(|e| format!("{path:?}: {e}"))?);
+        }
+        if let Some(visible) = self.visible {
+            part.vsb = visible;
+        }
+        if let Some(smooth) = self.smooth {
+            part.smo = smooth;
+        }
+        if let Some(move_to) = self.move_to {
+            let parent = customization_path(&move_to);
+            if parent == target || parent.starts_with(&format!("{target}/")) {
+                return Err(format!("cannot move {path:?} into itself"));
+            }
+            models.find(&parent).ok_or_else(|| missing(&move_to))?;
+            let part = take_part(models, &target).expect("part was found");
+            let parent = models.find_mut(&parent).expect("parent was found");
+            let mut children = std::mem::take(&mut parent.chld).into_vec();
+            children.push(part);
+            parent.chld = children.into();
+        }
+        Ok(())
+    }
+}
+
+/// [Lints][bbmodel::lint] every model that packing `dir` would compile, setting each lint's
+/// [file][Diagnostic::file] to the model's path within the folder.
+pub fn lint_models(dir: &Path) -> Result<Vec<Diagnostic>, PackError> {
+    fn visit(dir: &Path, prefix: &str, out: &mut Vec<Diagnostic>) -> Result<(), PackError> {
+        for (name, path, is_dir) in list(dir)? {
+            if is_dir {
+                visit(&path, &format!("{prefix}{name}/"), out)?;
+                continue;
+            }
+            if !name.ends_with(".bbmodel") {
+                continue;
+            }
+            let model = read_model(&path)?;
+            let file = format!("{prefix}{name}");
+            out.extend(bbmodel::lint(&model).into_iter().map(|lint| Diagnostic { file: Some(file.clone()), ..lint }));
+        }
+        Ok(())
+    }
+    let mut lints = vec![];
+    visit(dir, "", &mut lints)?;
+    Ok(lints)
+}
+
+impl Moon {
+    /// Packs an avatar folder; see [the module documentation][self].
+    pub fn pack_dir(dir: &Path) -> Result<Moon, PackError> {
+        Ok(Self::pack_dir_lazy(dir)?.load()?)
+    }
+
+    /// Like [pack_dir][Self::pack_dir], but leaves scripts, sounds, and resources on disk as
+    /// [Asset]s, to be read when the avatar is [written][LazyMoon::write_to] or
+    /// [loaded][LazyMoon::load]. Models still have to be compiled, so their textures are read.
+    pub fn pack_dir_lazy(dir: &Path) -> Result<LazyMoon, PackError> {
+        let avatar_json = dir.join("avatar.json");
+        let json = match fs::read(&avatar_json) {
+            Ok(json) => json,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(PackError::NotAnAvatar(dir.into())),
+            Err(e) => return Err(PackError::Io(avatar_json, e)),
+        };
+        let json: serde_json::Value = serde_json::from_slice(&json).map_err(|e| PackError::Json(avatar_json.clone(), e))?;
+        let mut moon = Moon { metadata: Metadata::from_avatar_json(&json), ..Default::default() };
+        if moon.metadata.ver.is_empty() {
+            moon.metadata.ver = FIGURA_VERSION.into();
+        }
+
+        let mut files = LazyMoon::default();
+        collect_files(&mut files, dir, "")?;
+        let mut models = ModelPart { name: "models".into(), vsb: true, ..Default::default() };
+        models.chld = moon.pack_models(dir, "")?.into();
+        if let Some(customizations) = json.get("customizations") {
+            let customizations: BTreeMap<String, Customization> = serde_json::from_value(customizations.clone())
+                .map_err(|e| PackError::Json(avatar_json.clone(), e))?;
+            for (path, customization) in customizations {
+                customization.apply(&mut models, &path).map_err(|e| PackError::Customization(avatar_json.clone(), e))?;
+            }
+            for model in models.chld.iter_mut() {
+                model.reindex_collections();
+            }
+        }
+        moon.models = Some(models);
+        let ignored = json.get("ignoredTextures").and_then(|t| t.as_array()).into_iter().flatten();
+        for name in ignored.filter_map(|name| name.as_str()) {
+            moon.textures.src.remove(name);
+        }
+
+        let patterns: Vec<Glob> = json.get("resources").and_then(|r| r.as_array()).map_or_else(Vec::new, |patterns| {
+            patterns.iter().filter_map(|p| p.as_str()).map(Glob::new).collect()
+        });
+        if !patterns.is_empty() {
+            for path in glob::expand(dir, &patterns).map_err(|e| PackError::Io(dir.into(), e))? {
+                let file = dir.join(&path);
+                files.resources.insert(path, Asset::OnDisk(file));
+            }
+        }
+        let mut lazy = LazyMoon::from(moon);
+        lazy.scripts.extend(files.scripts);
+        lazy.sounds.extend(files.sounds);
+        lazy.resources.extend(files.resources);
+        Ok(lazy)
+    }
+
+    /// Compiles the models under `dir` into parts, adding their textures and animations.
+    /// `folders` is the path to `dir` from the avatar's root, with a dot after each folder, as
+    /// Figura puts it before texture names.
+    fn pack_models(&mut self, dir: &Path, folders: &str) -> Result<Vec<ModelPart>, PackError> {
+        let mut parts = vec![];
+        for (name, path, is_dir) in list(dir)? {
+            if is_dir {
+                let children = self.pack_models(&path, &format!("{folders}{name}."))?;
+                if !children.is_empty() {
+                    parts.push(ModelPart {
+                        pt: ParentType::for_part(&name),
+                        name,
+                        chld: children.into(),
+                        vsb: true,
+                        ..Default::default()
+                    });
+                }
+                continue;
+            }
+            let Some(stem) = name.strip_suffix(".bbmodel") else { continue };
+            parts.push(self.pack_model(&path, folders, stem)?);
+        }
+        Ok(parts)
+    }
+
+    /// Compiles the model at `path` into a part named `stem`, adding its textures and animations.
+    /// `folders` is as for [pack_models][Self::pack_models].
+    fn pack_model(&mut self, path: &Path, folders: &str, stem: &str) -> Result<ModelPart, PackError> {
+        let mut model = read_model(path)?;
+        embed_linked_textures(&mut model, path.parent().unwrap_or(Path::new(".")));
+        // textures and animations are named after the whole path, the part only after the file
+        model.name = Some(format!("{folders}{stem}"));
+        let (mut part, textures) = bbmodel::compile(&model).map_err(|e| PackError::Model(path.into(), e))?;
+        part.name = stem.into();
+        part.pt = ParentType::for_part(stem);
+
+        let offset = self.textures.data.len();
+        part.remap_textures(&(0..textures.data.len()).map(|i| (i, offset + i)).collect());
+        part.offset_animations(self.animations.len());
+        self.animations.extend(bbmodel::compile_animations(&model));
+        let mut data = std::mem::take(&mut self.textures.data).into_vec();
+        data.extend(textures.data.into_vec());
+        self.textures.data = data.into();
+        self.textures.src.extend(textures.src);
+        Ok(part)
+    }
+
+    /// Packs a lone Blockbench model as though it were the only file in an avatar folder, with
+    /// an `avatar.json` naming the avatar after the model.
+    pub fn pack_bbmodel(path: &Path) -> Result<Moon, PackError> {
+        let stem = path.file_stem().map_or_else(|| "model".into(), |s| s.to_string_lossy().into_owned());
+        let json = serde_json::json!({ "name": stem, "version": FIGURA_VERSION });
+        let mut moon = Moon { metadata: Metadata::from_avatar_json(&json), ..Default::default() };
+        let mut models = ModelPart { name: "models".into(), vsb: true, ..Default::default() };
+        models.chld = vec![moon.pack_model(path, "", &stem)?].into();
+        moon.models = Some(models);
+        Ok(moon)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Packs each folder under `tests/fixtures/pack` and compares the result with the moon next to
+    /// it, written out as SNBT. The expected moons were checked by hand against how Figura builds
+    /// each tag; `basic` has a `MOVE_RightArm` group, which Figura gives no parent type.
+    #[test]
+    fn packs_fixtures() {
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/pack");
+        for (name, path, is_dir) in list(&fixtures).unwrap() {
+            if !is_dir {
+                continue;
+            }
+            let mut moon = Moon::pack_dir(&path).unwrap();
+            // quartz_nbt reads quoted SNBT strings that start with a digit as numbers, so the
+            // version is left out of the expected moons
+            assert_eq!(moon.metadata.ver, FIGURA_VERSION, "{name}");
+            moon.metadata.ver.clear();
+            let expected = fs::read_to_string(fixtures.join(format!("{name}.snbt"))).unwrap();
+            let expected = quartz_nbt::snbt::parse(&expected).unwrap();
+            assert_eq!(moon.to_nbt().unwrap(), expected, "{name}");
+        }
+    }
+}
@@ -0,0 +1,64 @@
+//! What an avatar asks of the people who see it. Figura gates some of its Lua APIs behind each
+//! viewer's trust settings, and some avatars also do things (like making network requests) that
+//! server owners want to know about before allowing them. Authors can declare these in a
+//! [Permissions] manifest stored in the avatar's metadata, and [infer] works them out from the
+//! scripts, so reviewers can compare the two.
+
+use std::collections::BTreeSet;
+use serde::{Serialize, Deserialize};
+use crate::check::{Diagnostic, Severity};
+use crate::scripts::access_chains;
+use crate::Moon;
+
+/// The APIs worth declaring, with what using each one means.
+pub const GATED_APIS: &[(&str, &str)] = &[
+    ("vanilla_model", "hides or changes the vanilla player model"),
+    ("nameplate", "changes the player's nameplate"),
+    ("sounds", "plays sounds"),
+    ("particles", "spawns particles"),
+    ("renderer", "changes how the player and camera are rendered"),
+    ("pings", "sends pings to other players"),
+    ("net", "makes network requests"),
+    ("file", "reads or writes files on the host's computer"),
+    ("host", "uses host-only APIs"),
+];
+
+/// A permissions manifest, stored under `permissions` in the avatar's
+/// [metadata][crate::moon::Metadata]. Figura doesn't read this; it's for tools and reviewers.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Permissions {
+    /// The trust level the avatar expects viewers to give it, such as `HIGH`, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trust: Option<String>,
+    /// The [gated APIs][GATED_APIS] the avatar uses.
+    #[serde(default)]
+    pub apis: BTreeSet<String>,
+}
+
+/// Works out which [gated APIs][GATED_APIS] an avatar's scripts use. Only direct uses are found;
+/// an API stored in a variable first is missed.
+pub fn infer(moon: &Moon) -> Permissions {
+    let mut apis = BTreeSet::new();
+    for source in moon.scripts.values() {
+        for chain in access_chains(source.as_ref()) {
+            let root = chain.split(['.', ':']).next().unwrap_or_default();
+            if let Some((api, _)) = GATED_APIS.iter().find(|(api, _)| *api == root) {
+                apis.insert(api.to_string());
+            }
+        }
+    }
+    Permissions { trust: None, apis }
+}
+
+/// Compares the declared manifest (if any) against what the scripts use, warning about every API
+/// used without being declared.
+pub fn check(moon: &Moon) -> Vec<Diagnostic> {
+    let Some(declared) = &moon.metadata.permissions else { return vec![] };
+    infer(moon).apis.difference(&declared.apis).map(|api| Diagnostic {
+        severity: Severity::Warning,
+        code: "undeclared-api",
+        message: format!("scripts use {api} without declaring it in the permissions manifest"),
+        file: None,
+        part: None,
+    }).collect()
+}
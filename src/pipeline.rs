@@ -0,0 +1,138 @@
+//! Transforms that a backend applies to every avatar uploaded to it before storing it. This lets
+//! backend operators shrink avatars on their users' behalf (for example, by recompressing textures
+//! that Figura saved with poor compression) without anybody needing to run fia themselves.
+
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+use flate2::Compression;
+use quartz_nbt::io::{Flavor, NbtIoError};
+use thiserror::Error;
+use crate::{image, scripts, Moon};
+
+/// One step of a [Pipeline].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Transform {
+    /// Re-encode every texture, keeping the result only if it is smaller.
+    RecompressTextures,
+    /// [Minify][scripts::minify] every script.
+    MinifyScripts,
+    /// Remove all sounds.
+    StripSounds,
+    /// Remove unused and duplicate textures; see [Moon::gc_textures].
+    GcTextures,
+    /// Combine every script into one; see [scripts::bundle].
+    BundleScripts,
+}
+
+impl Transform {
+    /// Every transform, in the order they are listed in help text.
+    pub const ALL: &'static [Transform] = &[
+        Transform::RecompressTextures,
+        Transform::MinifyScripts,
+        Transform::StripSounds,
+        Transform::GcTextures,
+        Transform::BundleScripts,
+    ];
+
+    /// The name of this transform, as accepted by [FromStr].
+    pub fn name(self) -> &'static str {
+        match self {
+            Transform::RecompressTextures => "recompress-textures",
+            Transform::MinifyScripts      => "minify-scripts",
+            Transform::StripSounds        => "strip-sounds",
+            Transform::GcTextures         => "gc-textures",
+            Transform::BundleScripts      => "bundle-scripts",
+        }
+    }
+
+    /// Applies this transform to a moon. Textures that fail to decode are left as they are.
+    pub fn apply(self, moon: &mut Moon) {
+        match self {
+            Transform::RecompressTextures => {
+                for data in moon.textures.src.values_mut() {
+                    if let Ok(new) = image::recompress_if_smaller(data.as_ref()) {
+                        *data = new.into();
+                    }
+                }
+            }
+            Transform::MinifyScripts => {
+                for data in moon.scripts.values_mut() {
+                    *data = scripts::minify(data.as_ref()).into();
+                }
+            }
+            Transform::StripSounds => moon.sounds.clear(),
+            Transform::GcTextures => { moon.gc_textures(); }
+            Transform::BundleScripts => {
+                if !moon.scripts.is_empty() {
+                    let mut run: Vec<String> = moon.scripts.keys().cloned().collect();
+                    run.sort();
+                    let bundled = scripts::bundle(&moon.scripts, &run);
+                    moon.scripts.clear();
+                    moon.scripts.insert("bundle".into(), bundled.into());
+                }
+            }
+        }
+    }
+}
+
+impl Display for Transform {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        fmt.write_str(self.name())
+    }
+}
+
+/// Returned when parsing an unknown [Transform] name.
+#[derive(Debug, Error)]
+#[error("unknown transform {0:?}")]
+pub struct UnknownTransform(pub String);
+
+impl FromStr for Transform {
+    type Err = UnknownTransform;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Transform::ALL.iter().copied().find(|t| t.name() == s).ok_or_else(|| UnknownTransform(s.into()))
+    }
+}
+
+/// An ordered list of [Transform]s applied to uploaded avatars.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Pipeline {
+    /// The transforms to apply, in order.
+    pub transforms: Vec<Transform>,
+    /// Whether to keep the avatar as it was uploaded alongside the transformed one.
+    pub keep_original: bool,
+}
+
+/// The result of running an upload through a [Pipeline].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Processed {
+    /// The avatar data to store and serve.
+    pub data: Vec<u8>,
+    /// The avatar data as uploaded, if [Pipeline::keep_original] was set.
+    pub original: Option<Vec<u8>>,
+}
+
+impl Pipeline {
+    /// Applies every transform to a moon in order.
+    pub fn apply(&self, moon: &mut Moon) {
+        for transform in &self.transforms {
+            transform.apply(moon);
+        }
+    }
+
+    /// Runs uploaded avatar data through the pipeline. If there are no transforms, the upload is
+    /// stored as-is without being decoded. Uploads that are smaller as uploaded than after being
+    /// transformed are also stored as-is.
+    pub fn process(&self, upload: Vec<u8>) -> Result<Processed, NbtIoError> {
+        if self.transforms.is_empty() {
+            return Ok(Processed { data: upload, original: None });
+        }
+        let (mut moon, name) = Moon::read_auto(&*upload)?;
+        self.apply(&mut moon);
+        let data = moon.to_canonical_bytes(&name, Flavor::GzCompressedWith(Compression::best()))?;
+        if data.len() >= upload.len() {
+            return Ok(Processed { data: upload, original: None });
+        }
+        let original = self.keep_original.then_some(upload);
+        Ok(Processed { data, original })
+    }
+}
@@ -0,0 +1,227 @@
+//! What an avatar's scripts cost to run, as `fia profile` shows. Figura stops any avatar whose
+//! scripts run too many Lua instructions at once, with separate limits for loading the scripts,
+//! for each tick, and for each frame, all set by each viewer's permission level. An avatar that
+//! goes over breaks for whoever has it set lower, so it's worth knowing how close it is.
+//!
+//! Costs can be [estimated][estimate] from the scripts alone, which is rough (loops are counted
+//! once, and calls aren't followed), or measured by running the avatar with the
+//! [runtime][crate::runtime], which counts what's actually executed.
+
+use std::fmt::{self, Display, Formatter};
+use serde::Serialize;
+use crate::scripts::{self, FunctionInfo};
+use crate::Moon;
+
+/// One of Figura's instruction limits, each of which covers some events.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Budget {
+    /// Loading the scripts, and `entity_init`.
+    Init,
+    /// `world_tick`.
+    WorldTick,
+    /// `tick`.
+    Tick,
+    /// `world_render` and `post_world_render`.
+    WorldRender,
+    /// `render` and `post_render`.
+    Render,
+}
+
+impl Budget {
+    /// The budget an event counts against, by its name in `events` (in any case), or `init` for
+    /// loading the scripts. Events this doesn't know the limit of have none.
+    pub fn of_event(event: &str) -> Option<Self> {
+        match &*event.to_lowercase() {
+            "init" | "entity_init" => Some(Budget::Init),
+            "world_tick" => Some(Budget::WorldTick),
+            "tick" => Some(Budget::Tick),
+            "world_render" | "post_world_render" => Some(Budget::WorldRender),
+            "render" | "post_render" => Some(Budget::Render),
+            _ => None,
+        }
+    }
+
+    /// Figura's limit at the default permission level, in instructions per call.
+    pub fn default_limit(self) -> usize {
+        match self {
+            Budget::Init => 16384,
+            Budget::WorldTick => 256,
+            Budget::Tick => 4096,
+            Budget::WorldRender => 256,
+            Budget::Render => 4096,
+        }
+    }
+}
+
+impl Display for Budget {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        fmt.write_str(match self {
+            Budget::Init => "init",
+            Budget::WorldTick => "world tick",
+            Budget::Tick => "tick",
+            Budget::WorldRender => "world render",
+            Budget::Render => "render",
+        })
+    }
+}
+
+/// Serialized as it's [displayed][Display].
+impl Serialize for Budget {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// The events a profile covers, in the order Figura first runs them.
+pub const EVENTS: [&str; 8] = ["init", "entity_init", "world_tick", "tick", "world_render", "render", "post_render", "post_world_render"];
+
+/// What one function contributes to an event.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct FunctionCost {
+    /// The script the function is in.
+    pub script: String,
+    /// The function's [label].
+    pub function: String,
+    /// The line the function is defined on, or 0 for a script's main chunk.
+    pub line: usize,
+    /// Its instructions per call of the event: estimated, or measured on average.
+    pub instructions: usize,
+    /// Whether [instructions][Self::instructions] is only a lower bound, as estimates of functions
+    /// with loops are.
+    pub lower_bound: bool,
+}
+
+impl Display for FunctionCost {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        let bound = if self.lower_bound { "≥" } else { "" };
+        match self.line {
+            0 => write!(fmt, "{} ({}): {bound}{}", self.script, self.function, self.instructions),
+            line => write!(fmt, "{}:{line} {}: {bound}{}", self.script, self.function, self.instructions),
+        }
+    }
+}
+
+/// What one event costs per call, against its [Budget]'s limit.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct EventCost {
+    /// The event, in lowercase, or `init` for loading the scripts.
+    pub event: String,
+    /// The budget it counts against.
+    pub budget: Budget,
+    /// The budget's [default limit][Budget::default_limit].
+    pub limit: usize,
+    /// Instructions per call: estimated, or the most any one call measured.
+    pub instructions: usize,
+    /// The average instructions per call, if measured.
+    pub average: Option<usize>,
+    /// Whether [instructions][Self::instructions] is only a lower bound.
+    pub lower_bound: bool,
+    /// What each function contributes, most expensive first.
+    pub functions: Vec<FunctionCost>,
+}
+
+impl EventCost {
+    /// Whether this event goes over its limit.
+    pub fn over_limit(&self) -> bool {
+        self.instructions > self.limit
+    }
+}
+
+impl Display for EventCost {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        let bound = if self.lower_bound { "≥" } else { "" };
+        write!(fmt, "\x1b[1m{}\x1b[22m: {bound}{}", self.event, self.instructions)?;
+        if let Some(average) = self.average {
+            write!(fmt, " at most, {average} on average,")?;
+        }
+        write!(fmt, " of {} ({}%)", self.limit, self.instructions * 100 / self.limit)?;
+        if self.over_limit() {
+            write!(fmt, " \x1b[31mover the {} limit\x1b[39m", self.budget)?;
+        }
+        writeln!(fmt)?;
+        for function in &self.functions {
+            writeln!(fmt, "  • {function}")?;
+        }
+        Ok(())
+    }
+}
+
+/// What an avatar's scripts cost, from [estimate] or [crate::runtime::profile]. Events without
+/// any handlers are left out.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Profile {
+    /// Whether the costs were measured by running the avatar, rather than estimated.
+    pub measured: bool,
+    /// The cost of each event, in the order of [EVENTS].
+    pub events: Vec<EventCost>,
+}
+
+impl Profile {
+    /// Whether any event goes over its limit.
+    pub fn over_limit(&self) -> bool {
+        self.events.iter().any(EventCost::over_limit)
+    }
+}
+
+impl Display for Profile {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        let kind = if self.measured { "Measured" } else { "Estimated" };
+        writeln!(fmt, "{kind} instructions per call, against Figura's limits at the default permission level:")?;
+        for event in &self.events {
+            write!(fmt, "{event}")?;
+        }
+        Ok(())
+    }
+}
+
+/// How a function is named in a profile: the name it's defined with, `main chunk` for a script's
+/// main chunk, or `anonymous function`.
+pub fn label(function: &FunctionInfo) -> String {
+    match (&function.name, function.line) {
+        (_, 0) => "main chunk".into(),
+        (Some(name), _) => name.clone(),
+        (None, _) => "anonymous function".into(),
+    }
+}
+
+/// Estimates what each event costs from the avatar's scripts, without running them: loading the
+/// scripts costs their main chunks, and each event costs the handlers assigned to it or
+/// registered with it directly. Handlers registered any other way are missed.
+pub fn estimate(moon: &Moon) -> Profile {
+    let mut names: Vec<&String> = moon.scripts.keys().collect();
+    names.sort();
+    let mut events: Vec<EventCost> = vec![];
+    for name in names {
+        for function in scripts::functions(moon.scripts[name].as_ref()) {
+            let event = match (&function.event, function.line) {
+                (_, 0) => "init".into(),
+                (Some(event), _) => event.to_lowercase(),
+                (None, _) => continue,
+            };
+            let Some(budget) = Budget::of_event(&event) else { continue };
+            let index = match events.iter().position(|cost| cost.event == event) {
+                Some(index) => index,
+                None => {
+                    let limit = budget.default_limit();
+                    events.push(EventCost { event, budget, limit, instructions: 0, average: None, lower_bound: false, functions: vec![] });
+                    events.len() - 1
+                }
+            };
+            let cost = &mut events[index];
+            cost.instructions += function.instructions;
+            cost.lower_bound |= function.loops;
+            cost.functions.push(FunctionCost {
+                script: name.clone(),
+                function: label(&function),
+                line: function.line,
+                instructions: function.instructions,
+                lower_bound: function.loops,
+            });
+        }
+    }
+    for cost in &mut events {
+        cost.functions.sort_by(|a, b| b.instructions.cmp(&a.instructions));
+    }
+    events.sort_by_key(|cost| EVENTS.iter().position(|event| *event == cost.event));
+    Profile { measured: false, events }
+}
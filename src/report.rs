@@ -0,0 +1,373 @@
+//! Reporting what a command found or did, either for people or for other programs. In
+//! [plain][OutputFormat::Plain] output, results are printed to stdout as text and messages to
+//! stderr. Otherwise, everything becomes a JSON record with a `type` of `result`, `diagnostic`,
+//! `info`, `warning`, or `error`, so that tools don't have to scrape warnings out of stderr.
+//!
+//! Plain output is only colored when a [ColorChoice] says so, with colors stripped from whatever
+//! reaches a reporter otherwise.
+//!
+//! Commands that can't go on stop with a [CliError], which says what was being done to which file
+//! and picks the exit status.
+//!
+//! A path of `-` [stands for][is_stdio] stdin when reading and stdout when writing, so that fia can
+//! be used in pipelines.
+//!
+//! In a [dry run][Reporter::is_dry_run], files are reported (as `would_write` records) instead of
+//! written, so that what a command would change can be checked before it touches anything.
+
+use std::borrow::Cow;
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::io::{self, stdout, IsTerminal, Write};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use thiserror::Error;
+use crate::check::Diagnostic;
+
+/// How a [Reporter] prints what it's given.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum OutputFormat {
+    /// Text for people to read.
+    #[default]
+    Plain,
+    /// One JSON array of every record, printed once the command is done.
+    Json,
+    /// One JSON record per line, printed as soon as it's reported.
+    Ndjson,
+}
+
+/// An error from parsing an [OutputFormat] that doesn't exist.
+#[derive(Debug, Error)]
+#[error("unknown output format {0:?} (expected plain, json, or ndjson)")]
+pub struct UnknownFormat(pub String);
+
+impl FromStr for OutputFormat {
+    type Err = UnknownFormat;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(OutputFormat::Plain),
+            "json" => Ok(OutputFormat::Json),
+            "ndjson" => Ok(OutputFormat::Ndjson),
+            _ => Err(UnknownFormat(s.into())),
+        }
+    }
+}
+
+impl Display for OutputFormat {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        fmt.write_str(match self {
+            OutputFormat::Plain => "plain",
+            OutputFormat::Json => "json",
+            OutputFormat::Ndjson => "ndjson",
+        })
+    }
+}
+
+/// Why a command couldn't go on. Each kind of error exits with its own
+/// [status][Self::exit_code], so that scripts can tell bad input from a full disk; 1 is left for
+/// commands that ran fine but found problems, such as `fia check`.
+#[derive(Debug, Error)]
+pub enum CliError {
+    /// The command was given arguments that don't make sense, or that name something that
+    /// doesn't exist.
+    #[error("{0}")]
+    Usage(String),
+    /// A file couldn't be read, written, or created.
+    #[error("couldn't {operation} {}: {source}", .path.display())]
+    Io {
+        /// What was being done, such as `read`.
+        operation: &'static str,
+        /// The file it was being done to.
+        path: PathBuf,
+        /// What went wrong.
+        #[source]
+        source: io::Error,
+    },
+    /// A file was read, but isn't what it should be, such as a corrupt moon.
+    #[error("couldn't {operation} {}: {source}", .path.display())]
+    Parse {
+        /// What was being done, such as `load avatar`.
+        operation: &'static str,
+        /// The file it was being done to.
+        path: PathBuf,
+        /// What went wrong.
+        #[source]
+        source: Box<dyn Error + Send + Sync>,
+    },
+    /// A backend or Mojang's API couldn't be reached, or refused a request.
+    #[error("{0}")]
+    Backend(#[source] Box<dyn Error + Send + Sync>),
+    /// Something fia made couldn't be finished, such as an avatar that can't be serialized.
+    #[error("couldn't {operation}: {source}")]
+    Failed {
+        /// What was being done, such as `serialize avatar`.
+        operation: &'static str,
+        /// What went wrong.
+        #[source]
+        source: Box<dyn Error + Send + Sync>,
+    },
+}
+
+impl CliError {
+    /// A [Usage][Self::Usage] error.
+    pub fn usage(message: impl Display) -> Self {
+        CliError::Usage(message.to_string())
+    }
+
+    /// An [Io][Self::Io] error.
+    pub fn io(operation: &'static str, path: impl Into<PathBuf>, source: io::Error) -> Self {
+        CliError::Io { operation, path: path.into(), source }
+    }
+
+    /// A [Parse][Self::Parse] error.
+    pub fn parse(operation: &'static str, path: impl Into<PathBuf>, source: impl Into<Box<dyn Error + Send + Sync>>) -> Self {
+        CliError::Parse { operation, path: path.into(), source: source.into() }
+    }
+
+    /// A [Backend][Self::Backend] error.
+    pub fn backend(source: impl Into<Box<dyn Error + Send + Sync>>) -> Self {
+        CliError::Backend(source.into())
+    }
+
+    /// A [Failed][Self::Failed] error.
+    pub fn failed(operation: &'static str, source: impl Into<Box<dyn Error + Send + Sync>>) -> Self {
+        CliError::Failed { operation, source: source.into() }
+    }
+
+    /// The status fia exits with because of this error: 2 for [usage][Self::Usage] (as for
+    /// arguments that can't be parsed at all), 3 for [parse][Self::Parse], 4 for [I/O][Self::Io],
+    /// 5 for [anything else][Self::Failed], and 6 for [backends][Self::Backend].
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CliError::Usage(_) => 2,
+            CliError::Parse { .. } => 3,
+            CliError::Io { .. } => 4,
+            CliError::Failed { .. } => 5,
+            CliError::Backend(_) => 6,
+        }
+    }
+}
+
+/// When to color plain output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorChoice {
+    /// Only when printing to a terminal, and `$NO_COLOR` isn't set.
+    #[default]
+    Auto,
+    /// Always.
+    Always,
+    /// Never.
+    Never,
+}
+
+/// An error from parsing a [ColorChoice] that doesn't exist.
+#[derive(Debug, Error)]
+#[error("unknown color choice {0:?} (expected auto, always, or never)")]
+pub struct UnknownColorChoice(pub String);
+
+impl FromStr for ColorChoice {
+    type Err = UnknownColorChoice;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(UnknownColorChoice(s.into())),
+        }
+    }
+}
+
+impl Display for ColorChoice {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        fmt.write_str(match self {
+            ColorChoice::Auto => "auto",
+            ColorChoice::Always => "always",
+            ColorChoice::Never => "never",
+        })
+    }
+}
+
+impl ColorChoice {
+    /// Whether output should be colored.
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Auto => stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+/// Removes escape sequences (such as colors) from `text`.
+pub fn strip_escapes(text: &str) -> Cow<'_, str> {
+    if !text.contains('\x1b') {
+        return Cow::Borrowed(text);
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            out.push(c);
+        } else if chars.clone().next() == Some('[') {
+            // a control sequence ends with a character from @ to ~
+            chars.by_ref().skip(1).find(|c| ('@'..='~').contains(c));
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// Whether `path` is `-`, which stands for stdin when reading and stdout when writing.
+pub fn is_stdio(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+/// Prints a command's results and messages in an [OutputFormat]. In [JSON][OutputFormat::Json]
+/// output nothing is printed until [finish][Self::finish] (or [exit][Self::exit]) is called.
+#[derive(Debug, Default)]
+pub struct Reporter {
+    format: OutputFormat,
+    dry_run: bool,
+    color: bool,
+    records: Vec<Value>,
+}
+
+impl Reporter {
+    /// Makes a reporter that prints in the given format, and only reports files instead of
+    /// writing them if `dry_run` is set.
+    pub fn new(format: OutputFormat, dry_run: bool) -> Self {
+        Self { format, dry_run, color: false, records: vec![] }
+    }
+
+    /// Sets when to color plain output. Until this is called, it isn't.
+    pub fn set_color(&mut self, color: ColorChoice) {
+        self.color = color.enabled();
+    }
+
+    /// `text` as it should be printed: with its colors stripped unless output is colored.
+    pub fn styled<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        if self.color { Cow::Borrowed(text) } else { strip_escapes(text) }
+    }
+
+    /// Whether this is a dry run, where nothing should be written or uploaded.
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    /// The format this reporter prints in.
+    pub fn format(&self) -> OutputFormat {
+        self.format
+    }
+
+    /// Whether this reporter prints text for people.
+    pub fn is_plain(&self) -> bool {
+        self.format == OutputFormat::Plain
+    }
+
+    fn record(&mut self, record: Value) {
+        match self.format {
+            OutputFormat::Plain => unreachable!("plain output has no records"),
+            OutputFormat::Json => self.records.push(record),
+            OutputFormat::Ndjson => println!("{record}"),
+        }
+    }
+
+    fn message(&mut self, kind: &str, prefix: &str, message: impl Display) {
+        match self.format {
+            OutputFormat::Plain => eprintln!("{prefix}{}", self.styled(&message.to_string())),
+            _ => self.record(json!({ "type": kind, "message": message.to_string() })),
+        }
+    }
+
+    /// Reports progress or a summary, such as how many files were written.
+    pub fn info(&mut self, message: impl Display) {
+        self.message("info", "", message);
+    }
+
+    /// Reports something that went wrong without stopping the command.
+    pub fn warning(&mut self, message: impl Display) {
+        self.message("warning", "warning: ", message);
+    }
+
+    /// Reports something that went wrong and stopped (part of) the command.
+    pub fn error(&mut self, message: impl Display) {
+        self.message("error", "", message);
+    }
+
+    /// Reports a problem found in an avatar.
+    pub fn diagnostic(&mut self, diagnostic: &Diagnostic) {
+        match self.format {
+            OutputFormat::Plain => eprintln!("{}", self.styled(&diagnostic.to_string())),
+            _ => self.record(json!({
+                "type": "diagnostic",
+                "severity": diagnostic.severity.to_string(),
+                "code": diagnostic.code,
+                "message": diagnostic.message,
+                "file": diagnostic.file,
+                "part": diagnostic.part,
+            })),
+        }
+    }
+
+    /// Reports what the command produced: `plain` is printed to stdout as is, and `value` becomes
+    /// a result record.
+    pub fn result(&mut self, plain: impl Display, value: impl Serialize) {
+        match self.format {
+            OutputFormat::Plain => print!("{}", self.styled(&plain.to_string())),
+            // results are only ever built from fia's own types, which all serialize
+            _ => self.record(json!({ "type": "result", "value": serde_json::to_value(value).expect("result serializes to JSON") })),
+        }
+    }
+
+    /// Writes a file, or in a dry run reports its size and whether it would be created, replaced,
+    /// or left as it is. A path of `-` writes to stdout, which only plain output leaves free.
+    pub fn write_file(&mut self, path: &Path, data: &[u8]) -> Result<(), CliError> {
+        let stdio = is_stdio(path);
+        if !self.dry_run {
+            return match stdio {
+                false => fs::write(path, data).map_err(|e| CliError::io("write", path, e)),
+                true if !self.is_plain() => Err(CliError::usage(format!("can't write to stdout with --output {}", self.format))),
+                true => stdout().lock().write_all(data).map_err(|e| CliError::io("write", "standard output", e)),
+            };
+        }
+        let existing = if stdio { None } else { fs::read(path).ok() };
+        match self.format {
+            OutputFormat::Plain if stdio => eprintln!("would write {}B to standard output", data.len()),
+            OutputFormat::Plain => eprintln!("would write {} ({}B, {})", path.display(), data.len(), match &existing {
+                None => "new".into(),
+                Some(old) if old == data => "unchanged".into(),
+                Some(old) => format!("replacing {}B", old.len()),
+            }),
+            _ => self.record(json!({
+                "type": "would_write",
+                "path": path,
+                "bytes": data.len(),
+                "existing_bytes": existing.as_ref().map(Vec::len),
+                "unchanged": existing.as_deref() == Some(data),
+            })),
+        }
+        Ok(())
+    }
+
+    /// Prints everything reported so far, for formats that wait until the end.
+    pub fn finish(&mut self) {
+        if self.format == OutputFormat::Json {
+            println!("{:#}", Value::Array(std::mem::take(&mut self.records)));
+        }
+    }
+
+    /// [Finishes][Self::finish] and exits with the given status.
+    pub fn exit(&mut self, code: i32) -> ! {
+        self.finish();
+        std::process::exit(code)
+    }
+
+    /// Reports an error and exits with its [status][CliError::exit_code].
+    pub fn fail(&mut self, error: CliError) -> ! {
+        self.error(&error);
+        self.exit(error.exit_code())
+    }
+}
@@ -0,0 +1,359 @@
+//! Running an avatar's scripts outside of Minecraft, as `fia run` does, so that avatars can be
+//! tested without launching the game. Scripts run in a Lua 5.2 interpreter (the version Figura's
+//! own is compatible with) with only the libraries Figura offers, so nothing can touch the files or
+//! processes of whoever runs them.
+//!
+//! Only what's needed to get through an avatar's events is real: `print` (and its relatives),
+//! `require`, and `events`. Figura's other APIs, such as `models` and `vanilla_model`, are stand-ins
+//! that accept any call or arithmetic and return themselves, so scripts can run up to the point
+//! where they actually need something from the game, such as comparing the player's position.
+//!
+//! Running an avatar can also [profile] it, counting the instructions each of its functions runs
+//! during each event.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use mlua::{Function, HookTriggers, Lua, LuaOptions, StdLib, Value, Variadic};
+use thiserror::Error;
+use crate::profile::{label, Budget, EventCost, FunctionCost, Profile};
+use crate::scripts;
+use crate::Moon;
+
+/// Lua code that sets up the Figura APIs. It's called with a table of every script's source, the
+/// avatar's `autoScripts` (if it has any), and [scripts::resolve], and returns a function that
+/// runs the avatar's scripts and one that fires an event by name.
+const PRELUDE: &str = r#"
+local scripts, autoScripts, resolve = ...
+dofile, loadfile = nil, nil
+-- Lua 5.2 doesn't verify bytecode, so running a crafted chunk of it escapes the sandbox
+local rawload = load
+function load(chunk, name, mode, ...)
+    return rawload(chunk, name, "t", ...)
+end
+loadstring, string.dump = nil, nil
+
+local Event = {}
+Event.__index = Event
+function Event:register(fn, name)
+    if type(fn) ~= "function" then
+        error("event callbacks must be functions", 2)
+    end
+    self[#self + 1] = { fn = fn, name = name }
+    return self
+end
+function Event:remove(name)
+    local removed = 0
+    for i = #self, 1, -1 do
+        if self[i].name == name then
+            table.remove(self, i)
+            removed = removed + 1
+        end
+    end
+    return removed
+end
+function Event:clear()
+    for i = #self, 1, -1 do
+        self[i] = nil
+    end
+end
+function Event:getRegisteredCount(name)
+    local count = 0
+    for _, callback in ipairs(self) do
+        if callback.name == name then
+            count = count + 1
+        end
+    end
+    return count
+end
+
+local list = {}
+for _, name in ipairs({
+    "ENTITY_INIT", "TICK", "WORLD_TICK", "RENDER", "POST_RENDER", "WORLD_RENDER", "POST_WORLD_RENDER",
+    "SKULL_RENDER", "ARROW_RENDER", "TRIDENT_RENDER", "ITEM_RENDER", "CHAT_SEND_MESSAGE",
+    "CHAT_RECEIVE_MESSAGE", "MOUSE_SCROLL", "MOUSE_MOVE", "MOUSE_PRESS", "KEY_PRESS", "CHAR_TYPED",
+    "USE_ITEM", "ON_PLAY_SOUND", "RESOURCE_RELOAD", "DAMAGE", "TOTEM",
+}) do
+    list[name] = setmetatable({}, Event)
+end
+events = setmetatable({}, {
+    -- Figura's event names aren't case-sensitive, and assigning a function registers it
+    __index = function(_, key)
+        return list[tostring(key):upper()]
+    end,
+    __newindex = function(_, key, fn)
+        local event = list[tostring(key):upper()]
+        if not event then
+            error("event \"" .. tostring(key) .. "\" not found", 2)
+        end
+        event:register(fn)
+    end,
+})
+
+local function stub(path)
+    local function itself(value)
+        return value
+    end
+    return setmetatable({}, {
+        __index = function(value, key)
+            local field = stub(path .. "." .. tostring(key))
+            rawset(value, key, field)
+            return field
+        end,
+        __call = itself,
+        __add = itself, __sub = itself, __mul = itself, __div = itself, __mod = itself, __pow = itself, __unm = itself,
+        __tostring = function()
+            return path
+        end,
+    })
+end
+for _, name in ipairs({
+    "models", "vanilla_model", "animations", "nameplate", "renderer", "sounds", "particles", "host",
+    "client", "world", "player", "user", "avatar", "keybinds", "action_wheel", "config", "textures",
+    "vectors", "matrices", "net", "file", "json", "data", "raycast", "resources", "vec",
+}) do
+    _G[name] = stub(name)
+end
+pings = {}
+log = print
+printJson, logJson = print, print
+function printTable(value)
+    if type(value) ~= "table" then
+        return print(value)
+    end
+    for key, field in pairs(value) do
+        print(tostring(key) .. " = " .. tostring(field))
+    end
+end
+logTable = printTable
+
+local loaded, loading, running = {}, {}, {}
+function require(name)
+    local resolved = resolve(running[#running] or "", tostring(name))
+    if loaded[resolved] ~= nil then
+        return loaded[resolved]
+    end
+    local source = scripts[resolved]
+    if not source then
+        error("tried to require nonexistent script \"" .. resolved .. "\"", 2)
+    elseif loading[resolved] then
+        error("script \"" .. resolved .. "\" requires itself", 2)
+    end
+    local chunk = assert(rawload(source, "=" .. resolved, "t"))
+    loading[resolved] = true
+    running[#running + 1] = resolved
+    local result = chunk(resolved)
+    running[#running] = nil
+    loading[resolved] = nil
+    loaded[resolved] = result == nil or result
+    return loaded[resolved]
+end
+
+local function init()
+    local names = autoScripts
+    if not names then
+        names = {}
+        for name in pairs(scripts) do
+            names[#names + 1] = name
+        end
+        table.sort(names)
+    end
+    for _, name in ipairs(names) do
+        require(name)
+    end
+end
+
+local function fire(name, ...)
+    for _, callback in ipairs(list[name]) do
+        callback.fn(...)
+    end
+end
+
+return init, fire
+"#;
+
+/// How long [run] runs an avatar for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RunOptions {
+    /// How many ticks to run after `entity_init`.
+    pub ticks: u32,
+    /// How many frames to render after each tick.
+    pub renders: u32,
+    /// How many Lua instructions loading the scripts, or handling one event, may take before it's
+    /// stopped, as Figura does. Without a limit, an endless loop runs forever.
+    pub max_instructions: Option<u32>,
+    /// How many bytes the scripts may allocate in all, past which allocations fail with an error.
+    pub max_memory: Option<usize>,
+}
+
+/// A script error, which stops the avatar as it would in Figura.
+#[derive(Debug, Error)]
+#[error("error in {during}: {source}")]
+pub struct ScriptError {
+    /// What was running: `init` (loading the scripts), or an event and the tick it was in, such as
+    /// `render event of tick 3`.
+    pub during: String,
+    /// The error itself, along with a stack traceback.
+    #[source]
+    pub source: mlua::Error,
+}
+
+/// The error for going over `limit` instructions.
+fn too_many(limit: u32) -> mlua::Error {
+    mlua::Error::runtime(format!("ran more than {limit} instructions"))
+}
+
+/// Loads `moon`'s scripts and fires its events as [run] describes, calling `before` with the
+/// interpreter and the event's name in lowercase (or `init`, for loading the scripts) just before
+/// each one, so that it can set a hook.
+fn drive(moon: &Moon, options: &RunOptions, mut print: impl FnMut(&str), mut before: impl FnMut(&Lua, &'static str)) -> Result<(), ScriptError> {
+    let setup = |source| ScriptError { during: "setup".into(), source };
+    let libraries = StdLib::COROUTINE | StdLib::TABLE | StdLib::STRING | StdLib::BIT | StdLib::MATH;
+    let lua = Lua::new_with(libraries, LuaOptions::new()).map_err(setup)?;
+    if let Some(limit) = options.max_memory {
+        lua.set_memory_limit(limit).map_err(setup)?;
+    }
+    let output = Rc::new(RefCell::new(Vec::<String>::new()));
+    let lines = output.clone();
+    let print_fn = lua.create_function(move |_, values: Variadic<Value>| {
+        let text = values.iter().map(Value::to_string).collect::<mlua::Result<Vec<_>>>()?.join("\t");
+        lines.borrow_mut().push(text);
+        Ok(())
+    }).map_err(setup)?;
+    lua.globals().set("print", print_fn).map_err(setup)?;
+    let sources = lua.create_table().map_err(setup)?;
+    for (name, data) in &moon.scripts {
+        sources.set(name.as_str(), lua.create_string(data.as_ref()).map_err(setup)?).map_err(setup)?;
+    }
+    let resolve = lua.create_function(|_, (from, name): (String, String)| Ok(scripts::resolve(&from, &name))).map_err(setup)?;
+    let (init, fire): (Function, Function) = lua.load(PRELUDE).set_name("=fia")
+        .call((sources, moon.metadata.auto_scripts.clone(), resolve))
+        .map_err(setup)?;
+
+    let mut step = |event: &'static str, tick: Option<u32>, call: &dyn Fn() -> mlua::Result<()>| {
+        before(&lua, event);
+        let result = call().map_err(|source| {
+            let during = match (event, tick) {
+                ("init", _) => event.into(),
+                (_, None) => format!("{event} event"),
+                (_, Some(tick)) => format!("{event} event of tick {tick}"),
+            };
+            ScriptError { during, source }
+        });
+        // what was printed before an error is still worth seeing
+        output.borrow_mut().drain(..).for_each(|line| print(&line));
+        result
+    };
+    step("init", None, &|| init.call(()))?;
+    step("entity_init", None, &|| fire.call("ENTITY_INIT"))?;
+    for tick in 1..=options.ticks {
+        for event in ["world_tick", "tick"] {
+            step(event, Some(tick), &|| fire.call(event.to_uppercase()))?;
+        }
+        for frame in 0..options.renders {
+            let delta = f64::from(frame) / f64::from(options.renders);
+            for event in ["world_render", "render", "post_render", "post_world_render"] {
+                step(event, Some(tick), &|| match event {
+                    "world_render" | "post_world_render" => fire.call((event.to_uppercase(), delta)),
+                    _ => fire.call((event.to_uppercase(), delta, "RENDER")),
+                })?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Runs `moon`'s scripts: loads its `autoScripts` (or every script, if it doesn't pick any), fires
+/// `entity_init`, and then fires `world_tick` and `tick`, followed by `world_render`, `render`,
+/// `post_render` and `post_world_render` for each frame, for as many ticks as `options` says.
+/// Each line the scripts print is passed to `print` once the event that printed it is over.
+pub fn run(moon: &Moon, options: &RunOptions, print: impl FnMut(&str)) -> Result<(), ScriptError> {
+    let limit = options.max_instructions;
+    drive(moon, options, print, |lua, _| if let Some(limit) = limit {
+        // setting the hook again restarts its count
+        lua.set_hook(HookTriggers::new().every_nth_instruction(limit), move |_, _| Err(too_many(limit)));
+    })
+}
+
+/// Instructions counted by [profile].
+#[derive(Debug, Default)]
+struct Tally {
+    /// The event running now, and how many instructions this call of it has run so far.
+    current: Option<(&'static str, usize)>,
+    /// The instructions each call of each event ran, in the order the events first ran.
+    calls: Vec<(&'static str, Vec<usize>)>,
+    /// The instructions each function, by script and line, ran during each event in total.
+    functions: HashMap<(&'static str, String, usize), usize>,
+}
+
+impl Tally {
+    /// Records the instructions the current call ran.
+    fn end_call(&mut self) {
+        let Some((event, count)) = self.current.take() else { return };
+        match self.calls.iter_mut().find(|(e, _)| *e == event) {
+            Some((_, counts)) => counts.push(count),
+            None => self.calls.push((event, vec![count])),
+        }
+    }
+}
+
+/// Runs `moon` as [run] does, counting the instructions each function runs during each event.
+/// Instructions run by Figura's APIs (rather than by the avatar's own scripts) aren't counted, as
+/// Figura doesn't count them either.
+pub fn profile(moon: &Moon, options: &RunOptions, print: impl FnMut(&str)) -> Result<Profile, ScriptError> {
+    let tally = Rc::new(RefCell::new(Tally::default()));
+    let limit = options.max_instructions;
+    drive(moon, options, print, |lua, event| {
+        let mut current = tally.borrow_mut();
+        current.end_call();
+        current.current = Some((event, 0));
+        let tally = tally.clone();
+        lua.set_hook(HookTriggers::new().every_nth_instruction(1), move |_, debug| {
+            let source = debug.source();
+            let Some(script) = source.short_src.filter(|script| script != "fia") else { return Ok(()) };
+            let mut tally = tally.borrow_mut();
+            let Some((event, count)) = &mut tally.current else { return Ok(()) };
+            *count += 1;
+            let (event, count) = (*event, *count);
+            *tally.functions.entry((event, script.into_owned(), source.line_defined.unwrap_or(0))).or_default() += 1;
+            match limit {
+                Some(limit) if count > limit as usize => Err(too_many(limit)),
+                _ => Ok(()),
+            }
+        });
+    })?;
+    let mut tally = tally.take();
+    tally.end_call();
+
+    let labels: HashMap<(&str, usize), String> = moon.scripts.iter()
+        .flat_map(|(name, data)| scripts::functions(data.as_ref()).into_iter().map(move |f| ((name.as_str(), f.line), label(&f))))
+        .collect();
+    let mut events = vec![];
+    for (event, counts) in tally.calls {
+        let (Some(budget), Some(&max)) = (Budget::of_event(event), counts.iter().max()) else { continue };
+        if max == 0 {
+            continue;
+        }
+        let mut functions: Vec<FunctionCost> = tally.functions.iter()
+            .filter(|((e, _, _), _)| *e == event)
+            .map(|((_, script, line), total)| FunctionCost {
+                script: script.clone(),
+                function: labels.get(&(script.as_str(), *line)).cloned().unwrap_or_else(|| format!("function at line {line}")),
+                line: *line,
+                instructions: total / counts.len(),
+                lower_bound: false,
+            })
+            .collect();
+        functions.sort_by(|a, b| b.instructions.cmp(&a.instructions).then_with(|| (&a.script, a.line).cmp(&(&b.script, b.line))));
+        events.push(EventCost {
+            event: event.into(),
+            budget,
+            limit: budget.default_limit(),
+            instructions: max,
+            average: Some(counts.iter().sum::<usize>() / counts.len()),
+            lower_bound: false,
+            functions,
+        });
+    }
+    Ok(Profile { measured: true, events })
+}
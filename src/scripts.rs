@@ -0,0 +1,955 @@
+//! Static analysis of an avatar's Lua scripts. Figura names scripts after their path in the avatar
+//! folder, with the extension removed and directories separated by dots (so `libs/util.lua` is
+//! stored as `libs.util`), and scripts load each other with `require`. This module finds those
+//! `require` calls without running anything, which is enough to:
+//!
+//! * Build a [dependency graph][RequireGraph] between scripts.
+//! * Find requires of modules that don't exist.
+//! * [Bundle][bundle] every script into one, to cut down on per-script overhead.
+//!
+//! Only requires of string literals are understood; anything computed at runtime is ignored.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ops::Range;
+use quartz_nbt::serde::Array;
+
+/// Walks the code of a script, skipping comments and strings, and calls `ident` with the start and
+/// end of each identifier. `ident` returns the index to continue scanning from, which is usually
+/// the end it was given.
+fn scan_idents(source: &[u8], mut ident: impl FnMut(usize, usize) -> usize) {
+    let mut i = 0;
+    while i < source.len() {
+        match source[i] {
+            b'-' if source[i..].starts_with(b"--") => {
+                i += 2;
+                if let Some(level) = long_bracket(&source[i..]) {
+                    i = skip_long(source, i + level + 2, level);
+                } else {
+                    while i < source.len() && source[i] != b'\n' {
+                        i += 1;
+                    }
+                }
+            }
+            b'"' | b'\'' => i = skip_string(source, i).1,
+            b'[' if long_bracket(&source[i..]).is_some() => {
+                let level = long_bracket(&source[i..]).unwrap();
+                i = skip_long(source, i + level + 2, level);
+            }
+            c if is_ident(c) => {
+                let start = i;
+                while i < source.len() && is_ident(source[i]) {
+                    i += 1;
+                }
+                i = ident(start, i);
+            }
+            _ => i += 1,
+        }
+    }
+}
+
+/// Finds the module names passed to `require` in a script, in order of appearance. Names are
+/// returned exactly as written; see [resolve] to turn them into script names.
+pub fn requires(source: &[u8]) -> Vec<String> {
+    let mut found = vec![];
+    scan_idents(source, |start, i| {
+        let method = start > 0 && matches!(source[start - 1], b'.' | b':');
+        if &source[start..i] != b"require" || method {
+            return i;
+        }
+        let mut j = skip_space(source, i);
+        if source.get(j) == Some(&b'(') {
+            j = skip_space(source, j + 1);
+        }
+        if let Some(b'"' | b'\'') = source.get(j) {
+            let (name, end) = skip_string(source, j);
+            found.push(name);
+            return end;
+        }
+        i
+    });
+    found
+}
+
+const KEYWORDS: &[&[u8]] = &[
+    b"and", b"break", b"do", b"else", b"elseif", b"end", b"false", b"for", b"function", b"goto", b"if",
+    b"in", b"local", b"nil", b"not", b"or", b"repeat", b"return", b"then", b"true", b"until", b"while",
+];
+
+/// Finds the chains of field and method accesses in a script that start from a name, such as
+/// `vanilla_model.HEAD:setVisible` or `host`. A chain ends at anything other than `.` or `:`
+/// followed by a name, so `models.Body["x"]` gives `models.Body`. Local variables aren't told
+/// apart from globals.
+pub fn access_chains(source: &[u8]) -> BTreeSet<String> {
+    let mut found = BTreeSet::new();
+    scan_idents(source, |start, mut i| {
+        let concat = start > 1 && &source[start - 2..start] == b"..";
+        if start > 0 && matches!(source[start - 1], b'.' | b':') && !concat || source[start].is_ascii_digit() {
+            return i;
+        }
+        loop {
+            let j = skip_space(source, i);
+            if !matches!(source.get(j), Some(b'.' | b':')) || source.get(j + 1) == Some(&b'.') {
+                break;
+            }
+            let k = skip_space(source, j + 1);
+            let end = k + source[k..].iter().take_while(|&&c| is_ident(c)).count();
+            if end == k {
+                break;
+            }
+            i = end;
+        }
+        if KEYWORDS.contains(&&source[start..i]) {
+            return i;
+        }
+        let chain: Vec<u8> = source[start..i].iter().copied().filter(|c| !c.is_ascii_whitespace()).collect();
+        found.insert(String::from_utf8_lossy(&chain).into_owned());
+        i
+    });
+    found
+}
+
+fn is_ident(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_'
+}
+
+fn skip_space(source: &[u8], mut i: usize) -> usize {
+    while i < source.len() && source[i].is_ascii_whitespace() {
+        i += 1;
+    }
+    i
+}
+
+/// Returns the level of a long bracket (`[[`, `[=[`, …) starting at the beginning of `s`.
+fn long_bracket(s: &[u8]) -> Option<usize> {
+    let level = s.iter().skip(1).take_while(|&&c| c == b'=').count();
+    (s.first() == Some(&b'[') && s.get(level + 1) == Some(&b'[')).then_some(level)
+}
+
+/// Skips to just after the long bracket of the given level closing a string or comment.
+fn skip_long(source: &[u8], mut i: usize, level: usize) -> usize {
+    while i < source.len() {
+        if source[i] == b']' {
+            let eq = source[i + 1..].iter().take_while(|&&c| c == b'=').count();
+            if eq == level && source.get(i + 1 + eq) == Some(&b']') {
+                return i + eq + 2;
+            }
+        }
+        i += 1;
+    }
+    i
+}
+
+/// Skips a quoted string starting at `i`, returning its (roughly unescaped) contents and the index
+/// just after the closing quote.
+fn skip_string(source: &[u8], i: usize) -> (String, usize) {
+    let quote = source[i];
+    let mut out = vec![];
+    let mut j = i + 1;
+    while j < source.len() && source[j] != quote && source[j] != b'\n' {
+        if source[j] == b'\\' && j + 1 < source.len() {
+            j += 1;
+        }
+        out.push(source[j]);
+        j += 1;
+    }
+    (String::from_utf8_lossy(&out).into_owned(), j + 1)
+}
+
+/// Resolves a module name as Figura would when it is required from the script `from`. Slashes and
+/// dots both separate directories, a trailing `.lua` is ignored, and names starting with `./` or
+/// `../` are relative to the directory containing `from`.
+pub fn resolve(from: &str, name: &str) -> String {
+    let name = name.strip_suffix(".lua").unwrap_or(name);
+    let mut parts: Vec<&str> = vec![];
+    if name.starts_with("./") || name.starts_with("../") {
+        parts.extend(from.split('.'));
+        parts.pop();
+        for part in name.split('/') {
+            match part {
+                "." | "" => {}
+                ".." => { parts.pop(); }
+                part => parts.extend(part.split('.')),
+            }
+        }
+    } else {
+        parts.extend(name.split(['/', '.']).filter(|p| !p.is_empty()));
+    }
+    parts.join(".")
+}
+
+/// The `require` relationships between an avatar's scripts.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RequireGraph {
+    /// Maps each script to the (resolved) names of the modules it requires.
+    pub edges: BTreeMap<String, BTreeSet<String>>,
+    /// Pairs of script name and resolved module name for each require of a module that doesn't
+    /// exist.
+    pub missing: Vec<(String, String)>,
+}
+
+impl RequireGraph {
+    /// Scans every script for requires.
+    pub fn build(scripts: &HashMap<String, Array<Vec<u8>>>) -> Self {
+        let mut graph = Self::default();
+        let mut names: Vec<&String> = scripts.keys().collect();
+        names.sort();
+        for name in names {
+            let deps = graph.edges.entry(name.clone()).or_default();
+            for req in requires(scripts[name].as_ref()) {
+                let req = resolve(name, &req);
+                if !scripts.contains_key(&req) {
+                    graph.missing.push((name.clone(), req.clone()));
+                }
+                deps.insert(req);
+            }
+        }
+        graph
+    }
+
+    /// Returns the scripts that are never required by another script. These are the ones that
+    /// need to be run directly.
+    pub fn roots(&self) -> Vec<&str> {
+        let required: BTreeSet<&String> = self.edges.values().flatten().collect();
+        self.edges.keys().filter(|k| !required.contains(k)).map(|k| &**k).collect()
+    }
+
+    /// Finds a cycle of requires, if there is one. Figura will error at runtime if a script
+    /// requires itself, directly or indirectly.
+    pub fn find_cycle(&self) -> Option<Vec<String>> {
+        fn visit<'a>(graph: &'a RequireGraph, node: &'a str, stack: &mut Vec<&'a str>, done: &mut BTreeSet<&'a str>) -> Option<Vec<String>> {
+            if let Some(i) = stack.iter().position(|&n| n == node) {
+                let mut cycle: Vec<String> = stack[i..].iter().map(|&s| s.into()).collect();
+                cycle.push(node.into());
+                return Some(cycle);
+            }
+            if !done.insert(node) {
+                return None;
+            }
+            stack.push(node);
+            for dep in graph.edges.get(node).into_iter().flatten() {
+                if let Some(cycle) = visit(graph, dep, stack, done) {
+                    return Some(cycle);
+                }
+            }
+            stack.pop();
+            None
+        }
+        let mut done = BTreeSet::new();
+        for node in self.edges.keys() {
+            if let Some(cycle) = visit(self, node, &mut vec![], &mut done) {
+                return Some(cycle);
+            }
+        }
+        None
+    }
+}
+
+/// Combines scripts into a single script, which runs the scripts in `run` in order when loaded.
+/// Each script is wrapped in a function and registered with a small loader, which replaces
+/// `require` so that bundled modules are found (and only run once) while anything else falls
+/// through to Figura's own `require`.
+pub fn bundle(scripts: &HashMap<String, Array<Vec<u8>>>, run: &[String]) -> Vec<u8> {
+    let mut names: Vec<&String> = scripts.keys().collect();
+    names.sort();
+    let mut out = Vec::<u8>::new();
+    out.extend_from_slice(b"local __modules, __loaded, __require = {}, {}, require\n");
+    out.extend_from_slice(br#"local function __resolve(from, name)
+  name = name:gsub("%.lua$", "")
+  local parts = {}
+  if name:sub(1, 2) == "./" or name:sub(1, 3) == "../" then
+    for part in from:gmatch("[^.]+") do parts[#parts + 1] = part end
+    parts[#parts] = nil
+    for part in name:gmatch("[^/]+") do
+      if part == ".." then parts[#parts] = nil
+      elseif part ~= "." then for p in part:gmatch("[^.]+") do parts[#parts + 1] = p end end
+    end
+  else
+    for part in name:gmatch("[^/.]+") do parts[#parts + 1] = part end
+  end
+  return table.concat(parts, ".")
+end
+local function __requirer(from)
+  return function(name)
+    local resolved = __resolve(from, name)
+    local module = __modules[resolved]
+    if not module then return __require(name) end
+    if __loaded[resolved] == nil then
+      __loaded[resolved] = true
+      local value = module(resolved)
+      if value ~= nil then __loaded[resolved] = value end
+    end
+    return __loaded[resolved]
+  end
+end
+"#);
+    for name in names {
+        let quoted = lua_string(name);
+        out.extend_from_slice(format!("__modules[{quoted}] = function(...)\nlocal require = __requirer({quoted})\n").as_bytes());
+        out.extend_from_slice(scripts[name].as_ref());
+        out.extend_from_slice(b"\nend\n");
+    }
+    out.extend_from_slice(b"local require = __requirer(\"\")\n");
+    for name in run {
+        out.extend_from_slice(format!("require({})\n", lua_string(name)).as_bytes());
+    }
+    out
+}
+
+/// Quotes a string as a Lua string literal.
+pub(crate) fn lua_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' | '\\' => { out.push('\\'); out.push(c); }
+            c if c < ' ' => out += &format!("\\{:03}", c as u32),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Removes comments, indentation, trailing whitespace, and blank lines from a script without
+/// changing what it does. String literals (including long strings) are left untouched, and code
+/// stays on the line it was on, blank lines aside.
+pub fn minify(source: &[u8]) -> Vec<u8> {
+    let mut out = Vec::<u8>::with_capacity(source.len());
+    let mut i = 0;
+    while i < source.len() {
+        match source[i] {
+            b'-' if source[i..].starts_with(b"--") => {
+                i += 2;
+                if let Some(level) = long_bracket(&source[i..]) {
+                    i = skip_long(source, i + level + 2, level);
+                    if out.last().is_some_and(|c| !c.is_ascii_whitespace()) {
+                        out.push(b' ');
+                    }
+                } else {
+                    while i < source.len() && source[i] != b'\n' {
+                        i += 1;
+                    }
+                }
+            }
+            b'"' | b'\'' => {
+                let end = skip_string(source, i).1.min(source.len());
+                out.extend_from_slice(&source[i..end]);
+                i = end;
+            }
+            b'[' if long_bracket(&source[i..]).is_some() => {
+                let level = long_bracket(&source[i..]).unwrap();
+                let end = skip_long(source, i + level + 2, level);
+                out.extend_from_slice(&source[i..end]);
+                i = end;
+            }
+            b'\n' => {
+                while out.last().is_some_and(|c| *c == b' ' || *c == b'\t' || *c == b'\r') {
+                    out.pop();
+                }
+                if out.last().is_some_and(|c| *c != b'\n') {
+                    out.push(b'\n');
+                }
+                i = skip_space(source, i);
+            }
+            c if c.is_ascii_whitespace() => {
+                if out.last().is_some_and(|c| !c.is_ascii_whitespace()) {
+                    out.push(b' ');
+                }
+                i += 1;
+            }
+            c => {
+                out.push(c);
+                i += 1;
+            }
+        }
+    }
+    while out.last().is_some_and(u8::is_ascii_whitespace) {
+        out.pop();
+    }
+    out
+}
+
+/// Whether a number literal starts at `i`.
+fn starts_number(source: &[u8], i: usize) -> bool {
+    source[i].is_ascii_digit() || source[i] == b'.' && source.get(i + 1).is_some_and(u8::is_ascii_digit)
+}
+
+/// Skips a number literal starting at `i`. This is close enough for hex, exponents, and fractions
+/// alike.
+fn skip_number(source: &[u8], mut i: usize) -> usize {
+    while i < source.len() && (is_ident(source[i]) || source[i] == b'.'
+        || matches!(source[i], b'+' | b'-') && matches!(source[i - 1], b'e' | b'E' | b'p' | b'P')) {
+        i += 1;
+    }
+    i
+}
+
+/// What a [token][tokens] of a script is, for highlighting.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TokenKind {
+    /// Anything not covered below: names, operators, punctuation, and whitespace.
+    Code,
+    /// A reserved word, such as `local` or `end`.
+    Keyword,
+    /// A string literal, including long strings.
+    String,
+    /// A comment, including long comments.
+    Comment,
+    /// A number literal.
+    Number,
+}
+
+/// Splits a script into tokens for highlighting, as the kind and byte range of each. The ranges
+/// cover the whole script, in order; runs of plain code are merged into one token.
+pub fn tokens(source: &[u8]) -> Vec<(TokenKind, Range<usize>)> {
+    let mut out: Vec<(TokenKind, Range<usize>)> = vec![];
+    let mut push = |kind, range: Range<usize>| match out.last_mut() {
+        Some((TokenKind::Code, last)) if kind == TokenKind::Code && last.end == range.start => last.end = range.end,
+        _ => out.push((kind, range)),
+    };
+    let mut i = 0;
+    while i < source.len() {
+        let start = i;
+        let kind = match source[i] {
+            b'-' if source[i..].starts_with(b"--") => {
+                i += 2;
+                if let Some(level) = long_bracket(&source[i..]) {
+                    i = skip_long(source, i + level + 2, level);
+                } else {
+                    while i < source.len() && source[i] != b'\n' {
+                        i += 1;
+                    }
+                }
+                TokenKind::Comment
+            }
+            b'"' | b'\'' => {
+                i = skip_string(source, i).1.min(source.len());
+                TokenKind::String
+            }
+            b'[' if long_bracket(&source[i..]).is_some() => {
+                let level = long_bracket(&source[i..]).unwrap();
+                i = skip_long(source, i + level + 2, level);
+                TokenKind::String
+            }
+            _ if starts_number(source, i) => {
+                i = skip_number(source, i);
+                TokenKind::Number
+            }
+            c if is_ident(c) => {
+                while i < source.len() && is_ident(source[i]) {
+                    i += 1;
+                }
+                if KEYWORDS.contains(&&source[start..i]) { TokenKind::Keyword } else { TokenKind::Code }
+            }
+            _ => {
+                i += 1;
+                TokenKind::Code
+            }
+        };
+        push(kind, start..i);
+    }
+    out
+}
+
+/// A token of a script, for [shorten_locals] and [functions]. Comments and whitespace aren't
+/// tokens.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Token<'a> {
+    Name(&'a [u8]),
+    Keyword(&'a [u8]),
+    Punct(&'a [u8]),
+    /// A string or number.
+    Literal,
+}
+
+/// Punctuation longer than one character, longest first.
+const PUNCTUATION: &[&[u8]] = &[b"...", b"..", b"::", b"==", b"~=", b"<=", b">=", b"//", b"<<", b">>"];
+
+/// Splits a script into [Token]s with their byte ranges.
+fn lex(source: &[u8]) -> Vec<(Token<'_>, Range<usize>)> {
+    let mut out = vec![];
+    let mut i = 0;
+    while i < source.len() {
+        let start = i;
+        let token = match source[i] {
+            b'-' if source[i..].starts_with(b"--") => {
+                i += 2;
+                if let Some(level) = long_bracket(&source[i..]) {
+                    i = skip_long(source, i + level + 2, level);
+                } else {
+                    while i < source.len() && source[i] != b'\n' {
+                        i += 1;
+                    }
+                }
+                continue;
+            }
+            c if c.is_ascii_whitespace() => {
+                i += 1;
+                continue;
+            }
+            b'"' | b'\'' => {
+                i = skip_string(source, i).1.min(source.len());
+                Token::Literal
+            }
+            b'[' if long_bracket(&source[i..]).is_some() => {
+                let level = long_bracket(&source[i..]).unwrap();
+                i = skip_long(source, i + level + 2, level);
+                Token::Literal
+            }
+            _ if starts_number(source, i) => {
+                i = skip_number(source, i);
+                Token::Literal
+            }
+            c if is_ident(c) => {
+                while i < source.len() && is_ident(source[i]) {
+                    i += 1;
+                }
+                let word = &source[start..i];
+                if KEYWORDS.contains(&word) { Token::Keyword(word) } else { Token::Name(word) }
+            }
+            _ => {
+                i += PUNCTUATION.iter().find(|p| source[i..].starts_with(p)).map_or(1, |p| p.len());
+                Token::Punct(&source[start..i])
+            }
+        };
+        out.push((token, start..i));
+    }
+    out
+}
+
+/// The locals declared in one block of a script, with what they're renamed to. Names that can't
+/// be renamed are still recorded, since they hide outer locals of the same name.
+struct Scope<'a> {
+    renamable: bool,
+    locals: Vec<(&'a [u8], Option<String>)>,
+}
+
+/// Makes up short names that don't clash with anything in the script.
+struct Namer<'a> {
+    taken: BTreeSet<&'a [u8]>,
+    names: Vec<String>,
+    next: usize,
+}
+
+impl Namer<'_> {
+    /// The `n`th short name, counting from 0.
+    fn nth(&mut self, n: usize) -> String {
+        const LETTERS: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+        while self.names.len() <= n {
+            let (mut i, mut name) = (self.next, vec![]);
+            self.next += 1;
+            loop {
+                name.push(LETTERS[i % LETTERS.len()]);
+                i /= LETTERS.len();
+                if i == 0 {
+                    break;
+                }
+                i -= 1;
+            }
+            if !self.taken.contains(&name[..]) {
+                self.names.push(String::from_utf8(name).expect("letters are ASCII"));
+            }
+        }
+        self.names[n].clone()
+    }
+}
+
+/// Renames local variables (including function parameters and loop variables) to short names,
+/// for use alongside [minify]. Each local gets the shortest name not used by anything else in the
+/// script or by another local in scope, so globals, fields, and table keys are left alone. Locals
+/// declared directly inside `repeat` blocks aren't renamed, since `until` can refer to them.
+///
+/// This only follows the block structure of the script, not its full grammar, so scripts whose
+/// blocks don't balance are returned unchanged.
+pub fn shorten_locals(source: &[u8]) -> Vec<u8> {
+    let tokens = lex(source);
+    let token = |i: usize| tokens.get(i).map(|(token, _)| *token);
+    let mut taken: BTreeSet<&[u8]> = KEYWORDS.iter().copied().collect();
+    taken.extend(tokens.iter().filter_map(|(token, _)| match token {
+        Token::Name(name) => Some(*name),
+        _ => None,
+    }));
+    let mut namer = Namer { taken, names: vec![], next: 0 };
+    let mut scopes = vec![Scope { renamable: true, locals: vec![] }];
+    let mut brackets = vec![];
+    let mut renames: Vec<(Range<usize>, String)> = vec![];
+    // locals from `local x = …`, which only come into scope once the statement ends, along with
+    // how deeply nested the statement is. There's one entry per unfinished statement, since the
+    // expression can define a function with `local` statements of its own.
+    let mut pending: Vec<((usize, usize), Vec<(&[u8], Option<String>)>)> = vec![];
+    // the variables of a `for` loop, which come into scope at its `do`
+    let mut loop_vars: Vec<(&[u8], Range<usize>)> = vec![];
+
+    macro_rules! declare {
+        ($name:expr, $range:expr, $pending:expr) => {{
+            let scope = scopes.last().unwrap();
+            let new = scope.renamable.then(|| {
+                let in_scope = scopes.iter().flat_map(|s| &s.locals)
+                    .chain(pending.iter().flat_map(|(_, locals)| locals))
+                    .filter(|(_, new)| new.is_some())
+                    .count();
+                namer.nth(in_scope)
+            });
+            if let Some(new) = &new {
+                renames.push(($range, new.clone()));
+            }
+            if $pending {
+                pending.last_mut().expect("statement was started").1.push(($name, new));
+            } else {
+                scopes.last_mut().unwrap().locals.push(($name, new));
+            }
+        }};
+    }
+
+    let mut i = 0;
+    while i < tokens.len() {
+        let (current, range) = tokens[i].clone();
+        let previous = i.checked_sub(1).and_then(token);
+        if pending.last().is_some_and(|(depth, _)| *depth == (scopes.len(), brackets.len())) {
+            let ends_expression = matches!(previous, Some(Token::Name(_) | Token::Literal | Token::Punct(b")" | b"]" | b"}" | b"...")
+                | Token::Keyword(b"nil" | b"true" | b"false" | b"end")));
+            let starts_statement = matches!(current, Token::Name(_) | Token::Punct(b"::")
+                | Token::Keyword(b"local" | b"if" | b"for" | b"while" | b"repeat" | b"return" | b"break" | b"goto" | b"do"
+                    | b"function" | b"end" | b"else" | b"elseif" | b"until"));
+            if current == Token::Punct(b";") || ends_expression && starts_statement {
+                let (_, mut locals) = pending.pop().unwrap();
+                scopes.last_mut().unwrap().locals.append(&mut locals);
+            }
+        }
+        match current {
+            Token::Keyword(b"local") if token(i + 1) == Some(Token::Keyword(b"function")) => {
+                if let Some(Token::Name(name)) = token(i + 2) {
+                    declare!(name, tokens[i + 2].1.clone(), false);
+                }
+                i += 1;
+                continue;
+            }
+            Token::Keyword(b"local") => {
+                let mut j = i + 1;
+                let mut names = vec![];
+                while let Some(Token::Name(name)) = token(j) {
+                    names.push((name, tokens[j].1.clone()));
+                    j += 1;
+                    if token(j) == Some(Token::Punct(b"<")) {
+                        j += 3;
+                    }
+                    if token(j) != Some(Token::Punct(b",")) {
+                        break;
+                    }
+                    j += 1;
+                }
+                let assigned = token(j) == Some(Token::Punct(b"="));
+                if assigned {
+                    pending.push(((scopes.len(), brackets.len()), vec![]));
+                }
+                for (name, range) in names {
+                    declare!(name, range, assigned);
+                }
+                i = j;
+                continue;
+            }
+            Token::Keyword(b"function") => {
+                let mut j = i + 1;
+                let mut method = false;
+                if let Some(Token::Name(name)) = token(j) {
+                    // declared already if this is a local function
+                    if previous != Some(Token::Keyword(b"local")) {
+                        if let Some(new) = resolve_local(&scopes, name) {
+                            renames.push((tokens[j].1.clone(), new));
+                        }
+                    }
+                    j += 1;
+                    while let Some(Token::Punct(separator @ (b"." | b":"))) = token(j) {
+                        method |= separator == b":";
+                        j += 2;
+                    }
+                }
+                if token(j) != Some(Token::Punct(b"(")) {
+                    return source.to_vec();
+                }
+                scopes.push(Scope { renamable: true, locals: vec![] });
+                if method {
+                    scopes.last_mut().unwrap().locals.push((b"self", None));
+                }
+                j += 1;
+                while let Some(parameter) = token(j) {
+                    match parameter {
+                        Token::Punct(b")") => break,
+                        Token::Name(name) => declare!(name, tokens[j].1.clone(), false),
+                        _ => {}
+                    }
+                    j += 1;
+                }
+                i = j + 1;
+                continue;
+            }
+            Token::Keyword(b"for") => {
+                let mut j = i + 1;
+                while let Some(Token::Name(name)) = token(j) {
+                    loop_vars.push((name, tokens[j].1.clone()));
+                    j += if token(j + 1) == Some(Token::Punct(b",")) { 2 } else { 1 };
+                }
+                i = j;
+                continue;
+            }
+            Token::Keyword(b"do") => {
+                scopes.push(Scope { renamable: true, locals: vec![] });
+                for (name, range) in std::mem::take(&mut loop_vars) {
+                    declare!(name, range, false);
+                }
+            }
+            Token::Keyword(b"then") => scopes.push(Scope { renamable: true, locals: vec![] }),
+            Token::Keyword(b"repeat") => scopes.push(Scope { renamable: false, locals: vec![] }),
+            Token::Keyword(b"else") => {
+                if scopes.len() < 2 {
+                    return source.to_vec();
+                }
+                scopes.pop();
+                scopes.push(Scope { renamable: true, locals: vec![] });
+            }
+            Token::Keyword(b"elseif" | b"end" | b"until") => {
+                if scopes.len() < 2 {
+                    return source.to_vec();
+                }
+                scopes.pop();
+            }
+            // labels aren't variables
+            Token::Keyword(b"goto") => i += 1,
+            Token::Punct(b"::") => i += 2,
+            Token::Punct(open @ (b"(" | b"[" | b"{")) => brackets.push(open),
+            Token::Punct(close @ (b")" | b"]" | b"}")) => {
+                let open = match close { b")" => b"(", b"]" => b"[", _ => b"{" };
+                if brackets.pop() != Some(open) {
+                    return source.to_vec();
+                }
+            }
+            Token::Name(name) => {
+                let field = matches!(previous, Some(Token::Punct(b"." | b":")));
+                let key = brackets.last() == Some(&&b"{"[..])
+                    && matches!(previous, Some(Token::Punct(b"{" | b"," | b";")))
+                    && token(i + 1) == Some(Token::Punct(b"="));
+                if !field && !key {
+                    if let Some(new) = resolve_local(&scopes, name) {
+                        renames.push((range, new));
+                    }
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    if scopes.len() != 1 || !brackets.is_empty() {
+        return source.to_vec();
+    }
+
+    renames.sort_by_key(|(range, _)| range.start);
+    let mut out = Vec::with_capacity(source.len());
+    let mut last = 0;
+    for (range, new) in renames {
+        out.extend_from_slice(&source[last..range.start]);
+        out.extend_from_slice(new.as_bytes());
+        last = range.end;
+    }
+    out.extend_from_slice(&source[last..]);
+    out
+}
+
+/// What a name refers to in the given scopes: the new name of the local it refers to, or [None]
+/// if it's a global or a local that isn't renamed.
+fn resolve_local(scopes: &[Scope], name: &[u8]) -> Option<String> {
+    scopes.iter().rev()
+        .flat_map(|scope| scope.locals.iter().rev())
+        .find(|(local, _)| *local == name)
+        .and_then(|(_, new)| new.clone())
+}
+
+/// A function defined in a script, found by [functions].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FunctionInfo {
+    /// The function's name where it's defined, such as `events.tick` or `util:lerp`, if it has one.
+    pub name: Option<String>,
+    /// The line the function is defined on, counting from 1, or 0 for the script's main chunk.
+    pub line: usize,
+    /// The event the function handles, in capitals, if it's assigned to `events` or registered
+    /// with one of them.
+    pub event: Option<String>,
+    /// A rough count of the instructions one call runs: one for each name, literal, operator, and
+    /// most keywords in its body, not counting the bodies of functions defined inside it. Loops are
+    /// counted once, and calls aren't followed.
+    pub instructions: usize,
+    /// Whether the body has a loop, which makes [instructions][Self::instructions] a lower bound.
+    pub loops: bool,
+}
+
+/// The event a function named `name` handles, if it's a field of `events`.
+fn event_of(name: &str) -> Option<String> {
+    name.strip_prefix("events.").filter(|event| !event.contains(['.', ':'])).map(str::to_uppercase)
+}
+
+/// The name of the function whose `function` keyword is `tokens[i]`, and the event it handles:
+/// from the name after the keyword, what it's assigned to, or the event it's registered with.
+fn function_name(tokens: &[(Token, Range<usize>)], i: usize) -> (Option<String>, Option<String>) {
+    let token = |i: usize| tokens.get(i).map(|(token, _)| *token);
+    let text = |token| match token {
+        Some(Token::Name(name) | Token::Punct(name)) => String::from_utf8_lossy(name).into_owned(),
+        _ => String::new(),
+    };
+    let name = if let Some(Token::Name(_)) = token(i + 1) {
+        let mut name = text(token(i + 1));
+        let mut j = i + 2;
+        while let (Some(Token::Punct(b"." | b":")), Some(Token::Name(_))) = (token(j), token(j + 1)) {
+            name += &text(token(j));
+            name += &text(token(j + 1));
+            j += 2;
+        }
+        Some(name)
+    } else if i >= 2 && token(i - 1) == Some(Token::Punct(b"=")) && matches!(token(i - 2), Some(Token::Name(_))) {
+        let mut start = i - 2;
+        while start >= 2 && matches!(token(start - 1), Some(Token::Punct(b"." | b":"))) && matches!(token(start - 2), Some(Token::Name(_))) {
+            start -= 2;
+        }
+        Some((start..i - 1).map(|j| text(token(j))).collect())
+    } else {
+        // events.tick:register(function …)
+        let registered = i >= 6
+            && token(i - 1) == Some(Token::Punct(b"("))
+            && token(i - 2) == Some(Token::Name(b"register"))
+            && token(i - 3) == Some(Token::Punct(b":"))
+            && matches!(token(i - 4), Some(Token::Name(_)))
+            && token(i - 5) == Some(Token::Punct(b"."))
+            && token(i - 6) == Some(Token::Name(b"events"));
+        return (None, registered.then(|| text(token(i - 4)).to_uppercase()));
+    };
+    let event = name.as_deref().and_then(event_of);
+    (name, event)
+}
+
+/// Finds every function defined in a script, along with a rough estimate of what one call to each
+/// costs, so that expensive event handlers can be spotted without running anything. The first is
+/// always the script's main chunk, which runs once when the avatar loads.
+///
+/// Like [shorten_locals], this only follows the block structure of the script.
+pub fn functions(source: &[u8]) -> Vec<FunctionInfo> {
+    let tokens = lex(source);
+    let line = |offset: usize| source[..offset].iter().filter(|&&c| c == b'\n').count() + 1;
+    let mut out = vec![FunctionInfo { name: None, line: 0, event: None, instructions: 0, loops: false }];
+    // the open blocks, as the index in `out` of each function and None for anything else
+    let mut blocks: Vec<Option<usize>> = vec![];
+    // whether the innermost function's name and parameters are still being read
+    let mut header = false;
+    for (i, (current, range)) in tokens.iter().enumerate() {
+        let function = blocks.iter().rev().find_map(|block| *block).unwrap_or(0);
+        match current {
+            Token::Keyword(b"function") => {
+                let (name, event) = function_name(&tokens, i);
+                out.push(FunctionInfo { name, line: line(range.start), event, instructions: 0, loops: false });
+                blocks.push(Some(out.len() - 1));
+                header = true;
+                continue;
+            }
+            Token::Punct(b")") if header => {
+                header = false;
+                continue;
+            }
+            _ if header => continue,
+            Token::Keyword(b"do" | b"if" | b"repeat") => blocks.push(None),
+            Token::Keyword(b"end" | b"until") => {
+                blocks.pop();
+            }
+            _ => {}
+        }
+        if matches!(current, Token::Keyword(b"while" | b"for" | b"repeat")) {
+            out[function].loops = true;
+        }
+        out[function].instructions += match current {
+            Token::Name(_) | Token::Literal => 1,
+            Token::Keyword(word) => matches!(*word, b"if" | b"elseif" | b"while" | b"for" | b"until" | b"return" | b"and" | b"or"
+                | b"not" | b"nil" | b"true" | b"false" | b"break" | b"goto") as usize,
+            Token::Punct(punct) => !matches!(*punct, b")" | b"]" | b"}" | b"," | b";" | b"::") as usize,
+        };
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shorten(source: &str) -> String {
+        String::from_utf8(shorten_locals(source.as_bytes())).unwrap()
+    }
+
+    #[test]
+    fn lex_skips_comments_and_whitespace() {
+        let source = b"local s = \"a -- b\" -- comment\nx = [[long]] .. 0x1F --[==[ long\ncomment ]==] ...";
+        let tokens: Vec<_> = lex(source).into_iter().map(|(token, _)| token).collect();
+        assert_eq!(tokens, [
+            Token::Keyword(b"local"), Token::Name(b"s"), Token::Punct(b"="), Token::Literal,
+            Token::Name(b"x"), Token::Punct(b"="), Token::Literal, Token::Punct(b".."), Token::Literal,
+            Token::Punct(b"..."),
+        ]);
+    }
+
+    #[test]
+    fn lex_ranges_cover_tokens() {
+        let source = b"a.b:c(1e-3, 'q')";
+        let texts: Vec<_> = lex(source).into_iter().map(|(_, range)| &source[range]).collect();
+        assert_eq!(texts, [&b"a"[..], b".", b"b", b":", b"c", b"(", b"1e-3", b",", b"'q'", b")"]);
+    }
+
+    #[test]
+    fn tokens_cover_whole_script() {
+        let source = "local x = 'hi' -- greet\nreturn 42";
+        let tokens = tokens(source.as_bytes());
+        let kinds: Vec<_> = tokens.iter().map(|(kind, range)| (*kind, &source[range.clone()])).collect();
+        assert_eq!(kinds, [
+            (TokenKind::Keyword, "local"), (TokenKind::Code, " x = "), (TokenKind::String, "'hi'"),
+            (TokenKind::Code, " "), (TokenKind::Comment, "-- greet"), (TokenKind::Code, "\n"),
+            (TokenKind::Keyword, "return"), (TokenKind::Code, " "), (TokenKind::Number, "42"),
+        ]);
+    }
+
+    #[test]
+    fn shortens_function_expression_initializers() {
+        assert_eq!(shorten("local f = function() local y = 1 end\nprint(f)"), "local a = function() local b = 1 end\nprint(a)");
+        assert_eq!(
+            shorten("local inc = function(n) local step = 1 return n + step end\ninc(2)"),
+            "local a = function(b) local c = 1 return b + c end\na(2)",
+        );
+    }
+
+    #[test]
+    fn shortens_local_functions() {
+        assert_eq!(
+            shorten("local function fact(n) if n < 2 then return 1 end return n * fact(n - 1) end\nprint(fact(5))"),
+            "local function a(b) if b < 2 then return 1 end return b * a(b - 1) end\nprint(a(5))",
+        );
+    }
+
+    #[test]
+    fn shortens_multiple_assignment() {
+        assert_eq!(
+            shorten("local first, second = 1, 2\nlocal sum = first + second\nprint(sum)"),
+            "local a, b = 1, 2\nlocal c = a + b\nprint(c)",
+        );
+    }
+
+    #[test]
+    fn respects_shadowing() {
+        assert_eq!(
+            shorten("local x = 1\ndo local x = x + 1 print(x) end\nprint(x)"),
+            "local a = 1\ndo local b = a + 1 print(b) end\nprint(a)",
+        );
+        // the initializer sees the global, not the new local
+        assert_eq!(shorten("local print = print\nprint(1)"), "local a = print\na(1)");
+    }
+
+    #[test]
+    fn leaves_fields_and_keys_alone() {
+        assert_eq!(shorten("local t = {t = 1}\nprint(t.t, t:t())"), "local a = {t = 1}\nprint(a.t, a:t())");
+    }
+
+    #[test]
+    fn leaves_unbalanced_scripts_alone() {
+        assert_eq!(shorten("local x = 1 end"), "local x = 1 end");
+    }
+}
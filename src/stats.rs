@@ -0,0 +1,142 @@
+//! An overview of where an avatar's size goes, for finding out why it won't upload (or how close
+//! it is to not uploading) without unpacking it. Sizes are given both as stored and after the
+//! compression the backend measures, since textures and sounds barely compress while scripts and
+//! models compress a lot.
+
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use quartz_nbt::io::{Flavor, NbtIoError};
+use quartz_nbt::serde::Array;
+use serde::Serialize;
+use crate::complexity::Complexity;
+use crate::image;
+use crate::limits::{self, LimitReport, Tier};
+use crate::Moon;
+
+/// The size of one part of an avatar, such as its textures.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ComponentSize {
+    /// Which part of the avatar this is, named as in [LimitReport::components].
+    pub name: &'static str,
+    /// How many bytes it takes up uncompressed.
+    pub uncompressed: usize,
+    /// How many bytes it takes up on its own once compressed.
+    pub compressed: usize,
+}
+
+/// A texture or script, with its size.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct FileSize {
+    /// The texture's or script's name.
+    pub name: String,
+    /// How many bytes it takes up.
+    pub size: usize,
+    /// The width and height of a texture, if it could be read. Always [None] for scripts.
+    pub dimensions: Option<(u32, u32)>,
+}
+
+/// Where an avatar's size goes, from [Moon::stats].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Stats {
+    /// How the compressed avatar compares to the size limit.
+    pub limits: LimitReport,
+    /// The size of the whole avatar uncompressed, in bytes.
+    pub uncompressed: usize,
+    /// The size of each part of the avatar, largest (once compressed) first.
+    pub components: Vec<ComponentSize>,
+    /// The textures, largest first.
+    pub textures: Vec<FileSize>,
+    /// The scripts, largest first.
+    pub scripts: Vec<FileSize>,
+    /// How many cubes, meshes, and faces the models have.
+    pub complexity: Complexity,
+}
+
+impl Stats {
+    /// Drops all but the `n` largest textures and scripts.
+    pub fn truncate(&mut self, n: usize) {
+        self.textures.truncate(n);
+        self.scripts.truncate(n);
+    }
+}
+
+/// Draws how full something is as a bar `width` characters wide.
+fn bar(used: usize, limit: usize, width: usize) -> String {
+    let filled = (used as f64 / limit.max(1) as f64 * width as f64).round().min(width as f64) as usize;
+    "█".repeat(filled) + &"░".repeat(width - filled)
+}
+
+impl Display for Stats {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        let (total, limit) = (self.limits.total, self.limits.tier.limit());
+        let percent = total as f64 / limit.max(1) as f64 * 100.0;
+        writeln!(fmt, "[{}] {percent:.0}% of the {} tier's {limit}B", bar(total, limit, 40), self.limits.tier)?;
+        match self.limits.headroom() {
+            headroom if headroom < 0 => writeln!(fmt, "{total}B compressed ({}B uncompressed), {}B over", self.uncompressed, -headroom)?,
+            headroom => writeln!(fmt, "{total}B compressed ({}B uncompressed), {headroom}B left", self.uncompressed)?,
+        }
+        writeln!(fmt)?;
+        writeln!(fmt, "{:<12}{:>12}{:>14}", "component", "compressed", "uncompressed")?;
+        for component in &self.components {
+            writeln!(fmt, "{:<12}{:>11}B{:>13}B", component.name, component.compressed, component.uncompressed)?;
+        }
+        for (title, files) in [("textures", &self.textures), ("scripts", &self.scripts)] {
+            if files.is_empty() {
+                continue;
+            }
+            writeln!(fmt)?;
+            writeln!(fmt, "largest {title}:")?;
+            for file in files {
+                write!(fmt, "• {}: {}B", file.name, file.size)?;
+                if let Some((width, height)) = file.dimensions {
+                    write!(fmt, " ({width}×{height})")?;
+                }
+                writeln!(fmt)?;
+            }
+        }
+        writeln!(fmt)?;
+        writeln!(fmt, "models: {}", self.complexity)
+    }
+}
+
+/// Lists the files in a map by size, largest first.
+fn by_size(map: &HashMap<String, Array<Vec<u8>>>, textures: bool) -> Vec<FileSize> {
+    let mut files: Vec<FileSize> = map.iter().map(|(name, data)| FileSize {
+        name: name.clone(),
+        size: data.as_ref().len(),
+        dimensions: if textures { image::dimensions(data.as_ref()).ok() } else { None },
+    }).collect();
+    files.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+    files
+}
+
+impl Moon {
+    /// Measures where this avatar's size goes, comparing it against a tier's limit; see [the
+    /// module documentation][crate::stats].
+    pub fn stats(&self, tier: Tier) -> Result<Stats, NbtIoError> {
+        let limits = self.check_limits(tier)?;
+        let uncompressed = quartz_nbt::serde::serialize(self, Some(""), Flavor::Uncompressed)?.len();
+        let mut components = vec![];
+        for &(name, compressed) in &limits.components {
+            let uncompressed = match name {
+                "textures" => limits::serialized_size(name, &self.textures, Flavor::Uncompressed)?,
+                "scripts" => limits::serialized_size(name, &self.scripts, Flavor::Uncompressed)?,
+                "sounds" => limits::serialized_size(name, &self.sounds, Flavor::Uncompressed)?,
+                "animations" => limits::serialized_size(name, &self.animations, Flavor::Uncompressed)?,
+                "models" => limits::serialized_size(name, &self.models, Flavor::Uncompressed)?,
+                "resources" => limits::serialized_size(name, &self.resources, Flavor::Uncompressed)?,
+                "metadata" => limits::serialized_size(name, &self.metadata, Flavor::Uncompressed)?,
+                _ => unreachable!("check_limits only measures these components"),
+            };
+            components.push(ComponentSize { name, uncompressed, compressed });
+        }
+        Ok(Stats {
+            limits,
+            uncompressed,
+            components,
+            textures: by_size(&self.textures.src, true),
+            scripts: by_size(&self.scripts, false),
+            complexity: self.models.as_ref().map_or_else(Complexity::default, |models| models.complexity()),
+        })
+    }
+}
@@ -0,0 +1,52 @@
+//! Watching an avatar folder, so that it can be [repacked][crate::pack] every time it's saved.
+//!
+//! Saving rarely changes just one file once: editors write temporary files and rename them over
+//! the original, and Blockbench writes a model several times over. Changes are therefore collected
+//! until the folder has been quiet for a moment, and only then is the avatar rebuilt.
+
+use std::path::{self, Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::Duration;
+use notify::{EventKind, RecursiveMode, Watcher};
+
+/// Whether a change to `path` could change the packed avatar. Hidden files and folders are skipped
+/// when packing, so changing them doesn't matter.
+fn relevant(dir: &Path, path: &Path, ignored: &[PathBuf]) -> bool {
+    if ignored.iter().any(|ignored| path.starts_with(ignored)) {
+        return false;
+    }
+    let Ok(relative) = path.strip_prefix(dir) else { return true };
+    !relative.components().any(|c| c.as_os_str().to_string_lossy().starts_with('.'))
+}
+
+/// Watches `dir` and everything in it, calling `rebuild` once at first and again after each burst
+/// of changes, once `debounce` has passed without any more. Changes to hidden files and to
+/// anything under `ignored`, such as where the rebuilt avatar is written, don't count. Only returns
+/// if the folder can't be watched.
+pub fn watch(dir: &Path, debounce: Duration, ignored: &[PathBuf], mut rebuild: impl FnMut()) -> notify::Result<!> {
+    let dir = path::absolute(dir).map_err(notify::Error::io)?;
+    let ignored = ignored.iter().map(path::absolute).collect::<Result<Vec<_>, _>>().map_err(notify::Error::io)?;
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    watcher.watch(&dir, RecursiveMode::Recursive)?;
+    rebuild();
+    loop {
+        let mut timeout = None;
+        loop {
+            let event = match timeout {
+                None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+                Some(timeout) => rx.recv_timeout(timeout),
+            };
+            match event {
+                // reading files while packing counts as an access, which would rebuild forever
+                Ok(Ok(event)) if matches!(event.kind, EventKind::Access(_)) => {}
+                Ok(Ok(event)) if !event.paths.is_empty() && !event.paths.iter().any(|p| relevant(&dir, p, &ignored)) => {}
+                // errors (such as the event queue overflowing) mean changes may have been missed
+                Ok(_) => timeout = Some(debounce),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => unreachable!("the watcher outlives the loop"),
+            }
+        }
+        rebuild();
+    }
+}